@@ -27,12 +27,12 @@ impl From<IconImage> for IconSet {
     }
 }
 
-#[doc(hidden)]
-/// Platform-agnostic icon operations
+/// Platform-agnostic icon operations for individual files and directories
 pub trait IconProvider {
     /// Set the icon for a file/directory
-    fn set_icon_for_path<P, I>(&self, path: P, icon_set: &I) -> Result<()>
-    where
-        P: AsRef<Path>,
-        I: Into<IconSet>;
+    fn set_icon_for_path<P: AsRef<Path>>(&self, path: P, icon_set: &IconSet) -> Result<()>;
+    /// Reset the icon for a file/directory
+    fn reset_icon_for_path<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    /// Read back the custom icon of a file/directory, if one is set
+    fn icon_for_path<P: AsRef<Path>>(&self, path: P) -> Result<Option<IconSet>>;
 }
@@ -0,0 +1,271 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use super::error::{FolderSettingsError, Result};
+#[cfg(target_os = "linux")]
+use super::sys::linux::DesktopLauncher;
+use crate::api::{IconImage, IconSet};
+use crate::icon::IconError;
+
+mod icns;
+mod plist;
+mod squashfs;
+
+use squashfs::SquashFs;
+
+/// The icon of an AppDir: a PNG or SVG file, or a link to one.
+const DIR_ICON: &str = ".DirIcon";
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+/// Enough of the ELF header to locate the section header table.
+const ELF_HEADER_SIZE: usize = 64;
+/// The AppImage type, stored in the padding of the ELF identification.
+const APPIMAGE_TYPE: std::ops::Range<usize> = 8..11;
+const APPIMAGE_TYPE_1: &[u8] = b"AI\x01";
+
+impl IconSet {
+    /// Decode an Apple Icon Image (`.icns`), one image per size. PNG, ARGB
+    /// and legacy RGB elements are read; JPEG 2000 elements are skipped.
+    pub fn from_icns(data: &[u8]) -> Result<IconSet> {
+        let images =
+            icns::decode(data).map_err(|e| IconError::IconImage(format!("invalid ICNS: {e}")))?;
+        Ok(IconSet {
+            images: images.into_iter().map(|data| IconImage { data }).collect(),
+            svg: None,
+        })
+    }
+
+    /// The icon of a macOS application bundle such as `Acme.app`, on any
+    /// platform: the `.icns` file named by `CFBundleIconFile` in
+    /// `Contents/Info.plist` (XML or binary), from `Contents/Resources`.
+    /// Bundles that only ship an asset catalog (`CFBundleIconName`) are not
+    /// supported.
+    pub fn from_app_bundle<P: AsRef<Path>>(path: P) -> Result<IconSet> {
+        let bundle = path.as_ref();
+        let invalid =
+            |message: String| FolderSettingsError::AppBundle(bundle.to_path_buf(), message);
+        let contents = bundle.join("Contents");
+        let info_path = contents.join("Info.plist");
+        let info = std::fs::read(&info_path).map_err(|e| FolderSettingsError::Io(info_path, e))?;
+        let name = plist::dict_string(&info, "CFBundleIconFile")
+            .map_err(|e| invalid(format!("Info.plist: {e}")))?
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| invalid("Info.plist has no CFBundleIconFile".to_string()))?;
+        // The extension may be left out.
+        let file_name = if Path::new(&name).extension().is_some() {
+            name
+        } else {
+            format!("{name}.icns")
+        };
+        let icon_path = contents.join("Resources").join(file_name);
+        let data = std::fs::read(&icon_path).map_err(|e| FolderSettingsError::Io(icon_path, e))?;
+        IconSet::from_icns(&data)
+    }
+
+    /// The icon of a freedesktop application launcher (`.desktop` file):
+    /// its `Icon=`, looked up in the active icon theme or loaded from the
+    /// absolute path it names (see `DesktopLauncher::icon_set`).
+    #[cfg(target_os = "linux")]
+    pub fn from_desktop_entry<P: AsRef<Path>>(path: P) -> Result<IconSet> {
+        let set = DesktopLauncher::at(path.as_ref()).icon_set()?;
+        Ok(set.into())
+    }
+
+    /// The icon of an AppImage: the `.DirIcon` at the root of its embedded
+    /// SquashFS file system, read from the file without mounting or running
+    /// it. A PNG icon becomes the only image of the set, an SVG icon its
+    /// `svg`.
+    pub fn from_appimage<P: AsRef<Path>>(path: P) -> Result<IconSet> {
+        let path = path.as_ref();
+        let invalid = |message: String| FolderSettingsError::AppImage(path.to_path_buf(), message);
+        let mut file =
+            File::open(path).map_err(|e| FolderSettingsError::Io(path.to_path_buf(), e))?;
+        let mut header = [0; ELF_HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("not an ELF executable".to_string()))?;
+        let offset = squashfs_offset(&header).map_err(invalid)?;
+        let mut fs = SquashFs::open(BufReader::new(file), offset).map_err(invalid)?;
+        let icon = fs
+            .read_file(DIR_ICON)
+            .map_err(invalid)?
+            .ok_or_else(|| invalid(format!("no {DIR_ICON}")))?;
+        decode_image(&icon).map_err(|e| invalid(format!("{DIR_ICON}: {e}")))
+    }
+}
+
+/// Where the file system of a type 2 AppImage starts: right after the ELF
+/// runtime, whose section header table comes last.
+fn squashfs_offset(header: &[u8; ELF_HEADER_SIZE]) -> std::result::Result<u64, String> {
+    if !header.starts_with(ELF_MAGIC) {
+        return Err("not an ELF executable".to_string());
+    }
+    if &header[APPIMAGE_TYPE] == APPIMAGE_TYPE_1 {
+        return Err("type 1 AppImages (ISO 9660) are not supported".to_string());
+    }
+    let big_endian = header[5] == 2;
+    let read = |pos: usize, size: usize| {
+        let bytes = header[pos..pos + size].iter();
+        if big_endian {
+            bytes.fold(0u64, |n, &b| n << 8 | u64::from(b))
+        } else {
+            bytes.rev().fold(0u64, |n, &b| n << 8 | u64::from(b))
+        }
+    };
+    let (table, entry_size, entries) = match header[4] {
+        1 => (read(0x20, 4), read(0x2E, 2), read(0x30, 2)),
+        2 => (read(0x28, 8), read(0x3A, 2), read(0x3C, 2)),
+        _ => return Err("unknown ELF class".to_string()),
+    };
+    entry_size
+        .checked_mul(entries)
+        .and_then(|size| table.checked_add(size))
+        .ok_or_else(|| "invalid section header table".to_string())
+}
+
+/// A PNG (or other raster) or SVG icon file as a set.
+fn decode_image(data: &[u8]) -> std::result::Result<IconSet, String> {
+    if let Ok(text) = std::str::from_utf8(data)
+        && text.contains("<svg")
+    {
+        return Ok(IconSet {
+            images: Vec::new(),
+            svg: Some(text.to_string()),
+        });
+    }
+    let data = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    Ok(IconImage { data }.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::squashfs::builder::{Builder, Node};
+    use super::*;
+    use image::{DynamicImage, RgbaImage};
+
+    fn png(size: u32) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::new(size, size))
+            .write_to(&mut out, image::ImageFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    /// A 64-bit little-endian ELF header whose section header table ends
+    /// at `end`, padded to that length.
+    fn runtime(end: usize) -> Vec<u8> {
+        let mut elf = vec![0; end];
+        elf[..4].copy_from_slice(ELF_MAGIC);
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[APPIMAGE_TYPE].copy_from_slice(b"AI\x02");
+        let table = end as u64 - 3 * 64;
+        elf[0x28..0x30].copy_from_slice(&table.to_le_bytes());
+        elf[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3C..0x3E].copy_from_slice(&3u16.to_le_bytes());
+        elf
+    }
+
+    #[test]
+    fn reads_the_dir_icon_of_appimages() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = Node::Dir(vec![
+            (
+                ".DirIcon".to_string(),
+                Node::Symlink("acme.png".to_string()),
+            ),
+            ("acme.png".to_string(), Node::File(png(48))),
+        ]);
+        let mut appimage = runtime(1000);
+        appimage.extend(Builder::new(1, 4096).build(&tree));
+        let path = dir.path().join("Acme-x86_64.AppImage");
+        std::fs::write(&path, appimage).unwrap();
+
+        let set = IconSet::from_appimage(&path).unwrap();
+        assert_eq!(set.images.len(), 1);
+        assert_eq!(set.images[0].data.width(), 48);
+
+        let svg_tree = Node::Dir(vec![(
+            ".DirIcon".to_string(),
+            Node::File(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>".to_vec()),
+        )]);
+        let mut appimage = runtime(400);
+        appimage.extend(Builder::new(6, 4096).build(&svg_tree));
+        std::fs::write(&path, appimage).unwrap();
+        let set = IconSet::from_appimage(&path).unwrap();
+        assert!(set.images.is_empty());
+        assert!(set.svg.unwrap().starts_with("<svg"));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_appimages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.AppImage");
+        std::fs::write(&path, "#!/bin/sh\necho not an AppImage, just a script\n").unwrap();
+        assert!(matches!(
+            IconSet::from_appimage(&path),
+            Err(FolderSettingsError::AppImage(..))
+        ));
+
+        let mut type1 = runtime(200);
+        type1[APPIMAGE_TYPE].copy_from_slice(APPIMAGE_TYPE_1);
+        std::fs::write(&path, type1).unwrap();
+        let err = IconSet::from_appimage(&path).unwrap_err().to_string();
+        assert!(err.contains("type 1"), "{err}");
+
+        let mut overflowing = runtime(200);
+        overflowing[0x28..0x30].copy_from_slice(&(u64::MAX - 10).to_le_bytes());
+        std::fs::write(&path, overflowing).unwrap();
+        assert!(matches!(
+            IconSet::from_appimage(&path),
+            Err(FolderSettingsError::AppImage(..))
+        ));
+
+        let mut no_icon = runtime(200);
+        no_icon.extend(Builder::new(1, 4096).build(&Node::Dir(Vec::new())));
+        std::fs::write(&path, no_icon).unwrap();
+        let err = IconSet::from_appimage(&path).unwrap_err().to_string();
+        assert!(err.contains(".DirIcon"), "{err}");
+    }
+
+    #[test]
+    fn reads_the_icon_of_app_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("Acme.app");
+        let resources = bundle.join("Contents/Resources");
+        std::fs::create_dir_all(&resources).unwrap();
+        std::fs::write(
+            bundle.join("Contents/Info.plist"),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\">\n<dict>\n\
+             \t<key>CFBundleIconFile</key>\n\t<string>Acme</string>\n</dict>\n</plist>\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            IconSet::from_app_bundle(&bundle),
+            Err(FolderSettingsError::Io(..))
+        ));
+
+        let mut icns = b"icns".to_vec();
+        let body = [
+            &b"ic07"[..],
+            &(png(128).len() as u32 + 8).to_be_bytes(),
+            &png(128),
+        ]
+        .concat();
+        icns.extend((body.len() as u32 + 8).to_be_bytes());
+        icns.extend(body);
+        std::fs::write(resources.join("Acme.icns"), icns).unwrap();
+        let set = IconSet::from_app_bundle(&bundle).unwrap();
+        assert_eq!(set.images.len(), 1);
+        assert_eq!(set.images[0].data.width(), 128);
+
+        std::fs::write(
+            bundle.join("Contents/Info.plist"),
+            "<plist><dict><key>CFBundleIconName</key><string>AppIcon</string></dict></plist>",
+        )
+        .unwrap();
+        assert!(matches!(
+            IconSet::from_app_bundle(&bundle),
+            Err(FolderSettingsError::AppBundle(..))
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use image::{DynamicImage, RgbaImage};
+
+const MAGIC: &[u8; 4] = b"icns";
+const HEADER_SIZE: usize = 8;
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Prefix of the RLE-compressed ARGB data of `ic04` and `ic05`.
+const ARGB_MAGIC: &[u8] = b"ARGB";
+
+/// Pre-PNG element types: PackBits-compressed RGB planes, their size, the
+/// element holding their 8-bit alpha mask, and how many padding bytes
+/// precede the data.
+const LEGACY_TYPES: [(&[u8; 4], u32, &[u8; 4], usize); 4] = [
+    (b"is32", 16, b"s8mk", 0),
+    (b"il32", 32, b"l8mk", 0),
+    (b"ih32", 48, b"h8mk", 0),
+    (b"it32", 128, b"t8mk", 4),
+];
+
+/// Element types that may hold RLE-compressed ARGB data instead of a PNG.
+const ARGB_TYPES: [(&[u8; 4], u32); 2] = [(b"ic04", 16), (b"ic05", 32)];
+
+/// Decode an Apple Icon Image (`.icns`) into one image per pixel size, from
+/// smallest to largest.
+///
+/// PNG, ARGB and the legacy RGB + mask elements are read. JPEG 2000
+/// elements, used by some older icons, are skipped; if nothing else is
+/// left, that is an error. When several elements have the same size (e.g.
+/// `ic11`, 16x16@2x, next to `icp5`, 32x32), the first wins, and PNG or ARGB
+/// data beats the legacy format.
+pub(crate) fn decode(data: &[u8]) -> Result<Vec<DynamicImage>, String> {
+    let elements = elements(data)?;
+    let mut images: BTreeMap<u32, (bool, DynamicImage)> = BTreeMap::new();
+    let mut keep = |image: DynamicImage, modern: bool| {
+        let better = images
+            .get(&image.width())
+            .is_none_or(|(existing, _)| modern && !existing);
+        if better {
+            images.insert(image.width(), (modern, image));
+        }
+    };
+
+    for (kind, body) in &elements {
+        if body.starts_with(PNG_MAGIC) {
+            let image = image::load_from_memory_with_format(body, image::ImageFormat::Png)
+                .map_err(|e| format!("invalid PNG in {}: {e}", kind_name(kind)))?;
+            keep(image, true);
+        } else if let Some(&(_, size)) = ARGB_TYPES.iter().find(|(k, _)| k == kind)
+            && let Some(argb) = body.strip_prefix(ARGB_MAGIC)
+        {
+            keep(decode_argb(argb, size)?, true);
+        } else if let Some(&(_, size, mask_kind, padding)) =
+            LEGACY_TYPES.iter().find(|(k, ..)| k == kind)
+        {
+            let mask = elements
+                .iter()
+                .find(|(k, _)| *k == mask_kind)
+                .map(|(_, mask)| *mask);
+            let rgb = body.get(padding..).ok_or("truncated legacy icon")?;
+            keep(decode_rgb(rgb, mask, size)?, false);
+        }
+    }
+
+    if images.is_empty() {
+        return Err("no PNG or RGB images (JPEG 2000 icons are not supported)".to_string());
+    }
+    Ok(images.into_values().map(|(_, image)| image).collect())
+}
+
+/// A type code and the data that follows it.
+type Element<'a> = (&'a [u8; 4], &'a [u8]);
+
+/// The elements of an icon file.
+fn elements(data: &[u8]) -> Result<Vec<Element<'_>>, String> {
+    if data.len() < HEADER_SIZE || &data[..4] != MAGIC {
+        return Err("not an ICNS file".to_string());
+    }
+    let total = (read_u32(data, 4) as usize).min(data.len());
+    let mut elements = Vec::new();
+    let mut pos = HEADER_SIZE;
+    while pos + HEADER_SIZE <= total {
+        let kind: &[u8; 4] = data[pos..pos + 4].try_into().unwrap();
+        let len = read_u32(data, pos + 4) as usize;
+        if len < HEADER_SIZE || len > total - pos {
+            return Err(format!("truncated {} element", kind_name(kind)));
+        }
+        elements.push((kind, &data[pos + HEADER_SIZE..pos + len]));
+        pos += len;
+    }
+    Ok(elements)
+}
+
+/// Planar RGB decompressed with PackBits, plus an optional alpha mask.
+fn decode_rgb(data: &[u8], mask: Option<&[u8]>, size: u32) -> Result<DynamicImage, String> {
+    let pixels = (size * size) as usize;
+    let rgb = unpack_bits(data, pixels * 3)?;
+    let alpha = mask.filter(|mask| mask.len() == pixels);
+    Ok(planar_image(size, |channel, i| match channel {
+        0 => alpha.map_or(0xFF, |alpha| alpha[i]),
+        c => rgb[(c - 1) * pixels + i],
+    }))
+}
+
+/// Planar ARGB decompressed with PackBits.
+fn decode_argb(data: &[u8], size: u32) -> Result<DynamicImage, String> {
+    let pixels = (size * size) as usize;
+    let argb = unpack_bits(data, pixels * 4)?;
+    Ok(planar_image(size, |channel, i| argb[channel * pixels + i]))
+}
+
+/// Build an image from `sample(channel, pixel)`, channels being A, R, G, B.
+fn planar_image(size: u32, sample: impl Fn(usize, usize) -> u8) -> DynamicImage {
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        let i = (y * size + x) as usize;
+        image::Rgba([sample(1, i), sample(2, i), sample(3, i), sample(0, i)])
+    });
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Apple's PackBits variant: a header byte below 0x80 copies that many plus
+/// one literal bytes, any other repeats the next byte `header - 0x80 + 3`
+/// times.
+fn unpack_bits(data: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+    while out.len() < len {
+        let header = *data.get(pos).ok_or("truncated RLE data")? as usize;
+        if header < 0x80 {
+            let literal = data
+                .get(pos + 1..pos + 2 + header)
+                .ok_or("truncated RLE data")?;
+            out.extend_from_slice(literal);
+            pos += 2 + header;
+        } else {
+            let value = *data.get(pos + 1).ok_or("truncated RLE data")?;
+            out.extend(std::iter::repeat_n(value, header - 0x80 + 3));
+            pos += 2;
+        }
+    }
+    out.truncate(len);
+    Ok(out)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn kind_name(kind: &[u8; 4]) -> String {
+    String::from_utf8_lossy(kind).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_vec();
+        out.extend((body.len() as u32 + 8).to_be_bytes());
+        out.extend(body);
+        out
+    }
+
+    fn icns(elements: &[Vec<u8>]) -> Vec<u8> {
+        let body = elements.concat();
+        let mut out = MAGIC.to_vec();
+        out.extend((body.len() as u32 + 8).to_be_bytes());
+        out.extend(body);
+        out
+    }
+
+    fn png(size: u32) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(size, size, image::Rgba([1, 2, 3, 4])))
+            .write_to(&mut out, image::ImageFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    /// PackBits runs of `value` covering `len` bytes.
+    fn run(value: u8, mut len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        while len > 0 {
+            let n = len.min(130);
+            if n < 3 {
+                out.push(n as u8 - 1);
+                out.extend(std::iter::repeat_n(value, n));
+            } else {
+                out.extend([(n - 3 + 0x80) as u8, value]);
+            }
+            len -= n;
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_png_and_legacy_elements() {
+        let pixels = 16 * 16;
+        let rgb = [run(0xFF, pixels), run(0x80, pixels), run(0x00, pixels)].concat();
+        let data = icns(&[
+            element(b"is32", &rgb),
+            element(b"s8mk", &[0x40; 16 * 16]),
+            element(b"ic11", &png(32)),
+            element(b"ic07", &png(128)),
+            element(b"icp5", &png(32)),
+        ]);
+        let images = decode(&data).unwrap();
+        let sizes: Vec<_> = images.iter().map(|i| i.width()).collect();
+        assert_eq!(sizes, [16, 32, 128]);
+        assert_eq!(
+            images[0].to_rgba8().get_pixel(3, 7).0,
+            [0xFF, 0x80, 0, 0x40]
+        );
+        assert_eq!(images[1].to_rgba8().get_pixel(0, 0).0, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn decodes_argb_and_prefers_it_to_legacy_data() {
+        let pixels = 16 * 16;
+        let argb = [
+            ARGB_MAGIC.to_vec(),
+            run(0xFF, pixels),
+            run(0x10, pixels),
+            run(0x20, pixels),
+            run(0x30, pixels),
+        ]
+        .concat();
+        let legacy = run(0, pixels * 3);
+        let data = icns(&[element(b"is32", &legacy), element(b"ic04", &argb)]);
+        let images = decode(&data).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            images[0].to_rgba8().get_pixel(15, 15).0,
+            [0x10, 0x20, 0x30, 0xFF]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(decode(b"not an icon").is_err());
+        let mut data = icns(&[element(b"ic07", &png(128))]);
+        data.truncate(data.len() - 1);
+        assert!(decode(&data).is_err());
+        // JPEG 2000 only.
+        let jp2 = icns(&[element(b"ic08", b"\0\0\0\x0cjP  \r\n\x87\n")]);
+        assert!(decode(&jp2).is_err());
+    }
+}
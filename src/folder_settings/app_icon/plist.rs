@@ -0,0 +1,245 @@
+use crate::folder_settings::xml::{self, Element, Node};
+
+/// Signature of a binary property list.
+const BINARY_MAGIC: &[u8] = b"bplist00";
+/// Size of the trailer at the end of a binary property list.
+const TRAILER_SIZE: usize = 32;
+
+/// The string stored under `key` in the top-level dictionary of a property
+/// list, e.g. `CFBundleIconFile` in an `Info.plist`. Both the XML and the
+/// binary (`bplist00`) formats are read; `None` if the key is missing or its
+/// value is not a string.
+pub(crate) fn dict_string(data: &[u8], key: &str) -> Result<Option<String>, String> {
+    if data.starts_with(BINARY_MAGIC) {
+        BinaryPlist::parse(data)?.dict_string(key)
+    } else {
+        let text = std::str::from_utf8(data).map_err(|e| format!("not UTF-8: {e}"))?;
+        xml_dict_string(text, key)
+    }
+}
+
+fn xml_dict_string(text: &str, key: &str) -> Result<Option<String>, String> {
+    let root = xml::parse(text)?;
+    if root.name != "plist" {
+        return Err(format!("root element is <{}>, not <plist>", root.name));
+    }
+    let dict = root
+        .elements()
+        .next()
+        .filter(|e| e.name == "dict")
+        .ok_or("the top-level object is not a dictionary")?;
+    let mut elements = dict.elements();
+    while let Some(name) = elements.next() {
+        let value = elements.next().ok_or("dictionary key without a value")?;
+        if name.name == "key" && text_of(name) == key {
+            return Ok((value.name == "string").then(|| text_of(value)));
+        }
+    }
+    Ok(None)
+}
+
+fn text_of(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            Node::Text(text) => Some(text.as_str()),
+            Node::Element(_) => None,
+        })
+        .collect()
+}
+
+/// A binary property list: objects are found through an offset table and
+/// refer to each other by index.
+struct BinaryPlist<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    top: usize,
+}
+
+impl<'a> BinaryPlist<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let trailer = data
+            .len()
+            .checked_sub(TRAILER_SIZE)
+            .filter(|&start| start >= BINARY_MAGIC.len())
+            .map(|start| &data[start..])
+            .ok_or("truncated binary property list")?;
+        let offset_size = usize::from(trailer[6]);
+        let ref_size = usize::from(trailer[7]);
+        let count = read_uint(trailer, 8, 8)? as usize;
+        let top = read_uint(trailer, 16, 8)? as usize;
+        let table = read_uint(trailer, 24, 8)? as usize;
+        if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+            return Err("invalid integer sizes in trailer".to_string());
+        }
+        if count.saturating_mul(offset_size) > data.len() || top >= count {
+            return Err("invalid object count in trailer".to_string());
+        }
+        let offsets = (0..count)
+            .map(|i| read_uint(data, table + i * offset_size, offset_size).map(|o| o as usize))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            data,
+            offsets,
+            ref_size,
+            top,
+        })
+    }
+
+    fn dict_string(&self, key: &str) -> Result<Option<String>, String> {
+        let (marker, count, start) = self.object(self.top)?;
+        if marker != 0xD {
+            return Err("the top-level object is not a dictionary".to_string());
+        }
+        for i in 0..count {
+            let key_ref = self.reference(start + i * self.ref_size)?;
+            if self.string(key_ref)?.as_deref() == Some(key) {
+                let value_ref = self.reference(start + (count + i) * self.ref_size)?;
+                return self.string(value_ref);
+            }
+        }
+        Ok(None)
+    }
+
+    /// The string object `index`, or `None` if it is some other type.
+    fn string(&self, index: usize) -> Result<Option<String>, String> {
+        let (marker, len, start) = self.object(index)?;
+        let bytes = |size: usize| {
+            len.checked_mul(size)
+                .and_then(|n| self.data.get(start..start.checked_add(n)?))
+                .ok_or("truncated string object")
+        };
+        match marker {
+            // ASCII
+            0x5 => Ok(Some(bytes(1)?.iter().map(|&b| char::from(b)).collect())),
+            // UTF-16BE
+            0x6 => {
+                let units = bytes(2)?
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+                Ok(Some(
+                    char::decode_utf16(units)
+                        .map(|c| c.unwrap_or('\u{FFFD}'))
+                        .collect(),
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The type nibble, length and payload offset of object `index`.
+    fn object(&self, index: usize) -> Result<(u8, usize, usize), String> {
+        let offset = *self
+            .offsets
+            .get(index)
+            .ok_or_else(|| format!("object reference {index} out of range"))?;
+        let byte = *self.data.get(offset).ok_or("object offset out of range")?;
+        let (marker, len) = (byte >> 4, usize::from(byte & 0xF));
+        if len != 0xF {
+            return Ok((marker, len, offset + 1));
+        }
+        // The length follows as an integer object.
+        let int = *self.data.get(offset + 1).ok_or("truncated object length")?;
+        if int >> 4 != 0x1 || int & 0xF > 3 {
+            return Err("invalid object length".to_string());
+        }
+        let size = 1 << (int & 0xF);
+        let len = read_uint(self.data, offset + 2, size)? as usize;
+        Ok((marker, len, offset + 2 + size))
+    }
+
+    fn reference(&self, pos: usize) -> Result<usize, String> {
+        read_uint(self.data, pos, self.ref_size).map(|r| r as usize)
+    }
+}
+
+/// A big-endian unsigned integer of `size` bytes at `pos`.
+fn read_uint(data: &[u8], pos: usize, size: usize) -> Result<u64, String> {
+    let bytes = pos
+        .checked_add(size)
+        .and_then(|end| data.get(pos..end))
+        .ok_or("integer out of range")?;
+    Ok(bytes.iter().fold(0, |n, &b| n << 8 | u64::from(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_xml_plists() {
+        let plist = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key>
+    <string>com.example.Acme</string>
+    <key>LSRequiresNativeExecution</key>
+    <true/>
+    <key>CFBundleIconFile</key>
+    <string>Acme &amp; Co</string>
+</dict>
+</plist>
+"#;
+        assert_eq!(
+            dict_string(plist, "CFBundleIconFile").unwrap().as_deref(),
+            Some("Acme & Co")
+        );
+        assert_eq!(
+            dict_string(plist, "LSRequiresNativeExecution").unwrap(),
+            None
+        );
+        assert_eq!(dict_string(plist, "CFBundleIconName").unwrap(), None);
+        assert!(dict_string(b"<plist><array/></plist>", "x").is_err());
+    }
+
+    /// `{"CFBundleName": "Äcme", "CFBundleIconFile": "AppIcon"}` in the
+    /// binary format, with one-byte offsets and references.
+    fn binary_plist() -> Vec<u8> {
+        let mut data = b"bplist00".to_vec();
+        let mut offsets = Vec::new();
+        // 0: dict with 2 entries, keys 1 and 2, values 3 and 4.
+        offsets.push(data.len());
+        data.extend([0xD2, 1, 2, 3, 4]);
+        offsets.push(data.len());
+        data.push(0x50 | 12);
+        data.extend(b"CFBundleName");
+        offsets.push(data.len());
+        // Longer than 14 characters: the length follows as an integer.
+        data.extend([0x5F, 0x10, 16]);
+        data.extend(b"CFBundleIconFile");
+        offsets.push(data.len());
+        data.push(0x60 | 4);
+        for unit in "Äcme".encode_utf16() {
+            data.extend(unit.to_be_bytes());
+        }
+        offsets.push(data.len());
+        data.push(0x50 | 7);
+        data.extend(b"AppIcon");
+        let table = data.len();
+        data.extend(offsets.iter().map(|&o| o as u8));
+        data.extend([0; 6]);
+        data.extend([1, 1]);
+        data.extend(5u64.to_be_bytes());
+        data.extend(0u64.to_be_bytes());
+        data.extend((table as u64).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn reads_binary_plists() {
+        let data = binary_plist();
+        assert_eq!(
+            dict_string(&data, "CFBundleIconFile").unwrap().as_deref(),
+            Some("AppIcon")
+        );
+        assert_eq!(
+            dict_string(&data, "CFBundleName").unwrap().as_deref(),
+            Some("Äcme")
+        );
+        assert_eq!(dict_string(&data, "CFBundleIconName").unwrap(), None);
+        assert!(dict_string(&data[..data.len() - 1], "CFBundleName").is_err());
+    }
+}
@@ -0,0 +1,838 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// `hsqs`, little-endian.
+const MAGIC: u32 = 0x7371_7368;
+const SUPERBLOCK_SIZE: usize = 96;
+const METADATA_BLOCK_SIZE: usize = 8192;
+/// Set in a metadata block header when the block is stored uncompressed.
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+/// Set in a data block or fragment size when it is stored uncompressed.
+const DATA_UNCOMPRESSED: u32 = 1 << 24;
+const NO_FRAGMENT: u32 = 0xFFFF_FFFF;
+const FRAGMENT_ENTRY_SIZE: usize = 16;
+/// The block sizes `mksquashfs` accepts: powers of two in this range.
+const MIN_BLOCK_SIZE: u32 = 4 * 1024;
+const MAX_BLOCK_SIZE: u32 = 1024 * 1024;
+/// Symbolic links followed while resolving one path, as in `ELOOP`.
+const MAX_SYMLINKS: usize = 8;
+/// Largest file `read_file` returns. Icons are far smaller; this keeps a
+/// corrupt inode from requesting an absurd allocation.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+const GZIP: u16 = 1;
+const XZ: u16 = 4;
+const ZSTD: u16 = 6;
+
+/// A read-only SquashFS 4.0 image, such as the file system embedded in an
+/// AppImage, read without mounting it.
+///
+/// Only what is needed to find and read a file is implemented: directory
+/// lookup, symbolic links, data blocks and fragments. Images compressed
+/// with gzip, xz or zstd are supported; lzo, lz4 and legacy lzma are not.
+pub(crate) struct SquashFs<R> {
+    reader: R,
+    /// Where the image starts in `reader`; table positions are relative to it.
+    offset: u64,
+    compressor: u16,
+    block_size: u32,
+    root_inode: u64,
+    inode_table: u64,
+    directory_table: u64,
+    fragment_table: u64,
+}
+
+#[derive(Debug)]
+enum Inode {
+    Directory {
+        start_block: u32,
+        offset: u16,
+        size: u32,
+    },
+    File {
+        blocks_start: u64,
+        size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+        block_sizes: Vec<u32>,
+    },
+    Symlink(String),
+    Other,
+}
+
+/// Reads consecutive metadata blocks as one stream.
+struct MetadataCursor {
+    /// Position of the next block to read.
+    next_block: u64,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read + Seek> SquashFs<R> {
+    /// Open the image that starts `offset` bytes into `reader`.
+    pub(crate) fn open(mut reader: R, offset: u64) -> Result<Self, String> {
+        let mut superblock = [0; SUPERBLOCK_SIZE];
+        reader
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| reader.read_exact(&mut superblock))
+            .map_err(|e| format!("cannot read the superblock: {e}"))?;
+        if le_u32(&superblock, 0) != MAGIC {
+            return Err("not a SquashFS image".to_string());
+        }
+        let version = (le_u16(&superblock, 28), le_u16(&superblock, 30));
+        if version.0 != 4 {
+            return Err(format!("unsupported version {}.{}", version.0, version.1));
+        }
+        let block_size = le_u32(&superblock, 12);
+        if !block_size.is_power_of_two() || !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+        {
+            return Err(format!("invalid block size {block_size}"));
+        }
+        Ok(Self {
+            reader,
+            offset,
+            block_size,
+            compressor: le_u16(&superblock, 20),
+            root_inode: le_u64(&superblock, 32),
+            inode_table: le_u64(&superblock, 64),
+            directory_table: le_u64(&superblock, 72),
+            fragment_table: le_u64(&superblock, 80),
+        })
+    }
+
+    /// The contents of the regular file at `path`, relative to the root,
+    /// following symbolic links. `None` if it does not exist.
+    pub(crate) fn read_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.lookup(path)? {
+            None => Ok(None),
+            Some(Inode::File {
+                blocks_start,
+                size,
+                fragment,
+                fragment_offset,
+                block_sizes,
+            }) => self
+                .file_data(blocks_start, size, fragment, fragment_offset, &block_sizes)
+                .map(Some),
+            Some(_) => Err(format!("{path} is not a regular file")),
+        }
+    }
+
+    /// Resolve `path` from the root directory.
+    fn lookup(&mut self, path: &str) -> Result<Option<Inode>, String> {
+        let mut pending: Vec<String> = components(path).rev().map(str::to_string).collect();
+        // The directories above `current`, from the root down.
+        let mut parents: Vec<Inode> = Vec::new();
+        let mut current = self.inode(self.root_inode)?;
+        let mut symlinks = 0;
+
+        while let Some(name) = pending.pop() {
+            if name == ".." {
+                if let Some(parent) = parents.pop() {
+                    current = parent;
+                }
+                continue;
+            }
+            let Inode::Directory {
+                start_block,
+                offset,
+                size,
+            } = current
+            else {
+                return Ok(None);
+            };
+            let Some(reference) = self
+                .read_dir(start_block, offset, size)?
+                .into_iter()
+                .find(|(entry, _)| *entry == name)
+                .map(|(_, reference)| reference)
+            else {
+                return Ok(None);
+            };
+            match self.inode(reference)? {
+                Inode::Symlink(target) => {
+                    symlinks += 1;
+                    if symlinks > MAX_SYMLINKS {
+                        return Err(format!("too many levels of symbolic links in {path}"));
+                    }
+                    if target.starts_with('/') {
+                        // Absolute links point into the image, not the host.
+                        parents.clear();
+                        current = self.inode(self.root_inode)?;
+                    } else {
+                        current = Inode::Directory {
+                            start_block,
+                            offset,
+                            size,
+                        };
+                    }
+                    pending.extend(components(&target).rev().map(str::to_string));
+                }
+                inode => {
+                    parents.push(Inode::Directory {
+                        start_block,
+                        offset,
+                        size,
+                    });
+                    current = inode;
+                }
+            }
+        }
+        Ok(Some(current))
+    }
+
+    /// The `(name, inode reference)` entries of a directory.
+    fn read_dir(
+        &mut self,
+        start_block: u32,
+        offset: u16,
+        size: u32,
+    ) -> Result<Vec<(String, u64)>, String> {
+        // The stored size counts the `.` and `..` entries that are not there.
+        let len = (size as usize).saturating_sub(3);
+        let mut cursor =
+            MetadataCursor::new(position(self.directory_table, u64::from(start_block))?);
+        cursor.skip(self, usize::from(offset))?;
+        let mut entries = Vec::new();
+        let mut read = 0;
+        while read < len {
+            let header = cursor.take(self, 12)?;
+            let count = le_u32(&header, 0) as usize + 1;
+            let start = le_u32(&header, 4);
+            read += 12;
+            for _ in 0..count {
+                let entry = cursor.take(self, 8)?;
+                let name_len = usize::from(le_u16(&entry, 6)) + 1;
+                let name = cursor.take(self, name_len)?;
+                let reference = u64::from(start) << 16 | u64::from(le_u16(&entry, 0));
+                entries.push((String::from_utf8_lossy(&name).into_owned(), reference));
+                read += 8 + name_len;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn inode(&mut self, reference: u64) -> Result<Inode, String> {
+        let mut cursor = MetadataCursor::new(position(self.inode_table, reference >> 16)?);
+        cursor.skip(self, (reference & 0xFFFF) as usize)?;
+        let header = cursor.take(self, 16)?;
+        let inode = match le_u16(&header, 0) {
+            // Basic directory
+            1 => {
+                let body = cursor.take(self, 16)?;
+                Inode::Directory {
+                    start_block: le_u32(&body, 0),
+                    size: u32::from(le_u16(&body, 8)),
+                    offset: le_u16(&body, 10),
+                }
+            }
+            // Extended directory
+            8 => {
+                let body = cursor.take(self, 24)?;
+                Inode::Directory {
+                    size: le_u32(&body, 4),
+                    start_block: le_u32(&body, 8),
+                    offset: le_u16(&body, 18),
+                }
+            }
+            // Basic file
+            2 => {
+                let body = cursor.take(self, 16)?;
+                self.file_inode(
+                    &mut cursor,
+                    u64::from(le_u32(&body, 0)),
+                    u64::from(le_u32(&body, 12)),
+                    le_u32(&body, 4),
+                    le_u32(&body, 8),
+                )?
+            }
+            // Extended file
+            9 => {
+                let body = cursor.take(self, 40)?;
+                self.file_inode(
+                    &mut cursor,
+                    le_u64(&body, 0),
+                    le_u64(&body, 8),
+                    le_u32(&body, 28),
+                    le_u32(&body, 32),
+                )?
+            }
+            // Basic and extended symbolic link
+            3 | 10 => {
+                let body = cursor.take(self, 8)?;
+                let len = le_u32(&body, 4) as usize;
+                if len > METADATA_BLOCK_SIZE {
+                    return Err("invalid symbolic link".to_string());
+                }
+                Inode::Symlink(String::from_utf8_lossy(&cursor.take(self, len)?).into_owned())
+            }
+            _ => Inode::Other,
+        };
+        Ok(inode)
+    }
+
+    fn file_inode(
+        &mut self,
+        cursor: &mut MetadataCursor,
+        blocks_start: u64,
+        size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+    ) -> Result<Inode, String> {
+        if size > MAX_FILE_SIZE {
+            return Err(format!("file of {size} bytes is too large"));
+        }
+        let block_size = u64::from(self.block_size);
+        let blocks = if fragment == NO_FRAGMENT {
+            size.div_ceil(block_size)
+        } else {
+            size / block_size
+        } as usize;
+        let sizes = cursor.take(self, blocks * 4)?;
+        Ok(Inode::File {
+            blocks_start,
+            size,
+            fragment,
+            fragment_offset,
+            block_sizes: sizes.chunks_exact(4).map(|s| le_u32(s, 0)).collect(),
+        })
+    }
+
+    fn file_data(
+        &mut self,
+        blocks_start: u64,
+        size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+        block_sizes: &[u32],
+    ) -> Result<Vec<u8>, String> {
+        let size = size as usize;
+        let mut data = Vec::with_capacity(size);
+        let mut pos = blocks_start;
+        for &stored in block_sizes {
+            let want = (self.block_size as usize).min(size - data.len());
+            if stored == 0 {
+                // Sparse block
+                data.resize(data.len() + want, 0);
+                continue;
+            }
+            let block = self.data_block(pos, stored)?;
+            pos = position(pos, u64::from(stored & !DATA_UNCOMPRESSED))?;
+            data.extend_from_slice(block.get(..want).ok_or("truncated data block")?);
+        }
+
+        if fragment != NO_FRAGMENT && data.len() < size {
+            let (start, stored) = self.fragment_entry(fragment)?;
+            let block = self.data_block(start, stored)?;
+            let from = fragment_offset as usize;
+            let tail = block
+                .get(from..from + (size - data.len()))
+                .ok_or("truncated fragment")?;
+            data.extend_from_slice(tail);
+        }
+        Ok(data)
+    }
+
+    /// The position and stored size of fragment block `index`.
+    fn fragment_entry(&mut self, index: u32) -> Result<(u64, u32), String> {
+        let per_block = (METADATA_BLOCK_SIZE / FRAGMENT_ENTRY_SIZE) as u64;
+        let pointer = self.read_at(
+            position(self.fragment_table, u64::from(index) / per_block * 8)?,
+            8,
+        )?;
+        let mut cursor = MetadataCursor::new(le_u64(&pointer, 0));
+        cursor.skip(
+            self,
+            (u64::from(index) % per_block) as usize * FRAGMENT_ENTRY_SIZE,
+        )?;
+        let entry = cursor.take(self, FRAGMENT_ENTRY_SIZE)?;
+        Ok((le_u64(&entry, 0), le_u32(&entry, 8)))
+    }
+
+    fn data_block(&mut self, pos: u64, stored: u32) -> Result<Vec<u8>, String> {
+        let len = (stored & !DATA_UNCOMPRESSED) as usize;
+        if len > self.block_size as usize {
+            return Err("invalid data block size".to_string());
+        }
+        let raw = self.read_at(pos, len)?;
+        if stored & DATA_UNCOMPRESSED != 0 {
+            Ok(raw)
+        } else {
+            self.decompress(&raw, self.block_size as usize)
+        }
+    }
+
+    /// The metadata block at `pos` and the position of the next one.
+    fn metadata_block(&mut self, pos: u64) -> Result<(Vec<u8>, u64), String> {
+        let header = self.read_at(pos, 2)?;
+        let header = le_u16(&header, 0);
+        let len = usize::from(header & !METADATA_UNCOMPRESSED);
+        let data = position(pos, 2)?;
+        let raw = self.read_at(data, len)?;
+        let block = if header & METADATA_UNCOMPRESSED != 0 {
+            raw
+        } else {
+            self.decompress(&raw, METADATA_BLOCK_SIZE)?
+        };
+        Ok((block, position(data, len as u64)?))
+    }
+
+    fn read_at(&mut self, pos: u64, len: usize) -> Result<Vec<u8>, String> {
+        let start = position(self.offset, pos)?;
+        let mut buffer = vec![0; len];
+        self.reader
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| self.reader.read_exact(&mut buffer))
+            .map_err(|e| format!("cannot read {len} bytes at {pos}: {e}"))?;
+        Ok(buffer)
+    }
+
+    fn decompress(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+        let out = match self.compressor {
+            GZIP => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data, limit)
+                .map_err(|e| e.to_string())?,
+            XZ => {
+                let mut out = LimitedWriter {
+                    buffer: Vec::new(),
+                    limit,
+                };
+                lzma_rs::xz_decompress(&mut std::io::Cursor::new(data), &mut out)
+                    .map_err(|e| e.to_string())?;
+                out.buffer
+            }
+            ZSTD => {
+                let decoder =
+                    ruzstd::decoding::StreamingDecoder::new(data).map_err(|e| e.to_string())?;
+                let mut out = Vec::new();
+                decoder
+                    .take(limit as u64 + 1)
+                    .read_to_end(&mut out)
+                    .map_err(|e| e.to_string())?;
+                out
+            }
+            other => {
+                return Err(format!(
+                    "unsupported compression {}",
+                    compressor_name(other)
+                ));
+            }
+        };
+        if out.len() > limit {
+            return Err("decompressed block is too large".to_string());
+        }
+        Ok(out)
+    }
+}
+
+/// A buffer that refuses to grow past `limit`, so a decoder without an
+/// output limit stops early on an oversized block.
+struct LimitedWriter {
+    buffer: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.buffer.len() + data.len() > self.limit {
+            return Err(std::io::Error::other("decompressed block is too large"));
+        }
+        self.buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MetadataCursor {
+    fn new(next_block: u64) -> Self {
+        Self {
+            next_block,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn take<R: Read + Seek>(
+        &mut self,
+        fs: &mut SquashFs<R>,
+        len: usize,
+    ) -> Result<Vec<u8>, String> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            if self.pos == self.buffer.len() {
+                let (block, next) = fs.metadata_block(self.next_block)?;
+                if block.is_empty() {
+                    return Err("empty metadata block".to_string());
+                }
+                self.buffer = block;
+                self.pos = 0;
+                self.next_block = next;
+            }
+            let n = (len - out.len()).min(self.buffer.len() - self.pos);
+            out.extend_from_slice(&self.buffer[self.pos..self.pos + n]);
+            self.pos += n;
+        }
+        Ok(out)
+    }
+
+    fn skip<R: Read + Seek>(&mut self, fs: &mut SquashFs<R>, len: usize) -> Result<(), String> {
+        self.take(fs, len).map(drop)
+    }
+}
+
+/// `base + delta`, failing instead of wrapping on a corrupt position.
+fn position(base: u64, delta: u64) -> Result<u64, String> {
+    base.checked_add(delta)
+        .ok_or_else(|| format!("position {base} + {delta} is out of range"))
+}
+
+fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty() && *c != ".")
+}
+
+fn compressor_name(id: u16) -> String {
+    match id {
+        2 => "lzma".to_string(),
+        3 => "lzo".to_string(),
+        5 => "lz4".to_string(),
+        other => format!("#{other}"),
+    }
+}
+
+fn le_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap())
+}
+
+fn le_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn le_u64(data: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+/// Builds small SquashFS images in memory, for tests.
+#[cfg(test)]
+pub(crate) mod builder {
+    use super::*;
+
+    pub(crate) enum Node {
+        File(Vec<u8>),
+        Symlink(String),
+        Dir(Vec<(String, Node)>),
+    }
+
+    /// Everything goes into one metadata block per table, so images must
+    /// stay small. Files at least one block long use data blocks and keep
+    /// their tail in a shared fragment block.
+    pub(crate) struct Builder {
+        pub(crate) compressor: u16,
+        pub(crate) block_size: u32,
+        data: Vec<u8>,
+        inodes: Vec<u8>,
+        directories: Vec<u8>,
+        fragment: Vec<u8>,
+        inode_count: u32,
+    }
+
+    impl Builder {
+        pub(crate) fn new(compressor: u16, block_size: u32) -> Self {
+            Self {
+                compressor,
+                block_size,
+                data: vec![0; SUPERBLOCK_SIZE],
+                inodes: Vec::new(),
+                directories: Vec::new(),
+                fragment: Vec::new(),
+                inode_count: 0,
+            }
+        }
+
+        pub(crate) fn build(mut self, root: &Node) -> Vec<u8> {
+            let root_ref = self.add(root);
+            // The fragment block, if any files use it.
+            let fragment_entry = (!self.fragment.is_empty()).then(|| {
+                let fragment = std::mem::take(&mut self.fragment);
+                let start = self.data.len() as u64;
+                let stored = self.write_data(&fragment);
+                (start, stored)
+            });
+            let inode_table = self.data.len() as u64;
+            let inodes = std::mem::take(&mut self.inodes);
+            self.write_metadata(&inodes);
+            let directory_table = self.data.len() as u64;
+            let directories = std::mem::take(&mut self.directories);
+            self.write_metadata(&directories);
+            let fragment_table = match fragment_entry {
+                Some((start, stored)) => {
+                    let block = self.data.len() as u64;
+                    let mut entry = start.to_le_bytes().to_vec();
+                    entry.extend(stored.to_le_bytes());
+                    entry.extend(0u32.to_le_bytes());
+                    self.write_metadata(&entry);
+                    let table = self.data.len() as u64;
+                    self.data.extend(block.to_le_bytes());
+                    table
+                }
+                None => u64::MAX,
+            };
+
+            let mut sb = Vec::with_capacity(SUPERBLOCK_SIZE);
+            sb.extend(MAGIC.to_le_bytes());
+            sb.extend(self.inode_count.to_le_bytes());
+            sb.extend(0u32.to_le_bytes());
+            sb.extend(self.block_size.to_le_bytes());
+            sb.extend(u32::from(fragment_entry.is_some()).to_le_bytes());
+            sb.extend(self.compressor.to_le_bytes());
+            sb.extend((self.block_size.trailing_zeros() as u16).to_le_bytes());
+            sb.extend(0u16.to_le_bytes());
+            sb.extend(1u16.to_le_bytes());
+            sb.extend(4u16.to_le_bytes());
+            sb.extend(0u16.to_le_bytes());
+            sb.extend(root_ref.to_le_bytes());
+            sb.extend((self.data.len() as u64).to_le_bytes());
+            for table in [u64::MAX, u64::MAX, inode_table, directory_table] {
+                sb.extend(table.to_le_bytes());
+            }
+            sb.extend(fragment_table.to_le_bytes());
+            sb.extend(u64::MAX.to_le_bytes());
+            self.data[..SUPERBLOCK_SIZE].copy_from_slice(&sb);
+            self.data
+        }
+
+        /// Add a node's inode, returning its reference.
+        fn add(&mut self, node: &Node) -> u64 {
+            let body = match node {
+                Node::File(contents) => {
+                    let start = self.data.len() as u32;
+                    let whole =
+                        contents.len() / self.block_size as usize * self.block_size as usize;
+                    let mut sizes = Vec::new();
+                    for block in contents[..whole].chunks(self.block_size as usize) {
+                        sizes.push(self.write_data(block));
+                    }
+                    let (fragment, offset) = if whole < contents.len() {
+                        let offset = self.fragment.len() as u32;
+                        self.fragment.extend_from_slice(&contents[whole..]);
+                        (0, offset)
+                    } else {
+                        (NO_FRAGMENT, 0)
+                    };
+                    let mut body = header(2, self.inode_count);
+                    body.extend(start.to_le_bytes());
+                    body.extend(fragment.to_le_bytes());
+                    body.extend(offset.to_le_bytes());
+                    body.extend((contents.len() as u32).to_le_bytes());
+                    for size in sizes {
+                        body.extend(size.to_le_bytes());
+                    }
+                    body
+                }
+                Node::Symlink(target) => {
+                    let mut body = header(3, self.inode_count);
+                    body.extend(1u32.to_le_bytes());
+                    body.extend((target.len() as u32).to_le_bytes());
+                    body.extend(target.as_bytes());
+                    body
+                }
+                Node::Dir(entries) => {
+                    let children: Vec<_> = entries
+                        .iter()
+                        .map(|(name, child)| (name, self.add(child)))
+                        .collect();
+                    let start = self.directories.len();
+                    let mut listing = Vec::new();
+                    if !children.is_empty() {
+                        listing.extend((children.len() as u32 - 1).to_le_bytes());
+                        listing.extend(0u32.to_le_bytes());
+                        listing.extend(0u32.to_le_bytes());
+                        for (name, reference) in &children {
+                            listing.extend((*reference as u16).to_le_bytes());
+                            listing.extend(0i16.to_le_bytes());
+                            listing.extend(1u16.to_le_bytes());
+                            listing.extend((name.len() as u16 - 1).to_le_bytes());
+                            listing.extend(name.as_bytes());
+                        }
+                    }
+                    self.directories.extend(&listing);
+                    let mut body = header(1, self.inode_count);
+                    body.extend(0u32.to_le_bytes());
+                    body.extend(2u32.to_le_bytes());
+                    body.extend((listing.len() as u16 + 3).to_le_bytes());
+                    body.extend((start as u16).to_le_bytes());
+                    body.extend(0u32.to_le_bytes());
+                    body
+                }
+            };
+            self.inode_count += 1;
+            let reference = self.inodes.len() as u64;
+            self.inodes.extend(body);
+            reference
+        }
+
+        /// Append a data block, returning its stored size.
+        fn write_data(&mut self, block: &[u8]) -> u32 {
+            match self.compress(block) {
+                Some(compressed) => {
+                    self.data.extend(&compressed);
+                    compressed.len() as u32
+                }
+                None => {
+                    self.data.extend(block);
+                    block.len() as u32 | DATA_UNCOMPRESSED
+                }
+            }
+        }
+
+        fn write_metadata(&mut self, block: &[u8]) {
+            assert!(block.len() <= METADATA_BLOCK_SIZE, "test image too large");
+            match self.compress(block) {
+                Some(compressed) => {
+                    self.data.extend((compressed.len() as u16).to_le_bytes());
+                    self.data.extend(compressed);
+                }
+                None => {
+                    self.data
+                        .extend((block.len() as u16 | METADATA_UNCOMPRESSED).to_le_bytes());
+                    self.data.extend(block);
+                }
+            }
+        }
+
+        /// The compressed block, unless compressing does not make it
+        /// smaller; `mksquashfs` then stores it uncompressed as well.
+        fn compress(&self, block: &[u8]) -> Option<Vec<u8>> {
+            let compressed = match self.compressor {
+                GZIP => Some(miniz_oxide::deflate::compress_to_vec_zlib(block, 6)),
+                ZSTD => Some(ruzstd::encoding::compress_to_vec(
+                    block,
+                    ruzstd::encoding::CompressionLevel::Fastest,
+                )),
+                _ => None,
+            };
+            compressed.filter(|compressed| compressed.len() < block.len())
+        }
+    }
+
+    fn header(kind: u16, inode_number: u32) -> Vec<u8> {
+        let mut header = kind.to_le_bytes().to_vec();
+        header.extend(0o644u16.to_le_bytes());
+        header.extend([0; 4]);
+        header.extend(0u32.to_le_bytes());
+        header.extend((inode_number + 1).to_le_bytes());
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builder::{Builder, Node};
+    use super::*;
+    use std::io::Cursor;
+
+    fn tree() -> Node {
+        let big: Vec<u8> = (0..10_000u32).map(|i| (i / 1000) as u8).collect();
+        Node::Dir(vec![
+            ("AppRun".to_string(), Node::File(b"#!/bin/sh\n".to_vec())),
+            (
+                ".DirIcon".to_string(),
+                Node::Symlink("usr/share/acme.png".to_string()),
+            ),
+            (
+                "usr".to_string(),
+                Node::Dir(vec![(
+                    "share".to_string(),
+                    Node::Dir(vec![
+                        ("acme.png".to_string(), Node::File(big)),
+                        ("loop".to_string(), Node::Symlink("loop".to_string())),
+                        ("up".to_string(), Node::Symlink("../../AppRun".to_string())),
+                        ("root".to_string(), Node::Symlink("/AppRun".to_string())),
+                    ]),
+                )]),
+            ),
+        ])
+    }
+
+    fn check(compressor: u16) {
+        let mut image = vec![0xAA; 100];
+        image.extend(Builder::new(compressor, 4096).build(&tree()));
+        let mut fs = SquashFs::open(Cursor::new(image), 100).unwrap();
+
+        let big = fs.read_file(".DirIcon").unwrap().unwrap();
+        assert_eq!(big.len(), 10_000);
+        assert!(big.iter().enumerate().all(|(i, &b)| b == (i / 1000) as u8));
+        for path in ["AppRun", "/usr/share/up", "usr/./share/root"] {
+            assert_eq!(
+                fs.read_file(path).unwrap().as_deref(),
+                Some(&b"#!/bin/sh\n"[..]),
+                "{path}"
+            );
+        }
+        assert_eq!(fs.read_file("usr/missing").unwrap(), None);
+        assert_eq!(fs.read_file("AppRun/x").unwrap(), None);
+        assert!(fs.read_file("usr").is_err());
+        assert!(fs.read_file("usr/share/loop").is_err());
+    }
+
+    #[test]
+    fn reads_uncompressed_blocks_without_a_decompressor() {
+        // The test builder stores every block uncompressed for lzo.
+        check(3);
+    }
+
+    #[test]
+    fn decompresses_xz_blocks() {
+        // The test builder cannot write xz, so it stores every block
+        // uncompressed; check the decompressor on its own. Made with
+        // Python's `lzma.compress(b"icon-sys " * 64, check=lzma.CHECK_CRC32)`.
+        const BLOCK: [u8; 80] = [
+            253, 55, 122, 88, 90, 0, 0, 1, 105, 34, 222, 54, 2, 0, 33, 1, 22, 0, 0, 0, 116, 47,
+            229, 163, 224, 2, 63, 0, 19, 93, 0, 52, 152, 202, 68, 221, 252, 54, 110, 223, 91, 25,
+            130, 39, 74, 164, 103, 249, 212, 0, 0, 0, 161, 196, 167, 255, 0, 1, 43, 192, 4, 0, 0,
+            0, 164, 94, 73, 200, 62, 48, 13, 139, 2, 0, 0, 0, 0, 1, 89, 90,
+        ];
+        check(XZ);
+        let image = Builder::new(XZ, 4096).build(&tree());
+        let fs = SquashFs::open(Cursor::new(image), 0).unwrap();
+        assert_eq!(
+            fs.decompress(&BLOCK, 8192).unwrap(),
+            b"icon-sys ".repeat(64)
+        );
+        assert!(fs.decompress(&BLOCK, 100).is_err());
+        assert!(fs.decompress(&BLOCK[..40], 8192).is_err());
+    }
+
+    #[test]
+    fn reads_gzip_images() {
+        check(GZIP);
+    }
+
+    #[test]
+    fn reads_zstd_images() {
+        check(ZSTD);
+    }
+
+    #[test]
+    fn rejects_other_files_and_compressors() {
+        assert!(SquashFs::open(Cursor::new(vec![0; 200]), 0).is_err());
+        for block_size in [0, 4096 + 1, 1 << 21] {
+            let mut image = Builder::new(GZIP, 4096).build(&tree());
+            image[12..16].copy_from_slice(&u32::to_le_bytes(block_size));
+            assert!(SquashFs::open(Cursor::new(image), 0).is_err());
+        }
+        let mut image = vec![0xAA; 100];
+        image.extend(Builder::new(GZIP, 4096).build(&tree()));
+        image[164..172].copy_from_slice(&u64::to_le_bytes(u64::MAX - 10));
+        let mut fs = SquashFs::open(Cursor::new(image), 100).unwrap();
+        assert!(fs.read_file("AppRun").is_err());
+
+        let mut image = Builder::new(3, 4096).build(&tree());
+        // Pretend the uncompressed tables were compressed with lzo.
+        let first_table = le_u64(&image, 64) as usize;
+        image[first_table + 1] &= 0x7F;
+        let mut fs = SquashFs::open(Cursor::new(image), 0).unwrap();
+        let err = fs.read_file("AppRun").unwrap_err();
+        assert!(err.contains("lzo"), "{err}");
+    }
+}
@@ -0,0 +1,514 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::FolderMetadata;
+use super::error::{FolderSettingsError, Result};
+
+/// Section of `desktop.ini` holding a folder's shell customizations.
+pub const SHELL_CLASS_INFO: &str = ".ShellClassInfo";
+
+/// `[.ShellClassInfo]` key with the name Explorer shows instead of the
+/// folder's file name.
+pub(crate) const DISPLAY_NAME_KEY: &str = "LocalizedResourceName";
+
+/// `[.ShellClassInfo]` key with the folder's tooltip.
+pub(crate) const TOOLTIP_KEY: &str = "InfoTip";
+
+/// A Windows `desktop.ini` file that is edited in place.
+///
+/// Untouched lines are written back verbatim, so comments, unknown sections
+/// and settings written by Explorer survive a round trip. Section and key
+/// names are matched case-insensitively, as Windows does. Files are read in
+/// either encoding Explorer understands (UTF-16LE with a byte order mark, or
+/// 8-bit text) and are saved as UTF-16LE as soon as they contain non-ASCII
+/// text, since the 8-bit form is read in the system code page.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopIni {
+    lines: Vec<Line>,
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Encoding {
+    /// No byte order mark: ASCII, UTF-8 or the system code page.
+    #[default]
+    Ansi,
+    Utf8Bom,
+    Utf16Le,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// A comment, blank line or anything unparseable, kept verbatim.
+    Other(String),
+    Section {
+        raw: String,
+        name: String,
+    },
+    Entry {
+        raw: String,
+        key: String,
+        value: String,
+    },
+}
+
+impl Line {
+    fn parse(raw: &str) -> Self {
+        let text = raw.trim_end_matches(['\n', '\r']).trim();
+        if let Some(name) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            return Line::Section {
+                raw: raw.to_string(),
+                name: name.trim().to_string(),
+            };
+        }
+        if text.starts_with(';') {
+            return Line::Other(raw.to_string());
+        }
+        match text.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Line::Entry {
+                raw: raw.to_string(),
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            },
+            _ => Line::Other(raw.to_string()),
+        }
+    }
+
+    fn raw(&self) -> &str {
+        match self {
+            Line::Other(raw) | Line::Section { raw, .. } | Line::Entry { raw, .. } => raw,
+        }
+    }
+}
+
+impl DesktopIni {
+    /// Parse file contents. Parsing never fails: lines that aren't sections
+    /// or entries are kept as-is.
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: parse_lines(content),
+            encoding: Encoding::Ansi,
+        }
+    }
+
+    /// Parse raw file bytes, detecting the encoding from the byte order mark.
+    /// 8-bit files that aren't valid UTF-8 are read as Latin-1.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let (content, encoding) = if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            (String::from_utf16_lossy(&units), Encoding::Utf16Le)
+        } else if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+            (
+                String::from_utf8_lossy(rest).into_owned(),
+                Encoding::Utf8Bom,
+            )
+        } else {
+            let content = match std::str::from_utf8(bytes) {
+                Ok(content) => content.to_string(),
+                Err(_) => bytes.iter().map(|&b| b as char).collect(),
+            };
+            (content, Encoding::Ansi)
+        };
+        Self {
+            lines: parse_lines(&content),
+            encoding,
+        }
+    }
+
+    /// The file contents in the encoding they will be saved in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let content = self.to_string();
+        match self.encoding {
+            Encoding::Utf16Le => encode_utf16le(&content),
+            _ if !content.is_ascii() => encode_utf16le(&content),
+            Encoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], content.as_bytes()].concat(),
+            Encoding::Ansi => content.into_bytes(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        std::fs::read(path).map(|bytes| Self::from_bytes(&bytes))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    /// Section names in file order.
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Section { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The value of a key, with one pair of surrounding double quotes
+    /// removed as Windows does.
+    pub fn get(&self, section: &str, key: &str) -> Option<String> {
+        let i = self.find(section, key)?;
+        let Line::Entry { value, .. } = &self.lines[i] else {
+            return None;
+        };
+        let unquoted = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        Some(unquoted.to_string())
+    }
+
+    /// Set a key. An existing entry is replaced in place, keeping the
+    /// spelling of its key; otherwise the entry is appended to the section,
+    /// which is created at the end of the file if missing.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        if let Some(i) = self.find(section, key) {
+            let Line::Entry { raw, key, .. } = &self.lines[i] else {
+                unreachable!("find only returns entries");
+            };
+            let ending = line_ending(raw);
+            self.lines[i] = Line::parse(&format!("{key}={value}{ending}"));
+            return;
+        }
+
+        let ending = self
+            .lines
+            .first()
+            .map(|line| line_ending(line.raw()))
+            .filter(|ending| !ending.is_empty())
+            .unwrap_or("\r\n");
+        let line = Line::parse(&format!("{key}={value}{ending}"));
+        let insert_at = self.section_lines(section).last().map(|last| {
+            // Append after the section's last entry, before trailing comments
+            // and blank lines that separate it from the next section.
+            let header = self.section_lines(section).next().unwrap_or(last);
+            (header..=last)
+                .rev()
+                .find(|&i| matches!(self.lines[i], Line::Entry { .. } | Line::Section { .. }))
+                .unwrap_or(last)
+                + 1
+        });
+        match insert_at {
+            Some(at) => {
+                self.terminate_line(at - 1, ending);
+                self.lines.insert(at, line);
+            }
+            None => {
+                if let Some(last) = self.lines.len().checked_sub(1) {
+                    self.terminate_line(last, ending);
+                }
+                self.lines
+                    .push(Line::parse(&format!("[{section}]{ending}")));
+                self.lines.push(line);
+            }
+        }
+    }
+
+    /// Remove a key. Returns whether anything was removed.
+    pub fn remove(&mut self, section: &str, key: &str) -> bool {
+        let before = self.lines.len();
+        let mut current: Option<String> = None;
+        self.lines.retain(|line| match line {
+            Line::Section { name, .. } => {
+                current = Some(name.clone());
+                true
+            }
+            Line::Entry { key: k, .. } => {
+                !(current
+                    .as_deref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(section))
+                    && k.eq_ignore_ascii_case(key))
+            }
+            Line::Other(_) => true,
+        });
+        self.lines.len() != before
+    }
+
+    /// Remove a section with everything in it, up to the next section header.
+    pub fn remove_section(&mut self, section: &str) {
+        let mut inside = false;
+        self.lines.retain(|line| {
+            if let Line::Section { name, .. } = line {
+                inside = name.eq_ignore_ascii_case(section);
+            }
+            !inside
+        });
+    }
+
+    /// Whether `section` exists and has no entries.
+    pub fn is_section_empty(&self, section: &str) -> bool {
+        let mut lines = self.section_lines(section).peekable();
+        lines.peek().is_some() && lines.all(|i| !matches!(self.lines[i], Line::Entry { .. }))
+    }
+
+    /// Whether any section holds at least one entry.
+    pub fn has_entries(&self) -> bool {
+        let mut in_section = false;
+        self.lines.iter().any(|line| match line {
+            Line::Section { .. } => {
+                in_section = true;
+                false
+            }
+            Line::Entry { .. } => in_section,
+            Line::Other(_) => false,
+        })
+    }
+
+    /// Make sure the line at `index` is terminated before something is
+    /// inserted after it.
+    fn terminate_line(&mut self, index: usize, ending: &str) {
+        if line_ending(self.lines[index].raw()).is_empty() {
+            let raw = format!("{}{ending}", self.lines[index].raw());
+            self.lines[index] = Line::parse(&raw);
+        }
+    }
+
+    /// Indices of a section's header and body lines, across repeated headers.
+    fn section_lines<'a>(&'a self, section: &'a str) -> impl Iterator<Item = usize> + 'a {
+        let mut inside = false;
+        self.lines.iter().enumerate().filter_map(move |(i, line)| {
+            if let Line::Section { name, .. } = line {
+                inside = name.eq_ignore_ascii_case(section);
+            }
+            inside.then_some(i)
+        })
+    }
+
+    fn find(&self, section: &str, key: &str) -> Option<usize> {
+        self.section_lines(section).find(|&i| {
+            matches!(&self.lines[i], Line::Entry { key: k, .. } if k.eq_ignore_ascii_case(key))
+        })
+    }
+}
+
+impl fmt::Display for DesktopIni {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.lines
+            .iter()
+            .try_for_each(|line| f.write_str(line.raw()))
+    }
+}
+
+fn parse_lines(content: &str) -> Vec<Line> {
+    content.split_inclusive('\n').map(Line::parse).collect()
+}
+
+fn line_ending(raw: &str) -> &'static str {
+    if raw.ends_with("\r\n") {
+        "\r\n"
+    } else if raw.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    }
+}
+
+fn encode_utf16le(content: &str) -> Vec<u8> {
+    [0xFEFFu16]
+        .into_iter()
+        .chain(content.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Reads and writes folder display names (`LocalizedResourceName`) and
+/// tooltips (`InfoTip`) in a folder's `desktop.ini`, on any platform.
+///
+/// Only the file itself is written. Explorer ignores `desktop.ini` unless the
+/// folder has the ReadOnly or System attribute; on Windows, use the platform
+/// provider, and elsewhere `DesktopIniFolderSettingsProvider`, which also
+/// set them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DesktopIniFolderMetadata;
+
+impl FolderMetadata for DesktopIniFolderMetadata {
+    fn display_name<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), DISPLAY_NAME_KEY)
+    }
+
+    fn set_display_name<P: AsRef<Path>>(&self, path: P, name: Option<&str>) -> Result<()> {
+        write_shell_class_info(path.as_ref(), DISPLAY_NAME_KEY, name)
+    }
+
+    fn tooltip<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), TOOLTIP_KEY)
+    }
+
+    fn set_tooltip<P: AsRef<Path>>(&self, path: P, tooltip: Option<&str>) -> Result<()> {
+        write_shell_class_info(path.as_ref(), TOOLTIP_KEY, tooltip)
+    }
+}
+
+/// Path of the `desktop.ini` file of `folder`, which must exist.
+pub(crate) fn desktop_ini_path(folder: &Path) -> Result<PathBuf> {
+    if !folder.is_dir() {
+        return Err(FolderSettingsError::NotAFolder(folder.to_path_buf()));
+    }
+    Ok(folder.join("desktop.ini"))
+}
+
+/// Read a `[.ShellClassInfo]` key of `folder`'s `desktop.ini`.
+pub(crate) fn read_shell_class_info(folder: &Path, key: &str) -> Result<Option<String>> {
+    let ini_path = desktop_ini_path(folder)?;
+    match DesktopIni::load(&ini_path) {
+        Ok(ini) => Ok(ini.get(SHELL_CLASS_INFO, key).filter(|v| !v.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(FolderSettingsError::Io(ini_path, e)),
+    }
+}
+
+/// Set (or with `None`, remove) a `[.ShellClassInfo]` key of `folder`'s
+/// `desktop.ini`. The section is dropped once empty, and the file once
+/// nothing is left in it.
+pub(crate) fn write_shell_class_info(folder: &Path, key: &str, value: Option<&str>) -> Result<()> {
+    let ini_path = desktop_ini_path(folder)?;
+    let mut ini = match DesktopIni::load(&ini_path) {
+        Ok(ini) => ini,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => DesktopIni::default(),
+        Err(e) => return Err(FolderSettingsError::Io(ini_path, e)),
+    };
+
+    match value {
+        Some(value) => ini.set(SHELL_CLASS_INFO, key, value),
+        None => {
+            if !ini.remove(SHELL_CLASS_INFO, key) {
+                return Ok(());
+            }
+            if ini.is_section_empty(SHELL_CLASS_INFO) {
+                ini.remove_section(SHELL_CLASS_INFO);
+            }
+            if !ini.has_entries() {
+                return std::fs::remove_file(&ini_path)
+                    .map_err(|e| FolderSettingsError::Io(ini_path, e));
+            }
+        }
+    }
+    ini.save(&ini_path)
+        .map_err(|e| FolderSettingsError::Io(ini_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPLORER: &str = "[.ShellClassInfo]\r\n\
+        IconResource=C:\\Windows\\System32\\imageres.dll,-3\r\n\
+        \r\n\
+        ; written by Explorer\r\n\
+        [ViewState]\r\n\
+        Mode=\r\n\
+        Vid=\r\n\
+        FolderType=Pictures\r\n";
+
+    #[test]
+    fn round_trip_is_byte_identical() {
+        let ini = DesktopIni::from_bytes(EXPLORER.as_bytes());
+        assert_eq!(ini.to_bytes(), EXPLORER.as_bytes());
+        assert_eq!(
+            ini.sections().collect::<Vec<_>>(),
+            [".ShellClassInfo", "ViewState"]
+        );
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let mut ini = DesktopIni::parse("[.shellclassinfo]\ninfotip=\"Old\"\n");
+        assert_eq!(
+            ini.get(".ShellClassInfo", "InfoTip").as_deref(),
+            Some("Old")
+        );
+        ini.set(".ShellClassInfo", "InfoTip", "New");
+        assert_eq!(ini.to_string(), "[.shellclassinfo]\ninfotip=New\n");
+    }
+
+    #[test]
+    fn set_appends_to_section_with_file_line_endings() {
+        let mut ini = DesktopIni::parse(EXPLORER);
+        ini.set(SHELL_CLASS_INFO, "InfoTip", "Holiday photos");
+        assert_eq!(
+            ini.to_string(),
+            EXPLORER.replace(
+                "imageres.dll,-3\r\n",
+                "imageres.dll,-3\r\nInfoTip=Holiday photos\r\n"
+            )
+        );
+
+        let mut ini = DesktopIni::default();
+        ini.set(SHELL_CLASS_INFO, "InfoTip", "Tip");
+        assert_eq!(ini.to_string(), "[.ShellClassInfo]\r\nInfoTip=Tip\r\n");
+    }
+
+    #[test]
+    fn non_ascii_text_is_saved_as_utf16() {
+        let mut ini = DesktopIni::parse("[.ShellClassInfo]\r\n");
+        ini.set(SHELL_CLASS_INFO, DISPLAY_NAME_KEY, "Фотографии");
+        let bytes = ini.to_bytes();
+        assert_eq!(&bytes[..2], [0xFF, 0xFE]);
+
+        let reread = DesktopIni::from_bytes(&bytes);
+        assert_eq!(
+            reread.get(SHELL_CLASS_INFO, DISPLAY_NAME_KEY).as_deref(),
+            Some("Фотографии")
+        );
+        assert_eq!(reread.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn latin1_files_are_readable() {
+        let ini = DesktopIni::from_bytes(b"[.ShellClassInfo]\r\nInfoTip=Caf\xE9\r\n");
+        assert_eq!(
+            ini.get(SHELL_CLASS_INFO, "InfoTip").as_deref(),
+            Some("Café")
+        );
+    }
+
+    #[test]
+    fn metadata_is_written_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let ini_path = dir.path().join("desktop.ini");
+        let metadata = DesktopIniFolderMetadata;
+
+        assert_eq!(metadata.display_name(dir.path()).unwrap(), None);
+        metadata
+            .set_display_name(dir.path(), Some("Projects"))
+            .unwrap();
+        metadata
+            .set_tooltip(dir.path(), Some("Work in progress"))
+            .unwrap();
+        assert_eq!(
+            metadata.display_name(dir.path()).unwrap().as_deref(),
+            Some("Projects")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&ini_path).unwrap(),
+            "[.ShellClassInfo]\r\nLocalizedResourceName=Projects\r\nInfoTip=Work in progress\r\n"
+        );
+
+        metadata.set_display_name(dir.path(), None).unwrap();
+        metadata.set_tooltip(dir.path(), None).unwrap();
+        assert!(!ini_path.exists());
+    }
+
+    #[test]
+    fn removing_keeps_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let ini_path = dir.path().join("desktop.ini");
+        std::fs::write(&ini_path, EXPLORER).unwrap();
+
+        let metadata = DesktopIniFolderMetadata;
+        metadata.set_tooltip(dir.path(), Some("Tip")).unwrap();
+        metadata.set_tooltip(dir.path(), None).unwrap();
+        assert_eq!(std::fs::read_to_string(&ini_path).unwrap(), EXPLORER);
+    }
+
+    #[test]
+    fn missing_folder_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            DesktopIniFolderMetadata.tooltip(&missing),
+            Err(FolderSettingsError::NotAFolder(path)) if path == missing
+        ));
+    }
+}
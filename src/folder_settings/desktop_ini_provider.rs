@@ -0,0 +1,332 @@
+use std::fs;
+use std::path::Path;
+
+use uuid::Uuid;
+
+use super::desktop_ini::{
+    DISPLAY_NAME_KEY, DesktopIni, SHELL_CLASS_INFO, TOOLTIP_KEY, desktop_ini_path,
+    read_shell_class_info, write_shell_class_info,
+};
+use super::dos_attributes::{DosAttributeStore, DosAttributes, add_dos_attributes};
+use super::error::{FolderSettingsError, Result};
+use super::ico::{encode_windows_ico, is_generated_icon};
+use super::shortcut::IconLocation;
+use super::{FolderMetadata, FolderSettingsProvider};
+use crate::api::IconSet;
+use crate::icon::sys::windows::WindowsIconSet;
+
+const DEFAULT_GENERATED_ICON_PREFIX: &str = env!("CARGO_PKG_NAME");
+
+/// `[.ShellClassInfo]` key with the icon as `path,index`, written since
+/// Windows Vista.
+const ICON_RESOURCE_KEY: &str = "IconResource";
+/// `[.ShellClassInfo]` keys with the icon as Windows XP writes it.
+const ICON_FILE_KEY: &str = "IconFile";
+const ICON_INDEX_KEY: &str = "IconIndex";
+
+/// Windows folder icons, written the way Explorer writes them but on any
+/// platform, e.g. for folders a Linux Samba server shares with Windows
+/// clients, or folders on an NTFS volume.
+///
+/// The icon is saved as a hidden `.ico` file in the folder and named in its
+/// `desktop.ini`. Explorer only reads `desktop.ini` if the folder has the
+/// ReadOnly or System attribute, so the folder is made ReadOnly and the two
+/// files Hidden and System, in the extended attributes of the
+/// [`DosAttributeStore`].
+#[derive(Debug, Clone)]
+pub struct DesktopIniFolderSettingsProvider {
+    dos_attribute_store: DosAttributeStore,
+    generated_icon_prefix: String,
+}
+
+impl FolderSettingsProvider for DesktopIniFolderSettingsProvider {
+    fn new() -> Self {
+        Self {
+            dos_attribute_store: DosAttributeStore::default(),
+            generated_icon_prefix: DEFAULT_GENERATED_ICON_PREFIX.to_owned(),
+        }
+    }
+
+    fn set_icon_for_folder<P: AsRef<Path>>(&self, path: P, icon_set: &IconSet) -> Result<()> {
+        let windows_icon_set = WindowsIconSet::try_from(icon_set)?;
+        self.set_icon_for_folder_windows(path, &windows_icon_set)
+    }
+
+    fn reset_icon_for_folder<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let folder = path.as_ref();
+        let ini_path = desktop_ini_path(folder)?;
+        match DesktopIni::load(&ini_path) {
+            Ok(mut ini) => {
+                let mut removed = false;
+                for key in [ICON_RESOURCE_KEY, ICON_FILE_KEY, ICON_INDEX_KEY] {
+                    removed |= ini.remove(SHELL_CLASS_INFO, key);
+                }
+                if removed {
+                    if ini.is_section_empty(SHELL_CLASS_INFO) {
+                        ini.remove_section(SHELL_CLASS_INFO);
+                    }
+                    let result = if ini.has_entries() {
+                        ini.save(&ini_path)
+                    } else {
+                        fs::remove_file(&ini_path)
+                    };
+                    result.map_err(|e| FolderSettingsError::Io(ini_path, e))?;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(FolderSettingsError::Io(ini_path, e)),
+        }
+        self.remove_generated_icons(folder)
+    }
+}
+
+/// Display names and tooltips are written to `desktop.ini` like the icon,
+/// with the same attributes.
+impl FolderMetadata for DesktopIniFolderSettingsProvider {
+    fn display_name<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), DISPLAY_NAME_KEY)
+    }
+
+    fn set_display_name<P: AsRef<Path>>(&self, path: P, name: Option<&str>) -> Result<()> {
+        write_shell_class_info(path.as_ref(), DISPLAY_NAME_KEY, name)?;
+        self.mark_customized(path.as_ref())
+    }
+
+    fn tooltip<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), TOOLTIP_KEY)
+    }
+
+    fn set_tooltip<P: AsRef<Path>>(&self, path: P, tooltip: Option<&str>) -> Result<()> {
+        write_shell_class_info(path.as_ref(), TOOLTIP_KEY, tooltip)?;
+        self.mark_customized(path.as_ref())
+    }
+}
+
+impl DesktopIniFolderSettingsProvider {
+    pub fn with_dos_attribute_store(mut self, store: DosAttributeStore) -> Self {
+        self.dos_attribute_store = store;
+        self
+    }
+
+    pub fn with_generated_icon_prefix(mut self, prefix: &str) -> Self {
+        self.generated_icon_prefix = prefix.to_owned();
+        self
+    }
+
+    /// Set the icon from a set with every Windows icon size. A new,
+    /// uniquely named `.ico` replaces the previous one so clients don't
+    /// show a cached icon.
+    pub fn set_icon_for_folder_windows<P: AsRef<Path>>(
+        &self,
+        path: P,
+        icon_set: &WindowsIconSet,
+    ) -> Result<()> {
+        let folder = path.as_ref();
+        let ini_path = desktop_ini_path(folder)?;
+        self.remove_generated_icons(folder)?;
+
+        let ico_name = format!("{}-{}.ico", self.generated_icon_prefix, Uuid::new_v4());
+        let ico_path = folder.join(&ico_name);
+        let ico = encode_windows_ico(icon_set)?;
+        fs::write(&ico_path, ico).map_err(|e| FolderSettingsError::Io(ico_path.clone(), e))?;
+        add_dos_attributes(
+            &ico_path,
+            self.dos_attribute_store,
+            DosAttributes::HIDDEN | DosAttributes::SYSTEM,
+        )?;
+
+        let mut ini = match DesktopIni::load(&ini_path) {
+            Ok(ini) => ini,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DesktopIni::default(),
+            Err(e) => return Err(FolderSettingsError::Io(ini_path, e)),
+        };
+        let location = IconLocation::new(ico_name, 0);
+        ini.set(SHELL_CLASS_INFO, ICON_RESOURCE_KEY, &location.to_string());
+        ini.set(SHELL_CLASS_INFO, ICON_FILE_KEY, &location.path);
+        ini.set(
+            SHELL_CLASS_INFO,
+            ICON_INDEX_KEY,
+            &location.index.to_string(),
+        );
+        ini.save(&ini_path)
+            .map_err(|e| FolderSettingsError::Io(ini_path, e))?;
+        self.mark_customized(folder)
+    }
+
+    /// The icon `desktop.ini` names for a folder: its `IconResource`, or
+    /// `IconFile` and `IconIndex` as older Windows versions write them. The
+    /// path is as written, relative to the folder or a Windows path.
+    pub fn icon_location<P: AsRef<Path>>(&self, path: P) -> Result<Option<IconLocation>> {
+        let folder = path.as_ref();
+        if let Some(resource) = read_shell_class_info(folder, ICON_RESOURCE_KEY)? {
+            return Ok(Some(IconLocation::parse(&resource)));
+        }
+        let Some(file) = read_shell_class_info(folder, ICON_FILE_KEY)? else {
+            return Ok(None);
+        };
+        let index = read_shell_class_info(folder, ICON_INDEX_KEY)?
+            .and_then(|index| index.trim().parse().ok())
+            .unwrap_or(0);
+        Ok(Some(IconLocation::new(file, index)))
+    }
+
+    /// Give `desktop.ini`, if there is one, and its folder the attributes
+    /// Explorer requires.
+    fn mark_customized(&self, folder: &Path) -> Result<()> {
+        let ini_path = desktop_ini_path(folder)?;
+        if !ini_path.exists() {
+            return Ok(());
+        }
+        add_dos_attributes(
+            &ini_path,
+            self.dos_attribute_store,
+            DosAttributes::HIDDEN | DosAttributes::SYSTEM,
+        )?;
+        add_dos_attributes(folder, self.dos_attribute_store, DosAttributes::READONLY)
+    }
+
+    fn remove_generated_icons(&self, folder: &Path) -> Result<()> {
+        let io_error = |e| FolderSettingsError::Io(folder.to_path_buf(), e);
+        for entry in fs::read_dir(folder).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| is_generated_icon(name, &self.generated_icon_prefix))
+            {
+                fs::remove_file(&path).map_err(|e| FolderSettingsError::Io(path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::IconImage;
+    use crate::icon::sys::windows::WindowsIconSize;
+    use image::DynamicImage;
+
+    fn icon_set() -> IconSet {
+        IconSet {
+            images: WindowsIconSize::all()
+                .map(|size| IconImage {
+                    data: DynamicImage::new_rgba8(size.dimension(), size.dimension()),
+                })
+                .collect(),
+            svg: None,
+        }
+    }
+
+    fn provider() -> DesktopIniFolderSettingsProvider {
+        DesktopIniFolderSettingsProvider::new()
+            .with_dos_attribute_store(DosAttributeStore::None)
+            .with_generated_icon_prefix("test")
+    }
+
+    fn generated_icons(folder: &Path) -> Vec<String> {
+        fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| is_generated_icon(name, "test"))
+            .collect()
+    }
+
+    #[test]
+    fn icon_is_written_and_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let ini_path = dir.path().join("desktop.ini");
+        let provider = provider();
+
+        provider
+            .set_icon_for_folder(dir.path(), &icon_set())
+            .unwrap();
+        provider
+            .set_icon_for_folder(dir.path(), &icon_set())
+            .unwrap();
+        let icons = generated_icons(dir.path());
+        assert_eq!(icons.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&ini_path).unwrap(),
+            format!(
+                "[.ShellClassInfo]\r\nIconResource={0},0\r\nIconFile={0}\r\nIconIndex=0\r\n",
+                icons[0]
+            )
+        );
+        assert_eq!(
+            provider.icon_location(dir.path()).unwrap(),
+            Some(IconLocation::new(icons[0].as_str(), 0))
+        );
+        let ico = fs::read(dir.path().join(&icons[0])).unwrap();
+        assert_eq!(
+            u16::from_le_bytes([ico[4], ico[5]]) as usize,
+            WindowsIconSize::NUM_SIZES
+        );
+
+        provider.set_tooltip(dir.path(), Some("Shared")).unwrap();
+        provider.reset_icon_for_folder(dir.path()).unwrap();
+        assert!(generated_icons(dir.path()).is_empty());
+        assert_eq!(
+            fs::read_to_string(&ini_path).unwrap(),
+            "[.ShellClassInfo]\r\nInfoTip=Shared\r\n"
+        );
+        provider.set_tooltip(dir.path(), None).unwrap();
+        assert!(!ini_path.exists());
+    }
+
+    #[test]
+    fn icon_location_falls_back_to_icon_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = provider();
+        assert_eq!(provider.icon_location(dir.path()).unwrap(), None);
+
+        fs::write(
+            dir.path().join("desktop.ini"),
+            "[.ShellClassInfo]\r\nIconFile=%SystemRoot%\\system32\\SHELL32.dll\r\nIconIndex=4\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            provider.icon_location(dir.path()).unwrap(),
+            Some(IconLocation::new("%SystemRoot%\\system32\\SHELL32.dll", 4))
+        );
+    }
+
+    #[test]
+    fn incomplete_icon_sets_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut set = icon_set();
+        set.images.pop();
+        assert!(provider().set_icon_for_folder(dir.path(), &set).is_err());
+        assert!(!dir.path().join("desktop.ini").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn attributes_are_set_for_samba() {
+        use crate::folder_settings::dos_attributes::read_dos_attributes;
+
+        let dir = tempfile::tempdir().unwrap();
+        if xattr::set(dir.path(), "user.icon-sys-test", b"").is_err() {
+            eprintln!("user xattrs unavailable, skipping");
+            return;
+        }
+        let provider = provider().with_dos_attribute_store(DosAttributeStore::Samba);
+        provider
+            .set_icon_for_folder(dir.path(), &icon_set())
+            .unwrap();
+
+        let attributes = |path: &Path| {
+            read_dos_attributes(path, DosAttributeStore::Samba)
+                .unwrap()
+                .unwrap()
+        };
+        let hidden_system = DosAttributes::HIDDEN | DosAttributes::SYSTEM;
+        assert!(attributes(&dir.path().join("desktop.ini")).contains(hidden_system));
+        let icon = dir.path().join(&generated_icons(dir.path())[0]);
+        assert!(attributes(&icon).contains(hidden_system));
+        assert!(
+            attributes(dir.path()).contains(DosAttributes::READONLY | DosAttributes::DIRECTORY)
+        );
+    }
+}
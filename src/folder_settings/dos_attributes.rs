@@ -0,0 +1,387 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+use std::path::Path;
+
+use super::error::{FolderSettingsError, Result};
+
+/// Extended attribute in which Samba keeps DOS attributes when
+/// `store dos attributes` is on (the default).
+const SAMBA_XATTR: &str = "user.DOSATTRIB";
+/// Extended attribute through which ntfs-3g and the ntfs3 driver expose the
+/// attributes stored on an NTFS volume, in native byte order.
+const NTFS_XATTR: &str = "system.ntfs_attrib";
+
+/// Version of `xattr_DosInfo` written: valid flags, attributes and creation
+/// time.
+const SAMBA_INFO_VERSION: u16 = 5;
+/// Version Samba reports for a blob holding nothing but the hex string.
+const SAMBA_HEX_ONLY: u16 = 0xFFFF;
+const SAMBA_VALID_ATTRIB: u32 = 0x01;
+const SAMBA_VALID_CREATE_TIME: u32 = 0x10;
+
+/// Windows file attributes (`FILE_ATTRIBUTE_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DosAttributes(pub u32);
+
+impl DosAttributes {
+    pub const READONLY: Self = Self(0x01);
+    pub const HIDDEN: Self = Self(0x02);
+    pub const SYSTEM: Self = Self(0x04);
+    pub const DIRECTORY: Self = Self(0x10);
+    pub const ARCHIVE: Self = Self(0x20);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for DosAttributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for DosAttributes {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Display for DosAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Where DOS attributes are kept on file systems that have none of their
+/// own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DosAttributeStore {
+    /// ntfs-3g's attribute when the file has it, i.e. on NTFS volumes,
+    /// Samba's otherwise. Nothing is read or written outside Unix.
+    #[default]
+    Auto,
+    /// Samba's `user.DOSATTRIB`, which `smbd` serves to Windows clients.
+    Samba,
+    /// `system.ntfs_attrib`, the real attributes of a file on an NTFS
+    /// volume mounted with ntfs-3g or ntfs3.
+    Ntfs,
+    /// Leave attributes alone.
+    None,
+}
+
+/// The DOS attributes of `path`, or `None` if the store has none for it.
+pub fn read_dos_attributes<P: AsRef<Path>>(
+    path: P,
+    store: DosAttributeStore,
+) -> Result<Option<DosAttributes>> {
+    let path = path.as_ref();
+    let io_error = |e| FolderSettingsError::Io(path.to_path_buf(), e);
+    match resolve(path, store).map_err(io_error)? {
+        DosAttributeStore::Ntfs => Ok(get_xattr(path, NTFS_XATTR)
+            .map_err(io_error)?
+            .and_then(|value| value.try_into().ok())
+            .map(|value| DosAttributes(u32::from_ne_bytes(value)))),
+        DosAttributeStore::Samba => match get_xattr(path, SAMBA_XATTR).map_err(io_error)? {
+            Some(blob) => samba::decode(&blob)
+                .map(|info| Some(info.attributes))
+                .map_err(|e| invalid_samba_xattr(path, e)),
+            None => Ok(None),
+        },
+        DosAttributeStore::Auto | DosAttributeStore::None => Ok(None),
+    }
+}
+
+/// Replace the DOS attributes of `path`. Samba's creation time is kept.
+pub fn write_dos_attributes<P: AsRef<Path>>(
+    path: P,
+    store: DosAttributeStore,
+    attributes: DosAttributes,
+) -> Result<()> {
+    let path = path.as_ref();
+    let io_error = |e| FolderSettingsError::Io(path.to_path_buf(), e);
+    match resolve(path, store).map_err(io_error)? {
+        DosAttributeStore::Ntfs => {
+            set_xattr(path, NTFS_XATTR, &attributes.0.to_ne_bytes()).map_err(io_error)
+        }
+        DosAttributeStore::Samba => {
+            // An unreadable blob is replaced rather than kept forever.
+            let create_time = get_xattr(path, SAMBA_XATTR)
+                .map_err(io_error)?
+                .and_then(|blob| samba::decode(&blob).ok())
+                .and_then(|info| info.create_time);
+            let blob = samba::encode(&samba::DosInfo {
+                attributes,
+                create_time,
+            });
+            set_xattr(path, SAMBA_XATTR, &blob).map_err(io_error)
+        }
+        DosAttributeStore::Auto | DosAttributeStore::None => Ok(()),
+    }
+}
+
+/// Add `attributes` to those of `path`, starting from what a new file or
+/// folder gets if it has none yet.
+pub(crate) fn add_dos_attributes(
+    path: &Path,
+    store: DosAttributeStore,
+    attributes: DosAttributes,
+) -> Result<()> {
+    if store == DosAttributeStore::None {
+        return Ok(());
+    }
+    let current = read_dos_attributes(path, store)?.unwrap_or(if path.is_dir() {
+        DosAttributes::DIRECTORY
+    } else {
+        DosAttributes::ARCHIVE
+    });
+    if current.contains(attributes) {
+        return Ok(());
+    }
+    write_dos_attributes(path, store, current | attributes)
+}
+
+fn invalid_samba_xattr(path: &Path, message: String) -> FolderSettingsError {
+    FolderSettingsError::Io(
+        path.to_path_buf(),
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid {SAMBA_XATTR}: {message}"),
+        ),
+    )
+}
+
+/// The store `Auto` stands for at `path`.
+fn resolve(path: &Path, store: DosAttributeStore) -> std::io::Result<DosAttributeStore> {
+    if store != DosAttributeStore::Auto || !cfg!(unix) {
+        return Ok(store);
+    }
+    Ok(if get_xattr(path, NTFS_XATTR)?.is_some() {
+        DosAttributeStore::Ntfs
+    } else {
+        DosAttributeStore::Samba
+    })
+}
+
+#[cfg(unix)]
+fn get_xattr(path: &Path, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+    match xattr::get(path, name) {
+        // File systems without extended attributes simply have none.
+        Err(e) if e.raw_os_error() == Some(xattr_unsupported()) => Ok(None),
+        result => result,
+    }
+}
+
+#[cfg(unix)]
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> std::io::Result<()> {
+    xattr::set(path, name, value)
+}
+
+#[cfg(unix)]
+fn xattr_unsupported() -> i32 {
+    // ENOTSUP (EOPNOTSUPP on Linux).
+    if cfg!(target_os = "linux") { 95 } else { 45 }
+}
+
+#[cfg(not(unix))]
+fn get_xattr(_path: &Path, _name: &str) -> std::io::Result<Option<Vec<u8>>> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(unix))]
+fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Samba's `xattr_DOSATTRIB`: the attributes as a hex string for old
+/// readers, then an NDR-encoded `xattr_DosInfo` whose layout depends on its
+/// version.
+mod samba {
+    use super::*;
+
+    /// Offsets of the attributes and the creation time in each version, and
+    /// whether the version has valid flags telling if the time is set.
+    const LAYOUTS: [(u16, usize, usize, bool); 5] = [
+        (1, 0, 24, false),
+        (2, 4, 28, false),
+        (3, 4, 28, true),
+        (4, 4, 16, true),
+        (5, 4, 8, true),
+    ];
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(super) struct DosInfo {
+        pub(super) attributes: DosAttributes,
+        /// An NT time: 100 ns intervals since 1601.
+        pub(super) create_time: Option<u64>,
+    }
+
+    pub(super) fn decode(blob: &[u8]) -> std::result::Result<DosInfo, String> {
+        let nul = blob.iter().position(|&b| b == 0).unwrap_or(blob.len());
+        let hex = std::str::from_utf8(&blob[..nul]).map_err(|e| e.to_string())?;
+        let hex_attributes = hex
+            .strip_prefix("0x")
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        // Align the version after the string to 2 bytes.
+        let pos = (nul + 1).next_multiple_of(2);
+        let Some(version) = read_u16(blob, pos) else {
+            // Samba 3 wrote the string alone.
+            return hex_attributes
+                .map(|attributes| DosInfo {
+                    attributes: DosAttributes(attributes),
+                    create_time: None,
+                })
+                .ok_or_else(|| format!("unreadable attributes {hex:?}"));
+        };
+        // The union repeats the version, then its arm is aligned to 4 bytes.
+        let info = (pos + 4).next_multiple_of(4);
+        if version == SAMBA_HEX_ONLY {
+            let attributes = read_u32(blob, info).ok_or("truncated")?;
+            return Ok(DosInfo {
+                attributes: DosAttributes(attributes),
+                create_time: None,
+            });
+        }
+        let &(_, attributes, create_time, has_flags) = LAYOUTS
+            .iter()
+            .find(|(v, ..)| *v == version)
+            .ok_or_else(|| format!("unknown version {version}"))?;
+        let attributes = read_u32(blob, info + attributes).ok_or("truncated")?;
+        let valid = !has_flags
+            || read_u32(blob, info).is_some_and(|flags| flags & SAMBA_VALID_CREATE_TIME != 0);
+        let create_time = read_u64(blob, info + create_time).filter(|_| valid);
+        Ok(DosInfo {
+            attributes: DosAttributes(attributes),
+            create_time,
+        })
+    }
+
+    pub(super) fn encode(info: &DosInfo) -> Vec<u8> {
+        let mut out = format!("{}", info.attributes).into_bytes();
+        out.push(0);
+        out.resize(out.len().next_multiple_of(2), 0);
+        out.extend(SAMBA_INFO_VERSION.to_le_bytes());
+        out.extend(SAMBA_INFO_VERSION.to_le_bytes());
+        out.resize(out.len().next_multiple_of(4), 0);
+        let mut valid = SAMBA_VALID_ATTRIB;
+        if info.create_time.is_some() {
+            valid |= SAMBA_VALID_CREATE_TIME;
+        }
+        out.extend(valid.to_le_bytes());
+        out.extend(info.attributes.0.to_le_bytes());
+        out.extend(info.create_time.unwrap_or_default().to_le_bytes());
+        out
+    }
+
+    fn read_u16(blob: &[u8], pos: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(blob.get(pos..pos + 2)?.try_into().ok()?))
+    }
+
+    fn read_u32(blob: &[u8], pos: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(blob.get(pos..pos + 4)?.try_into().ok()?))
+    }
+
+    fn read_u64(blob: &[u8], pos: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(blob.get(pos..pos + 8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::samba::DosInfo;
+    use super::*;
+
+    #[test]
+    fn decodes_samba_blobs() {
+        // Version 3, as written by Samba 4.0 to 4.8 for a hidden file.
+        let mut v3 = b"0x22\0\0\x03\0\x03\0\0\0".to_vec();
+        v3.extend(0x11u32.to_le_bytes());
+        v3.extend(0x22u32.to_le_bytes());
+        v3.extend([0; 20]);
+        v3.extend(132_000_000_000_000_000u64.to_le_bytes());
+        v3.extend([0; 8]);
+        assert_eq!(
+            samba::decode(&v3).unwrap(),
+            DosInfo {
+                attributes: DosAttributes::HIDDEN | DosAttributes::ARCHIVE,
+                create_time: Some(132_000_000_000_000_000),
+            }
+        );
+
+        // Samba 3.
+        let legacy = samba::decode(b"0x10").unwrap();
+        assert_eq!(legacy.attributes, DosAttributes::DIRECTORY);
+        assert_eq!(legacy.create_time, None);
+
+        assert!(samba::decode(b"0x22\0\0\x09\0\x09\0\0\0").is_err());
+        assert!(samba::decode(b"garbage").is_err());
+    }
+
+    #[test]
+    fn encodes_samba_version_5_blobs() {
+        let info = DosInfo {
+            attributes: DosAttributes::HIDDEN | DosAttributes::SYSTEM | DosAttributes::ARCHIVE,
+            create_time: Some(0x0102_0304_0506_0708),
+        };
+        let blob = samba::encode(&info);
+        assert_eq!(
+            blob,
+            b"0x26\0\0\x05\0\x05\0\0\0\x11\0\0\0\x26\0\0\0\x08\x07\x06\x05\x04\x03\x02\x01"
+        );
+        assert_eq!(samba::decode(&blob).unwrap(), info);
+
+        let without_time = DosInfo {
+            create_time: None,
+            ..info
+        };
+        assert_eq!(
+            samba::decode(&samba::encode(&without_time)).unwrap(),
+            without_time
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn attributes_are_added_through_samba_xattrs() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("desktop.ini");
+        std::fs::write(&file, "").unwrap();
+        if xattr::set(&file, "user.icon-sys-test", b"").is_err() {
+            eprintln!("user xattrs unavailable, skipping");
+            return;
+        }
+
+        assert_eq!(
+            read_dos_attributes(&file, DosAttributeStore::Auto).unwrap(),
+            None
+        );
+        add_dos_attributes(&file, DosAttributeStore::Auto, DosAttributes::HIDDEN).unwrap();
+        add_dos_attributes(&file, DosAttributeStore::Samba, DosAttributes::SYSTEM).unwrap();
+        assert_eq!(
+            read_dos_attributes(&file, DosAttributeStore::Samba).unwrap(),
+            Some(DosAttributes::ARCHIVE | DosAttributes::HIDDEN | DosAttributes::SYSTEM)
+        );
+        assert!(get_xattr(&file, NTFS_XATTR).unwrap().is_none());
+
+        add_dos_attributes(
+            dir.path(),
+            DosAttributeStore::Samba,
+            DosAttributes::READONLY,
+        )
+        .unwrap();
+        assert_eq!(
+            read_dos_attributes(dir.path(), DosAttributeStore::Samba).unwrap(),
+            Some(DosAttributes::DIRECTORY | DosAttributes::READONLY)
+        );
+
+        add_dos_attributes(&file, DosAttributeStore::None, DosAttributes::READONLY).unwrap();
+        assert!(
+            !read_dos_attributes(&file, DosAttributeStore::Samba)
+                .unwrap()
+                .unwrap()
+                .contains(DosAttributes::READONLY)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, FolderSettingsError>;
@@ -12,6 +14,27 @@ pub enum FolderSettingsError {
     #[error(transparent)]
     Linux(#[from] crate::folder_settings::sys::linux::LinuxFolderSettingsError),
 
+    #[error("{path} is not a folder", path = .0.display())]
+    NotAFolder(PathBuf),
+
+    #[error("I/O error on {path}: {1}", path = .0.display())]
+    Io(PathBuf, std::io::Error),
+
+    #[error("{0} is not supported on this platform")]
+    Unsupported(String),
+
+    #[error("invalid shell link: {0}")]
+    ShellLink(String),
+
+    #[error("invalid icon library: {0}")]
+    IconLibrary(String),
+
+    #[error("invalid AppImage {path}: {1}", path = .0.display())]
+    AppImage(PathBuf, String),
+
+    #[error("invalid app bundle {path}: {1}", path = .0.display())]
+    AppBundle(PathBuf, String),
+
     #[error(transparent)]
     IconError(#[from] crate::icon::IconError),
 }
@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+
+use super::error::{FolderSettingsError, Result};
+use crate::api::IconSet;
+use crate::icon::IconError;
+use crate::icon::sys::windows::{WindowsIconSet, WindowsIconSize};
+
+/// Largest width or height an `.ico` frame can have.
+const MAX_ICO_DIMENSION: u32 = 256;
+
+/// Encode the raster images of `icon_set` as an `.ico` file, one
+/// PNG-compressed frame per size. Images larger than 256 pixels are scaled
+/// down to fit; of several images with the same dimensions, the first is
+/// used. Works on every platform.
+pub fn encode_ico(icon_set: &IconSet) -> Result<Vec<u8>> {
+    let mut images = Vec::new();
+    for icon in &icon_set.images {
+        let image =
+            if icon.data.width() > MAX_ICO_DIMENSION || icon.data.height() > MAX_ICO_DIMENSION {
+                icon.data
+                    .resize(MAX_ICO_DIMENSION, MAX_ICO_DIMENSION, FilterType::Lanczos3)
+                    .to_rgba8()
+            } else {
+                icon.data.to_rgba8()
+            };
+        if !images
+            .iter()
+            .any(|other: &image::RgbaImage| other.dimensions() == image.dimensions())
+        {
+            images.push(image);
+        }
+    }
+    if images.is_empty() {
+        return Err(IconError::IconSet("icon set contains no raster images".to_string()).into());
+    }
+
+    let frames = images
+        .iter()
+        .map(|image| {
+            IcoFrame::as_png(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                image::ExtendedColorType::Rgba8,
+            )
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| IconError::IconImage(e.to_string()))?;
+    encode_frames(&frames)
+}
+
+/// Encode a set with every Windows icon size as an `.ico` file.
+pub(crate) fn encode_windows_ico(icon_set: &WindowsIconSet) -> Result<Vec<u8>> {
+    let frames = to_ico_frames(icon_set).map_err(|e| IconError::IconImage(e.to_string()))?;
+    encode_frames(&frames)
+}
+
+fn encode_frames(frames: &[IcoFrame]) -> Result<Vec<u8>> {
+    let mut ico = Vec::new();
+    IcoEncoder::new(&mut ico)
+        .encode_images(frames)
+        .map_err(|e| IconError::IconImage(e.to_string()))?;
+    Ok(ico)
+}
+
+/// Write `icon_set` to `path` as an `.ico` file (see [`encode_ico`]).
+pub fn write_ico<P: AsRef<Path>>(icon_set: &IconSet, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let ico = encode_ico(icon_set)?;
+    std::fs::write(path, ico).map_err(|e| FolderSettingsError::Io(path.to_path_buf(), e))
+}
+
+/// Convert RGBA bitmaps to individual .ico sizes
+pub(crate) fn to_ico_frames<'a>(
+    windows_icon_set: &'a WindowsIconSet<'a>,
+) -> core::result::Result<Vec<IcoFrame<'a>>, image::error::ImageError> {
+    windows_icon_set.iter().try_fold(
+        Vec::with_capacity(WindowsIconSize::NUM_SIZES),
+        |mut ico_frames, (res, img)| {
+            let dim = res.dimension();
+            let ico_frame = IcoFrame::as_png(
+                img.image.as_bytes(),
+                dim,
+                dim,
+                image::ExtendedColorType::Rgba8,
+            )?;
+
+            ico_frames.push(ico_frame);
+            Ok(ico_frames)
+        },
+    )
+}
+
+/// Returns whether `file_name` names one of this crate's generated icon files:
+/// a `.ico` whose name begins with the configured prefix.
+///
+/// Kept free of filesystem access so the match logic can be unit-tested in
+/// isolation, mirroring the pure helpers used by the other platform backends.
+pub(crate) fn is_generated_icon(file_name: &str, prefix: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        == Some("ico")
+        && file_name.starts_with(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::IconImage;
+    use crate::icon::sys::windows::WindowsIconImage;
+    use image::{DynamicImage, ImageReader, RgbaImage};
+    use std::borrow::Cow;
+
+    fn image(size: u32) -> IconImage {
+        IconImage {
+            data: DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                size,
+                size,
+                image::Rgba([0, 128, 255, 255]),
+            )),
+        }
+    }
+
+    #[test]
+    fn frames_are_deduplicated_and_capped() {
+        let set = IconSet {
+            images: vec![image(16), image(512), image(16), image(48)],
+            svg: None,
+        };
+        let ico = encode_ico(&set).unwrap();
+        // ICONDIR: reserved, type 1, frame count.
+        assert_eq!(&ico[..4], &[0, 0, 1, 0]);
+        assert_eq!(u16::from_le_bytes([ico[4], ico[5]]), 3);
+
+        let decoded = ImageReader::with_format(std::io::Cursor::new(ico), image::ImageFormat::Ico)
+            .decode()
+            .unwrap();
+        // The decoder picks the largest frame.
+        assert_eq!(decoded.width(), 256);
+    }
+
+    #[test]
+    fn svg_only_sets_are_rejected() {
+        let set = IconSet {
+            images: Vec::new(),
+            svg: Some("<svg/>".to_string()),
+        };
+        assert!(matches!(
+            encode_ico(&set),
+            Err(FolderSettingsError::IconError(IconError::IconSet(_)))
+        ));
+    }
+    #[test]
+    fn is_generated_icon_matches_prefixed_ico() {
+        assert!(is_generated_icon("icon-sys-1234.ico", "icon-sys"));
+    }
+
+    #[test]
+    fn is_generated_icon_rejects_wrong_extension() {
+        assert!(!is_generated_icon("icon-sys-1234.png", "icon-sys"));
+    }
+
+    #[test]
+    fn is_generated_icon_rejects_missing_extension() {
+        assert!(!is_generated_icon("icon-sys-1234", "icon-sys"));
+    }
+
+    #[test]
+    fn is_generated_icon_rejects_wrong_prefix() {
+        assert!(!is_generated_icon("other-1234.ico", "icon-sys"));
+    }
+
+    #[test]
+    fn to_ico_frames_produces_one_frame_per_size() {
+        let icons = WindowsIconSize::all().map(|size| {
+            let dim = size.dimension();
+            WindowsIconImage {
+                size,
+                image: Cow::Owned(DynamicImage::new_rgba8(dim, dim)),
+            }
+        });
+        let set = WindowsIconSet::from_icons(icons).unwrap();
+        let frames = to_ico_frames(&set).unwrap();
+        assert_eq!(frames.len(), WindowsIconSize::NUM_SIZES);
+    }
+}
@@ -0,0 +1,871 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use image::DynamicImage;
+
+use super::error::{FolderSettingsError, Result};
+use crate::icon::IconError;
+use crate::icon::sys::windows::{WindowsIconImage, WindowsIconSet, WindowsIconSize};
+
+const RT_ICON: u16 = 3;
+const RT_GROUP_ICON: u16 = 14;
+/// `e_lfanew`: where the DOS header stores the offset of the new header.
+const NEW_HEADER_OFFSET: usize = 0x3C;
+/// Index of the resource table in the PE data directories.
+const PE_RESOURCE_DIRECTORY: usize = 2;
+/// Set on PE resource names that are strings and offsets that lead to a
+/// subdirectory.
+const PE_HIGH_BIT: u32 = 0x8000_0000;
+/// Offset of the resource table offset in an NE header.
+const NE_RESOURCE_TABLE: usize = 0x24;
+/// Set on NE types and resource IDs that are integers rather than names.
+const NE_INTEGER_ID: u16 = 0x8000;
+const NE_TYPE_INFO_SIZE: usize = 8;
+const NE_NAME_INFO_SIZE: usize = 12;
+/// `GRPICONDIR` and `ICONDIR` headers: reserved, type and count.
+const DIRECTORY_HEADER_SIZE: usize = 6;
+const GROUP_ENTRY_SIZE: usize = 14;
+const ICO_ENTRY_SIZE: usize = 16;
+/// `idType` of icons; cursors are 2.
+const ICON_TYPE: u16 = 1;
+/// Start of an `.ico` file: reserved, then `idType` 1.
+const ICO_MAGIC: &[u8] = &[0, 0, 1, 0];
+
+/// The ID of a resource: a number, or a name as used by most `.icl` files.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "#{id}"),
+            ResourceId::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// One image of an icon group, as described by its `GRPICONDIRENTRY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IconGroupEntry {
+    pub width: u32,
+    pub height: u32,
+    /// Number of palette colors, 0 for 8 bits per pixel or more.
+    pub color_count: u8,
+    pub planes: u16,
+    pub bit_count: u16,
+    pub bytes_in_res: u32,
+    /// The `RT_ICON` resource holding the image.
+    pub icon_id: u16,
+}
+
+/// An `RT_GROUP_ICON` resource: the sizes and color depths of one icon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconGroup {
+    pub id: ResourceId,
+    pub entries: Vec<IconGroupEntry>,
+}
+
+/// The icons of a Windows executable, DLL or icon library (`.exe`, `.dll`,
+/// `.icl`), read without Win32 so it works on any platform, e.g. on files
+/// from a Wine prefix.
+///
+/// Both the PE format and the 16-bit NE format of older DLLs and `.icl`
+/// files are read.
+#[derive(Debug, Clone, Default)]
+pub struct IconLibrary {
+    groups: Vec<IconGroup>,
+    icons: BTreeMap<u16, Vec<u8>>,
+}
+
+/// A resource of one of the types we keep.
+struct Resource<'a> {
+    kind: u16,
+    id: ResourceId,
+    data: &'a [u8],
+}
+
+impl IconLibrary {
+    /// Read a PE or NE file, or an `.ico` file as a library whose only
+    /// group, `#1`, holds its images.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.starts_with(ICO_MAGIC) {
+            return parse_ico(data).map_err(FolderSettingsError::IconLibrary);
+        }
+        let resources = resources(data).map_err(FolderSettingsError::IconLibrary)?;
+        let mut library = IconLibrary::default();
+        for resource in resources {
+            match (resource.kind, resource.id) {
+                (RT_ICON, ResourceId::Id(id)) => {
+                    library.icons.insert(id, resource.data.to_vec());
+                }
+                (RT_GROUP_ICON, id) => {
+                    let entries = parse_group_directory(resource.data).map_err(|e| {
+                        FolderSettingsError::IconLibrary(format!("icon group {id}: {e}"))
+                    })?;
+                    library.groups.push(IconGroup { id, entries });
+                }
+                _ => {}
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data =
+            std::fs::read(path).map_err(|e| FolderSettingsError::Io(path.to_path_buf(), e))?;
+        Self::parse(&data)
+    }
+
+    /// The icon groups in resource order, the order Windows numbers them in.
+    pub fn groups(&self) -> &[IconGroup] {
+        &self.groups
+    }
+
+    pub fn group(&self, id: &ResourceId) -> Option<&IconGroup> {
+        self.groups.iter().find(|group| group.id == *id)
+    }
+
+    /// The group an icon index such as the `3` of `shell32.dll,3` refers to,
+    /// resolved like `ExtractIcon`: the n-th group for an index n of 0 or
+    /// more, the group with resource ID -n for a negative one.
+    pub fn group_at_index(&self, index: i32) -> Option<&IconGroup> {
+        if index >= 0 {
+            self.groups.get(index as usize)
+        } else {
+            let id = u16::try_from(index.unsigned_abs()).ok()?;
+            self.group(&ResourceId::Id(id))
+        }
+    }
+
+    /// The raw `RT_ICON` resource `icon_id`: a DIB or a PNG.
+    pub fn icon_data(&self, icon_id: u16) -> Option<&[u8]> {
+        self.icons.get(&icon_id).map(Vec::as_slice)
+    }
+
+    /// The group as the bytes of an `.ico` file, with every image in it.
+    pub fn to_ico(&self, group: &IconGroup) -> Result<Vec<u8>> {
+        let images = group
+            .entries
+            .iter()
+            .map(|entry| Ok((entry, self.entry_data(group, entry)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ico_file(&images))
+    }
+
+    /// Decode every size in the group, taking the deepest color depth
+    /// available for each, from smallest to largest.
+    pub fn images(&self, group: &IconGroup) -> Result<Vec<DynamicImage>> {
+        best_entries(group)
+            .into_values()
+            .map(|entry| self.decode(group, entry))
+            .collect()
+    }
+
+    /// Decode the group at the sizes of `WindowsIconSize`, taking the
+    /// deepest color depth available for each. Images at other sizes are
+    /// skipped.
+    pub fn icon_set(&self, group: &IconGroup) -> Result<WindowsIconSet<'static>> {
+        let mut set = WindowsIconSet::from_icons([])?;
+        for ((width, height), entry) in best_entries(group) {
+            let Some(size) = WindowsIconSize::from_dimension(width).filter(|_| width == height)
+            else {
+                continue;
+            };
+            set.add_image(WindowsIconImage {
+                size,
+                image: Cow::Owned(self.decode(group, entry)?),
+            });
+        }
+        if set.iter().next().is_none() {
+            return Err(IconError::IconSet(format!(
+                "icon group {} has no images at a supported size",
+                group.id
+            ))
+            .into());
+        }
+        Ok(set)
+    }
+
+    fn decode(&self, group: &IconGroup, entry: &IconGroupEntry) -> Result<DynamicImage> {
+        let ico = ico_file(&[(entry, self.entry_data(group, entry)?)]);
+        let image =
+            image::load_from_memory_with_format(&ico, image::ImageFormat::Ico).map_err(|e| {
+                IconError::IconImage(format!(
+                    "icon #{} of group {}: {e}",
+                    entry.icon_id, group.id
+                ))
+            })?;
+        Ok(image)
+    }
+
+    /// The image data of `entry`, trimmed to its declared size: NE files pad
+    /// resources to their alignment.
+    fn entry_data(&self, group: &IconGroup, entry: &IconGroupEntry) -> Result<&[u8]> {
+        let data = self.icon_data(entry.icon_id).ok_or_else(|| {
+            FolderSettingsError::IconLibrary(format!(
+                "icon group {} refers to missing icon #{}",
+                group.id, entry.icon_id
+            ))
+        })?;
+        Ok(&data[..data.len().min(entry.bytes_in_res as usize)])
+    }
+}
+
+/// The entry with the deepest color depth at each size of the group.
+fn best_entries(group: &IconGroup) -> BTreeMap<(u32, u32), &IconGroupEntry> {
+    let mut best: BTreeMap<(u32, u32), &IconGroupEntry> = BTreeMap::new();
+    for entry in &group.entries {
+        let size = (entry.width, entry.height);
+        if best
+            .get(&size)
+            .is_none_or(|other| entry.bit_count > other.bit_count)
+        {
+            best.insert(size, entry);
+        }
+    }
+    best
+}
+
+/// Parse an `RT_GROUP_ICON` resource (`GRPICONDIR`).
+pub(crate) fn parse_group_directory(
+    data: &[u8],
+) -> std::result::Result<Vec<IconGroupEntry>, String> {
+    if data.len() < DIRECTORY_HEADER_SIZE {
+        return Err("truncated group directory".to_string());
+    }
+    let kind = read_u16(data, 2)?;
+    if kind != ICON_TYPE {
+        return Err(format!("resource type {kind} is not an icon"));
+    }
+    let count = usize::from(read_u16(data, 4)?);
+    (0..count)
+        .map(|i| {
+            let entry = DIRECTORY_HEADER_SIZE + i * GROUP_ENTRY_SIZE;
+            let dimension = |byte: u8| if byte == 0 { 256 } else { u32::from(byte) };
+            Ok(IconGroupEntry {
+                width: dimension(read_u8(data, entry)?),
+                height: dimension(read_u8(data, entry + 1)?),
+                color_count: read_u8(data, entry + 2)?,
+                planes: read_u16(data, entry + 4)?,
+                bit_count: read_u16(data, entry + 6)?,
+                bytes_in_res: read_u32(data, entry + 8)?,
+                icon_id: read_u16(data, entry + 12)?,
+            })
+        })
+        .collect()
+}
+
+/// An `.ico` file as a library: its `ICONDIR` is a group directory whose
+/// entries hold file offsets instead of resource IDs.
+fn parse_ico(data: &[u8]) -> std::result::Result<IconLibrary, String> {
+    let count = usize::from(read_u16(data, 4)?);
+    let mut library = IconLibrary::default();
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let entry = DIRECTORY_HEADER_SIZE + i * ICO_ENTRY_SIZE;
+        let icon_id = u16::try_from(i + 1).map_err(|e| e.to_string())?;
+        let dimension = |byte: u8| if byte == 0 { 256 } else { u32::from(byte) };
+        let bytes_in_res = read_u32(data, entry + 8)?;
+        let offset = read_u32(data, entry + 12)? as usize;
+        let image = data
+            .get(offset..offset.saturating_add(bytes_in_res as usize))
+            .ok_or_else(|| format!("image {i} is truncated"))?;
+        library.icons.insert(icon_id, image.to_vec());
+        entries.push(IconGroupEntry {
+            width: dimension(read_u8(data, entry)?),
+            height: dimension(read_u8(data, entry + 1)?),
+            color_count: read_u8(data, entry + 2)?,
+            planes: read_u16(data, entry + 4)?,
+            bit_count: read_u16(data, entry + 6)?,
+            bytes_in_res,
+            icon_id,
+        });
+    }
+    library.groups.push(IconGroup {
+        id: ResourceId::Id(1),
+        entries,
+    });
+    Ok(library)
+}
+
+/// An `.ico` file of the given images.
+fn ico_file(images: &[(&IconGroupEntry, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend(0u16.to_le_bytes());
+    out.extend(ICON_TYPE.to_le_bytes());
+    out.extend((images.len() as u16).to_le_bytes());
+    let mut offset = DIRECTORY_HEADER_SIZE + images.len() * ICO_ENTRY_SIZE;
+    for (entry, data) in images {
+        // 256 is stored as 0.
+        out.push(entry.width as u8);
+        out.push(entry.height as u8);
+        out.push(entry.color_count);
+        out.push(0);
+        out.extend(entry.planes.to_le_bytes());
+        out.extend(entry.bit_count.to_le_bytes());
+        out.extend((data.len() as u32).to_le_bytes());
+        out.extend((offset as u32).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, data) in images {
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+/// The icon and icon group resources of a PE or NE file.
+fn resources(data: &[u8]) -> std::result::Result<Vec<Resource<'_>>, String> {
+    if !data.starts_with(b"MZ") {
+        return Err("not an executable".to_string());
+    }
+    let header = read_u32(data, NEW_HEADER_OFFSET)? as usize;
+    match data.get(header..header + 4) {
+        Some(b"PE\0\0") => pe_resources(data, header),
+        Some([b'N', b'E', ..]) => ne_resources(data, header),
+        _ => Err("neither a PE nor an NE executable".to_string()),
+    }
+}
+
+fn pe_resources(data: &[u8], header: usize) -> std::result::Result<Vec<Resource<'_>>, String> {
+    let coff = header + 4;
+    let section_count = usize::from(read_u16(data, coff + 2)?);
+    let optional_size = usize::from(read_u16(data, coff + 16)?);
+    let optional = coff + 20;
+    let (directory_count, directories) = match read_u16(data, optional)? {
+        0x10B => (read_u32(data, optional + 92)?, optional + 96),
+        0x20B => (read_u32(data, optional + 108)?, optional + 112),
+        magic => return Err(format!("unknown optional header magic {magic:#x}")),
+    };
+    if (directory_count as usize) <= PE_RESOURCE_DIRECTORY {
+        return Ok(Vec::new());
+    }
+    let resource_rva = read_u32(data, directories + PE_RESOURCE_DIRECTORY * 8)?;
+    if resource_rva == 0 {
+        return Ok(Vec::new());
+    }
+
+    let sections = (0..section_count)
+        .map(|i| {
+            let section = optional + optional_size + i * 40;
+            Ok((
+                read_u32(data, section + 12)?,
+                read_u32(data, section + 8)?.max(read_u32(data, section + 16)?),
+                read_u32(data, section + 20)?,
+            ))
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()?;
+    let file_offset = |rva: u32| {
+        sections
+            .iter()
+            .find(|(address, size, _)| rva >= *address && rva - address < *size)
+            .ok_or_else(|| format!("address {rva:#x} is outside every section"))
+            .and_then(|(address, _, raw)| {
+                (*raw as usize)
+                    .checked_add((rva - address) as usize)
+                    .ok_or_else(|| format!("address {rva:#x} maps past the end of the file"))
+            })
+    };
+
+    let root = file_offset(resource_rva)?;
+    let mut resources = Vec::new();
+    for (kind, types) in pe_directory(data, root, root)? {
+        let kind = match kind {
+            ResourceId::Id(kind @ (RT_ICON | RT_GROUP_ICON)) => kind,
+            _ => continue,
+        };
+        let Some(names) = types.subdirectory() else {
+            continue;
+        };
+        for (id, languages) in pe_directory(data, root, root + names)? {
+            let Some(languages) = languages.subdirectory() else {
+                continue;
+            };
+            // Take the first language; icons rarely differ between them.
+            let Some(entry) = pe_directory(data, root, root + languages)?
+                .into_iter()
+                .find_map(|(_, entry)| entry.data_entry())
+            else {
+                continue;
+            };
+            let entry = root + entry;
+            let start = file_offset(read_u32(data, entry)?)?;
+            let len = read_u32(data, entry + 4)? as usize;
+            let data = data
+                .get(start..start.saturating_add(len))
+                .ok_or_else(|| format!("resource {id} is truncated"))?;
+            resources.push(Resource { kind, id, data });
+        }
+    }
+    Ok(resources)
+}
+
+/// The target of a PE resource directory entry, relative to the resource
+/// section.
+struct PeEntry(u32);
+
+impl PeEntry {
+    fn subdirectory(&self) -> Option<usize> {
+        (self.0 & PE_HIGH_BIT != 0).then_some((self.0 & !PE_HIGH_BIT) as usize)
+    }
+
+    fn data_entry(&self) -> Option<usize> {
+        (self.0 & PE_HIGH_BIT == 0).then_some(self.0 as usize)
+    }
+}
+
+/// The entries of an `IMAGE_RESOURCE_DIRECTORY`.
+fn pe_directory(
+    data: &[u8],
+    root: usize,
+    directory: usize,
+) -> std::result::Result<Vec<(ResourceId, PeEntry)>, String> {
+    let count =
+        usize::from(read_u16(data, directory + 12)?) + usize::from(read_u16(data, directory + 14)?);
+    (0..count)
+        .map(|i| {
+            let entry = directory + 16 + i * 8;
+            let name = read_u32(data, entry)?;
+            let id = if name & PE_HIGH_BIT != 0 {
+                let at = root + (name & !PE_HIGH_BIT) as usize;
+                let len = usize::from(read_u16(data, at)?);
+                let units = (0..len)
+                    .map(|i| read_u16(data, at + 2 + i * 2))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                ResourceId::Name(String::from_utf16_lossy(&units))
+            } else {
+                ResourceId::Id(name as u16)
+            };
+            Ok((id, PeEntry(read_u32(data, entry + 4)?)))
+        })
+        .collect()
+}
+
+fn ne_resources(data: &[u8], header: usize) -> std::result::Result<Vec<Resource<'_>>, String> {
+    let table = header + usize::from(read_u16(data, header + NE_RESOURCE_TABLE)?);
+    let shift = read_u16(data, table)?;
+    if shift > 16 {
+        return Err(format!("invalid resource alignment shift {shift}"));
+    }
+    let mut resources = Vec::new();
+    let mut pos = table + 2;
+    loop {
+        let kind = read_u16(data, pos)?;
+        if kind == 0 {
+            break;
+        }
+        let count = usize::from(read_u16(data, pos + 2)?);
+        pos += NE_TYPE_INFO_SIZE;
+        let kind = kind & !NE_INTEGER_ID;
+        let wanted = matches!(kind, RT_ICON | RT_GROUP_ICON);
+        for i in 0..count {
+            if !wanted {
+                continue;
+            }
+            let info = pos + i * NE_NAME_INFO_SIZE;
+            let start = usize::from(read_u16(data, info)?) << shift;
+            let len = usize::from(read_u16(data, info + 2)?) << shift;
+            let raw_id = read_u16(data, info + 6)?;
+            let id = if raw_id & NE_INTEGER_ID != 0 {
+                ResourceId::Id(raw_id & !NE_INTEGER_ID)
+            } else {
+                // A length-prefixed string in the resource table.
+                let at = table + usize::from(raw_id);
+                let len = usize::from(read_u8(data, at)?);
+                let name = data
+                    .get(at + 1..at + 1 + len)
+                    .ok_or("truncated resource name")?;
+                ResourceId::Name(name.iter().map(|&b| char::from(b)).collect())
+            };
+            // The length is rounded up to the alignment, possibly past the end.
+            let data = data
+                .get(start..start.saturating_add(len).min(data.len()))
+                .ok_or_else(|| format!("resource {id} is truncated"))?;
+            resources.push(Resource { kind, id, data });
+        }
+        pos += count * NE_NAME_INFO_SIZE;
+    }
+    Ok(resources)
+}
+
+fn read_u8(data: &[u8], pos: usize) -> std::result::Result<u8, String> {
+    data.get(pos)
+        .copied()
+        .ok_or_else(|| "unexpected end of file".to_string())
+}
+
+fn read_u16(data: &[u8], pos: usize) -> std::result::Result<u16, String> {
+    data.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "unexpected end of file".to_string())
+}
+
+fn read_u32(data: &[u8], pos: usize) -> std::result::Result<u32, String> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "unexpected end of file".to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod builder {
+    use super::*;
+    use image::RgbaImage;
+
+    /// A 32-bit DIB of one color, as stored in `RT_ICON`.
+    pub(crate) fn dib(size: u32, bgra: [u8; 4]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(40u32.to_le_bytes());
+        out.extend((size as i32).to_le_bytes());
+        // Color and AND mask rows together.
+        out.extend((size as i32 * 2).to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend(32u16.to_le_bytes());
+        out.extend([0; 24]);
+        for _ in 0..size * size {
+            out.extend(bgra);
+        }
+        let mask_row = size.div_ceil(32) * 4;
+        out.extend(vec![0; (mask_row * size) as usize]);
+        out
+    }
+
+    pub(crate) fn png(size: u32) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            size,
+            size,
+            image::Rgba([9, 8, 7, 255]),
+        ))
+        .write_to(&mut out, image::ImageFormat::Png)
+        .unwrap();
+        out.into_inner()
+    }
+
+    pub(crate) fn group(entries: &[(u32, u16, u16, usize)]) -> Vec<u8> {
+        let mut out = vec![0, 0, 1, 0];
+        out.extend((entries.len() as u16).to_le_bytes());
+        for &(size, bit_count, icon_id, len) in entries {
+            out.extend([size as u8, size as u8, 0, 0]);
+            out.extend(1u16.to_le_bytes());
+            out.extend(bit_count.to_le_bytes());
+            out.extend((len as u32).to_le_bytes());
+            out.extend(icon_id.to_le_bytes());
+        }
+        out
+    }
+
+    /// Icons 1 to 4 and two groups: `#1` with every icon, then `FOLDER`.
+    pub(crate) fn fixture_resources() -> Vec<Fixture> {
+        let icons = [
+            dib(16, [0, 0, 255, 255]),
+            dib(32, [0, 255, 0, 255]),
+            png(256),
+            // A 16x16 image with less color depth, which must lose.
+            dib(16, [255, 0, 0, 255]),
+        ];
+        let main = group(&[
+            (16, 32, 1, icons[0].len()),
+            (32, 32, 2, icons[1].len()),
+            (256, 32, 3, icons[2].len()),
+            (16, 8, 4, icons[3].len()),
+        ]);
+        let folder = group(&[(32, 32, 2, icons[1].len())]);
+        let mut resources: Vec<_> = icons
+            .into_iter()
+            .enumerate()
+            .map(|(i, icon)| (RT_ICON, ResourceId::Id(i as u16 + 1), icon))
+            .collect();
+        resources.push((RT_GROUP_ICON, ResourceId::Id(1), main));
+        resources.push((
+            RT_GROUP_ICON,
+            ResourceId::Name("FOLDER".to_string()),
+            folder,
+        ));
+        resources
+    }
+
+    /// A resource type, ID and data.
+    pub(crate) type Fixture = (u16, ResourceId, Vec<u8>);
+
+    fn by_type(resources: &[Fixture]) -> BTreeMap<u16, Vec<&Fixture>> {
+        let mut types: BTreeMap<u16, Vec<&Fixture>> = BTreeMap::new();
+        for resource in resources {
+            types.entry(resource.0).or_default().push(resource);
+        }
+        types
+    }
+
+    pub(crate) fn dos_header(new_header: u32) -> Vec<u8> {
+        let mut out = vec![0; 0x40];
+        out[..2].copy_from_slice(b"MZ");
+        out[NEW_HEADER_OFFSET..NEW_HEADER_OFFSET + 4].copy_from_slice(&new_header.to_le_bytes());
+        out
+    }
+
+    fn patch_u32(out: &mut [u8], pos: usize, value: u32) {
+        out[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn patch_u16(out: &mut [u8], pos: usize, value: u16) {
+        out[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// A `.rsrc` section at `rva`, one language per resource.
+    fn rsrc_section(resources: &[Fixture], rva: u32) -> Vec<u8> {
+        let types = by_type(resources);
+        let directory = |out: &mut Vec<u8>, named: usize, ids: usize| {
+            out.extend([0; 12]);
+            out.extend((named as u16).to_le_bytes());
+            out.extend((ids as u16).to_le_bytes());
+            let entries = out.len();
+            out.resize(entries + (named + ids) * 8, 0);
+            entries
+        };
+
+        let mut out = Vec::new();
+        let root_entries = directory(&mut out, 0, types.len());
+        let mut names = Vec::new();
+        let mut payloads = Vec::new();
+        for (i, (kind, members)) in types.iter().enumerate() {
+            patch_u32(&mut out, root_entries + i * 8, u32::from(*kind));
+            let types = out.len() as u32;
+            patch_u32(&mut out, root_entries + i * 8 + 4, PE_HIGH_BIT | types);
+            let named = members
+                .iter()
+                .filter(|r| matches!(r.1, ResourceId::Name(_)))
+                .count();
+            let entries = directory(&mut out, named, members.len() - named);
+            for (j, (_, id, data)) in members.iter().enumerate() {
+                let entry = entries + j * 8;
+                match id {
+                    ResourceId::Id(id) => patch_u32(&mut out, entry, u32::from(*id)),
+                    ResourceId::Name(name) => names.push((entry, name)),
+                }
+                let languages = out.len() as u32;
+                patch_u32(&mut out, entry + 4, PE_HIGH_BIT | languages);
+                let languages = directory(&mut out, 0, 1);
+                patch_u32(&mut out, languages, 0x409);
+                let data_entry = out.len() as u32;
+                patch_u32(&mut out, languages + 4, data_entry);
+                payloads.push((out.len(), data));
+                out.extend([0; 16]);
+            }
+        }
+        for (entry, name) in names {
+            let at = out.len() as u32;
+            patch_u32(&mut out, entry, PE_HIGH_BIT | at);
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(name.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        for (entry, data) in payloads {
+            out.resize(out.len().next_multiple_of(4), 0);
+            let at = out.len() as u32;
+            patch_u32(&mut out, entry, rva + at);
+            patch_u32(&mut out, entry + 4, data.len() as u32);
+            out.extend(data);
+        }
+        out
+    }
+
+    /// A PE32 (or PE32+) image with only a resource section.
+    pub(crate) fn pe(resources: &[Fixture], pe32_plus: bool) -> Vec<u8> {
+        const RSRC_RVA: u32 = 0x1000;
+        const RSRC_OFFSET: usize = 0x400;
+        let mut out = dos_header(0x40);
+        out.extend(b"PE\0\0");
+        let optional_size: u16 = if pe32_plus { 240 } else { 224 };
+        out.extend(0x14Cu16.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend([0; 12]);
+        out.extend(optional_size.to_le_bytes());
+        out.extend(0x2102u16.to_le_bytes());
+        let optional = out.len();
+        out.resize(optional + usize::from(optional_size), 0);
+        let (magic, count, directories) = if pe32_plus {
+            (0x20B, optional + 108, optional + 112)
+        } else {
+            (0x10B, optional + 92, optional + 96)
+        };
+        patch_u16(&mut out, optional, magic);
+        patch_u32(&mut out, count, 16);
+
+        let section = rsrc_section(resources, RSRC_RVA);
+        patch_u32(&mut out, directories + 16, RSRC_RVA);
+        patch_u32(&mut out, directories + 20, section.len() as u32);
+        let header = out.len();
+        out.resize(header + 40, 0);
+        out[header..header + 5].copy_from_slice(b".rsrc");
+        patch_u32(&mut out, header + 8, section.len() as u32);
+        patch_u32(&mut out, header + 12, RSRC_RVA);
+        patch_u32(&mut out, header + 16, section.len() as u32);
+        patch_u32(&mut out, header + 20, RSRC_OFFSET as u32);
+        out.resize(RSRC_OFFSET, 0);
+        out.extend(section);
+        out
+    }
+
+    /// An NE image with only a resource table, aligned to 16 bytes.
+    pub(crate) fn ne(resources: &[Fixture]) -> Vec<u8> {
+        const SHIFT: u16 = 4;
+        let mut out = dos_header(0x40);
+        let header = out.len();
+        out.resize(header + 0x40, 0);
+        out[header..header + 2].copy_from_slice(b"NE");
+        let table = out.len();
+        patch_u16(
+            &mut out,
+            header + NE_RESOURCE_TABLE,
+            (table - header) as u16,
+        );
+        out.extend(SHIFT.to_le_bytes());
+
+        let types = by_type(resources);
+        let mut infos = Vec::new();
+        for (kind, members) in &types {
+            out.extend((NE_INTEGER_ID | kind).to_le_bytes());
+            out.extend((members.len() as u16).to_le_bytes());
+            out.extend([0; 4]);
+            for (_, id, data) in members {
+                infos.push((out.len(), id, data));
+                out.extend([0; NE_NAME_INFO_SIZE]);
+            }
+        }
+        out.extend(0u16.to_le_bytes());
+        for (info, id, _) in &infos {
+            let raw_id = match id {
+                ResourceId::Id(id) => NE_INTEGER_ID | id,
+                ResourceId::Name(name) => {
+                    let at = (out.len() - table) as u16;
+                    out.push(name.len() as u8);
+                    out.extend(name.as_bytes());
+                    at
+                }
+            };
+            patch_u16(&mut out, info + 6, raw_id);
+        }
+        for (info, _, data) in infos {
+            out.resize(out.len().next_multiple_of(1 << SHIFT), 0);
+            let at = (out.len() >> SHIFT) as u16;
+            patch_u16(&mut out, info, at);
+            patch_u16(&mut out, info + 2, data.len().div_ceil(1 << SHIFT) as u16);
+            out.extend(data);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builder::*;
+    use super::*;
+
+    fn check(library: &IconLibrary) {
+        let ids: Vec<_> = library.groups().iter().map(|g| g.id.to_string()).collect();
+        assert_eq!(ids, ["#1", "FOLDER"]);
+        assert_eq!(library.group_at_index(1).unwrap().entries.len(), 1);
+        assert_eq!(library.group_at_index(-1), library.groups().first());
+        assert_eq!(library.group_at_index(2), None);
+        assert_eq!(library.group_at_index(-2), None);
+
+        let main = library.group(&ResourceId::Id(1)).unwrap();
+        assert_eq!(main.entries[2].width, 256);
+        let set = library.icon_set(main).unwrap();
+        let sizes: Vec<_> = set.iter().map(|(size, _)| size.dimension()).collect();
+        assert_eq!(sizes, [16, 32, 256]);
+        let pixel = |size| {
+            set.get_image(size)
+                .unwrap()
+                .image
+                .to_rgba8()
+                .get_pixel(0, 0)
+                .0
+        };
+        assert_eq!(pixel(WindowsIconSize::Px16), [255, 0, 0, 255]);
+        assert_eq!(pixel(WindowsIconSize::Px32), [0, 255, 0, 255]);
+        assert_eq!(pixel(WindowsIconSize::Px256), [9, 8, 7, 255]);
+
+        let ico = library.to_ico(main).unwrap();
+        assert_eq!(u16::from_le_bytes([ico[4], ico[5]]), 4);
+        let largest = image::load_from_memory_with_format(&ico, image::ImageFormat::Ico).unwrap();
+        assert_eq!(largest.width(), 256);
+    }
+
+    #[test]
+    fn reads_pe32_and_pe32_plus_files() {
+        check(&IconLibrary::parse(&pe(&fixture_resources(), false)).unwrap());
+        check(&IconLibrary::parse(&pe(&fixture_resources(), true)).unwrap());
+    }
+
+    #[test]
+    fn reads_ne_icon_libraries() {
+        check(&IconLibrary::parse(&ne(&fixture_resources())).unwrap());
+    }
+
+    #[test]
+    fn reads_ico_files_as_one_group() {
+        let library = IconLibrary::parse(&pe(&fixture_resources(), false)).unwrap();
+        let ico = library.to_ico(library.group_at_index(0).unwrap()).unwrap();
+
+        let ico_library = IconLibrary::parse(&ico).unwrap();
+        assert_eq!(ico_library.groups().len(), 1);
+        let group = ico_library.group_at_index(0).unwrap();
+        assert_eq!(group.id, ResourceId::Id(1));
+        let images = ico_library.images(group).unwrap();
+        let sizes: Vec<_> = images.iter().map(|image| image.width()).collect();
+        assert_eq!(sizes, [16, 32, 256]);
+        assert_eq!(images[0].to_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+
+        assert!(IconLibrary::parse(&ico[..ico.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn parses_group_directories() {
+        let entries = parse_group_directory(&group(&[(48, 32, 7, 9640), (0, 32, 8, 12)])).unwrap();
+        assert_eq!(
+            entries[0],
+            IconGroupEntry {
+                width: 48,
+                height: 48,
+                color_count: 0,
+                planes: 1,
+                bit_count: 32,
+                bytes_in_res: 9640,
+                icon_id: 7,
+            }
+        );
+        assert_eq!((entries[1].width, entries[1].height), (256, 256));
+
+        let mut cursor = group(&[(32, 32, 1, 10)]);
+        cursor[2] = 2;
+        assert!(parse_group_directory(&cursor).is_err());
+        let truncated = group(&[(32, 32, 1, 10)]);
+        assert!(parse_group_directory(&truncated[..truncated.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_other_files_and_broken_groups() {
+        assert!(IconLibrary::parse(b"#!/bin/sh\n").is_err());
+        assert!(IconLibrary::parse(&dos_header(0x40)).is_err());
+
+        // A group pointing at an icon that is not there.
+        let mut resources = fixture_resources();
+        resources.retain(|(kind, id, _)| !(*kind == RT_ICON && *id == ResourceId::Id(2)));
+        let library = IconLibrary::parse(&pe(&resources, false)).unwrap();
+        let folder = library
+            .group(&ResourceId::Name("FOLDER".to_string()))
+            .unwrap();
+        assert!(library.to_ico(folder).is_err());
+        assert!(library.icon_set(folder).is_err());
+
+        // A resource section whose file offset is out of range.
+        let mut image = pe(&fixture_resources(), false);
+        let section = image.windows(5).position(|name| name == b".rsrc").unwrap();
+        image[section + 20..section + 24].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        assert!(IconLibrary::parse(&image).is_err());
+    }
+}
@@ -1,5 +1,23 @@
+mod app_icon;
+pub mod desktop_ini;
+pub mod desktop_ini_provider;
+pub mod dos_attributes;
 pub mod error;
+pub mod ico;
+pub mod icon_library;
+pub mod shortcut;
+// Only plist reading uses it outside Linux.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod xml;
+pub use desktop_ini::{DesktopIni, DesktopIniFolderMetadata};
+pub use desktop_ini_provider::DesktopIniFolderSettingsProvider;
+pub use dos_attributes::{
+    DosAttributeStore, DosAttributes, read_dos_attributes, write_dos_attributes,
+};
 pub use error::{FolderSettingsError, Result};
+pub use ico::{encode_ico, write_ico};
+pub use icon_library::{IconGroup, IconGroupEntry, IconLibrary, ResourceId};
+pub use shortcut::{IconLocation, InternetShortcut, ShellLink};
 use std::path::Path;
 
 use crate::api::IconSet;
@@ -13,10 +31,42 @@ pub trait FolderSettingsProvider {
     fn reset_icon_for_folder<P: AsRef<Path>>(&self, path: P) -> Result<()>;
 }
 
+/// Provides folder display name and tooltip operations
+pub trait FolderMetadata {
+    /// Get the name shown for a folder in place of its file name, if any
+    fn display_name<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>>;
+    /// Set the name shown for a folder. `None` restores the file name
+    fn set_display_name<P: AsRef<Path>>(&self, path: P, name: Option<&str>) -> Result<()>;
+    /// Get the tooltip shown when hovering over a folder, if any
+    fn tooltip<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>>;
+    /// Set the tooltip shown when hovering over a folder. `None` removes it
+    fn set_tooltip<P: AsRef<Path>>(&self, path: P, tooltip: Option<&str>) -> Result<()>;
+}
+
 /// Provides default system folder icon operations
 pub trait DefaultFolderIconProvider {
     /// Dump the default folder icon
     fn dump_default_folder_icon(&self) -> Result<IconSet>;
+    /// Replace the default folder icon everywhere, not just for one folder
+    fn set_default_folder_icon(&self, _icon_set: &IconSet) -> Result<()> {
+        Err(FolderSettingsError::Unsupported(
+            "replacing the default folder icon".to_string(),
+        ))
+    }
+    /// Undo `set_default_folder_icon`, restoring the system's folder icon
+    fn restore_default_folder_icon(&self) -> Result<()> {
+        Err(FolderSettingsError::Unsupported(
+            "restoring the default folder icon".to_string(),
+        ))
+    }
+}
+
+/// Provides default system file icon operations
+pub trait DefaultFileIconProvider {
+    /// Dump the default icon for a file, based on its type
+    fn dump_default_file_icon<P: AsRef<Path>>(&self, path: P) -> Result<IconSet>;
+    /// Dump the default icon for a MIME type such as `application/pdf`
+    fn dump_default_mime_type_icon(&self, mime_type: &str) -> Result<IconSet>;
 }
 
 pub mod sys {
@@ -44,6 +94,7 @@ pub use sys::macos::{
 
 #[cfg(target_os = "linux")]
 pub use sys::linux::{
+    LinuxDefaultFileIconProvider as PlatformDefaultFileIconProvider,
     LinuxDefaultFolderIconProvider as PlatformDefaultFolderIconProvider,
     LinuxFolderSettingsProvider as PlatformFolderSettingsProvider,
 };
@@ -0,0 +1,794 @@
+use std::fmt;
+use std::path::Path;
+
+use super::DesktopIni;
+use super::error::{FolderSettingsError, Result};
+
+/// Section of a `.url` file holding the target and icon.
+const INTERNET_SHORTCUT: &str = "InternetShortcut";
+
+/// Size of the Shell Link header, which is also its first field.
+const HEADER_SIZE: usize = 0x4C;
+/// `00021401-0000-0000-C000-000000000046`, as stored in the header.
+const LINK_CLSID: [u8; 16] = [
+    0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+
+const FLAGS_OFFSET: usize = 0x14;
+const FILE_ATTRIBUTES_OFFSET: usize = 0x18;
+const ICON_INDEX_OFFSET: usize = 0x38;
+const SHOW_COMMAND_OFFSET: usize = 0x3C;
+
+// LinkFlags.
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x1;
+const HAS_LINK_INFO: u32 = 0x2;
+const HAS_NAME: u32 = 0x4;
+const HAS_RELATIVE_PATH: u32 = 0x8;
+const HAS_WORKING_DIR: u32 = 0x10;
+const HAS_ARGUMENTS: u32 = 0x20;
+const HAS_ICON_LOCATION: u32 = 0x40;
+const IS_UNICODE: u32 = 0x80;
+const HAS_EXP_ICON: u32 = 0x4000;
+
+/// Flags of the StringData fields, in the order they are stored.
+const STRING_FLAGS: [u32; 5] = [
+    HAS_NAME,
+    HAS_RELATIVE_PATH,
+    HAS_WORKING_DIR,
+    HAS_ARGUMENTS,
+    HAS_ICON_LOCATION,
+];
+const NAME: usize = 0;
+const WORKING_DIR: usize = 2;
+const ARGUMENTS: usize = 3;
+const ICON_LOCATION: usize = 4;
+
+// LinkInfoFlags.
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+const COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX: u32 = 0x2;
+
+/// Signature of the IconEnvironmentDataBlock, which holds the icon path
+/// with unexpanded environment variables.
+const ICON_ENVIRONMENT_SIGNATURE: u32 = 0xA000_0007;
+/// Offset of the UTF-16 path in an IconEnvironmentDataBlock, after the
+/// size, signature and 260-byte ANSI path.
+const ICON_ENVIRONMENT_UNICODE_OFFSET: usize = 8 + 260;
+
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+const DRIVE_FIXED: u32 = 3;
+const SW_SHOWNORMAL: u32 = 1;
+
+/// Where a Windows shortcut takes its icon from: an `.ico`, `.exe` or
+/// `.dll` file and the index of the icon in it. Negative indices are
+/// resource IDs, as in `imageres.dll,-3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconLocation {
+    pub path: String,
+    pub index: i32,
+}
+
+impl IconLocation {
+    pub fn new(path: impl Into<String>, index: i32) -> Self {
+        Self {
+            path: path.into(),
+            index,
+        }
+    }
+
+    /// Parse the `path,index` form used by `desktop.ini` and the registry.
+    /// Without a valid index, the whole value is the path and the index is 0.
+    pub fn parse(value: &str) -> Self {
+        match value.rsplit_once(',') {
+            Some((path, index)) if index.trim().parse::<i32>().is_ok() => {
+                Self::new(path.trim(), index.trim().parse().unwrap_or_default())
+            }
+            _ => Self::new(value.trim(), 0),
+        }
+    }
+}
+
+impl fmt::Display for IconLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.path, self.index)
+    }
+}
+
+/// A Windows Internet shortcut (`.url` file), read and written on any
+/// platform.
+///
+/// The target is the `URL=` of the `[InternetShortcut]` section and the
+/// icon its `IconFile=` and `IconIndex=`. Everything else in the file, such
+/// as the property-store sections Explorer adds, is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InternetShortcut {
+    ini: DesktopIni,
+}
+
+impl InternetShortcut {
+    /// A shortcut to `url`, e.g. `https://example.com/` or
+    /// `file:///C:/Projects`.
+    pub fn new(url: &str) -> Self {
+        let mut ini = DesktopIni::default();
+        ini.set(INTERNET_SHORTCUT, "URL", url);
+        Self { ini }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            ini: DesktopIni::from_bytes(bytes),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.ini.to_bytes()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        DesktopIni::load(path).map(|ini| Self { ini })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.ini.save(path)
+    }
+
+    pub fn url(&self) -> Option<String> {
+        self.ini.get(INTERNET_SHORTCUT, "URL")
+    }
+
+    pub fn set_url(&mut self, url: &str) {
+        self.ini.set(INTERNET_SHORTCUT, "URL", url);
+    }
+
+    /// The custom icon, if `IconFile=` is set. A missing `IconIndex=` means 0.
+    pub fn icon_location(&self) -> Option<IconLocation> {
+        let path = self
+            .ini
+            .get(INTERNET_SHORTCUT, "IconFile")
+            .filter(|p| !p.is_empty())?;
+        let index = self
+            .ini
+            .get(INTERNET_SHORTCUT, "IconIndex")
+            .and_then(|i| i.trim().parse().ok())
+            .unwrap_or(0);
+        Some(IconLocation::new(path, index))
+    }
+
+    /// Set the custom icon, or with `None` go back to the browser's icon.
+    pub fn set_icon_location(&mut self, location: Option<&IconLocation>) {
+        match location {
+            Some(location) => {
+                self.ini.set(INTERNET_SHORTCUT, "IconFile", &location.path);
+                self.ini
+                    .set(INTERNET_SHORTCUT, "IconIndex", &location.index.to_string());
+            }
+            None => {
+                self.ini.remove(INTERNET_SHORTCUT, "IconFile");
+                self.ini.remove(INTERNET_SHORTCUT, "IconIndex");
+            }
+        }
+    }
+}
+
+/// A Windows shortcut (`.lnk` file) in the Shell Link binary format
+/// (MS-SHLLINK), read and written on any platform.
+///
+/// Parts this type does not interpret (the target ID list, link info and
+/// extra data blocks) are kept as they are, so editing the icon of a link
+/// created by Explorer changes nothing else. Strings are written as UTF-16
+/// whenever one of them is not ASCII; 8-bit strings of existing links are
+/// read as Latin-1, since the code page they were written in is unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellLink {
+    header: [u8; HEADER_SIZE],
+    /// The IDList, without its size field.
+    id_list: Option<Vec<u8>>,
+    /// The whole LinkInfo structure.
+    link_info: Option<Vec<u8>>,
+    /// StringData fields, indexed like `STRING_FLAGS`.
+    strings: [Option<String>; 5],
+    /// Whole extra data blocks, without the terminal block.
+    extra_data: Vec<Vec<u8>>,
+}
+
+impl ShellLink {
+    /// A shortcut to the absolute Windows path `target`, either on a drive
+    /// (`C:\Projects\report.docx`) or a share (`\\server\share\Projects`).
+    /// `is_folder` tells Explorer what to show before it resolves the target.
+    pub fn new(target: &str, is_folder: bool) -> Result<Self> {
+        let link_info = build_link_info(target)?;
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[4..20].copy_from_slice(&LINK_CLSID);
+        let attributes = if is_folder {
+            FILE_ATTRIBUTE_DIRECTORY
+        } else {
+            FILE_ATTRIBUTE_ARCHIVE
+        };
+        let mut link = Self {
+            header,
+            id_list: None,
+            link_info: Some(link_info),
+            strings: Default::default(),
+            extra_data: Vec::new(),
+        };
+        link.set_u32(FILE_ATTRIBUTES_OFFSET, attributes);
+        link.set_u32(SHOW_COMMAND_OFFSET, SW_SHOWNORMAL);
+        Ok(link)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let header: [u8; HEADER_SIZE] = reader
+            .take(HEADER_SIZE)?
+            .try_into()
+            .expect("took exactly the header size");
+        if read_u32(&header, 0) != HEADER_SIZE as u32 || header[4..20] != LINK_CLSID {
+            return Err(FolderSettingsError::ShellLink(
+                "not a shell link".to_string(),
+            ));
+        }
+        let flags = read_u32(&header, FLAGS_OFFSET);
+
+        let id_list = if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+            let size = reader.u16()? as usize;
+            Some(reader.take(size)?.to_vec())
+        } else {
+            None
+        };
+        let link_info = if flags & HAS_LINK_INFO != 0 {
+            let size = read_u32(reader.peek(4)?, 0) as usize;
+            if size < 4 {
+                return Err(FolderSettingsError::ShellLink(
+                    "LinkInfo is too small".to_string(),
+                ));
+            }
+            Some(reader.take(size)?.to_vec())
+        } else {
+            None
+        };
+
+        let mut strings: [Option<String>; 5] = Default::default();
+        for (string, flag) in strings.iter_mut().zip(STRING_FLAGS) {
+            if flags & flag == 0 {
+                continue;
+            }
+            let count = reader.u16()? as usize;
+            *string = Some(if flags & IS_UNICODE != 0 {
+                decode_utf16(reader.take(count * 2)?)
+            } else {
+                decode_latin1(reader.take(count)?)
+            });
+        }
+
+        // Blocks run until the terminal block, whose size is below 4.
+        // Links that end without one are accepted.
+        let mut extra_data = Vec::new();
+        while let Ok(size) = reader.peek(4).map(|size| read_u32(size, 0) as usize) {
+            if size < 4 {
+                break;
+            }
+            extra_data.push(reader.take(size)?.to_vec());
+        }
+
+        Ok(Self {
+            header,
+            id_list,
+            link_info,
+            strings,
+            extra_data,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let unicode =
+            self.flags() & IS_UNICODE != 0 || self.strings.iter().flatten().any(|s| !s.is_ascii());
+        let mut flags = self.flags()
+            & !(HAS_LINK_TARGET_ID_LIST | HAS_LINK_INFO | IS_UNICODE)
+            & !STRING_FLAGS.iter().fold(0, |all, flag| all | flag);
+        if self.id_list.is_some() {
+            flags |= HAS_LINK_TARGET_ID_LIST;
+        }
+        if self.link_info.is_some() {
+            flags |= HAS_LINK_INFO;
+        }
+        if unicode {
+            flags |= IS_UNICODE;
+        }
+        for (string, flag) in self.strings.iter().zip(STRING_FLAGS) {
+            if string.is_some() {
+                flags |= flag;
+            }
+        }
+
+        let mut out = self.header.to_vec();
+        out[FLAGS_OFFSET..FLAGS_OFFSET + 4].copy_from_slice(&flags.to_le_bytes());
+        if let Some(id_list) = &self.id_list {
+            let size = u16::try_from(id_list.len())
+                .map_err(|_| FolderSettingsError::ShellLink("IDList is too large".to_string()))?;
+            out.extend_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(id_list);
+        }
+        if let Some(link_info) = &self.link_info {
+            out.extend_from_slice(link_info);
+        }
+        for string in self.strings.iter().flatten() {
+            let encoded = if unicode {
+                string.encode_utf16().flat_map(u16::to_le_bytes).collect()
+            } else {
+                string.as_bytes().to_vec()
+            };
+            let count = if unicode {
+                encoded.len() / 2
+            } else {
+                encoded.len()
+            };
+            let count = u16::try_from(count).map_err(|_| {
+                FolderSettingsError::ShellLink(format!("string is too long: {string:?}"))
+            })?;
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(&encoded);
+        }
+        for block in &self.extra_data {
+            out.extend_from_slice(block);
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+        Ok(out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).map_err(|e| FolderSettingsError::Io(path.to_path_buf(), e))?;
+        Self::parse(&bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?)
+            .map_err(|e| FolderSettingsError::Io(path.to_path_buf(), e))
+    }
+
+    /// The target path recorded in the link info, if there is one. Links
+    /// that only have an ID list (e.g. to Control Panel items) have none.
+    pub fn target(&self) -> Option<String> {
+        let info = self.link_info.as_deref()?;
+        let header_size = read_u32_at(info, 4)? as usize;
+        let flags = read_u32_at(info, 8)?;
+        let unicode_offset = |field: usize| {
+            (header_size >= 0x24)
+                .then(|| read_u32_at(info, field))
+                .flatten()
+                .filter(|&offset| offset != 0)
+        };
+        let suffix = unicode_offset(0x20)
+            .map(|offset| utf16z_at(info, offset as usize))
+            .or_else(|| Some(ansiz_at(info, read_u32_at(info, 0x18)? as usize)))
+            .unwrap_or_default();
+
+        let base = if flags & VOLUME_ID_AND_LOCAL_BASE_PATH != 0 {
+            unicode_offset(0x1C)
+                .map(|offset| utf16z_at(info, offset as usize))
+                .or_else(|| Some(ansiz_at(info, read_u32_at(info, 0x10)? as usize)))?
+        } else if flags & COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX != 0 {
+            let link = info.get(read_u32_at(info, 0x14)? as usize..)?;
+            let net_name_offset = read_u32_at(link, 8)? as usize;
+            if net_name_offset > 0x14 {
+                utf16z_at(link, read_u32_at(link, 0x14)? as usize)
+            } else {
+                ansiz_at(link, net_name_offset)
+            }
+        } else {
+            return None;
+        };
+        Some(if suffix.is_empty() {
+            base
+        } else if base.ends_with('\\') {
+            format!("{base}{suffix}")
+        } else {
+            format!("{base}\\{suffix}")
+        })
+    }
+
+    /// The custom icon, if the link has one. A path with environment
+    /// variables (such as `%SystemRoot%`) is returned unexpanded.
+    pub fn icon_location(&self) -> Option<IconLocation> {
+        let index = read_u32(&self.header, ICON_INDEX_OFFSET) as i32;
+        let expandable = (self.flags() & HAS_EXP_ICON != 0)
+            .then(|| self.icon_environment_block())
+            .flatten()
+            .map(|block| {
+                let unicode = utf16z_at(block, ICON_ENVIRONMENT_UNICODE_OFFSET);
+                if unicode.is_empty() {
+                    ansiz_at(block, 8)
+                } else {
+                    unicode
+                }
+            })
+            .filter(|path| !path.is_empty());
+        let path = expandable.or_else(|| self.strings[ICON_LOCATION].clone())?;
+        Some(IconLocation::new(path, index))
+    }
+
+    /// Set the custom icon, or with `None` go back to the target's icon.
+    pub fn set_icon_location(&mut self, location: Option<&IconLocation>) {
+        // An expandable icon path would take precedence over the new one.
+        self.extra_data
+            .retain(|block| read_u32_at(block, 4) != Some(ICON_ENVIRONMENT_SIGNATURE));
+        let flags = self.flags() & !HAS_EXP_ICON;
+        self.set_u32(FLAGS_OFFSET, flags);
+        self.strings[ICON_LOCATION] = location.map(|l| l.path.clone());
+        self.set_u32(ICON_INDEX_OFFSET, location.map_or(0, |l| l.index) as u32);
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.strings[NAME].as_deref()
+    }
+
+    pub fn arguments(&self) -> Option<&str> {
+        self.strings[ARGUMENTS].as_deref()
+    }
+
+    pub fn working_dir(&self) -> Option<&str> {
+        self.strings[WORKING_DIR].as_deref()
+    }
+
+    /// The comment shown as the shortcut's tooltip.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.strings[NAME] = Some(description.to_string());
+        self
+    }
+
+    pub fn with_arguments(mut self, arguments: &str) -> Self {
+        self.strings[ARGUMENTS] = Some(arguments.to_string());
+        self
+    }
+
+    pub fn with_working_dir(mut self, working_dir: &str) -> Self {
+        self.strings[WORKING_DIR] = Some(working_dir.to_string());
+        self
+    }
+
+    fn flags(&self) -> u32 {
+        read_u32(&self.header, FLAGS_OFFSET)
+    }
+
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn icon_environment_block(&self) -> Option<&[u8]> {
+        self.extra_data
+            .iter()
+            .find(|block| read_u32_at(block, 4) == Some(ICON_ENVIRONMENT_SIGNATURE))
+            .map(Vec::as_slice)
+    }
+}
+
+/// Build a LinkInfo structure for an absolute Windows path. Paths that
+/// aren't ASCII also get the optional Unicode fields.
+fn build_link_info(target: &str) -> Result<Vec<u8>> {
+    let unicode = !target.is_ascii();
+    let header_size: u32 = if unicode { 0x24 } else { 0x1C };
+
+    let (flags, location, suffix) = if let Some(unc) = target.strip_prefix(r"\\") {
+        // \\server\share is the net name; the rest is the suffix.
+        let mut parts = unc.splitn(3, '\\');
+        let (Some(server), Some(share)) = (parts.next(), parts.next()) else {
+            return Err(not_absolute(target));
+        };
+        if server.is_empty() || share.is_empty() {
+            return Err(not_absolute(target));
+        }
+        let net_name = format!(r"\\{server}\{share}");
+        let suffix = parts.next().unwrap_or("");
+        (
+            COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX,
+            common_network_relative_link(&net_name),
+            suffix,
+        )
+    } else {
+        let bytes = target.as_bytes();
+        if bytes.len() < 3 || !bytes[0].is_ascii_alphabetic() || &bytes[1..3] != b":\\" {
+            return Err(not_absolute(target));
+        }
+        (VOLUME_ID_AND_LOCAL_BASE_PATH, volume_id(), target)
+    };
+
+    let mut body = Vec::new();
+    let location_offset = header_size;
+    body.extend_from_slice(&location);
+    let local_base_path_offset = header_size + body.len() as u32;
+    if flags == VOLUME_ID_AND_LOCAL_BASE_PATH {
+        body.extend_from_slice(&encode_ansiz(target));
+    }
+    let suffix_offset = header_size + body.len() as u32;
+    body.extend_from_slice(&encode_ansiz(if flags == VOLUME_ID_AND_LOCAL_BASE_PATH {
+        ""
+    } else {
+        suffix
+    }));
+    let (mut local_base_path_offset_unicode, mut suffix_offset_unicode) = (0, 0);
+    if unicode {
+        if flags == VOLUME_ID_AND_LOCAL_BASE_PATH {
+            local_base_path_offset_unicode = header_size + body.len() as u32;
+            body.extend_from_slice(&encode_utf16z(target));
+        }
+        suffix_offset_unicode = header_size + body.len() as u32;
+        body.extend_from_slice(&encode_utf16z(if flags == VOLUME_ID_AND_LOCAL_BASE_PATH {
+            ""
+        } else {
+            suffix
+        }));
+    }
+
+    let (volume_id_offset, local_base_path_offset, network_offset) =
+        if flags == VOLUME_ID_AND_LOCAL_BASE_PATH {
+            (location_offset, local_base_path_offset, 0)
+        } else {
+            (0, 0, location_offset)
+        };
+    let mut info = Vec::new();
+    let size = header_size + body.len() as u32;
+    for field in [
+        size,
+        header_size,
+        flags,
+        volume_id_offset,
+        local_base_path_offset,
+        network_offset,
+        suffix_offset,
+    ] {
+        info.extend_from_slice(&field.to_le_bytes());
+    }
+    if unicode {
+        info.extend_from_slice(&local_base_path_offset_unicode.to_le_bytes());
+        info.extend_from_slice(&suffix_offset_unicode.to_le_bytes());
+    }
+    info.extend_from_slice(&body);
+    Ok(info)
+}
+
+/// A VolumeID for a fixed drive with no serial number or label; Windows
+/// resolves the link by path.
+fn volume_id() -> Vec<u8> {
+    let mut volume = Vec::new();
+    for field in [0x11, DRIVE_FIXED, 0, 0x10] {
+        volume.extend_from_slice(&u32::to_le_bytes(field));
+    }
+    volume.push(0);
+    volume
+}
+
+/// A CommonNetworkRelativeLink naming the share, with the Unicode net name
+/// when it isn't ASCII.
+fn common_network_relative_link(net_name: &str) -> Vec<u8> {
+    let unicode = !net_name.is_ascii();
+    let header_size: u32 = if unicode { 0x1C } else { 0x14 };
+    let ansi = encode_ansiz(net_name);
+    let mut fields = vec![0, 0, header_size, 0, 0];
+    if unicode {
+        fields.push(header_size + ansi.len() as u32);
+        fields.push(0);
+    }
+    let mut link: Vec<u8> = fields.iter().flat_map(|f: &u32| f.to_le_bytes()).collect();
+    link.extend_from_slice(&ansi);
+    if unicode {
+        link.extend_from_slice(&encode_utf16z(net_name));
+    }
+    let size = link.len() as u32;
+    link[..4].copy_from_slice(&size.to_le_bytes());
+    link
+}
+
+fn not_absolute(target: &str) -> FolderSettingsError {
+    FolderSettingsError::ShellLink(format!("{target} is not an absolute Windows path"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self, len: usize) -> Result<&'a [u8]> {
+        self.bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| FolderSettingsError::ShellLink("truncated shell link".to_string()))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.peek(len)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    read_u32_at(bytes, offset).unwrap_or(0)
+}
+
+fn read_u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// The NUL-terminated 8-bit string at `offset`.
+fn ansiz_at(bytes: &[u8], offset: usize) -> String {
+    let rest = bytes.get(offset..).unwrap_or_default();
+    let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    decode_latin1(&rest[..end])
+}
+
+/// The NUL-terminated UTF-16 string at `offset`.
+fn utf16z_at(bytes: &[u8], offset: usize) -> String {
+    let rest = bytes.get(offset..).unwrap_or_default();
+    let end = rest
+        .chunks_exact(2)
+        .position(|pair| pair == [0, 0])
+        .map_or(rest.len() / 2 * 2, |i| i * 2);
+    decode_utf16(&rest[..end])
+}
+
+/// `text` as a NUL-terminated 8-bit string; characters outside Latin-1
+/// become `?`, as in a lossy code page conversion.
+fn encode_ansiz(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+        .chain([0])
+        .collect()
+}
+
+fn encode_utf16z(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_locations_parse_and_format() {
+        let location = IconLocation::parse(r"C:\Windows\System32\imageres.dll,-3");
+        assert_eq!(location.path, r"C:\Windows\System32\imageres.dll");
+        assert_eq!(location.index, -3);
+        assert_eq!(location.to_string(), r"C:\Windows\System32\imageres.dll,-3");
+        assert_eq!(
+            IconLocation::parse(r"C:\Icons\a,b.ico"),
+            IconLocation::new(r"C:\Icons\a,b.ico", 0)
+        );
+    }
+
+    #[test]
+    fn internet_shortcut_icon_round_trip() {
+        let original = "[{000214A0-0000-0000-C000-000000000046}]\r\nProp3=19,11\r\n\
+                        [InternetShortcut]\r\nIDList=\r\nURL=https://example.com/\r\n";
+        let mut shortcut = InternetShortcut::from_bytes(original.as_bytes());
+        assert_eq!(shortcut.url().as_deref(), Some("https://example.com/"));
+        assert_eq!(shortcut.icon_location(), None);
+
+        shortcut.set_icon_location(Some(&IconLocation::new(r"C:\Icons\site.ico", 0)));
+        let text = String::from_utf8(shortcut.to_bytes()).unwrap();
+        assert!(text.starts_with("[{000214A0"));
+        assert!(text.ends_with("IconFile=C:\\Icons\\site.ico\r\nIconIndex=0\r\n"));
+        assert_eq!(
+            InternetShortcut::from_bytes(text.as_bytes()).icon_location(),
+            Some(IconLocation::new(r"C:\Icons\site.ico", 0))
+        );
+
+        shortcut.set_icon_location(None);
+        assert_eq!(String::from_utf8(shortcut.to_bytes()).unwrap(), original);
+    }
+
+    #[test]
+    fn new_internet_shortcut() {
+        let shortcut = InternetShortcut::new("file:///C:/Projects");
+        assert_eq!(
+            String::from_utf8(shortcut.to_bytes()).unwrap(),
+            "[InternetShortcut]\r\nURL=file:///C:/Projects\r\n"
+        );
+    }
+
+    #[test]
+    fn shell_link_to_a_local_folder() {
+        let link = ShellLink::new(r"C:\Users\ada\Projects", true)
+            .unwrap()
+            .with_description("Projects")
+            .with_working_dir(r"C:\Users\ada");
+        let bytes = link.to_bytes().unwrap();
+        assert_eq!(
+            read_u32(&bytes, FLAGS_OFFSET),
+            HAS_LINK_INFO | HAS_NAME | HAS_WORKING_DIR
+        );
+        assert_eq!(
+            read_u32(&bytes, FILE_ATTRIBUTES_OFFSET),
+            FILE_ATTRIBUTE_DIRECTORY
+        );
+        assert_eq!(&bytes[bytes.len() - 4..], &[0, 0, 0, 0]);
+
+        let parsed = ShellLink::parse(&bytes).unwrap();
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+        assert_eq!(parsed.target().as_deref(), Some(r"C:\Users\ada\Projects"));
+        assert_eq!(parsed.description(), Some("Projects"));
+        assert_eq!(parsed.icon_location(), None);
+    }
+
+    #[test]
+    fn shell_link_to_a_share_with_unicode_names() {
+        let link = ShellLink::new(r"\\nas\Öffentlich\Projekte\Bücher", true).unwrap();
+        let parsed = ShellLink::parse(&link.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            parsed.target().as_deref(),
+            Some(r"\\nas\Öffentlich\Projekte\Bücher")
+        );
+        assert_eq!(
+            ShellLink::new(r"\\nas", false).unwrap_err().to_string(),
+            r"invalid shell link: \\nas is not an absolute Windows path"
+        );
+        assert!(ShellLink::new("relative\\path", false).is_err());
+    }
+
+    #[test]
+    fn icon_location_replaces_the_expandable_icon() {
+        let mut link = ShellLink::new(r"C:\Tools\tool.exe", false).unwrap();
+        // An IconEnvironmentDataBlock as Explorer writes it.
+        let mut block = vec![0u8; 0x314];
+        block[..4].copy_from_slice(&0x314u32.to_le_bytes());
+        block[4..8].copy_from_slice(&ICON_ENVIRONMENT_SIGNATURE.to_le_bytes());
+        let path = encode_utf16z("%SystemRoot%\\system32\\shell32.dll");
+        block[ICON_ENVIRONMENT_UNICODE_OFFSET..ICON_ENVIRONMENT_UNICODE_OFFSET + path.len()]
+            .copy_from_slice(&path);
+        link.extra_data.push(block);
+        link.set_u32(FLAGS_OFFSET, HAS_LINK_INFO | HAS_EXP_ICON);
+        link.strings[ICON_LOCATION] = Some(r"C:\Windows\system32\shell32.dll".to_string());
+        link.set_u32(ICON_INDEX_OFFSET, 4);
+
+        let parsed = ShellLink::parse(&link.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            parsed.icon_location(),
+            Some(IconLocation::new(r"%SystemRoot%\system32\shell32.dll", 4))
+        );
+
+        let mut updated = parsed;
+        updated.set_icon_location(Some(&IconLocation::new(r"D:\Icons\tool.ico", -2)));
+        let bytes = updated.to_bytes().unwrap();
+        assert_eq!(read_u32(&bytes, FLAGS_OFFSET) & HAS_EXP_ICON, 0);
+        let reparsed = ShellLink::parse(&bytes).unwrap();
+        assert!(reparsed.extra_data.is_empty());
+        assert_eq!(
+            reparsed.icon_location(),
+            Some(IconLocation::new(r"D:\Icons\tool.ico", -2))
+        );
+    }
+
+    #[test]
+    fn malformed_links_are_errors() {
+        assert!(ShellLink::parse(b"not a link").is_err());
+        let bytes = ShellLink::new(r"C:\a", false)
+            .unwrap()
+            .with_arguments("--flag")
+            .to_bytes()
+            .unwrap();
+        assert!(ShellLink::parse(&bytes[..bytes.len() - 8]).is_err());
+    }
+}
@@ -1,6 +1,51 @@
 mod folder_settings_provider;
 pub use folder_settings_provider::{
-    LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    FailedAttempt, IconUpdate, LinuxBackend, LinuxFolderSettingsProvider,
+    LinuxFolderSettingsProviderExt, LinuxIconOutcome,
+};
+mod dconf;
+pub use dconf::DconfProfile;
+mod desktop_entry;
+pub use desktop_entry::DesktopEntry;
+mod desktop_launcher;
+pub use desktop_launcher::DesktopLauncher;
+mod desktop_detection;
+pub use desktop_detection::{
+    DesktopDetection, DesktopDetector, DesktopEnvironment, DetectionEvidence, DetectionSource,
+};
+mod file_manager_notifier;
+pub use file_manager_notifier::FileManagerNotifier;
+mod gvdb;
+mod hicolor_theme;
+pub use hicolor_theme::{HicolorTheme, IconContext};
+mod icon_theme;
+mod icon_theme_override;
+pub use icon_theme_override::IconThemeOverride;
+mod icon_theme_setting;
+pub use icon_theme_setting::IconThemeSetting;
+mod icon_theme_cache;
+pub use icon_theme_cache::{CachedIcon, IconThemeCache};
+mod kde_places;
+pub use kde_places::KdePlaces;
+mod gvfs_metadata;
+pub use gvfs_metadata::{GvfsMetadata, GvfsTransport};
+mod gvfs_metatree;
+pub use gvfs_metatree::{GvfsMetadataTree, MetadataValue};
+mod protected_folders;
+mod thumbnail_cache;
+pub use thumbnail_cache::ThumbnailCache;
+mod uri;
+mod windows_icon_import;
+pub use windows_icon_import::{SkippedFolder, WindowsFolderIconImport, WindowsIconImportReport};
+mod xdg;
+pub use protected_folders::{ProtectedFolderKind, ProtectedFolders};
+mod mime_database;
+pub use mime_database::{MagicValueType, MimeDatabase, MimeMagicMatch, MimeTypeDefinition};
+mod shared_mime_info;
+pub use shared_mime_info::SharedMimeInfo;
+mod default_file_icon_provider;
+pub use default_file_icon_provider::{
+    LinuxDefaultFileIconProvider, LinuxDefaultFileIconProviderExt,
 };
 mod default_folder_icon_provider;
 pub use default_folder_icon_provider::{
@@ -8,4 +53,4 @@ pub use default_folder_icon_provider::{
 };
 
 pub mod error;
-pub use error::LinuxFolderSettingsError;
+pub use error::{GvfsError, LinuxFolderSettingsError};
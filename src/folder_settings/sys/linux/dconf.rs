@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+
+use super::gvdb::{Gvdb, variant_str};
+use super::{LinuxFolderSettingsError, xdg};
+
+/// Where system databases compiled by `dconf update` live.
+const SYSTEM_DB_DIR: &str = "/etc/dconf/db";
+/// Where dconf profiles are looked up by name.
+const PROFILE_DIR: &str = "/etc/dconf/profile";
+
+/// The dconf databases a user's settings are read from, read directly from
+/// their GVDB files without `gsettings`, `dconf` or a D-Bus session.
+///
+/// A profile lists databases in priority order: normally the user database
+/// (`~/.config/dconf/user`) followed by system databases
+/// (`/etc/dconf/db/<name>`). The first database holding a key wins, unless a
+/// system database locks the key, in which case lookup starts there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DconfProfile {
+    databases: Vec<PathBuf>,
+}
+
+impl DconfProfile {
+    /// The profile named by `$DCONF_PROFILE` (default `user`), read from
+    /// `/etc/dconf/profile` or `$XDG_DATA_DIRS/dconf/profile`. Without a
+    /// profile file, only the user database is used.
+    pub fn for_current_user() -> Self {
+        let user_dir = xdg::config_home().unwrap_or_default().join("dconf");
+
+        let name = std::env::var("DCONF_PROFILE").unwrap_or_else(|_| "user".to_string());
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+        let candidates: Vec<PathBuf> = if Path::new(&name).is_absolute() {
+            vec![PathBuf::from(&name)]
+        } else {
+            std::iter::once(PathBuf::from(PROFILE_DIR).join(&name))
+                .chain(
+                    data_dirs
+                        .split(':')
+                        .filter(|d| !d.is_empty())
+                        .map(|d| Path::new(d).join("dconf/profile").join(&name)),
+                )
+                .collect()
+        };
+
+        let profile = candidates
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_else(|| "user-db:user".to_string());
+        Self::parse(&profile, &user_dir, Path::new(SYSTEM_DB_DIR))
+    }
+
+    /// Read from exactly these database files, highest priority first.
+    pub fn from_databases<I, P>(databases: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            databases: databases.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Parse profile lines such as `user-db:user`, `system-db:local` and
+    /// `file-db:/path`. `service-db` entries have no file and are skipped.
+    fn parse(profile: &str, user_dir: &Path, system_dir: &Path) -> Self {
+        let databases = profile
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter_map(|line| {
+                let (kind, name) = line.split_once(':')?;
+                match kind.trim() {
+                    "user-db" => Some(user_dir.join(name.trim())),
+                    "system-db" => Some(system_dir.join(name.trim())),
+                    "file-db" => Some(PathBuf::from(name.trim())),
+                    _ => None,
+                }
+            })
+            .collect();
+        Self { databases }
+    }
+
+    /// The database files, highest priority first.
+    pub fn databases(&self) -> &[PathBuf] {
+        &self.databases
+    }
+
+    /// Read a string key such as `/org/gnome/desktop/interface/icon-theme`.
+    /// Returns `None` if no database sets it (or it isn't a string).
+    /// Missing database files are skipped.
+    pub fn read_string(&self, key: &str) -> Result<Option<String>, LinuxFolderSettingsError> {
+        let databases = self
+            .databases
+            .iter()
+            .filter_map(|path| match std::fs::read(path) {
+                Ok(data) => Some(
+                    Gvdb::parse(data)
+                        .map(|db| (path, db))
+                        .map_err(|e| LinuxFolderSettingsError::Dconf(path.clone(), e)),
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => Some(Err(e.into())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let tables = databases
+            .iter()
+            .map(|(path, db)| {
+                db.root()
+                    .map_err(|e| LinuxFolderSettingsError::Dconf(path.to_path_buf(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A lock in any database hides the values of those above it.
+        let start = tables
+            .iter()
+            .position(|t| {
+                t.table(".locks")
+                    .is_some_and(|locks| locks.value(key).is_some())
+            })
+            .unwrap_or(0);
+
+        Ok(tables[start..]
+            .iter()
+            .find_map(|t| t.value(key))
+            .and_then(variant_str)
+            .map(str::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICON_THEME: &str = "/org/gnome/desktop/interface/icon-theme";
+
+    fn fixtures(names: &[&str]) -> DconfProfile {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dconf");
+        DconfProfile::from_databases(names.iter().map(|n| dir.join(n)))
+    }
+
+    #[test]
+    fn user_value_overrides_system_defaults() {
+        let profile = fixtures(&["user", "defaults"]);
+        assert_eq!(
+            profile.read_string(ICON_THEME).unwrap().as_deref(),
+            Some("Papirus")
+        );
+        assert_eq!(
+            profile
+                .read_string("/org/gnome/desktop/interface/cursor-theme")
+                .unwrap()
+                .as_deref(),
+            Some("DMZ")
+        );
+    }
+
+    #[test]
+    fn missing_user_database_falls_back_to_system() {
+        let profile = fixtures(&["does-not-exist", "defaults"]);
+        assert_eq!(
+            profile.read_string(ICON_THEME).unwrap().as_deref(),
+            Some("Yaru")
+        );
+    }
+
+    #[test]
+    fn locked_key_ignores_user_value() {
+        let profile = fixtures(&["user", "locked", "defaults"]);
+        assert_eq!(
+            profile.read_string(ICON_THEME).unwrap().as_deref(),
+            Some("Corporate")
+        );
+    }
+
+    #[test]
+    fn unset_key_is_none() {
+        let profile = fixtures(&["user"]);
+        assert_eq!(
+            profile
+                .read_string("/org/gnome/desktop/interface/font-name")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn corrupt_database_is_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user");
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(matches!(
+            DconfProfile::from_databases([&path]).read_string(ICON_THEME),
+            Err(LinuxFolderSettingsError::Dconf(..))
+        ));
+
+        // A valid header whose root table lies past the end of the file.
+        let mut header = b"GVariant".to_vec();
+        header.extend([0, 0, 0, 0, 0, 0, 0, 0]);
+        header.extend(1000u32.to_le_bytes());
+        header.extend(2000u32.to_le_bytes());
+        std::fs::write(&path, header).unwrap();
+        match DconfProfile::from_databases([&path]).read_string(ICON_THEME) {
+            Err(LinuxFolderSettingsError::Dconf(error_path, _)) => assert_eq!(error_path, path),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_profile_resolves_database_kinds() {
+        let profile = DconfProfile::parse(
+            "# site profile\nuser-db:user\nservice-db:keyfile/user\nsystem-db:local # comment\nfile-db:/opt/db\n",
+            Path::new("/home/ada/.config/dconf"),
+            Path::new("/etc/dconf/db"),
+        );
+        assert_eq!(
+            profile.databases(),
+            [
+                PathBuf::from("/home/ada/.config/dconf/user"),
+                PathBuf::from("/etc/dconf/db/local"),
+                PathBuf::from("/opt/db"),
+            ]
+        );
+    }
+}
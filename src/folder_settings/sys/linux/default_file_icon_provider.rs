@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use super::icon_theme::load_icon_set;
+use super::{LinuxFolderSettingsError, SharedMimeInfo};
+use crate::folder_settings::DefaultFileIconProvider;
+use crate::icon::sys::linux::LinuxIconSet;
+
+pub trait LinuxDefaultFileIconProviderExt {
+    /// Dump the icon the active icon theme shows for a file of this type,
+    /// detected from its name and contents.
+    fn dump_default_file_icon_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError>;
+
+    /// Dump the icon the active icon theme shows for a MIME type.
+    fn dump_default_mime_type_icon_linux(
+        &self,
+        mime_type: &str,
+    ) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError>;
+}
+
+/// Looks up file icons by MIME type through shared-mime-info and the
+/// active icon theme, the way file managers do.
+#[derive(Debug, Clone)]
+pub struct LinuxDefaultFileIconProvider {
+    mime_info: SharedMimeInfo,
+}
+
+impl Default for LinuxDefaultFileIconProvider {
+    /// Uses the current user's MIME database.
+    fn default() -> Self {
+        Self::new(SharedMimeInfo::for_current_user())
+    }
+}
+
+impl LinuxDefaultFileIconProvider {
+    pub fn new(mime_info: SharedMimeInfo) -> Self {
+        Self { mime_info }
+    }
+
+    pub fn mime_info(&self) -> &SharedMimeInfo {
+        &self.mime_info
+    }
+}
+
+impl LinuxDefaultFileIconProviderExt for LinuxDefaultFileIconProvider {
+    fn dump_default_file_icon_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError> {
+        let mime_type = self.mime_info.mime_type_for_path(path)?;
+        self.dump_default_mime_type_icon_linux(&mime_type)
+    }
+
+    fn dump_default_mime_type_icon_linux(
+        &self,
+        mime_type: &str,
+    ) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError> {
+        let names = self.mime_info.icon_names(mime_type);
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        load_icon_set(&names).ok_or_else(|| {
+            LinuxFolderSettingsError::Error(format!(
+                "could not locate an icon for {mime_type} in any installed theme"
+            ))
+        })
+    }
+}
+
+impl DefaultFileIconProvider for LinuxDefaultFileIconProvider {
+    fn dump_default_file_icon<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<crate::api::IconSet, crate::folder_settings::FolderSettingsError> {
+        let set = self.dump_default_file_icon_linux(path)?;
+        Ok(crate::api::IconSet::from(set))
+    }
+
+    fn dump_default_mime_type_icon(
+        &self,
+        mime_type: &str,
+    ) -> Result<crate::api::IconSet, crate::folder_settings::FolderSettingsError> {
+        let set = self.dump_default_mime_type_icon_linux(mime_type)?;
+        Ok(crate::api::IconSet::from(set))
+    }
+}
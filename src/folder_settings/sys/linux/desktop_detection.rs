@@ -0,0 +1,447 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::{LinuxBackend, LinuxFolderSettingsError};
+
+/// Desktop names recognized out of the box, matched case-insensitively
+/// against each detection source.
+const DEFAULT_DESKTOP_MAPPINGS: [(&str, LinuxBackend); 11] = [
+    ("gnome", LinuxBackend::GioMetadata),
+    ("cinnamon", LinuxBackend::GioMetadata),
+    ("mate", LinuxBackend::GioMetadata),
+    ("budgie", LinuxBackend::GioMetadata),
+    ("unity", LinuxBackend::GioMetadata),
+    ("ubuntu", LinuxBackend::GioMetadata),
+    ("pop", LinuxBackend::GioMetadata),
+    ("kde", LinuxBackend::DirectoryFile),
+    ("plasma", LinuxBackend::DirectoryFile),
+    ("xfce", LinuxBackend::DirectoryFile),
+    ("lxqt", LinuxBackend::DirectoryFile),
+];
+
+/// File manager process names recognized out of the box.
+const DEFAULT_FILE_MANAGER_MAPPINGS: [(&str, LinuxBackend); 7] = [
+    ("nautilus", LinuxBackend::GioMetadata),
+    ("nemo", LinuxBackend::GioMetadata),
+    ("caja", LinuxBackend::GioMetadata),
+    ("dolphin", LinuxBackend::DirectoryFile),
+    ("konqueror", LinuxBackend::DirectoryFile),
+    ("thunar", LinuxBackend::DirectoryFile),
+    ("pcmanfm-qt", LinuxBackend::DirectoryFile),
+];
+
+/// Where a piece of detection evidence was observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionSource {
+    /// `XDG_CURRENT_DESKTOP` (a colon-separated list of desktop names).
+    XdgCurrentDesktop,
+    /// `XDG_SESSION_DESKTOP`.
+    XdgSessionDesktop,
+    /// `DESKTOP_SESSION` (a session name, or a path to a session file).
+    DesktopSession,
+    /// `KDE_FULL_SESSION`, set to `true` inside Plasma sessions.
+    KdeFullSession,
+    /// `GNOME_DESKTOP_SESSION_ID`, set by older GNOME sessions.
+    GnomeDesktopSessionId,
+    /// A running file manager process.
+    FileManagerProcess,
+}
+
+/// A single observation made during desktop detection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectionEvidence {
+    pub source: DetectionSource,
+    /// The raw value observed, e.g. `"ubuntu:GNOME"` or `"dolphin"`.
+    pub value: String,
+    /// The mapping-table name this value matched, if any.
+    pub matched: Option<String>,
+}
+
+/// The result of a successful desktop detection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopDetection {
+    /// The backend mapped to the detected desktop.
+    pub backend: LinuxBackend,
+    /// The mapping-table name that decided the result.
+    pub desktop: String,
+    /// Every source that was present, in the order it was consulted. The
+    /// first entry with a match is the one that decided the result.
+    pub evidence: Vec<DetectionEvidence>,
+}
+
+/// A snapshot of the inputs desktop detection looks at.
+///
+/// Use [`DesktopEnvironment::current`] for the running session, or build one
+/// by hand to detect against a known environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEnvironment {
+    pub xdg_current_desktop: Option<String>,
+    pub xdg_session_desktop: Option<String>,
+    pub desktop_session: Option<String>,
+    pub kde_full_session: Option<String>,
+    pub gnome_desktop_session_id: Option<String>,
+    /// Names of running processes (as in `/proc/<pid>/comm`).
+    pub processes: Vec<String>,
+}
+
+impl DesktopEnvironment {
+    /// Capture the current process environment. Running processes are only
+    /// listed when no desktop variable is set; `/proc` is scanned once and
+    /// the result reused for the life of the process.
+    pub fn current() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let mut env = Self {
+            xdg_current_desktop: var("XDG_CURRENT_DESKTOP"),
+            xdg_session_desktop: var("XDG_SESSION_DESKTOP"),
+            desktop_session: var("DESKTOP_SESSION"),
+            kde_full_session: var("KDE_FULL_SESSION"),
+            gnome_desktop_session_id: var("GNOME_DESKTOP_SESSION_ID"),
+            processes: Vec::new(),
+        };
+        if !env.has_desktop_variables() {
+            env.processes = cached_process_names().to_vec();
+        }
+        env
+    }
+
+    fn has_desktop_variables(&self) -> bool {
+        [
+            &self.xdg_current_desktop,
+            &self.xdg_session_desktop,
+            &self.desktop_session,
+            &self.kde_full_session,
+            &self.gnome_desktop_session_id,
+        ]
+        .iter()
+        .any(|value| value.is_some())
+    }
+}
+
+/// Maps desktop environments to the folder icon backend they read.
+///
+/// The built-in table covers GNOME, Cinnamon, MATE, Budgie, Unity, KDE
+/// Plasma, XFCE and LXQt. Applications can register further desktops
+/// (Pantheon, Deepin, COSMIC, Enlightenment, ...) or override built-in ones;
+/// registered mappings take precedence over the defaults.
+///
+/// Sources are consulted in order: `XDG_CURRENT_DESKTOP`,
+/// `XDG_SESSION_DESKTOP`, `DESKTOP_SESSION`, `KDE_FULL_SESSION`,
+/// `GNOME_DESKTOP_SESSION_ID`, then running file manager processes.
+#[derive(Debug, Clone)]
+pub struct DesktopDetector {
+    /// Lowercase desktop name prefixes, most recently registered first.
+    desktops: Vec<(String, LinuxBackend)>,
+    /// Lowercase file manager process names, most recently registered first.
+    file_managers: Vec<(String, LinuxBackend)>,
+}
+
+impl Default for DesktopDetector {
+    fn default() -> Self {
+        Self {
+            desktops: DEFAULT_DESKTOP_MAPPINGS
+                .iter()
+                .map(|(name, backend)| (name.to_string(), *backend))
+                .collect(),
+            file_managers: DEFAULT_FILE_MANAGER_MAPPINGS
+                .iter()
+                .map(|(name, backend)| (name.to_string(), *backend))
+                .collect(),
+        }
+    }
+}
+
+impl DesktopDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a desktop name to a backend. The name is matched
+    /// case-insensitively as a prefix of each desktop component, so
+    /// `"pantheon"` also matches a `DESKTOP_SESSION` of `"pantheon-wayland"`.
+    ///
+    /// Mapping to [`LinuxBackend::Auto`] makes that desktop undetectable.
+    pub fn with_desktop(mut self, name: &str, backend: LinuxBackend) -> Self {
+        self.desktops
+            .insert(0, (name.to_ascii_lowercase(), backend));
+        self
+    }
+
+    /// Map a file manager process name to a backend, used when no
+    /// environment variable identifies the desktop.
+    pub fn with_file_manager(mut self, process: &str, backend: LinuxBackend) -> Self {
+        self.file_managers
+            .insert(0, (process.to_ascii_lowercase(), backend));
+        self
+    }
+
+    /// Detect the backend for the current session.
+    pub fn detect(&self) -> Result<DesktopDetection, LinuxFolderSettingsError> {
+        let mut env = DesktopEnvironment::current();
+        match self.detect_from(&env) {
+            // The variables named no known desktop; try the processes.
+            Err(LinuxFolderSettingsError::UndetectedDesktop) if env.processes.is_empty() => {
+                env.processes = cached_process_names().to_vec();
+                self.detect_from(&env)
+            }
+            result => result,
+        }
+    }
+
+    /// Detect the backend for a given environment snapshot.
+    pub fn detect_from(
+        &self,
+        env: &DesktopEnvironment,
+    ) -> Result<DesktopDetection, LinuxFolderSettingsError> {
+        let mut evidence = Vec::new();
+        let mut decision = None;
+
+        let mut observe = |source, value: &str, matched: Option<(String, LinuxBackend)>| {
+            if decision.is_none()
+                && let Some((name, backend)) = &matched
+                && *backend != LinuxBackend::Auto
+            {
+                decision = Some((name.clone(), *backend));
+            }
+            evidence.push(DetectionEvidence {
+                source,
+                value: value.to_string(),
+                matched: matched.map(|(name, _)| name),
+            });
+        };
+
+        let desktop_vars = [
+            (DetectionSource::XdgCurrentDesktop, &env.xdg_current_desktop),
+            (DetectionSource::XdgSessionDesktop, &env.xdg_session_desktop),
+            (DetectionSource::DesktopSession, &env.desktop_session),
+        ];
+        for (source, value) in desktop_vars {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                observe(source, value, self.match_desktop(value));
+            }
+        }
+
+        if let Some(value) = env.kde_full_session.as_deref()
+            && !value.is_empty()
+        {
+            let matched = value
+                .eq_ignore_ascii_case("true")
+                .then(|| self.match_desktop("kde"))
+                .flatten();
+            observe(DetectionSource::KdeFullSession, value, matched);
+        }
+
+        if let Some(value) = env.gnome_desktop_session_id.as_deref()
+            && !value.is_empty()
+        {
+            observe(
+                DetectionSource::GnomeDesktopSessionId,
+                value,
+                self.match_desktop("gnome"),
+            );
+        }
+
+        for process in &env.processes {
+            let process = process.to_ascii_lowercase();
+            if let Some((name, backend)) =
+                self.file_managers.iter().find(|(name, _)| *name == process)
+            {
+                observe(
+                    DetectionSource::FileManagerProcess,
+                    &process,
+                    Some((name.clone(), *backend)),
+                );
+            }
+        }
+
+        let (desktop, backend) = decision.ok_or(LinuxFolderSettingsError::UndetectedDesktop)?;
+        Ok(DesktopDetection {
+            backend,
+            desktop,
+            evidence,
+        })
+    }
+
+    /// Match a desktop variable's value against the mapping table. The value
+    /// may be a colon-separated list (`"Budgie:GNOME"`), carry an `X-` vendor
+    /// prefix (`"X-Cinnamon"`), or be a path to a session file.
+    fn match_desktop(&self, value: &str) -> Option<(String, LinuxBackend)> {
+        value
+            .split(':')
+            .map(|component| {
+                let component = Path::new(component)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or(component)
+                    .to_ascii_lowercase();
+                match component.strip_prefix("x-") {
+                    Some(stripped) => stripped.to_string(),
+                    None => component,
+                }
+            })
+            .find_map(|component| {
+                self.desktops
+                    .iter()
+                    .find(|(name, _)| component.starts_with(name.as_str()))
+                    .cloned()
+            })
+    }
+}
+
+/// The names of the processes running when first asked for.
+fn cached_process_names() -> &'static [String] {
+    static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    NAMES.get_or_init(|| running_process_names(Path::new("/proc")))
+}
+
+/// Names of running processes, read from `<proc>/<pid>/comm`.
+fn running_process_names(proc: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(proc) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().is_some_and(is_pid))
+        .filter_map(|e| std::fs::read_to_string(e.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect()
+}
+
+fn is_pid(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_with_current(desktop: &str) -> DesktopEnvironment {
+        DesktopEnvironment {
+            xdg_current_desktop: Some(desktop.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn xdg_current_desktop_takes_priority() {
+        let env = DesktopEnvironment {
+            xdg_current_desktop: Some("KDE".to_string()),
+            desktop_session: Some("gnome".to_string()),
+            ..Default::default()
+        };
+        let detection = DesktopDetector::default().detect_from(&env).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::DirectoryFile);
+        assert_eq!(detection.desktop, "kde");
+        // Both sources are reported, in the order they were consulted.
+        assert_eq!(detection.evidence.len(), 2);
+        assert_eq!(
+            detection.evidence[0].source,
+            DetectionSource::XdgCurrentDesktop
+        );
+        assert_eq!(detection.evidence[1].matched.as_deref(), Some("gnome"));
+    }
+
+    #[test]
+    fn desktop_session_path_and_suffix_are_matched() {
+        let env = DesktopEnvironment {
+            desktop_session: Some("/usr/share/xsessions/plasmawayland".to_string()),
+            ..Default::default()
+        };
+        let detection = DesktopDetector::default().detect_from(&env).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::DirectoryFile);
+        assert_eq!(detection.desktop, "plasma");
+    }
+
+    #[test]
+    fn kde_full_session_is_consulted() {
+        let env = DesktopEnvironment {
+            kde_full_session: Some("true".to_string()),
+            ..Default::default()
+        };
+        let detection = DesktopDetector::default().detect_from(&env).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::DirectoryFile);
+        assert_eq!(
+            detection.evidence[0].source,
+            DetectionSource::KdeFullSession
+        );
+    }
+
+    #[test]
+    fn gnome_desktop_session_id_is_consulted() {
+        let env = DesktopEnvironment {
+            gnome_desktop_session_id: Some("this-is-deprecated".to_string()),
+            ..Default::default()
+        };
+        let detection = DesktopDetector::default().detect_from(&env).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::GioMetadata);
+    }
+
+    #[test]
+    fn running_file_manager_is_last_resort() {
+        let env = DesktopEnvironment {
+            xdg_current_desktop: Some("Hyprland".to_string()),
+            processes: vec!["bash".to_string(), "thunar".to_string()],
+            ..Default::default()
+        };
+        let detection = DesktopDetector::default().detect_from(&env).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::DirectoryFile);
+        assert_eq!(detection.desktop, "thunar");
+        // The unmatched desktop variable is still reported as evidence.
+        assert_eq!(detection.evidence[0].value, "Hyprland");
+        assert!(detection.evidence[0].matched.is_none());
+    }
+
+    #[test]
+    fn registered_desktop_is_detected() {
+        let detector =
+            DesktopDetector::default().with_desktop("Enlightenment", LinuxBackend::DirectoryFile);
+        let detection = detector
+            .detect_from(&env_with_current("Enlightenment"))
+            .unwrap();
+        assert_eq!(detection.backend, LinuxBackend::DirectoryFile);
+    }
+
+    #[test]
+    fn registered_desktop_overrides_builtin() {
+        let detector = DesktopDetector::default().with_desktop("gnome", LinuxBackend::All);
+        let detection = detector.detect_from(&env_with_current("GNOME")).unwrap();
+        assert_eq!(detection.backend, LinuxBackend::All);
+    }
+
+    #[test]
+    fn registered_file_manager_is_detected() {
+        let detector =
+            DesktopDetector::default().with_file_manager("nemo-desktop", LinuxBackend::All);
+        let env = DesktopEnvironment {
+            processes: vec!["nemo-desktop".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            detector.detect_from(&env).unwrap().backend,
+            LinuxBackend::All
+        );
+    }
+
+    #[test]
+    fn nothing_recognized_is_error() {
+        let env = DesktopEnvironment {
+            xdg_current_desktop: Some("COSMIC".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            DesktopDetector::default().detect_from(&env),
+            Err(LinuxFolderSettingsError::UndetectedDesktop)
+        ));
+    }
+
+    #[test]
+    fn running_process_names_reads_comm_of_pid_dirs() {
+        let proc = tempfile::tempdir().unwrap();
+        for (dir, comm) in [("1", "systemd\n"), ("42", "dolphin\n"), ("self", "x\n")] {
+            std::fs::create_dir(proc.path().join(dir)).unwrap();
+            std::fs::write(proc.path().join(dir).join("comm"), comm).unwrap();
+        }
+        let mut names = running_process_names(proc.path());
+        names.sort();
+        assert_eq!(names, ["dolphin", "systemd"]);
+    }
+}
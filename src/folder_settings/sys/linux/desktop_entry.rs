@@ -0,0 +1,525 @@
+use std::fmt;
+use std::path::Path;
+
+/// A Desktop Entry-format file (`.desktop`, `.directory`, `index.theme`)
+/// that is edited in place.
+///
+/// Unlike a generic INI parser, untouched lines are written back byte for
+/// byte: comments, blank lines, key order, unknown groups and KDE key flags
+/// (`Key[$e]`) all survive a round trip. Values are unescaped and escaped
+/// following the Desktop Entry Specification, and localized keys such as
+/// `Name[de]` are kept separate from their unlocalized form.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// A comment, blank line or anything unparseable, kept verbatim.
+    Other(String),
+    Group {
+        raw: String,
+        name: String,
+    },
+    Entry {
+        raw: String,
+        key: String,
+        locale: Option<String>,
+        /// The value as written, still escaped.
+        value: String,
+    },
+}
+
+impl Line {
+    fn parse(raw: &str) -> Self {
+        let text = raw.trim_end_matches(['\n', '\r']).trim_start();
+        if let Some(name) = text
+            .strip_prefix('[')
+            .and_then(|t| t.trim_end().strip_suffix(']'))
+        {
+            return Line::Group {
+                raw: raw.to_string(),
+                name: name.to_string(),
+            };
+        }
+        if text.starts_with('#') {
+            return Line::Other(raw.to_string());
+        }
+        let Some((key, value)) = text.split_once('=') else {
+            return Line::Other(raw.to_string());
+        };
+        let (key, locale) = split_key(key.trim_end());
+        if key.is_empty() {
+            return Line::Other(raw.to_string());
+        }
+        Line::Entry {
+            raw: raw.to_string(),
+            key: key.to_string(),
+            locale,
+            value: value.trim_start().to_string(),
+        }
+    }
+
+    fn raw(&self) -> &str {
+        match self {
+            Line::Other(raw) | Line::Group { raw, .. } | Line::Entry { raw, .. } => raw,
+        }
+    }
+}
+
+/// Split `Name[de][$e]` into `Name` and its locale. KDE flag suffixes
+/// (`[$e]`, `[$i]`) are not locales and are ignored.
+fn split_key(key: &str) -> (&str, Option<String>) {
+    let Some(open) = key.find('[') else {
+        return (key, None);
+    };
+    let locale = key[open..]
+        .split('[')
+        .filter_map(|part| part.strip_suffix(']'))
+        .find(|part| !part.starts_with('$'))
+        .map(str::to_string);
+    (&key[..open], locale)
+}
+
+impl DesktopEntry {
+    /// Parse file contents. Parsing never fails: lines that aren't groups or
+    /// entries are kept as-is.
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: content.split_inclusive('\n').map(Line::parse).collect(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        std::fs::read_to_string(path).map(|content| Self::parse(&content))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Group names in file order.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Group { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The unescaped value of an unlocalized key.
+    pub fn get(&self, group: &str, key: &str) -> Option<String> {
+        self.find(group, key, None).map(|i| self.value_at(i))
+    }
+
+    /// The value of a localized key for `locale` (e.g. `de_DE.UTF-8@euro`),
+    /// matched as the specification describes: `lang_COUNTRY@MODIFIER`,
+    /// `lang_COUNTRY`, `lang@MODIFIER`, `lang`, then the unlocalized key.
+    pub fn get_localized(&self, group: &str, key: &str, locale: &str) -> Option<String> {
+        locale_fallbacks(locale)
+            .iter()
+            .find_map(|l| self.find(group, key, Some(l)))
+            .or_else(|| self.find(group, key, None))
+            .map(|i| self.value_at(i))
+    }
+
+    /// Set an unlocalized key, escaping the value. An existing entry is
+    /// replaced in place; otherwise the entry is appended to the group,
+    /// which is created at the end of the file if missing.
+    pub fn set(&mut self, group: &str, key: &str, value: &str) {
+        self.set_entry(group, key, None, value);
+    }
+
+    /// Set a localized key such as `Name[de]`.
+    pub fn set_localized(&mut self, group: &str, key: &str, locale: &str, value: &str) {
+        self.set_entry(group, key, Some(locale), value);
+    }
+
+    /// Remove an unlocalized key, leaving its localized variants. Returns
+    /// whether anything was removed.
+    pub fn remove(&mut self, group: &str, key: &str) -> bool {
+        self.remove_entry(group, key, None)
+    }
+
+    /// Remove a localized key such as `Name[de]`. Returns whether anything
+    /// was removed.
+    pub fn remove_localized(&mut self, group: &str, key: &str, locale: &str) -> bool {
+        self.remove_entry(group, key, Some(locale))
+    }
+
+    /// The locales `key` has localized variants for, such as `de` for
+    /// `Name[de]`, in file order.
+    pub fn locales(&self, group: &str, key: &str) -> Vec<String> {
+        self.group_lines(group)
+            .filter_map(|i| match &self.lines[i] {
+                Line::Entry {
+                    key: k,
+                    locale: Some(locale),
+                    ..
+                } if k == key => Some(locale.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Remove a group with everything in it, up to the next group header.
+    /// When it was the last group, the blank lines that separated it from
+    /// the previous one go too, undoing what [`set`](Self::set) adds.
+    pub fn remove_group(&mut self, group: &str) {
+        let mut inside = false;
+        self.lines.retain(|line| {
+            if let Line::Group { name, .. } = line {
+                inside = name == group;
+            }
+            !inside
+        });
+        if inside {
+            while self
+                .lines
+                .last()
+                .is_some_and(|line| matches!(line, Line::Other(raw) if raw.trim().is_empty()))
+            {
+                self.lines.pop();
+            }
+        }
+    }
+
+    /// Whether `group` exists and has no entries.
+    pub fn is_group_empty(&self, group: &str) -> bool {
+        let mut entries = self.group_lines(group).peekable();
+        entries.peek().is_some() && entries.all(|i| !matches!(self.lines[i], Line::Entry { .. }))
+    }
+
+    /// Whether any group holds at least one entry.
+    pub fn has_entries(&self) -> bool {
+        let mut in_group = false;
+        self.lines.iter().any(|line| match line {
+            Line::Group { .. } => {
+                in_group = true;
+                false
+            }
+            Line::Entry { .. } => in_group,
+            Line::Other(_) => false,
+        })
+    }
+
+    fn set_entry(&mut self, group: &str, key: &str, locale: Option<&str>, value: &str) {
+        let full_key = match locale {
+            Some(locale) => format!("{key}[{locale}]"),
+            None => key.to_string(),
+        };
+        let raw = format!("{full_key}={}\n", escape(value));
+        let line = Line::parse(&raw);
+
+        if let Some(i) = self.find(group, key, locale) {
+            // Keep the original line ending.
+            let line = if self.lines[i].raw().ends_with("\r\n") {
+                Line::parse(&raw.replace('\n', "\r\n"))
+            } else {
+                line
+            };
+            self.lines[i] = line;
+            return;
+        }
+
+        let insert_at = self.group_lines(group).last().map(|last| {
+            // Append after the group's last entry, before trailing comments
+            // and blank lines that separate it from the next group.
+            let header = self.group_lines(group).next().unwrap_or(last);
+            (header..=last)
+                .rev()
+                .find(|&i| matches!(self.lines[i], Line::Entry { .. } | Line::Group { .. }))
+                .unwrap_or(last)
+                + 1
+        });
+        match insert_at {
+            Some(at) => {
+                self.terminate_line(at - 1);
+                self.lines.insert(at, line);
+            }
+            None => {
+                if let Some(last) = self.lines.len().checked_sub(1) {
+                    self.terminate_line(last);
+                    if !self.lines[last].raw().trim().is_empty() {
+                        self.lines.push(Line::Other("\n".to_string()));
+                    }
+                }
+                self.lines.push(Line::parse(&format!("[{group}]\n")));
+                self.lines.push(line);
+            }
+        }
+    }
+
+    fn remove_entry(&mut self, group: &str, key: &str, locale: Option<&str>) -> bool {
+        let before = self.lines.len();
+        let mut current = None;
+        self.lines.retain(|line| match line {
+            Line::Group { name, .. } => {
+                current = Some(name.clone());
+                true
+            }
+            Line::Entry {
+                key: k, locale: l, ..
+            } => !(current.as_deref() == Some(group) && k == key && l.as_deref() == locale),
+            _ => true,
+        });
+        self.lines.len() != before
+    }
+
+    /// Make sure the line at `index` ends with a newline before something is
+    /// inserted after it.
+    fn terminate_line(&mut self, index: usize) {
+        if !self.lines[index].raw().ends_with('\n') {
+            let raw = format!("{}\n", self.lines[index].raw());
+            self.lines[index] = Line::parse(&raw);
+        }
+    }
+
+    /// Indices of a group's header and body lines, across repeated headers.
+    fn group_lines<'a>(&'a self, group: &'a str) -> impl Iterator<Item = usize> + 'a {
+        let mut inside = false;
+        self.lines.iter().enumerate().filter_map(move |(i, line)| {
+            if let Line::Group { name, .. } = line {
+                inside = name == group;
+            }
+            inside.then_some(i)
+        })
+    }
+
+    fn find(&self, group: &str, key: &str, locale: Option<&str>) -> Option<usize> {
+        self.group_lines(group).find(|&i| {
+            matches!(&self.lines[i], Line::Entry { key: k, locale: l, .. }
+                if k == key && l.as_deref() == locale)
+        })
+    }
+
+    fn value_at(&self, index: usize) -> String {
+        match &self.lines[index] {
+            Line::Entry { value, .. } => unescape(value.trim_end_matches(['\n', '\r'])),
+            _ => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.lines
+            .iter()
+            .try_for_each(|line| f.write_str(line.raw()))
+    }
+}
+
+/// Locale names to try for `lang_COUNTRY.ENCODING@MODIFIER`, most specific
+/// first. The encoding never takes part in matching.
+fn locale_fallbacks(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split('.').next().unwrap_or(rest);
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    let mut candidates = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        candidates.push(format!("{lang}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        candidates.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        candidates.push(format!("{lang}@{modifier}"));
+    }
+    candidates.push(lang.to_string());
+    candidates
+}
+
+/// Undo the specification's escapes: `\s`, `\n`, `\t`, `\r` and `\\`.
+/// Unknown escapes are kept as written.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Escape a value so it survives parsing: backslashes, control characters
+/// and leading whitespace (which readers would otherwise trim).
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut leading = true;
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ' ' if leading => out.push_str("\\s"),
+            c => out.push(c),
+        }
+        leading &= c == ' ';
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOLPHIN: &str = "# Written by Dolphin\r\n\
+        [Dolphin]\r\n\
+        Timestamp=2024,1,2,3,4,5.678\r\n\
+        Version=4\r\n\
+        ViewMode=1\r\n\
+        \r\n\
+        [Desktop Entry]\r\n\
+        Icon[$e]=$HOME/icons/old.png\r\n\
+        Name[de]=Dokumente\r\n\
+        \r\n\
+        [Settings]\r\n\
+        HiddenFilesShown=true";
+
+    #[test]
+    fn round_trip_is_byte_identical() {
+        for content in [
+            DOLPHIN,
+            "",
+            "\n\n",
+            "no group = value\n[g]\nk=v",
+            "[broken\n=x\n",
+        ] {
+            assert_eq!(DesktopEntry::parse(content).to_string(), content);
+        }
+    }
+
+    #[test]
+    fn set_replaces_in_place_and_keeps_everything_else() {
+        let mut entry = DesktopEntry::parse(DOLPHIN);
+        entry.set("Desktop Entry", "Icon", "/home/ada/icon.png");
+        assert_eq!(
+            entry.to_string(),
+            DOLPHIN.replace("Icon[$e]=$HOME/icons/old.png", "Icon=/home/ada/icon.png")
+        );
+    }
+
+    #[test]
+    fn set_appends_to_group_before_separator() {
+        let mut entry = DesktopEntry::parse("[A]\nx=1\n# about B\n\n[B]\ny=2\n");
+        entry.set("A", "z", "3");
+        assert_eq!(entry.to_string(), "[A]\nx=1\nz=3\n# about B\n\n[B]\ny=2\n");
+    }
+
+    #[test]
+    fn set_creates_missing_group() {
+        let mut entry = DesktopEntry::parse("[Settings]\nHiddenFilesShown=true");
+        entry.set("Desktop Entry", "Icon", "/x.png");
+        assert_eq!(
+            entry.to_string(),
+            "[Settings]\nHiddenFilesShown=true\n\n[Desktop Entry]\nIcon=/x.png\n"
+        );
+
+        let mut empty = DesktopEntry::default();
+        empty.set("Desktop Entry", "Icon", "/x.png");
+        assert_eq!(empty.to_string(), "[Desktop Entry]\nIcon=/x.png\n");
+    }
+
+    #[test]
+    fn localized_keys_are_distinct() {
+        let mut entry = DesktopEntry::parse(DOLPHIN);
+        assert_eq!(entry.get("Desktop Entry", "Name"), None);
+        assert_eq!(
+            entry
+                .get_localized("Desktop Entry", "Name", "de_DE.UTF-8@euro")
+                .as_deref(),
+            Some("Dokumente")
+        );
+        assert_eq!(entry.get_localized("Desktop Entry", "Name", "fr_FR"), None);
+
+        entry.set("Desktop Entry", "Name", "Documents");
+        entry.set_localized("Desktop Entry", "Name", "fr", "Documents FR");
+        assert_eq!(
+            entry
+                .get_localized("Desktop Entry", "Name", "fr_FR")
+                .as_deref(),
+            Some("Documents FR")
+        );
+        assert_eq!(
+            entry
+                .get_localized("Desktop Entry", "Name", "it")
+                .as_deref(),
+            Some("Documents")
+        );
+        assert!(entry.remove("Desktop Entry", "Name"));
+        assert!(entry.get_localized("Desktop Entry", "Name", "de").is_some());
+        assert!(entry.remove_localized("Desktop Entry", "Name", "de"));
+        assert!(!entry.remove_localized("Desktop Entry", "Name", "de"));
+        assert_eq!(entry.get_localized("Desktop Entry", "Name", "de"), None);
+    }
+
+    #[test]
+    fn kde_flags_are_not_locales() {
+        let entry = DesktopEntry::parse(DOLPHIN);
+        assert_eq!(
+            entry.get("Desktop Entry", "Icon").as_deref(),
+            Some("$HOME/icons/old.png")
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_and_unescaped() {
+        let value = "  two leading spaces\\path\twith\ttabs\nand lines ";
+        let mut entry = DesktopEntry::default();
+        entry.set("G", "k", value);
+        assert_eq!(
+            entry.to_string(),
+            "[G]\nk=\\s\\stwo leading spaces\\\\path\\twith\\ttabs\\nand lines \n"
+        );
+        assert_eq!(
+            DesktopEntry::parse(&entry.to_string())
+                .get("G", "k")
+                .as_deref(),
+            Some(value)
+        );
+        assert_eq!(unescape(r"a\;b\"), r"a\;b\");
+    }
+
+    #[test]
+    fn remove_group_and_emptiness() {
+        let mut entry = DesktopEntry::parse(DOLPHIN);
+        assert!(entry.remove("Desktop Entry", "Icon"));
+        assert!(!entry.remove("Desktop Entry", "Icon"));
+        assert!(!entry.is_group_empty("Desktop Entry"));
+        entry.remove_group("Desktop Entry");
+        assert_eq!(entry.groups().collect::<Vec<_>>(), ["Dolphin", "Settings"]);
+        assert!(entry.has_entries());
+
+        let mut entry = DesktopEntry::parse("[Dolphin]\nViewMode=1\n");
+        entry.set("Desktop Entry", "Name", "Docs");
+        entry.remove_group("Desktop Entry");
+        assert_eq!(entry.to_string(), "[Dolphin]\nViewMode=1\n");
+
+        let entry = DesktopEntry::parse("# only a comment\n[Desktop Entry]\n");
+        assert!(entry.is_group_empty("Desktop Entry"));
+        assert!(!entry.is_group_empty("Missing"));
+        assert!(!entry.has_entries());
+    }
+}
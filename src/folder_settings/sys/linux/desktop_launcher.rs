@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use super::{DesktopEntry, HicolorTheme, IconContext, LinuxFolderSettingsError, icon_theme};
+use crate::icon::sys::linux::LinuxIconSet;
+
+const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+const ICON_KEY: &str = "Icon";
+
+/// Image extensions an icon name must not carry, per the Desktop Entry
+/// Specification.
+const ICON_EXTENSIONS: [&str; 3] = [".png", ".svg", ".xpm"];
+
+/// Where icons not found in any theme are looked up last.
+const PIXMAPS_DIR: &str = "/usr/share/pixmaps";
+/// The formats of `ICON_EXTENSIONS` that can be decoded.
+const PIXMAP_EXTENSIONS: [&str; 2] = [".png", ".svg"];
+
+/// A freedesktop launcher (`.desktop` file) whose `Icon=` is read and set in
+/// place, keeping everything else in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopLauncher {
+    path: PathBuf,
+    icon_theme: Option<HicolorTheme>,
+}
+
+impl DesktopLauncher {
+    /// The launcher at `path`, with icons installed into the user's hicolor
+    /// theme.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            icon_theme: HicolorTheme::for_current_user(),
+        }
+    }
+
+    /// Where `install_icon` puts icons.
+    pub fn with_icon_theme(mut self, theme: HicolorTheme) -> Self {
+        self.icon_theme = Some(theme);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The unlocalized `Icon=`: a theme icon name or an absolute path.
+    pub fn icon(&self) -> Result<Option<String>, LinuxFolderSettingsError> {
+        Ok(self
+            .load()?
+            .get(DESKTOP_ENTRY_GROUP, ICON_KEY)
+            .filter(|icon| !icon.is_empty()))
+    }
+
+    /// The `Icon=` shown for `locale`, e.g. `de_DE.UTF-8`, falling back to
+    /// the unlocalized key as the specification describes.
+    pub fn icon_localized(&self, locale: &str) -> Result<Option<String>, LinuxFolderSettingsError> {
+        Ok(self
+            .load()?
+            .get_localized(DESKTOP_ENTRY_GROUP, ICON_KEY, locale)
+            .filter(|icon| !icon.is_empty()))
+    }
+
+    /// Set `Icon=` to a theme icon name (without extension) or an absolute
+    /// path, or remove it with `None`. Localized `Icon[..]=` variants are
+    /// removed either way, so the change applies in every language.
+    ///
+    /// The file is rewritten in place, keeping its permissions: launchers
+    /// on the desktop must stay executable to remain trusted.
+    pub fn set_icon(&self, icon: Option<&str>) -> Result<(), LinuxFolderSettingsError> {
+        if let Some(icon) = icon {
+            self.validate_icon(icon)?;
+        }
+        let mut entry = self.load()?;
+        for locale in entry.locales(DESKTOP_ENTRY_GROUP, ICON_KEY) {
+            entry.remove_localized(DESKTOP_ENTRY_GROUP, ICON_KEY, &locale);
+        }
+        match icon {
+            Some(icon) => entry.set(DESKTOP_ENTRY_GROUP, ICON_KEY, icon),
+            None => {
+                entry.remove(DESKTOP_ENTRY_GROUP, ICON_KEY);
+            }
+        }
+        entry.save(&self.path)?;
+        Ok(())
+    }
+
+    /// Install `icon_set` as the application icon `name` (see
+    /// `HicolorTheme::install`) and point `Icon=` at it.
+    pub fn install_icon(
+        &self,
+        name: &str,
+        icon_set: &LinuxIconSet,
+    ) -> Result<(), LinuxFolderSettingsError> {
+        let theme = self.icon_theme.as_ref().ok_or_else(|| {
+            LinuxFolderSettingsError::Error("no icon theme to install into".to_string())
+        })?;
+        self.validate_icon(name)?;
+        theme.install(IconContext::Applications, name, icon_set)?;
+        self.set_icon(Some(name))
+    }
+
+    /// Load the icon `Icon=` refers to: the file itself when it is an
+    /// absolute path, otherwise the named icon at every size found in the
+    /// active icon theme and its fallbacks, then in `/usr/share/pixmaps`.
+    pub fn icon_set(&self) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError> {
+        let icon = self.icon()?.ok_or_else(|| {
+            LinuxFolderSettingsError::IconOperation(
+                self.path.clone(),
+                format!("{} has no icon", self.path.display()),
+            )
+        })?;
+        if Path::new(&icon).is_absolute() {
+            return icon_theme::load_icon_file(Path::new(&icon));
+        }
+        // Many launchers name the icon with its extension anyway.
+        let name = ICON_EXTENSIONS
+            .iter()
+            .find_map(|ext| icon.strip_suffix(ext))
+            .unwrap_or(&icon);
+        if let Some(set) = icon_theme::load_icon_set(&[name]) {
+            return Ok(set);
+        }
+        for ext in PIXMAP_EXTENSIONS {
+            let path = Path::new(PIXMAPS_DIR).join(format!("{name}{ext}"));
+            if path.is_file() {
+                return icon_theme::load_icon_file(&path);
+            }
+        }
+        Err(LinuxFolderSettingsError::IconOperation(
+            self.path.clone(),
+            format!("icon {icon:?} not found in the icon theme or {PIXMAPS_DIR}"),
+        ))
+    }
+
+    /// Load the file, which must start with a `[Desktop Entry]` group.
+    fn load(&self) -> Result<DesktopEntry, LinuxFolderSettingsError> {
+        let entry = DesktopEntry::load(&self.path)?;
+        if entry.groups().next() != Some(DESKTOP_ENTRY_GROUP) {
+            return Err(LinuxFolderSettingsError::IconOperation(
+                self.path.clone(),
+                format!(
+                    "{} is not a desktop entry: its first group is not [{DESKTOP_ENTRY_GROUP}]",
+                    self.path.display()
+                ),
+            ));
+        }
+        Ok(entry)
+    }
+
+    fn validate_icon(&self, icon: &str) -> Result<(), LinuxFolderSettingsError> {
+        let problem = if icon.is_empty() {
+            Some("an icon name cannot be empty")
+        } else if Path::new(icon).is_absolute() {
+            None
+        } else if icon.contains('/') {
+            Some("icons must be a theme icon name or an absolute path")
+        } else if ICON_EXTENSIONS.iter().any(|ext| icon.ends_with(ext)) {
+            Some("icon names must not include a file extension")
+        } else {
+            None
+        };
+        match problem {
+            Some(problem) => Err(LinuxFolderSettingsError::IconOperation(
+                self.path.clone(),
+                format!("{problem}: {icon:?}"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icon::sys::linux::LinuxIconImage;
+    use image::{DynamicImage, RgbaImage};
+    use std::borrow::Cow;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    const LAUNCHER: &str = "[Desktop Entry]\n\
+        # Created by Acme\n\
+        Type=Application\n\
+        Name=Acme\n\
+        Name[de]=Acme DE\n\
+        Icon=acme-old\n\
+        Icon[de]=acme-old-de\n\
+        Exec=acme %U\n\
+        \n\
+        [Desktop Action new]\n\
+        Name=New\n\
+        Icon=document-new\n";
+
+    fn launcher_with(content: &str) -> (tempfile::TempDir, DesktopLauncher) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("acme.desktop");
+        fs::write(&path, content).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let launcher =
+            DesktopLauncher::at(path).with_icon_theme(HicolorTheme::at(dir.path().join("hicolor")));
+        (dir, launcher)
+    }
+
+    #[test]
+    fn reads_plain_and_localized_icons() {
+        let (_dir, launcher) = launcher_with(LAUNCHER);
+        assert_eq!(launcher.icon().unwrap().as_deref(), Some("acme-old"));
+        assert_eq!(
+            launcher.icon_localized("de_DE.UTF-8").unwrap().as_deref(),
+            Some("acme-old-de")
+        );
+        assert_eq!(
+            launcher.icon_localized("fr").unwrap().as_deref(),
+            Some("acme-old")
+        );
+    }
+
+    #[test]
+    fn set_icon_replaces_every_variant_and_keeps_the_rest() {
+        let (_dir, launcher) = launcher_with(LAUNCHER);
+        launcher.set_icon(Some("/opt/acme/acme.png")).unwrap();
+        assert_eq!(
+            fs::read_to_string(launcher.path()).unwrap(),
+            LAUNCHER
+                .replace("Icon=acme-old\n", "Icon=/opt/acme/acme.png\n")
+                .replace("Icon[de]=acme-old-de\n", "")
+        );
+        let mode = fs::metadata(launcher.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        launcher.set_icon(None).unwrap();
+        assert_eq!(launcher.icon().unwrap(), None);
+        // Action icons are not the launcher's icon.
+        assert!(
+            fs::read_to_string(launcher.path())
+                .unwrap()
+                .contains("Icon=document-new\n")
+        );
+    }
+
+    #[test]
+    fn invalid_icons_and_files_are_rejected() {
+        let (_dir, launcher) = launcher_with(LAUNCHER);
+        for icon in ["", "acme.png", "icons/acme"] {
+            assert!(launcher.set_icon(Some(icon)).is_err(), "{icon:?}");
+        }
+        assert_eq!(launcher.icon().unwrap().as_deref(), Some("acme-old"));
+
+        let (_dir, not_a_launcher) = launcher_with("[Other]\nIcon=x\n");
+        assert!(not_a_launcher.icon().is_err());
+    }
+
+    #[test]
+    fn icon_set_loads_absolute_paths() {
+        let (dir, launcher) = launcher_with(LAUNCHER);
+        let png = dir.path().join("acme.png");
+        DynamicImage::ImageRgba8(RgbaImage::new(64, 64))
+            .save(&png)
+            .unwrap();
+        launcher.set_icon(Some(png.to_str().unwrap())).unwrap();
+        let set = launcher.icon_set().unwrap();
+        assert!(set.get_image(64).is_some());
+
+        launcher.set_icon(Some("/nonexistent/acme.png")).unwrap();
+        assert!(launcher.icon_set().is_err());
+        launcher.set_icon(None).unwrap();
+        assert!(launcher.icon_set().is_err());
+    }
+
+    #[test]
+    fn install_icon_registers_an_application_icon() {
+        let (dir, launcher) = launcher_with(LAUNCHER);
+        let set = LinuxIconSet::from_icons([LinuxIconImage {
+            size: 48,
+            image: Cow::Owned(DynamicImage::ImageRgba8(RgbaImage::new(48, 48))),
+        }])
+        .unwrap();
+        launcher.install_icon("acme", &set).unwrap();
+        assert!(dir.path().join("hicolor/48x48/apps/acme.png").is_file());
+        assert_eq!(launcher.icon().unwrap().as_deref(), Some("acme"));
+    }
+}
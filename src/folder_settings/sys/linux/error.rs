@@ -1,21 +1,66 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use thiserror::Error;
 
+use super::{LinuxBackend, ProtectedFolderKind};
+
 #[derive(Debug, Error)]
 pub enum LinuxFolderSettingsError {
     /// Could not detect a supported desktop environment.
     #[error("could not detect a supported desktop environment")]
     UndetectedDesktop,
 
-    /// A `gio` subprocess failed.
+    /// Every backend in the fallback chain failed; each attempt's error is
+    /// kept in order.
+    #[error("every backend failed: {}", format_attempts(.0))]
+    AllBackendsFailed(Vec<(LinuxBackend, LinuxFolderSettingsError)>),
+
+    /// The folder is protected from icon changes (see `ProtectedFolders`).
+    #[error("refusing to change the icon of {path} ({1})", path = .0.display())]
+    ProtectedFolder(PathBuf, ProtectedFolderKind),
+
+    /// A `gio` subprocess failed. Converting a [`GvfsError`] yields this for
+    /// `gio` failures, as before the typed GVFS errors existed.
     #[error("gio command failed: {0}")]
     Gio(String),
 
+    /// Reading or writing GVFS metadata failed.
+    #[error(transparent)]
+    Gvfs(GvfsError),
+
+    /// The emblem name is not an `emblem-*` icon of the active icon theme.
+    #[error("{0} is not an emblem in the active icon theme")]
+    UnknownEmblem(String),
+
+    /// A dconf database file is malformed.
+    #[error("invalid dconf database {path}: {1}", path = .0.display())]
+    Dconf(PathBuf, String),
+
+    /// An `icon-theme.cache` file is malformed.
+    #[error("invalid icon theme cache {path}: {1}", path = .0.display())]
+    IconThemeCache(PathBuf, String),
+
+    /// A shared-mime-info package is malformed or could not be installed.
+    #[error("invalid MIME package {path}: {1}", path = .0.display())]
+    MimePackage(PathBuf, String),
+
+    /// An XBEL bookmarks file is malformed.
+    #[error("invalid XBEL document {path}: {1}", path = .0.display())]
+    Xbel(PathBuf, String),
+
+    /// A settings tool such as `gsettings` could not be run or failed.
+    #[error("{0} failed: {1}")]
+    SettingsTool(String, String),
+
     /// An icon operation on a path failed.
     #[error("{1}")]
     IconOperation(PathBuf, String),
 
+    /// A D-Bus connection or call failed.
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
@@ -25,3 +70,69 @@ pub enum LinuxFolderSettingsError {
     #[error("{0}")]
     Error(String),
 }
+
+/// Errors from the GVFS metadata store (see `GvfsMetadata`).
+#[derive(Debug, Error)]
+pub enum GvfsError {
+    /// The `gio` binary could not be started or waited on.
+    #[error("failed to run {program}: {1}", program = .0.display())]
+    Spawn(PathBuf, std::io::Error),
+
+    /// `gio` exited unsuccessfully; holds its stderr.
+    #[error("gio command failed: {0}")]
+    CommandFailed(String),
+
+    /// The operation did not complete within the configured timeout.
+    #[error("GVFS metadata operation timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// `gvfsd-metadata` is not running and could not be activated.
+    #[error("gvfsd-metadata is not available: {0}")]
+    ServiceUnavailable(String),
+
+    /// `gvfsd-metadata` answered the call with a D-Bus error.
+    #[error("gvfsd-metadata rejected the call: {0}")]
+    Rejected(String),
+
+    /// No metadata tree covers the path.
+    #[error("no GVFS metadata tree for {}", .0.display())]
+    NoTree(PathBuf),
+
+    /// A metadata tree or journal file could not be read.
+    #[error("failed to read {path}: {1}", path = .0.display())]
+    Read(PathBuf, std::io::Error),
+
+    /// A metadata tree file is malformed.
+    #[error("corrupt GVFS metadata database: {0}")]
+    Database(String),
+
+    /// The key is not in the `metadata::` namespace.
+    #[error("not a GVFS metadata attribute: {0}")]
+    NotMetadataKey(String),
+
+    /// The transport cannot perform the operation.
+    #[error("unsupported GVFS metadata operation: {0}")]
+    Unsupported(String),
+
+    /// Connecting to or talking over D-Bus failed.
+    #[error("D-Bus error: {0}")]
+    DBus(zbus::Error),
+}
+
+impl From<GvfsError> for LinuxFolderSettingsError {
+    fn from(error: GvfsError) -> Self {
+        match error {
+            GvfsError::Spawn(..) => Self::Gio(error.to_string()),
+            GvfsError::CommandFailed(stderr) => Self::Gio(stderr),
+            error => Self::Gvfs(error),
+        }
+    }
+}
+
+fn format_attempts(attempts: &[(LinuxBackend, LinuxFolderSettingsError)]) -> String {
+    attempts
+        .iter()
+        .map(|(backend, e)| format!("{backend:?}: {e}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+
+use super::LinuxFolderSettingsError;
+use super::uri::file_uri;
+
+/// Tells running file managers over the D-Bus session bus that folders
+/// changed, so they redraw icons without the folder's mtime being touched.
+///
+/// Emits KDE's `org.kde.KDirNotify.FilesChanged` broadcast signal, which
+/// Dolphin, Konqueror and the Plasma desktop listen for. The freedesktop
+/// `org.freedesktop.FileManager1` interface only offers window-opening
+/// methods (`ShowFolders`, `ShowItems`, ...) and has no change
+/// notification, so it is deliberately not called: doing so would pop up
+/// file manager windows. GTK file managers pick up the change through their
+/// GVFS/inotify monitors instead.
+#[derive(Debug, Clone, Default)]
+pub struct FileManagerNotifier {
+    /// Bus address to connect to; `None` uses the session bus.
+    address: Option<String>,
+}
+
+impl FileManagerNotifier {
+    /// Notify over the user's session bus.
+    pub fn session() -> Self {
+        Self::default()
+    }
+
+    /// Notify over the bus at a specific address (e.g. a private bus).
+    pub fn with_address(address: impl Into<String>) -> Self {
+        Self {
+            address: Some(address.into()),
+        }
+    }
+
+    /// Announce that the given paths changed.
+    pub fn notify_changed<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+    ) -> Result<(), LinuxFolderSettingsError> {
+        let uris = paths
+            .iter()
+            .map(|p| {
+                std::path::absolute(p.as_ref())
+                    .map(|p| file_uri(&p))
+                    .map_err(|e| {
+                        LinuxFolderSettingsError::IconOperation(
+                            p.as_ref().to_path_buf(),
+                            e.to_string(),
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let connection = self.connect()?;
+        connection.emit_signal(
+            None::<&str>,
+            "/",
+            "org.kde.KDirNotify",
+            "FilesChanged",
+            &(uris,),
+        )?;
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<Connection, LinuxFolderSettingsError> {
+        Ok(match &self.address {
+            Some(address) => Builder::address(address.as_str())?.build()?,
+            None => Connection::session()?,
+        })
+    }
+}
@@ -1,18 +1,36 @@
 use std::{
     fs,
     path::{Path, PathBuf},
-    process::Command,
 };
 
 use uuid::Uuid;
 
-use super::LinuxFolderSettingsError;
-use crate::folder_settings::FolderSettingsProvider;
+use super::icon_theme::{active_themes, load_icon_file};
+use super::uri::{file_path, file_uri};
+use super::xdg;
+use super::{
+    DesktopDetector, DesktopEntry, FileManagerNotifier, GvfsMetadata, LinuxFolderSettingsError,
+    ProtectedFolders, ThumbnailCache,
+};
+use crate::IconProvider;
 use crate::folder_settings::error::Result;
+use crate::folder_settings::{FolderMetadata, FolderSettingsProvider};
 use crate::icon::sys::linux::LinuxIconSet;
 
 const DEFAULT_GENERATED_ICON_PREFIX: &str = env!("CARGO_PKG_NAME");
 
+/// `.directory` group holding the folder's icon, display name and tooltip.
+const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+/// GVFS attribute pointing at a custom icon file.
+const CUSTOM_ICON_KEY: &str = "metadata::custom-icon";
+
+/// GVFS attribute listing the emblems drawn over an icon.
+const EMBLEMS_KEY: &str = "metadata::emblems";
+
+/// Icon name prefix shared by every freedesktop emblem icon.
+const EMBLEM_PREFIX: &str = "emblem-";
+
 /// File extensions this crate generates for folder icons, used both when
 /// writing a new icon and when cleaning up previously generated ones.
 const GENERATED_ICON_EXTENSIONS: [&str; 2] = ["png", "svg"];
@@ -24,48 +42,70 @@ const GENERATED_ICON_EXTENSIONS: [&str; 2] = ["png", "svg"];
 /// (Dolphin) and XFCE (Thunar) read a `.directory` file.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LinuxBackend {
-    /// Detect the mechanism from `XDG_CURRENT_DESKTOP`.
+    /// Detect the mechanism from the desktop environment (see
+    /// [`DesktopDetector`]).
     Auto,
     /// GVFS `metadata::custom-icon` (GNOME, Cinnamon, MATE, Budgie).
     GioMetadata,
     /// Freedesktop `.directory` file (KDE, XFCE).
     DirectoryFile,
+    /// Every known mechanism at once, for home directories shared between
+    /// desktop sessions. Resetting clears all of them, so an icon set under
+    /// one desktop never lingers after a reset under another.
+    All,
 }
 
 impl LinuxBackend {
-    /// Resolve `Auto` into a concrete backend using `XDG_CURRENT_DESKTOP`.
-    fn resolve(self) -> std::result::Result<Self, LinuxFolderSettingsError> {
+    /// Resolve `Auto` into a concrete backend using `detector`.
+    fn resolve(
+        self,
+        detector: &DesktopDetector,
+    ) -> std::result::Result<Self, LinuxFolderSettingsError> {
         match self {
-            LinuxBackend::Auto => {
-                let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
-                detect_backend(&desktop)
-            }
+            LinuxBackend::Auto => Ok(detector.detect()?.backend),
             other => Ok(other),
         }
     }
-}
 
-/// Map an `XDG_CURRENT_DESKTOP` value to a concrete backend.
-fn detect_backend(desktop: &str) -> std::result::Result<LinuxBackend, LinuxFolderSettingsError> {
-    let desktop = desktop.to_ascii_lowercase();
-    if desktop.is_empty() {
-        return Err(LinuxFolderSettingsError::UndetectedDesktop);
-    }
-    if ["gnome", "cinnamon", "mate", "budgie", "unity"]
-        .iter()
-        .any(|de| desktop.contains(de))
-    {
-        Ok(LinuxBackend::GioMetadata)
-    } else if ["kde", "xfce", "lxqt"]
-        .iter()
-        .any(|de| desktop.contains(de))
-    {
-        Ok(LinuxBackend::DirectoryFile)
-    } else {
-        Err(LinuxFolderSettingsError::UndetectedDesktop)
+    /// The concrete mechanisms a resolved backend reads and writes.
+    fn mechanisms(self) -> &'static [LinuxBackend] {
+        match self {
+            LinuxBackend::GioMetadata => &[LinuxBackend::GioMetadata],
+            LinuxBackend::DirectoryFile => &[LinuxBackend::DirectoryFile],
+            LinuxBackend::All => &[LinuxBackend::GioMetadata, LinuxBackend::DirectoryFile],
+            LinuxBackend::Auto => unreachable!("mechanisms() requires a resolved backend"),
+        }
     }
 }
 
+/// Outcome of setting a folder icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconUpdate {
+    /// The icon file was written and the folder now references it.
+    Applied,
+    /// The folder already referenced a generated icon with identical content,
+    /// so nothing on disk was touched.
+    Unchanged,
+}
+
+/// A backend that was tried and failed, paired with the error it produced.
+///
+/// The backend is the resolved one where resolution succeeded, or
+/// [`LinuxBackend::Auto`] if desktop detection itself failed.
+pub type FailedAttempt = (LinuxBackend, LinuxFolderSettingsError);
+
+/// Result of a folder icon operation run through the backend fallback chain.
+#[derive(Debug)]
+pub struct LinuxIconOutcome {
+    /// The concrete backend that carried out the operation.
+    pub backend: LinuxBackend,
+    /// Whether anything was written. Resets always report
+    /// [`IconUpdate::Applied`].
+    pub update: IconUpdate,
+    /// Backends tried before `backend`, in order, with the error each produced.
+    pub failed_attempts: Vec<FailedAttempt>,
+}
+
 /// Linux-specific extension to the cross-platform folder settings provider.
 pub trait LinuxFolderSettingsProviderExt {
     /// Construct with an explicit backend choice and optional prefix for
@@ -77,21 +117,111 @@ pub trait LinuxFolderSettingsProviderExt {
     ) -> Self;
 
     /// Set the icon for a folder using a Linux icon set.
+    ///
+    /// If the folder already references a generated icon with the same
+    /// content, the call is a no-op and reports [`IconUpdate::Unchanged`].
     fn set_icon_for_folder_linux<P: AsRef<Path>>(
         &self,
         path: P,
         icon_set: &LinuxIconSet,
-    ) -> Result<()>;
+    ) -> Result<LinuxIconOutcome>;
 
     /// Reset the icon for a folder.
-    fn reset_icon_for_folder_linux<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    fn reset_icon_for_folder_linux<P: AsRef<Path>>(&self, path: P) -> Result<LinuxIconOutcome>;
+
+    /// List the emblems GNOME-family file managers draw on a file or folder
+    /// (the `metadata::emblems` GVFS attribute).
+    fn emblems_linux<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>>;
+
+    /// Add an emblem such as `emblem-important`, leaving the icon itself
+    /// untouched. The name must be an `emblem-*` icon of the active theme.
+    /// Adding an emblem that is already shown is a no-op.
+    fn add_emblem_linux<P: AsRef<Path>>(&self, path: P, emblem: &str) -> Result<()>;
+
+    /// Remove an emblem. Removing one that isn't shown is a no-op.
+    fn remove_emblem_linux<P: AsRef<Path>>(&self, path: P, emblem: &str) -> Result<()>;
+
+    /// Remove every emblem.
+    fn clear_emblems_linux<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+
+    /// Set the icon of a regular file through GVFS `metadata::custom-icon`,
+    /// which Nautilus and Nemo show in place of the MIME type icon. The
+    /// generated icon file is kept in the file icon directory (see
+    /// [`LinuxFolderSettingsProvider::with_file_icon_dir`]) rather than next
+    /// to the file.
+    fn set_icon_for_file_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        icon_set: &LinuxIconSet,
+    ) -> Result<IconUpdate>;
+
+    /// Remove a file's custom icon, deleting its generated icon file.
+    fn reset_icon_for_file_linux<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+
+    /// Load the custom icon of a file or folder, as stored by the configured
+    /// backend for folders and by GVFS for files. Only icons stored as image
+    /// files are returned; icon-theme names (`Icon=folder-music`) are not
+    /// resolved.
+    fn icon_for_path_linux<P: AsRef<Path>>(&self, path: P)
+    -> Result<Option<LinuxIconSet<'static>>>;
+
+    /// The folder's display name (`Name` in its `.directory` file). With a
+    /// `locale` such as `de_DE.UTF-8`, the best matching `Name[...]` entry is
+    /// returned, falling back to the unlocalized one.
+    fn display_name_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        locale: Option<&str>,
+    ) -> Result<Option<String>>;
+
+    /// Set or, with `None`, remove the display name for exactly `locale`
+    /// (`Name[locale]`), or the unlocalized `Name` if `locale` is `None`.
+    fn set_display_name_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        name: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<()>;
+
+    /// The folder's tooltip (`Comment` in its `.directory` file), looked up
+    /// like [`display_name_linux`](Self::display_name_linux).
+    fn tooltip_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        locale: Option<&str>,
+    ) -> Result<Option<String>>;
+
+    /// Set or remove the tooltip, like
+    /// [`set_display_name_linux`](Self::set_display_name_linux).
+    fn set_tooltip_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        tooltip: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
 pub struct LinuxFolderSettingsProvider {
     backend: LinuxBackend,
+    /// Backends tried in order after `backend` fails.
+    fallbacks: Vec<LinuxBackend>,
+    /// Resolves `LinuxBackend::Auto`.
+    detector: DesktopDetector,
+    /// Refuse to set icons on the home directory, XDG user directories (ex.
+    /// Desktop, Downloads) and system roots, mirroring the Windows
+    /// known-folder check. Resets are always allowed.
+    protect_folders: bool,
     generated_icon_prefix: String,
     bump_mtime: bool,
+    /// Announces changes over D-Bus, independently of `bump_mtime`.
+    notifier: Option<FileManagerNotifier>,
+    /// Stale folder previews are removed from this cache after a change.
+    thumbnail_cache: Option<ThumbnailCache>,
+    /// Reads and writes `metadata::custom-icon` for the GIO backend.
+    gvfs: GvfsMetadata,
+    /// Where icons generated for regular files are written.
+    file_icon_dir: Option<PathBuf>,
 }
 
 impl FolderSettingsProvider for LinuxFolderSettingsProvider {
@@ -105,11 +235,69 @@ impl FolderSettingsProvider for LinuxFolderSettingsProvider {
         icon_set: &crate::IconSet,
     ) -> Result<()> {
         let linux_icon_set = LinuxIconSet::from(icon_set);
-        self.set_icon_for_folder_linux(path, &linux_icon_set)
+        self.set_icon_for_folder_linux(path, &linux_icon_set)?;
+        Ok(())
     }
 
     fn reset_icon_for_folder<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
-        self.reset_icon_for_folder_linux(path)
+        self.reset_icon_for_folder_linux(path)?;
+        Ok(())
+    }
+}
+
+/// Display names and tooltips are stored in the folder's `.directory` file
+/// whatever the configured backend, since GVFS metadata has no equivalent
+/// that file managers display. Reads use the current message locale
+/// (`LC_ALL`, `LC_MESSAGES`, `LANG`); writes set the unlocalized entries.
+impl FolderMetadata for LinuxFolderSettingsProvider {
+    fn display_name<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        self.display_name_linux(path, message_locale().as_deref())
+    }
+
+    fn set_display_name<P: AsRef<Path>>(&self, path: P, name: Option<&str>) -> Result<()> {
+        self.set_display_name_linux(path, name, None)
+    }
+
+    fn tooltip<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        self.tooltip_linux(path, message_locale().as_deref())
+    }
+
+    fn set_tooltip<P: AsRef<Path>>(&self, path: P, tooltip: Option<&str>) -> Result<()> {
+        self.set_tooltip_linux(path, tooltip, None)
+    }
+}
+
+/// Folders go through the configured backend like
+/// [`FolderSettingsProvider`]; other files always use GVFS metadata.
+impl IconProvider for LinuxFolderSettingsProvider {
+    fn set_icon_for_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+        icon_set: &crate::IconSet,
+    ) -> crate::error::Result<()> {
+        let linux_icon_set = LinuxIconSet::from(icon_set);
+        if path.as_ref().is_dir() {
+            self.set_icon_for_folder_linux(path, &linux_icon_set)?;
+        } else {
+            self.set_icon_for_file_linux(path, &linux_icon_set)?;
+        }
+        Ok(())
+    }
+
+    fn reset_icon_for_path<P: AsRef<Path>>(&self, path: P) -> crate::error::Result<()> {
+        if path.as_ref().is_dir() {
+            self.reset_icon_for_folder_linux(path)?;
+        } else {
+            self.reset_icon_for_file_linux(path)?;
+        }
+        Ok(())
+    }
+
+    fn icon_for_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> crate::error::Result<Option<crate::IconSet>> {
+        Ok(self.icon_for_path_linux(path)?.map(crate::IconSet::from))
     }
 }
 
@@ -125,8 +313,16 @@ impl LinuxFolderSettingsProviderExt for LinuxFolderSettingsProvider {
 
         Self {
             backend,
-            generated_icon_prefix,
+            fallbacks: Vec::new(),
+            detector: DesktopDetector::default(),
+            protect_folders: true,
             bump_mtime,
+            notifier: None,
+            thumbnail_cache: None,
+            gvfs: GvfsMetadata::default(),
+            file_icon_dir: xdg::data_home()
+                .map(|data| data.join(&generated_icon_prefix).join("file-icons")),
+            generated_icon_prefix,
         }
     }
 
@@ -134,52 +330,468 @@ impl LinuxFolderSettingsProviderExt for LinuxFolderSettingsProvider {
         &self,
         path: P,
         icon_set: &LinuxIconSet,
-    ) -> Result<()> {
+    ) -> Result<LinuxIconOutcome> {
         self.validate_folder(&path)?;
+        self.check_protection(path.as_ref())?;
 
-        match self.backend.resolve()? {
-            LinuxBackend::GioMetadata => self.set_via_gio_metadata(&path, icon_set)?,
-            LinuxBackend::DirectoryFile => self.set_via_directory_file(&path, icon_set)?,
-            LinuxBackend::Auto => unreachable!("resolve() never returns Auto"),
+        let previous_icons = self.generated_icons(&path).unwrap_or_default();
+        let outcome = try_backends(&self.detector, self.backend_chain(), |backend| {
+            self.set_with_backend(backend, &path, icon_set)
+        })?;
+        if outcome.update == IconUpdate::Applied {
+            self.invalidate_thumbnails(&path, &previous_icons);
+            self.refresh_file_managers(&path);
         }
-
-        self.maybe_bump_mtime(&path);
-        Ok(())
+        Ok(outcome)
     }
 
-    fn reset_icon_for_folder_linux<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fn reset_icon_for_folder_linux<P: AsRef<Path>>(&self, path: P) -> Result<LinuxIconOutcome> {
         self.validate_folder(&path)?;
 
-        match self.backend.resolve()? {
-            LinuxBackend::GioMetadata => self.reset_via_gio_metadata(&path)?,
-            LinuxBackend::DirectoryFile => self.reset_via_directory_file(&path)?,
-            LinuxBackend::Auto => unreachable!("resolve() never returns Auto"),
+        let previous_icons = self.generated_icons(&path).unwrap_or_default();
+        let outcome = try_backends(&self.detector, self.backend_chain(), |backend| {
+            self.reset_with_backend(backend, &path)
+                .map(|()| IconUpdate::Applied)
+        })?;
+        self.invalidate_thumbnails(&path, &previous_icons);
+        self.refresh_file_managers(&path);
+        Ok(outcome)
+    }
+
+    fn emblems_linux<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>> {
+        validate_exists(path.as_ref())?;
+        Ok(self
+            .gvfs
+            .get_list(path, EMBLEMS_KEY)
+            .map_err(LinuxFolderSettingsError::from)?
+            .unwrap_or_default())
+    }
+
+    fn add_emblem_linux<P: AsRef<Path>>(&self, path: P, emblem: &str) -> Result<()> {
+        validate_emblem(emblem)?;
+        let mut emblems = self.emblems_linux(&path)?;
+        if emblems.iter().any(|e| e == emblem) {
+            return Ok(());
+        }
+        emblems.push(emblem.to_string());
+        self.write_emblems(path, &emblems)
+    }
+
+    fn remove_emblem_linux<P: AsRef<Path>>(&self, path: P, emblem: &str) -> Result<()> {
+        let mut emblems = self.emblems_linux(&path)?;
+        let before = emblems.len();
+        emblems.retain(|e| e != emblem);
+        if emblems.len() == before {
+            return Ok(());
+        }
+        self.write_emblems(path, &emblems)
+    }
+
+    fn clear_emblems_linux<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        validate_exists(path.as_ref())?;
+        self.write_emblems(path, &[])
+    }
+
+    fn set_icon_for_file_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        icon_set: &LinuxIconSet,
+    ) -> Result<IconUpdate> {
+        let path = validate_file(path.as_ref())?;
+        let icon_dir = self.file_icon_dir()?;
+
+        let previous_icon = self.generated_file_icon(&path)?;
+        if previous_icon
+            .as_deref()
+            .is_some_and(|icon| generated_icon_matches(icon, icon_set))
+        {
+            return Ok(IconUpdate::Unchanged);
+        }
+
+        fs::create_dir_all(icon_dir).map_err(LinuxFolderSettingsError::from)?;
+        let icon_path = self.write_icon_file(icon_dir, icon_set)?;
+        if let Err(e) = self.gvfs.set(&path, CUSTOM_ICON_KEY, &file_uri(&icon_path)) {
+            let _ = fs::remove_file(&icon_path);
+            return Err(LinuxFolderSettingsError::from(e).into());
         }
+        if let Some(previous_icon) = previous_icon {
+            let _ = fs::remove_file(previous_icon);
+        }
+        self.refresh_file_managers_for_file(&path);
+        Ok(IconUpdate::Applied)
+    }
 
-        self.maybe_bump_mtime(&path);
+    fn reset_icon_for_file_linux<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = validate_file(path.as_ref())?;
+        let previous_icon = self.generated_file_icon(&path)?;
+        self.gvfs
+            .unset(&path, CUSTOM_ICON_KEY)
+            .map_err(LinuxFolderSettingsError::from)?;
+        if let Some(previous_icon) = previous_icon {
+            fs::remove_file(previous_icon).map_err(LinuxFolderSettingsError::from)?;
+        }
+        self.refresh_file_managers_for_file(&path);
         Ok(())
     }
+
+    fn icon_for_path_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Option<LinuxIconSet<'static>>> {
+        validate_exists(path.as_ref())?;
+        let path = std::path::absolute(path.as_ref()).map_err(LinuxFolderSettingsError::from)?;
+        let mechanisms = if path.is_dir() {
+            // An undetectable desktop leaves no preferred mechanism, so look
+            // at all of them.
+            self.backend
+                .resolve(&self.detector)
+                .map_or(LinuxBackend::All.mechanisms(), LinuxBackend::mechanisms)
+        } else {
+            &[LinuxBackend::GioMetadata]
+        };
+
+        for mechanism in mechanisms {
+            let icon = match mechanism {
+                LinuxBackend::GioMetadata => self
+                    .gvfs
+                    .get(&path, CUSTOM_ICON_KEY)
+                    .map_err(LinuxFolderSettingsError::from)?
+                    .and_then(|uri| file_path(&uri)),
+                LinuxBackend::DirectoryFile => read_directory_key(&path, "Icon", None)?
+                    .map(PathBuf::from)
+                    .filter(|icon| icon.is_absolute()),
+                _ => unreachable!("mechanisms() only yields concrete backends"),
+            };
+            if let Some(icon) = icon {
+                return Ok(Some(load_icon_file(&icon)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn display_name_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        locale: Option<&str>,
+    ) -> Result<Option<String>> {
+        read_directory_key(path.as_ref(), "Name", locale)
+    }
+
+    fn set_display_name_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        name: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<()> {
+        self.write_directory_key(path, "Name", name, locale)
+    }
+
+    fn tooltip_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        locale: Option<&str>,
+    ) -> Result<Option<String>> {
+        read_directory_key(path.as_ref(), "Comment", locale)
+    }
+
+    fn set_tooltip_linux<P: AsRef<Path>>(
+        &self,
+        path: P,
+        tooltip: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<()> {
+        self.write_directory_key(path, "Comment", tooltip, locale)
+    }
 }
 
 impl LinuxFolderSettingsProvider {
-    /// Nudge file-manager monitors to refresh by bumping the folder's mtime.
-    /// Best-effort: failures are ignored since the icon change still applies.
-    fn maybe_bump_mtime<P: AsRef<Path>>(&self, path: P) {
+    /// Set an ordered list of backends to try when the primary backend fails,
+    /// e.g. `[LinuxBackend::DirectoryFile]` behind `LinuxBackend::GioMetadata`
+    /// for systems where `gio` is missing. A failed `Auto` detection also
+    /// falls through to this list instead of being a hard error.
+    pub fn with_fallbacks<I>(mut self, fallbacks: I) -> Self
+    where
+        I: IntoIterator<Item = LinuxBackend>,
+    {
+        self.fallbacks = fallbacks.into_iter().collect();
+        self
+    }
+
+    /// Use a custom desktop detector (e.g. one with extra desktop mappings)
+    /// to resolve `LinuxBackend::Auto`.
+    pub fn with_desktop_detector(mut self, detector: DesktopDetector) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Enable or disable protection of the home directory, XDG user
+    /// directories and system roots (see [`ProtectedFolders`]). Enabled by
+    /// default; disabling it allows changing the icon of e.g. `~/Downloads`.
+    /// Only setting an icon (or display name) is refused, so icons set
+    /// before can still be reset.
+    pub fn with_folder_protection(mut self, enabled: bool) -> Self {
+        self.protect_folders = enabled;
+        self
+    }
+
+    /// Notify file managers over D-Bus after every change, in addition to
+    /// (or, with `bump_mtime` disabled, instead of) bumping the folder's
+    /// mtime. Pass `None` to disable.
+    pub fn with_file_manager_notifier(mut self, notifier: Option<FileManagerNotifier>) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Remove the folder's entries (and those of its previously generated
+    /// icon files) from a freedesktop thumbnail cache whenever an icon is
+    /// set or reset, so file managers don't keep showing stale previews.
+    /// Pass `ThumbnailCache::user()` for the current user's cache, or `None`
+    /// to disable.
+    pub fn with_thumbnail_cache(mut self, cache: Option<ThumbnailCache>) -> Self {
+        self.thumbnail_cache = cache;
+        self
+    }
+
+    /// Choose how the GIO backend talks to the GVFS metadata store, e.g.
+    /// `GvfsMetadata::dbus()` to call `gvfsd-metadata` directly instead of
+    /// spawning `gio`, or a `gio` binary with a custom timeout.
+    pub fn with_gvfs_metadata(mut self, gvfs: GvfsMetadata) -> Self {
+        self.gvfs = gvfs;
+        self
+    }
+
+    /// Store the emblem list, unsetting the attribute when it is empty.
+    fn write_emblems<P: AsRef<Path>>(&self, path: P, emblems: &[String]) -> Result<()> {
+        let written = if emblems.is_empty() {
+            self.gvfs.unset(&path, EMBLEMS_KEY)
+        } else {
+            self.gvfs.set_list(&path, EMBLEMS_KEY, emblems)
+        };
+        written.map_err(LinuxFolderSettingsError::from)?;
+        self.refresh_file_managers(&path);
+        Ok(())
+    }
+
+    /// Write icons generated for regular files to `dir` instead of
+    /// `$XDG_DATA_HOME/<generated icon prefix>/file-icons`.
+    pub fn with_file_icon_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.file_icon_dir = Some(dir.into());
+        self
+    }
+
+    fn file_icon_dir(&self) -> std::result::Result<&Path, LinuxFolderSettingsError> {
+        self.file_icon_dir.as_deref().ok_or_else(|| {
+            LinuxFolderSettingsError::Error(
+                "no data directory for generated file icons; set HOME or XDG_DATA_HOME".to_string(),
+            )
+        })
+    }
+
+    /// The generated icon file `path`'s custom icon points at, if it is one
+    /// of ours in the file icon directory.
+    fn generated_file_icon(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<Option<PathBuf>, LinuxFolderSettingsError> {
+        let Some(icon_dir) = &self.file_icon_dir else {
+            return Ok(None);
+        };
+        Ok(self
+            .gvfs
+            .get(path, CUSTOM_ICON_KEY)?
+            .and_then(|uri| file_path(&uri))
+            .filter(|icon| self.is_generated_icon_in(icon_dir, icon)))
+    }
+
+    /// Set or remove a `[Desktop Entry]` key of the folder's `.directory`
+    /// file, deleting the file once nothing meaningful is left in it.
+    fn write_directory_key<P: AsRef<Path>>(
+        &self,
+        path: P,
+        key: &str,
+        value: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<()> {
+        self.validate_folder(&path)?;
+        if value.is_some() {
+            self.check_protection(path.as_ref())?;
+        }
+        let directory_path = path.as_ref().join(".directory");
+        let entry = match DesktopEntry::load(&directory_path) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DesktopEntry::default(),
+            Err(e) => return Err(LinuxFolderSettingsError::from(e).into()),
+        };
+
+        let written = match (value, locale) {
+            (Some(value), Some(locale)) => {
+                let mut entry = entry;
+                entry.set_localized(DESKTOP_ENTRY_GROUP, key, locale, value);
+                entry.save(&directory_path)
+            }
+            (Some(value), None) => {
+                let mut entry = entry;
+                entry.set(DESKTOP_ENTRY_GROUP, key, value);
+                entry.save(&directory_path)
+            }
+            (None, _) if !directory_path.exists() => return Ok(()),
+            (None, locale) => match strip_entry(entry, key, locale) {
+                Some(entry) => entry.save(&directory_path),
+                None => fs::remove_file(&directory_path),
+            },
+        };
+        written.map_err(LinuxFolderSettingsError::from)?;
+        self.refresh_file_managers(&path);
+        Ok(())
+    }
+
+    /// The primary backend followed by its fallbacks.
+    fn backend_chain(&self) -> impl Iterator<Item = LinuxBackend> + '_ {
+        std::iter::once(self.backend).chain(self.fallbacks.iter().copied())
+    }
+
+    /// Apply `icon_set` with every mechanism of a resolved backend.
+    fn set_with_backend<P: AsRef<Path>>(
+        &self,
+        backend: LinuxBackend,
+        path: P,
+        icon_set: &LinuxIconSet,
+    ) -> std::result::Result<IconUpdate, LinuxFolderSettingsError> {
+        let mechanisms = backend.mechanisms();
+        if mechanisms
+            .iter()
+            .all(|mechanism| self.is_already_applied(*mechanism, &path, icon_set))
+        {
+            return Ok(IconUpdate::Unchanged);
+        }
+
+        // A single generated file is shared by every mechanism.
+        let icon_path = self.write_generated_icon(&path, icon_set)?;
+        for mechanism in mechanisms {
+            match mechanism {
+                LinuxBackend::GioMetadata => self.set_via_gio_metadata(&path, &icon_path)?,
+                LinuxBackend::DirectoryFile => self.set_via_directory_file(&path, &icon_path)?,
+                _ => unreachable!("mechanisms() only yields concrete backends"),
+            }
+        }
+        Ok(IconUpdate::Applied)
+    }
+
+    /// Clear the icon from every mechanism of a resolved backend.
+    fn reset_with_backend<P: AsRef<Path>>(
+        &self,
+        backend: LinuxBackend,
+        path: P,
+    ) -> std::result::Result<(), LinuxFolderSettingsError> {
+        // Attempt every mechanism even if one fails, so e.g. a missing `gio`
+        // does not leave a stale `.directory` entry behind.
+        let mut first_error = None;
+        for mechanism in backend.mechanisms() {
+            let result = match mechanism {
+                LinuxBackend::GioMetadata => self.reset_via_gio_metadata(&path),
+                LinuxBackend::DirectoryFile => self.reset_via_directory_file(&path),
+                _ => unreachable!("mechanisms() only yields concrete backends"),
+            };
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        // Only drop the generated files once nothing references them anymore.
+        self.remove_generated_icons(&path)
+    }
+
+    /// Nudge file managers to redraw the folder, by bumping its mtime and/or
+    /// over D-Bus as configured. Best-effort: failures are ignored since the
+    /// icon change still applies.
+    fn refresh_file_managers<P: AsRef<Path>>(&self, path: P) {
         if self.bump_mtime {
             let now = filetime::FileTime::now();
             let _ = filetime::set_file_mtime(path.as_ref(), now);
         }
+        if let Some(notifier) = &self.notifier {
+            let _ = notifier.notify_changed(&[path.as_ref()]);
+        }
+    }
+
+    /// Like [`refresh_file_managers`](Self::refresh_file_managers) for a
+    /// regular file. The mtime of the containing folder is bumped instead of
+    /// the file's, which would otherwise look modified to backup and build
+    /// tools.
+    fn refresh_file_managers_for_file(&self, path: &Path) {
+        if self.bump_mtime
+            && let Some(parent) = path.parent()
+        {
+            let now = filetime::FileTime::now();
+            let _ = filetime::set_file_mtime(parent, now);
+        }
+        if let Some(notifier) = &self.notifier {
+            let _ = notifier.notify_changed(&[path]);
+        }
+    }
+
+    /// Drop cached thumbnails for the folder and its replaced icon files.
+    /// Best-effort, like the other refresh steps.
+    fn invalidate_thumbnails<P: AsRef<Path>>(&self, path: P, previous_icons: &[PathBuf]) {
+        if let Some(cache) = &self.thumbnail_cache {
+            for stale in
+                std::iter::once(path.as_ref()).chain(previous_icons.iter().map(|p| p.as_path()))
+            {
+                let _ = cache.invalidate_path(stale);
+            }
+        }
+    }
+
+    /// Whether `backend` already points the folder at a generated icon whose
+    /// content matches `icon_set`. Any failure to read the current state is
+    /// treated as "not applied" so the caller falls through to a full write.
+    fn is_already_applied<P: AsRef<Path>>(
+        &self,
+        backend: LinuxBackend,
+        path: P,
+        icon_set: &LinuxIconSet,
+    ) -> bool {
+        let Ok(dir) = std::path::absolute(path.as_ref()) else {
+            return false;
+        };
+        let current = match backend {
+            LinuxBackend::GioMetadata => self
+                .gvfs
+                .get(&dir, CUSTOM_ICON_KEY)
+                .ok()
+                .flatten()
+                .and_then(|uri| file_path(&uri)),
+            LinuxBackend::DirectoryFile => DesktopEntry::load(dir.join(".directory"))
+                .ok()
+                .and_then(|entry| entry.get(DESKTOP_ENTRY_GROUP, "Icon"))
+                .map(PathBuf::from),
+            _ => None,
+        };
+        current.is_some_and(|current| {
+            self.is_generated_icon_in(&dir, &current) && generated_icon_matches(&current, icon_set)
+        })
+    }
+
+    /// Whether `icon_path` names one of this provider's generated icon files
+    /// directly inside `dir`.
+    fn is_generated_icon_in(&self, dir: &Path, icon_path: &Path) -> bool {
+        icon_path.parent() == Some(dir)
+            && icon_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| GENERATED_ICON_EXTENSIONS.contains(&e))
+            && icon_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&self.generated_icon_prefix))
     }
 
     fn validate_folder<P: AsRef<Path>>(&self, directory: P) -> Result<()> {
         let dir = directory.as_ref();
-        if !dir.exists() {
-            return Err(LinuxFolderSettingsError::IconOperation(
-                dir.to_path_buf(),
-                "Directory does not exist on filesystem".to_string(),
-            )
-            .into());
-        }
+        validate_exists(dir)?;
         if !dir.is_dir() {
             return Err(LinuxFolderSettingsError::IconOperation(
                 dir.to_path_buf(),
@@ -190,17 +802,28 @@ impl LinuxFolderSettingsProvider {
         Ok(())
     }
 
-    /// Write the icon set to a hidden generated file and point the folder's
-    /// `metadata::custom-icon` attribute at it.
+    /// Refuse protected folders, unless protection is disabled.
+    fn check_protection(&self, dir: &Path) -> Result<()> {
+        if self.protect_folders
+            && let Some(kind) = ProtectedFolders::for_current_user().check(dir)
+        {
+            return Err(
+                LinuxFolderSettingsError::ProtectedFolder(dir.to_path_buf(), kind.clone()).into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Point the folder's `metadata::custom-icon` attribute at a generated
+    /// icon file.
     fn set_via_gio_metadata<P: AsRef<Path>>(
         &self,
         path: P,
-        icon_set: &LinuxIconSet,
+        icon_path: &Path,
     ) -> std::result::Result<(), LinuxFolderSettingsError> {
-        let icon_path = self.write_generated_icon(&path, icon_set)?;
         // `gio` stores the custom icon as an absolute `file://` URI.
-        let uri = format!("file://{}", icon_path.display());
-        gio_set_metadata(&path, "metadata::custom-icon", Some(&uri))?;
+        self.gvfs
+            .set(&path, CUSTOM_ICON_KEY, &file_uri(icon_path))?;
         Ok(())
     }
 
@@ -208,29 +831,32 @@ impl LinuxFolderSettingsProvider {
         &self,
         path: P,
     ) -> std::result::Result<(), LinuxFolderSettingsError> {
-        gio_set_metadata(&path, "metadata::custom-icon", None)?;
-        self.remove_generated_icons(&path)?;
-        Ok(())
+        Ok(self.gvfs.unset(&path, CUSTOM_ICON_KEY)?)
     }
 
-    /// Write the icon set to a generated file and reference it from a
-    /// `.directory` file (KDE Dolphin, XFCE Thunar). A single icon is scaled
-    /// by the file manager.
+    /// Reference a generated icon file from a `.directory` file (KDE Dolphin,
+    /// XFCE Thunar). A single icon is scaled by the file manager.
     fn set_via_directory_file<P: AsRef<Path>>(
         &self,
         path: P,
-        icon_set: &LinuxIconSet,
+        icon_path: &Path,
     ) -> std::result::Result<(), LinuxFolderSettingsError> {
-        let icon_path = self.write_generated_icon(&path, icon_set)?;
-
         let directory_path = path.as_ref().join(".directory");
-        // Load existing entry if present so other settings are preserved.
-        let mut conf = ini::Ini::load_from_file(&directory_path).unwrap_or_default();
+        // Load existing entry if present so other settings (e.g. Dolphin's
+        // view properties) are preserved exactly.
+        let mut entry = match DesktopEntry::load(&directory_path) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DesktopEntry::default(),
+            Err(e) => return Err(e.into()),
+        };
         // `icon_path` is absolute so file managers treat it as a file rather
         // than an icon-theme name.
-        conf.with_section(Some("Desktop Entry"))
-            .set("Icon", icon_path.to_string_lossy().as_ref());
-        conf.write_to_file(&directory_path)?;
+        entry.set(
+            DESKTOP_ENTRY_GROUP,
+            "Icon",
+            icon_path.to_string_lossy().as_ref(),
+        );
+        entry.save(&directory_path)?;
 
         Ok(())
     }
@@ -241,15 +867,14 @@ impl LinuxFolderSettingsProvider {
     ) -> std::result::Result<(), LinuxFolderSettingsError> {
         let directory_path = path.as_ref().join(".directory");
         if directory_path.exists() {
-            let conf = ini::Ini::load_from_file(&directory_path).unwrap_or_default();
-            match strip_icon_entry(conf) {
+            let entry = DesktopEntry::load(&directory_path)?;
+            match strip_icon_entry(entry) {
                 // Rewrite with remaining settings preserved...
-                Some(conf) => conf.write_to_file(&directory_path)?,
+                Some(entry) => entry.save(&directory_path)?,
                 // ...or drop the file entirely if nothing meaningful remains.
                 None => fs::remove_file(&directory_path)?,
             }
         }
-        self.remove_generated_icons(&path)?;
         Ok(())
     }
 
@@ -277,7 +902,17 @@ impl LinuxFolderSettingsProvider {
         let dir = std::path::absolute(directory.as_ref()).map_err(|e| {
             LinuxFolderSettingsError::IconOperation(directory.as_ref().to_path_buf(), e.to_string())
         })?;
+        self.write_icon_file(&dir, icon_set)
+    }
 
+    /// Write the icon set to a new, uniquely named file in the absolute
+    /// directory `dir`, as described for
+    /// [`write_generated_icon`](Self::write_generated_icon).
+    fn write_icon_file(
+        &self,
+        dir: &Path,
+        icon_set: &LinuxIconSet,
+    ) -> std::result::Result<PathBuf, LinuxFolderSettingsError> {
         if let Some(svg) = icon_set.svg() {
             let icon_path = dir.join(format!(
                 "{}-{}.svg",
@@ -292,7 +927,7 @@ impl LinuxFolderSettingsProvider {
 
         let largest = icon_set.largest().ok_or_else(|| {
             LinuxFolderSettingsError::IconOperation(
-                dir.clone(),
+                dir.to_path_buf(),
                 "Icon set contains neither an SVG nor any raster images".to_string(),
             )
         })?;
@@ -311,22 +946,128 @@ impl LinuxFolderSettingsProvider {
         &self,
         directory: P,
     ) -> std::result::Result<(), LinuxFolderSettingsError> {
-        for entry in fs::read_dir(directory.as_ref())? {
-            let entry = entry?;
-            let p = entry.path();
-            let has_generated_ext = p
-                .extension()
-                .and_then(|e| e.to_str())
-                .is_some_and(|e| GENERATED_ICON_EXTENSIONS.contains(&e));
-            if has_generated_ext
-                && let Some(name) = p.file_name().and_then(|n| n.to_str())
-                && name.starts_with(&self.generated_icon_prefix)
-            {
-                fs::remove_file(p)?;
-            }
+        for icon in self.generated_icons(directory)? {
+            fs::remove_file(icon)?;
         }
         Ok(())
     }
+
+    /// Absolute paths of the generated icon files in `directory`.
+    fn generated_icons<P: AsRef<Path>>(
+        &self,
+        directory: P,
+    ) -> std::result::Result<Vec<PathBuf>, LinuxFolderSettingsError> {
+        let dir = std::path::absolute(directory.as_ref())?;
+        let mut icons = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let p = entry?.path();
+            if self.is_generated_icon_in(&dir, &p) {
+                icons.push(p);
+            }
+        }
+        Ok(icons)
+    }
+}
+
+/// Check that `path` exists.
+fn validate_exists(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(LinuxFolderSettingsError::IconOperation(
+            path.to_path_buf(),
+            "Path does not exist".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Check that `path` exists and is not a directory, returning it absolute.
+fn validate_file(path: &Path) -> Result<PathBuf> {
+    validate_exists(path)?;
+    if path.is_dir() {
+        return Err(LinuxFolderSettingsError::IconOperation(
+            path.to_path_buf(),
+            "Path is a directory".to_string(),
+        )
+        .into());
+    }
+    Ok(std::path::absolute(path).map_err(LinuxFolderSettingsError::from)?)
+}
+
+/// Read a `[Desktop Entry]` key of the folder's `.directory` file.
+fn read_directory_key(path: &Path, key: &str, locale: Option<&str>) -> Result<Option<String>> {
+    validate_exists(path)?;
+    let entry = match DesktopEntry::load(path.join(".directory")) {
+        Ok(entry) => entry,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(LinuxFolderSettingsError::from(e).into()),
+    };
+    Ok(match locale {
+        Some(locale) => entry.get_localized(DESKTOP_ENTRY_GROUP, key, locale),
+        None => entry.get(DESKTOP_ENTRY_GROUP, key),
+    })
+}
+
+/// The locale messages are shown in, from the first set of `LC_ALL`,
+/// `LC_MESSAGES` and `LANG`. The `C`/`POSIX` locale has no translations.
+fn message_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .filter(|locale| !matches!(locale.as_str(), "C" | "POSIX") && !locale.starts_with("C."))
+}
+
+/// Accept only `emblem-*` names the active icon theme (or its fallbacks)
+/// can draw.
+fn validate_emblem(emblem: &str) -> Result<()> {
+    let known = emblem.starts_with(EMBLEM_PREFIX)
+        && active_themes().iter().any(|theme| theme.contains(emblem));
+    if !known {
+        return Err(LinuxFolderSettingsError::UnknownEmblem(emblem.to_string()).into());
+    }
+    Ok(())
+}
+
+/// Run `op` against each backend in `chain` until one succeeds, resolving
+/// `Auto` along the way and collecting the error of every failed attempt.
+///
+/// A chain with a single backend returns that backend's error unchanged;
+/// longer chains report [`LinuxFolderSettingsError::AllBackendsFailed`].
+fn try_backends<I, F>(
+    detector: &DesktopDetector,
+    chain: I,
+    mut op: F,
+) -> std::result::Result<LinuxIconOutcome, LinuxFolderSettingsError>
+where
+    I: IntoIterator<Item = LinuxBackend>,
+    F: FnMut(LinuxBackend) -> std::result::Result<IconUpdate, LinuxFolderSettingsError>,
+{
+    let mut failed_attempts = Vec::new();
+    for backend in chain {
+        let resolved = match backend.resolve(detector) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                failed_attempts.push((backend, e));
+                continue;
+            }
+        };
+        match op(resolved) {
+            Ok(update) => {
+                return Ok(LinuxIconOutcome {
+                    backend: resolved,
+                    update,
+                    failed_attempts,
+                });
+            }
+            Err(e) => failed_attempts.push((resolved, e)),
+        }
+    }
+
+    match failed_attempts.len() {
+        1 => Err(failed_attempts.remove(0).1),
+        _ => Err(LinuxFolderSettingsError::AllBackendsFailed(failed_attempts)),
+    }
 }
 
 /// Remove the `Icon` key from a parsed `.directory` config, returning the
@@ -335,58 +1076,69 @@ impl LinuxFolderSettingsProvider {
 ///
 /// Kept free of filesystem access so the preserve-vs-delete decision can be
 /// unit-tested directly.
-fn strip_icon_entry(mut conf: ini::Ini) -> Option<ini::Ini> {
-    // Surgically drop only the Icon key, keeping other settings.
-    conf.with_section(Some("Desktop Entry")).delete(&"Icon");
+fn strip_icon_entry(entry: DesktopEntry) -> Option<DesktopEntry> {
+    strip_entry(entry, "Icon", None)
+}
 
-    // Drop the section header if Icon was its only key.
-    let section_empty = conf
-        .section(Some("Desktop Entry"))
-        .map(|s| s.is_empty())
-        .unwrap_or(true);
-    if section_empty {
-        conf.delete(Some("Desktop Entry"));
+/// Remove one `[Desktop Entry]` key, as [`strip_icon_entry`] does for `Icon`.
+fn strip_entry(mut entry: DesktopEntry, key: &str, locale: Option<&str>) -> Option<DesktopEntry> {
+    // Surgically drop only this key, keeping other settings.
+    let removed = match locale {
+        Some(locale) => entry.remove_localized(DESKTOP_ENTRY_GROUP, key, locale),
+        None => entry.remove(DESKTOP_ENTRY_GROUP, key),
+    };
+    if !removed {
+        return entry.has_entries().then_some(entry);
     }
 
-    // Keep the file only if some section still retains a key.
-    if conf.iter().any(|(_, props)| !props.is_empty()) {
-        Some(conf)
-    } else {
-        None
+    // Drop the group header if the key was its only one.
+    if entry.is_group_empty(DESKTOP_ENTRY_GROUP) {
+        entry.remove_group(DESKTOP_ENTRY_GROUP);
     }
+
+    // Keep the file only if some group still retains a key.
+    entry.has_entries().then_some(entry)
 }
 
-/// Set or unset a GVFS metadata attribute via the `gio` CLI.
-fn gio_set_metadata<P: AsRef<Path>>(
-    path: P,
-    key: &str,
-    value: Option<&str>,
-) -> std::result::Result<(), LinuxFolderSettingsError> {
-    let mut cmd = Command::new("gio");
-    cmd.arg("set");
-    if value.is_none() {
-        cmd.arg("-t").arg("unset");
-    }
-    cmd.arg(path.as_ref()).arg(key);
-    if let Some(value) = value {
-        cmd.arg(value);
-    }
-
-    let output = cmd
-        .output()
-        .map_err(|e| LinuxFolderSettingsError::Gio(format!("failed to spawn gio: {e}")))?;
-
-    if !output.status.success() {
-        return Err(LinuxFolderSettingsError::Gio(
-            String::from_utf8_lossy(&output.stderr).trim().to_string(),
-        ));
+/// Whether the generated icon file at `icon_path` holds the same content that
+/// `write_generated_icon` would produce for `icon_set`: the exact SVG markup
+/// if the set has one, otherwise the pixels of its largest raster image.
+fn generated_icon_matches(icon_path: &Path, icon_set: &LinuxIconSet) -> bool {
+    let ext = icon_path.extension().and_then(|e| e.to_str());
+    if let Some(svg) = icon_set.svg() {
+        return ext == Some("svg")
+            && fs::read_to_string(icon_path).is_ok_and(|existing| existing == svg);
     }
-    Ok(())
+    let Some(largest) = icon_set.largest() else {
+        return false;
+    };
+    // Compare decoded pixels rather than bytes, since PNG encoding is not
+    // guaranteed to be byte-for-byte stable.
+    ext == Some("png")
+        && image::open(icon_path).is_ok_and(|existing| {
+            existing.to_rgba8().as_raw() == largest.image.to_rgba8().as_raw()
+                && existing.width() == largest.image.width()
+                && existing.height() == largest.image.height()
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::folder_settings::sys::linux::{DesktopEnvironment, GvfsError};
+
+    /// Detect the backend from an `XDG_CURRENT_DESKTOP` value alone.
+    fn detect_backend(
+        desktop: &str,
+    ) -> std::result::Result<LinuxBackend, LinuxFolderSettingsError> {
+        let env = DesktopEnvironment {
+            xdg_current_desktop: Some(desktop.to_string()),
+            ..Default::default()
+        };
+        DesktopDetector::default()
+            .detect_from(&env)
+            .map(|detection| detection.backend)
+    }
 
     #[test]
     fn detect_backend_gnome_family_uses_gio() {
@@ -427,59 +1179,195 @@ mod tests {
     #[test]
     fn explicit_backend_resolves_to_itself() {
         assert_eq!(
-            LinuxBackend::GioMetadata.resolve().unwrap(),
+            LinuxBackend::GioMetadata
+                .resolve(&DesktopDetector::default())
+                .unwrap(),
             LinuxBackend::GioMetadata
         );
         assert_eq!(
-            LinuxBackend::DirectoryFile.resolve().unwrap(),
             LinuxBackend::DirectoryFile
+                .resolve(&DesktopDetector::default())
+                .unwrap(),
+            LinuxBackend::DirectoryFile
+        );
+        assert_eq!(
+            LinuxBackend::All
+                .resolve(&DesktopDetector::default())
+                .unwrap(),
+            LinuxBackend::All
         );
     }
 
-    fn ini_of(s: &str) -> ini::Ini {
-        ini::Ini::load_from_str(s).unwrap()
+    #[test]
+    fn all_backend_covers_every_mechanism() {
+        assert_eq!(
+            LinuxBackend::All.mechanisms(),
+            &[LinuxBackend::GioMetadata, LinuxBackend::DirectoryFile]
+        );
+        assert_eq!(
+            LinuxBackend::DirectoryFile.mechanisms(),
+            &[LinuxBackend::DirectoryFile]
+        );
+    }
+
+    fn raster_set(pixel: [u8; 4]) -> LinuxIconSet<'static> {
+        let img = image::RgbaImage::from_pixel(8, 8, image::Rgba(pixel));
+        LinuxIconSet::from_icons([crate::icon::sys::linux::LinuxIconImage {
+            size: 8,
+            image: std::borrow::Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+        }])
+        .unwrap()
+    }
+
+    #[test]
+    fn generated_icon_matches_compares_png_pixels() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("icon.png");
+        raster_set([255, 0, 0, 255])
+            .largest()
+            .unwrap()
+            .image
+            .save(&path)
+            .unwrap();
+
+        assert!(generated_icon_matches(&path, &raster_set([255, 0, 0, 255])));
+        assert!(!generated_icon_matches(
+            &path,
+            &raster_set([0, 255, 0, 255])
+        ));
+    }
+
+    #[test]
+    fn generated_icon_matches_compares_svg_markup() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\"/>";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("icon.svg");
+        fs::write(&path, svg).unwrap();
+
+        let mut set = LinuxIconSet::new();
+        set.set_svg(svg).unwrap();
+        assert!(generated_icon_matches(&path, &set));
+
+        // A raster-only set never matches an SVG file.
+        assert!(!generated_icon_matches(&path, &raster_set([0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn try_backends_stops_at_first_success() {
+        let mut tried = Vec::new();
+        let outcome = try_backends(
+            &DesktopDetector::default(),
+            [LinuxBackend::DirectoryFile, LinuxBackend::GioMetadata],
+            |backend| {
+                tried.push(backend);
+                Ok(IconUpdate::Applied)
+            },
+        )
+        .unwrap();
+        assert_eq!(outcome.backend, LinuxBackend::DirectoryFile);
+        assert!(outcome.failed_attempts.is_empty());
+        assert_eq!(tried, [LinuxBackend::DirectoryFile]);
+    }
+
+    #[test]
+    fn try_backends_records_failed_attempts() {
+        let outcome = try_backends(
+            &DesktopDetector::default(),
+            [LinuxBackend::GioMetadata, LinuxBackend::DirectoryFile],
+            |backend| match backend {
+                LinuxBackend::GioMetadata => {
+                    Err(GvfsError::CommandFailed("not supported".to_string()).into())
+                }
+                _ => Ok(IconUpdate::Applied),
+            },
+        )
+        .unwrap();
+        assert_eq!(outcome.backend, LinuxBackend::DirectoryFile);
+        assert_eq!(outcome.failed_attempts.len(), 1);
+        assert!(matches!(
+            outcome.failed_attempts[0],
+            (LinuxBackend::GioMetadata, LinuxFolderSettingsError::Gio(_))
+        ));
+    }
+
+    #[test]
+    fn try_backends_single_failure_returns_original_error() {
+        let result = try_backends(
+            &DesktopDetector::default(),
+            [LinuxBackend::GioMetadata],
+            |_| Err(LinuxFolderSettingsError::Error("boom".to_string())),
+        );
+        assert!(matches!(result, Err(LinuxFolderSettingsError::Error(_))));
+    }
+
+    #[test]
+    fn try_backends_reports_every_failure() {
+        let result = try_backends(
+            &DesktopDetector::default(),
+            [LinuxBackend::GioMetadata, LinuxBackend::DirectoryFile],
+            |_| Err(LinuxFolderSettingsError::Error("boom".to_string())),
+        );
+        match result {
+            Err(LinuxFolderSettingsError::AllBackendsFailed(attempts)) => {
+                assert_eq!(attempts.len(), 2);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    fn entry_of(s: &str) -> DesktopEntry {
+        DesktopEntry::parse(s)
     }
 
     #[test]
     fn strip_icon_entry_deletes_file_when_only_icon() {
-        let conf = ini_of("[Desktop Entry]\nIcon=/tmp/foo.png\n");
-        assert!(strip_icon_entry(conf).is_none());
+        let entry = entry_of("[Desktop Entry]\nIcon=/tmp/foo.png\n");
+        assert!(strip_icon_entry(entry).is_none());
     }
 
     #[test]
     fn strip_icon_entry_preserves_other_keys_in_same_section() {
-        let conf = ini_of("[Desktop Entry]\nIcon=/tmp/foo.png\nName=Docs\n");
-        let result = strip_icon_entry(conf).expect("file should be kept");
-        let section = result.section(Some("Desktop Entry")).unwrap();
-        assert_eq!(section.get("Name"), Some("Docs"));
-        assert!(section.get("Icon").is_none());
+        let entry = entry_of("[Desktop Entry]\nIcon=/tmp/foo.png\nName=Docs\n");
+        let result = strip_icon_entry(entry).expect("file should be kept");
+        assert_eq!(result.to_string(), "[Desktop Entry]\nName=Docs\n");
     }
 
     #[test]
     fn strip_icon_entry_preserves_other_sections() {
-        let conf = ini_of("[Desktop Entry]\nIcon=/tmp/foo.png\n\n[Settings]\nSortOrder=name\n");
-        let result = strip_icon_entry(conf).expect("file should be kept");
+        let entry = entry_of("[Desktop Entry]\nIcon=/tmp/foo.png\n\n[Settings]\nSortOrder=name\n");
+        let result = strip_icon_entry(entry).expect("file should be kept");
         // Icon-only Desktop Entry section is dropped...
-        assert!(result.section(Some("Desktop Entry")).is_none());
+        assert!(result.groups().all(|g| g != "Desktop Entry"));
         // ...but unrelated sections survive.
+        assert_eq!(result.to_string(), "[Settings]\nSortOrder=name\n");
+    }
+
+    #[test]
+    fn strip_icon_entry_preserves_comments_and_localized_icons() {
+        let entry = entry_of("# keep me\n[Desktop Entry]\nIcon=/tmp/foo.png\nIcon[de]=ordner\n");
+        let result = strip_icon_entry(entry).expect("file should be kept");
         assert_eq!(
-            result.section(Some("Settings")).unwrap().get("SortOrder"),
-            Some("name")
+            result.to_string(),
+            "# keep me\n[Desktop Entry]\nIcon[de]=ordner\n"
         );
     }
 
     #[test]
     fn strip_icon_entry_deletes_file_when_empty() {
-        assert!(strip_icon_entry(ini::Ini::new()).is_none());
+        assert!(strip_icon_entry(DesktopEntry::default()).is_none());
     }
 
     #[test]
     fn strip_icon_entry_is_idempotent_when_no_icon() {
-        let conf = ini_of("[Desktop Entry]\nName=Docs\n");
-        let result = strip_icon_entry(conf).expect("file should be kept");
-        assert_eq!(
-            result.section(Some("Desktop Entry")).unwrap().get("Name"),
-            Some("Docs")
-        );
+        let entry = entry_of("[Desktop Entry]\nName=Docs\n");
+        let result = strip_icon_entry(entry).expect("file should be kept");
+        assert_eq!(result.to_string(), "[Desktop Entry]\nName=Docs\n");
+    }
+    #[test]
+    fn strip_entry_removes_only_the_given_locale() {
+        let entry = entry_of("[Desktop Entry]\nName=Docs\nName[de]=Dokumente\n");
+        let result = strip_entry(entry, "Name", Some("de")).expect("file should be kept");
+        assert_eq!(result.to_string(), "[Desktop Entry]\nName=Docs\n");
+        assert!(strip_entry(result, "Name", None).is_none());
     }
 }
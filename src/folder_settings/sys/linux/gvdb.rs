@@ -0,0 +1,327 @@
+//! Reader for GVDB, the hash-table file format of dconf databases (and
+//! `gschemas.compiled`).
+
+/// `"GVariant"` as two little-endian words.
+const SIGNATURE: &[u8; 8] = b"GVariant";
+/// The signature as written by a machine of the opposite endianness.
+const SWAPPED_SIGNATURE: &[u8; 8] = b"raVGtnai";
+
+const HEADER_SIZE: usize = 24;
+const HASH_ITEM_SIZE: usize = 24;
+const NO_PARENT: u32 = u32::MAX;
+
+/// Deeper parent chains are treated as corrupt, guarding against cycles.
+const MAX_KEY_DEPTH: usize = 256;
+
+/// A GVDB file held in memory.
+pub(crate) struct Gvdb {
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+/// One hash table inside a GVDB file. Arrays are kept as (offset, length)
+/// pairs into the file.
+pub(crate) struct GvdbTable<'a> {
+    file: &'a Gvdb,
+    bloom_shift: u32,
+    bloom: (usize, usize),
+    buckets: (usize, usize),
+    items: (usize, usize),
+}
+
+struct HashItem<'a> {
+    hash: u32,
+    parent: u32,
+    key: &'a [u8],
+    kind: u8,
+    value: (u32, u32),
+}
+
+impl Gvdb {
+    pub(crate) fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let big_endian = match data.get(..8) {
+            Some(sig) if sig == SIGNATURE => false,
+            Some(sig) if sig == SWAPPED_SIGNATURE => true,
+            _ => return Err("not a GVDB file".to_string()),
+        };
+        if data.len() < HEADER_SIZE {
+            return Err("truncated header".to_string());
+        }
+        let this = Self { data, big_endian };
+        if this.u32_at(8) != Some(0) {
+            return Err("unsupported GVDB version".to_string());
+        }
+        Ok(this)
+    }
+
+    /// The root hash table.
+    pub(crate) fn root(&self) -> Result<GvdbTable<'_>, String> {
+        let start = self.u32_at(16).ok_or("truncated header")?;
+        let end = self.u32_at(20).ok_or("truncated header")?;
+        GvdbTable::parse(self, start, end)
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn slice(&self, start: u32, end: u32) -> Option<&[u8]> {
+        self.data.get(start as usize..end as usize)
+    }
+}
+
+impl<'a> GvdbTable<'a> {
+    fn parse(file: &'a Gvdb, start: u32, end: u32) -> Result<Self, String> {
+        let table = file.slice(start, end).ok_or("hash table out of bounds")?;
+        let start = start as usize;
+        let header = file.u32_at(start).ok_or("truncated hash table")?;
+        let n_buckets = file.u32_at(start + 4).ok_or("truncated hash table")? as usize;
+        let n_bloom = (header & ((1 << 27) - 1)) as usize;
+
+        let items_offset = 8 + (n_bloom + n_buckets) * 4;
+        if items_offset > table.len() {
+            return Err("truncated hash table".to_string());
+        }
+        Ok(Self {
+            file,
+            bloom_shift: header >> 27,
+            bloom: (start + 8, n_bloom),
+            buckets: (start + 8 + n_bloom * 4, n_buckets),
+            items: (
+                start + items_offset,
+                (table.len() - items_offset) / HASH_ITEM_SIZE,
+            ),
+        })
+    }
+
+    /// The serialized `v` GVariant stored under `key`.
+    pub(crate) fn value(&self, key: &str) -> Option<&'a [u8]> {
+        let item = self.lookup(key, b'v')?;
+        self.file.slice(item.value.0, item.value.1)
+    }
+
+    /// The nested hash table stored under `key`.
+    pub(crate) fn table(&self, key: &str) -> Option<GvdbTable<'a>> {
+        let item = self.lookup(key, b'H')?;
+        GvdbTable::parse(self.file, item.value.0, item.value.1).ok()
+    }
+
+    fn lookup(&self, key: &str, kind: u8) -> Option<HashItem<'a>> {
+        let n_buckets = self.buckets.1;
+        if n_buckets == 0 {
+            return None;
+        }
+        let hash = djb_hash(key.as_bytes());
+        if !self.bloom_contains(hash) {
+            return None;
+        }
+
+        let n_items = self.items.1;
+        let bucket = hash as usize % n_buckets;
+        let first = self.word(self.buckets, bucket)? as usize;
+        let last = if bucket == n_buckets - 1 {
+            n_items
+        } else {
+            (self.word(self.buckets, bucket + 1)? as usize).min(n_items)
+        };
+
+        (first..last).filter_map(|i| self.item(i)).find(|item| {
+            item.hash == hash && item.kind == kind && self.key_matches(item, key.as_bytes())
+        })
+    }
+
+    fn bloom_contains(&self, hash: u32) -> bool {
+        let n_bloom = self.bloom.1;
+        if n_bloom == 0 {
+            return true;
+        }
+        let word = (hash / 32) as usize % n_bloom;
+        let mask = (1 << (hash & 31)) | (1 << ((hash >> self.bloom_shift) & 31));
+        self.word(self.bloom, word)
+            .is_some_and(|bits| bits & mask == mask)
+    }
+
+    /// Whether `item`'s key, prefixed by its parents' keys, is `key`.
+    fn key_matches(&self, item: &HashItem, key: &[u8]) -> bool {
+        let mut item_key = item.key;
+        let mut parent = item.parent;
+        let mut rest = key;
+        for _ in 0..MAX_KEY_DEPTH {
+            let Some(prefix) = rest.strip_suffix(item_key) else {
+                return false;
+            };
+            rest = prefix;
+            if parent == NO_PARENT {
+                return rest.is_empty();
+            }
+            match self.item(parent as usize) {
+                Some(p) => {
+                    item_key = p.key;
+                    parent = p.parent;
+                }
+                None => return false,
+            }
+        }
+        false
+    }
+
+    fn item(&self, index: usize) -> Option<HashItem<'a>> {
+        if index >= self.items.1 {
+            return None;
+        }
+        let offset = self.items.0 + index * HASH_ITEM_SIZE;
+        let key_start = self.file.u32_at(offset + 8)?;
+        let key_size = self.file.u16_at(offset + 12)?;
+        Some(HashItem {
+            hash: self.file.u32_at(offset)?,
+            parent: self.file.u32_at(offset + 4)?,
+            key: self
+                .file
+                .slice(key_start, key_start.checked_add(key_size.into())?)?,
+            kind: *self.file.data.get(offset + 14)?,
+            value: (
+                self.file.u32_at(offset + 16)?,
+                self.file.u32_at(offset + 20)?,
+            ),
+        })
+    }
+
+    /// The `index`th word of a bloom or bucket array.
+    fn word(&self, (start, len): (usize, usize), index: usize) -> Option<u32> {
+        if index >= len {
+            return None;
+        }
+        self.file.u32_at(start + index * 4)
+    }
+}
+
+/// GVDB's key hash: djb2 over signed chars.
+fn djb_hash(key: &[u8]) -> u32 {
+    key.iter().fold(5381u32, |hash, &c| {
+        hash.wrapping_mul(33).wrapping_add(c as i8 as u32)
+    })
+}
+
+/// The string inside a serialized `v` GVariant holding an `s`, or `None` for
+/// any other type.
+pub(crate) fn variant_str(variant: &[u8]) -> Option<&str> {
+    // A variant is its child's data, a NUL, then the child's type string.
+    let split = variant.iter().rposition(|&b| b == 0)?;
+    let (data, signature) = (&variant[..split], &variant[split + 1..]);
+    if signature != b"s" {
+        return None;
+    }
+    std::str::from_utf8(data.strip_suffix(b"\0")?).ok()
+}
+
+/// The default of a string key in `gschemas.compiled`: the first child of
+/// the `(s...)` tuple stored for the key, followed by the key's options.
+pub(crate) fn schema_default_str(variant: &[u8]) -> Option<&str> {
+    let split = variant.iter().rposition(|&b| b == 0)?;
+    let (data, signature) = (&variant[..split], &variant[split + 1..]);
+    let string = if signature == b"(s)" {
+        data
+    } else if signature.starts_with(b"(s") {
+        // With options after it, the string's end is the tuple's last
+        // little-endian framing offset, sized by the tuple.
+        let size = match data.len() {
+            0..=0xff => 1,
+            0x100..=0xffff => 2,
+            _ => 4,
+        };
+        let offset = data.get(data.len().checked_sub(size)?..)?;
+        let end = offset
+            .iter()
+            .rev()
+            .fold(0usize, |end, &b| end << 8 | usize::from(b));
+        data.get(..end)?
+    } else {
+        return None;
+    };
+    std::str::from_utf8(string.strip_suffix(b"\0")?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Gvdb {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/dconf")
+            .join(name);
+        Gvdb::parse(std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn djb_hash_matches_gvdb() {
+        assert_eq!(djb_hash(b""), 5381);
+        assert_eq!(djb_hash(b"a"), 5381 * 33 + 97);
+        // Bytes above 0x7f are sign-extended.
+        assert_eq!(djb_hash(&[0xff]), (5381u32 * 33).wrapping_sub(1));
+    }
+
+    #[test]
+    fn looks_up_values_through_parent_chain() {
+        let db = fixture("user");
+        let root = db.root().unwrap();
+        let value = root
+            .value("/org/gnome/desktop/interface/icon-theme")
+            .unwrap();
+        assert_eq!(variant_str(value), Some("Papirus"));
+        // Directory items are lists, not values.
+        assert!(root.value("/org/gnome/").is_none());
+        assert!(root.value("icon-theme").is_none());
+        assert!(root.value("/org/gnome/desktop/interface/missing").is_none());
+    }
+
+    #[test]
+    fn reads_nested_tables() {
+        let db = fixture("locked");
+        let locks = db.root().unwrap().table(".locks").unwrap();
+        assert!(
+            locks
+                .value("/org/gnome/desktop/interface/icon-theme")
+                .is_some()
+        );
+        assert!(
+            fixture("defaults")
+                .root()
+                .unwrap()
+                .table(".locks")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn variant_str_requires_string_type() {
+        assert_eq!(variant_str(b"Adwaita\0\0s"), Some("Adwaita"));
+        assert_eq!(variant_str(b"\x01\0b"), None);
+    }
+
+    #[test]
+    fn schema_defaults_skip_the_options() {
+        assert_eq!(schema_default_str(b"Yaru\0\0(s)"), Some("Yaru"));
+        let with_choices = b"Yaru\0\0\0\0c\0\0\0\0\0\0\0\xffAdwaita\0\0\0\0\xff\x05\0(s(yau))";
+        assert_eq!(schema_default_str(with_choices), Some("Yaru"));
+        assert_eq!(schema_default_str(b"\x01\0(b)"), None);
+    }
+
+    #[test]
+    fn rejects_non_gvdb_data() {
+        assert!(Gvdb::parse(b"not a database".to_vec()).is_err());
+    }
+}
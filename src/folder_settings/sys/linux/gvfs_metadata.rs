@@ -0,0 +1,687 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::zvariant::Value;
+
+use super::error::GvfsError;
+use super::gvfs_metatree::{GvfsMetadataTree, MetadataValue};
+
+/// How long an operation may take before it is abandoned.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prefix of GVFS metadata attributes; the D-Bus interface takes keys
+/// without it.
+const METADATA_NAMESPACE: &str = "metadata::";
+
+const BUS_NAME: &str = "org.gtk.vfs.Metadata";
+const OBJECT_PATH: &str = "/org/gtk/vfs/metadata";
+const INTERFACE: &str = "org.gtk.vfs.Metadata";
+
+/// D-Bus errors meaning nobody owns (or can be activated for) the bus name.
+const UNAVAILABLE_ERRORS: [&str; 3] = [
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.NameHasNoOwner",
+    "org.freedesktop.DBus.Error.Spawn.ServiceNotFound",
+];
+
+/// How GVFS metadata attributes are read and written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GvfsTransport {
+    /// Spawn a `gio` command-line binary (`gio set` / `gio info`).
+    Cli {
+        /// The binary to run, looked up on `PATH` if not absolute.
+        program: PathBuf,
+    },
+    /// Call `gvfsd-metadata` directly over its `org.gtk.vfs.Metadata`
+    /// D-Bus interface.
+    DBus {
+        /// Bus address to connect to; `None` uses the session bus.
+        address: Option<String>,
+    },
+}
+
+/// Client for the GVFS metadata store that GNOME-family file managers read
+/// `metadata::custom-icon` from.
+///
+/// The default spawns `gio`. [`GvfsMetadata::dbus`] instead writes through
+/// `gvfsd-metadata`'s `Set`/`Remove`/`Move` methods, avoiding a process per
+/// call, and reads the tree files directly (see [`GvfsMetadataTree`]). Both
+/// transports enforce a timeout and report [`GvfsError`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GvfsMetadata {
+    transport: GvfsTransport,
+    timeout: Duration,
+}
+
+impl Default for GvfsMetadata {
+    fn default() -> Self {
+        Self::gio()
+    }
+}
+
+impl GvfsMetadata {
+    /// Use the given transport with the default timeout.
+    pub fn new(transport: GvfsTransport) -> Self {
+        Self {
+            transport,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Spawn `gio` from `PATH`.
+    pub fn gio() -> Self {
+        Self::new(GvfsTransport::Cli {
+            program: PathBuf::from("gio"),
+        })
+    }
+
+    /// Talk to `gvfsd-metadata` on the session bus.
+    pub fn dbus() -> Self {
+        Self::new(GvfsTransport::DBus { address: None })
+    }
+
+    /// Abandon operations that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn transport(&self) -> &GvfsTransport {
+        &self.transport
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Read a string attribute. Returns `None` if the attribute is unset.
+    pub fn get<P: AsRef<Path>>(&self, path: P, key: &str) -> Result<Option<String>, GvfsError> {
+        Ok(match self.read(path.as_ref(), key)? {
+            Some(MetadataValue::String(value)) => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Read a string-list attribute such as `metadata::emblems`. Returns
+    /// `None` if the attribute is unset.
+    pub fn get_list<P: AsRef<Path>>(
+        &self,
+        path: P,
+        key: &str,
+    ) -> Result<Option<Vec<String>>, GvfsError> {
+        Ok(match self.read(path.as_ref(), key)? {
+            Some(MetadataValue::List(values)) => Some(values),
+            // `gio info` prints lists as `[a, b]`.
+            Some(MetadataValue::String(value)) => parse_gio_stringv(&value),
+            None => None,
+        })
+    }
+
+    /// Set a string attribute.
+    pub fn set<P: AsRef<Path>>(&self, path: P, key: &str, value: &str) -> Result<(), GvfsError> {
+        self.write(
+            path.as_ref(),
+            key,
+            Some(MetadataValue::String(value.to_string())),
+        )
+    }
+
+    /// Set a string-list attribute such as `metadata::emblems`.
+    pub fn set_list<P: AsRef<Path>, S: AsRef<str>>(
+        &self,
+        path: P,
+        key: &str,
+        values: &[S],
+    ) -> Result<(), GvfsError> {
+        let values = values.iter().map(|v| v.as_ref().to_string()).collect();
+        self.write(path.as_ref(), key, Some(MetadataValue::List(values)))
+    }
+
+    /// Unset an attribute. Unsetting a missing attribute succeeds.
+    pub fn unset<P: AsRef<Path>>(&self, path: P, key: &str) -> Result<(), GvfsError> {
+        self.write(path.as_ref(), key, None)
+    }
+
+    /// Read an attribute. The CLI transport can't tell lists from strings,
+    /// so it always returns `gio info`'s text.
+    fn read(&self, path: &Path, key: &str) -> Result<Option<MetadataValue>, GvfsError> {
+        metadata_key(key)?;
+        match &self.transport {
+            GvfsTransport::Cli { program } => {
+                let mut cmd = Command::new(program);
+                cmd.args(["info", "-a", key]).arg(path);
+                let stdout = self.run(program, cmd)?;
+                Ok(parse_gio_info_attribute(&stdout, key).map(MetadataValue::String))
+            }
+            GvfsTransport::DBus { address } => {
+                // gvfsd-metadata has no read method; clients read the tree
+                // files directly, as GIO does.
+                let connection = self.connect(address.as_deref())?;
+                let (treefile, tree_path) = self.locate(&connection, path)?;
+                let tree = match GvfsMetadataTree::open(&treefile) {
+                    Ok(tree) => tree,
+                    Err(GvfsError::Read(_, e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(None);
+                    }
+                    Err(e) => return Err(e),
+                };
+                Ok(tree.get(&tree_path, key).cloned())
+            }
+        }
+    }
+
+    /// Drop every metadata attribute of `path`, e.g. after deleting it.
+    /// Only supported by the D-Bus transport.
+    pub fn remove<P: AsRef<Path>>(&self, path: P) -> Result<(), GvfsError> {
+        let GvfsTransport::DBus { address } = &self.transport else {
+            return Err(GvfsError::Unsupported(
+                "removing metadata via gio".to_string(),
+            ));
+        };
+        let connection = self.connect(address.as_deref())?;
+        let (treefile, tree_path) = self.locate(&connection, path.as_ref())?;
+        self.call(
+            &connection,
+            "Remove",
+            &(bytestring(treefile.as_os_str()), bytestring(&tree_path)),
+        )
+    }
+
+    /// Carry `from`'s metadata over to `to`, e.g. after renaming it. Both
+    /// paths must be in the same metadata tree. Only supported by the D-Bus
+    /// transport.
+    pub fn move_metadata<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<(), GvfsError> {
+        let GvfsTransport::DBus { address } = &self.transport else {
+            return Err(GvfsError::Unsupported(
+                "moving metadata via gio".to_string(),
+            ));
+        };
+        let connection = self.connect(address.as_deref())?;
+        let (treefile, from_path) = self.locate(&connection, from.as_ref())?;
+        let (to_treefile, to_path) = self.locate(&connection, to.as_ref())?;
+        if to_treefile != treefile {
+            return Err(GvfsError::Unsupported(
+                "moving metadata between trees".to_string(),
+            ));
+        }
+        self.call(
+            &connection,
+            "Move",
+            &(
+                bytestring(treefile.as_os_str()),
+                bytestring(&from_path),
+                bytestring(&to_path),
+            ),
+        )
+    }
+
+    fn write(&self, path: &Path, key: &str, value: Option<MetadataValue>) -> Result<(), GvfsError> {
+        let name = metadata_key(key)?;
+        match &self.transport {
+            GvfsTransport::Cli { program } => {
+                let mut cmd = Command::new(program);
+                cmd.arg("set");
+                match &value {
+                    None => cmd.args(["-t", "unset"]),
+                    Some(MetadataValue::List(_)) => cmd.args(["-t", "stringv"]),
+                    Some(MetadataValue::String(_)) => &mut cmd,
+                };
+                cmd.arg(path).arg(key);
+                match value {
+                    Some(MetadataValue::String(value)) => cmd.arg(value),
+                    Some(MetadataValue::List(values)) => cmd.args(values),
+                    None => &mut cmd,
+                };
+                self.run(program, cmd).map(|_| ())
+            }
+            GvfsTransport::DBus { address } => {
+                let connection = self.connect(address.as_deref())?;
+                let (treefile, tree_path) = self.locate(&connection, path)?;
+                // gvfsd-metadata unsets keys whose value is the byte 0.
+                let value = match value {
+                    Some(MetadataValue::String(value)) => Value::from(value),
+                    Some(MetadataValue::List(values)) => Value::from(values),
+                    None => Value::U8(0),
+                };
+                let data = HashMap::from([(name, value)]);
+                self.call(
+                    &connection,
+                    "Set",
+                    &(
+                        bytestring(treefile.as_os_str()),
+                        bytestring(&tree_path),
+                        data,
+                    ),
+                )
+            }
+        }
+    }
+
+    /// Run a `gio` command to completion within the timeout, returning its
+    /// stdout. The pipes are drained on their own threads, so output larger
+    /// than the pipe buffer doesn't stall the command.
+    fn run(&self, program: &Path, mut cmd: Command) -> Result<String, GvfsError> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| GvfsError::Spawn(program.to_path_buf(), e))?;
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|e| GvfsError::Spawn(program.to_path_buf(), e))?
+            {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(GvfsError::Timeout(self.timeout));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            return Err(GvfsError::CommandFailed(
+                String::from_utf8_lossy(&stderr).trim().to_string(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    fn connect(&self, address: Option<&str>) -> Result<Connection, GvfsError> {
+        let builder = match address {
+            Some(address) => Builder::address(address).map_err(|e| self.dbus_error(e))?,
+            None => Builder::session().map_err(|e| self.dbus_error(e))?,
+        };
+        builder
+            .method_timeout(self.timeout)
+            .build()
+            .map_err(|e| self.dbus_error(e))
+    }
+
+    fn call<B>(&self, connection: &Connection, method: &str, body: &B) -> Result<(), GvfsError>
+    where
+        B: zbus::export::serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        connection
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE), method, body)
+            .map(|_| ())
+            .map_err(|e| self.dbus_error(e))
+    }
+
+    /// The metadata tree file and the path within it that gvfsd-metadata
+    /// uses for `path`: the `home` tree for paths under `$HOME`, otherwise
+    /// the tree of the device `path` lives on, relative to its mount point.
+    fn locate(
+        &self,
+        connection: &Connection,
+        path: &Path,
+    ) -> Result<(PathBuf, PathBuf), GvfsError> {
+        let path = resolve(path).map_err(|_| GvfsError::NoTree(path.to_path_buf()))?;
+        let data_dir = gvfs_data_dir().ok_or_else(|| GvfsError::NoTree(path.clone()))?;
+
+        let home = std::env::var_os("HOME").map(|h| resolve(Path::new(&h)).unwrap_or(h.into()));
+        if let Some(location) = home.and_then(|home| locate_in_home(&path, &home, &data_dir)) {
+            return Ok(location);
+        }
+
+        let (dev, mount_point) = mount_of(&path).map_err(|_| GvfsError::NoTree(path.clone()))?;
+        let (tree,): (String,) = connection
+            .call_method(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                Some(INTERFACE),
+                "GetTreeFromDevice",
+                &(dev_major(dev), dev_minor(dev)),
+            )
+            .and_then(|reply| reply.body().deserialize())
+            .map_err(|e| self.dbus_error(e))?;
+        if tree.is_empty() {
+            return Err(GvfsError::NoTree(path));
+        }
+        let tree_path = Path::new("/").join(path.strip_prefix(&mount_point).unwrap_or(&path));
+        Ok((data_dir.join(tree), tree_path))
+    }
+
+    fn dbus_error(&self, error: zbus::Error) -> GvfsError {
+        match error {
+            zbus::Error::InputOutput(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                GvfsError::Timeout(self.timeout)
+            }
+            zbus::Error::MethodError(name, message, _) => {
+                let message = message.unwrap_or_default();
+                if UNAVAILABLE_ERRORS.contains(&name.as_str()) {
+                    GvfsError::ServiceUnavailable(message)
+                } else {
+                    GvfsError::Rejected(format!("{name}: {message}"))
+                }
+            }
+            e => GvfsError::DBus(e),
+        }
+    }
+}
+
+/// Read a child's pipe to the end on a separate thread.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        out
+    })
+}
+
+/// The attribute name without its `metadata::` namespace, or an error if
+/// `key` is not a metadata attribute.
+fn metadata_key(key: &str) -> Result<&str, GvfsError> {
+    key.strip_prefix(METADATA_NAMESPACE)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| GvfsError::NotMetadataKey(key.to_string()))
+}
+
+/// Extract `key`'s value from `gio info` output, where attributes are listed
+/// one per line as `  <key>: <value>` under an `attributes:` header.
+fn parse_gio_info_attribute(output: &str, key: &str) -> Option<String> {
+    let prefix = format!("{key}: ");
+    output
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix(&prefix))
+        .map(|value| value.trim_end().to_string())
+}
+
+/// Parse a string list as `gio info` prints it: `[a, b]`.
+fn parse_gio_stringv(value: &str) -> Option<Vec<String>> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?;
+    if inner.is_empty() {
+        return Some(Vec::new());
+    }
+    Some(inner.split(", ").map(str::to_string).collect())
+}
+
+/// A NUL-terminated byte string, as GVariant `ay` bytestrings are sent.
+fn bytestring<S: AsRef<OsStr> + ?Sized>(s: &S) -> Vec<u8> {
+    let mut bytes = s.as_ref().as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+/// `$XDG_DATA_HOME/gvfs-metadata`, defaulting to
+/// `~/.local/share/gvfs-metadata`.
+pub(super) fn gvfs_data_dir() -> Option<PathBuf> {
+    Some(super::xdg::data_home()?.join("gvfs-metadata"))
+}
+
+/// Location of `path` in the `home` tree, if it is inside `home`.
+fn locate_in_home(path: &Path, home: &Path, data_dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    let rest = path.strip_prefix(home).ok()?;
+    Some((data_dir.join("home"), Path::new("/").join(rest)))
+}
+
+/// Canonicalize `path`, resolving the parent only when `path` itself does
+/// not exist yet (e.g. a move destination).
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Ok(path),
+        Err(e) => {
+            let absolute = std::path::absolute(path)?;
+            match (absolute.parent(), absolute.components().next_back()) {
+                (Some(parent), Some(Component::Normal(name))) => {
+                    Ok(parent.canonicalize()?.join(name))
+                }
+                _ => Err(e),
+            }
+        }
+    }
+}
+
+/// The device `path` (or its nearest existing ancestor) lives on, and the
+/// topmost ancestor on that same device, i.e. its mount point.
+fn mount_of(path: &Path) -> std::io::Result<(u64, PathBuf)> {
+    let mut existing = path;
+    let dev = loop {
+        match existing.metadata() {
+            Ok(metadata) => break metadata.dev(),
+            Err(e) => existing = existing.parent().ok_or(e)?,
+        }
+    };
+    let mut mount_point = existing;
+    while let Some(parent) = mount_point.parent() {
+        if parent.metadata()?.dev() != dev {
+            break;
+        }
+        mount_point = parent;
+    }
+    Ok((dev, mount_point.to_path_buf()))
+}
+
+/// Major number of a Linux `dev_t`, as glibc's `major()` computes it.
+fn dev_major(dev: u64) -> u32 {
+    (((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff)) as u32
+}
+
+/// Minor number of a Linux `dev_t`, as glibc's `minor()` computes it.
+fn dev_minor(dev: u64) -> u32 {
+    ((dev & 0xff) | ((dev >> 12) & !0xff)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Write an executable shell script standing in for `gio`.
+    fn fake_gio(dir: &Path, body: &str) -> PathBuf {
+        let program = dir.join("gio");
+        std::fs::write(&program, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        program
+    }
+
+    #[test]
+    fn parse_gio_info_attribute_finds_value() {
+        let output =
+            "uri: file:///tmp/x\nattributes:\n  metadata::custom-icon: file:///tmp/x/icon.png\n";
+        assert_eq!(
+            parse_gio_info_attribute(output, "metadata::custom-icon").as_deref(),
+            Some("file:///tmp/x/icon.png")
+        );
+    }
+
+    #[test]
+    fn parse_gio_info_attribute_missing_is_none() {
+        let output = "uri: file:///tmp/x\nattributes:\n";
+        assert!(parse_gio_info_attribute(output, "metadata::custom-icon").is_none());
+    }
+
+    #[test]
+    fn parse_gio_stringv_splits_list() {
+        assert_eq!(
+            parse_gio_stringv("[emblem-important, emblem-shared]").unwrap(),
+            ["emblem-important", "emblem-shared"]
+        );
+        assert_eq!(parse_gio_stringv("[]").unwrap(), Vec::<String>::new());
+        assert!(parse_gio_stringv("file:///x.png").is_none());
+    }
+
+    #[test]
+    fn cli_writes_string_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("args");
+        let program = fake_gio(dir.path(), &format!("echo \"$@\" > {}", log.display()));
+        GvfsMetadata::new(GvfsTransport::Cli { program })
+            .set_list(
+                dir.path(),
+                "metadata::emblems",
+                &["emblem-ok", "emblem-new"],
+            )
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap().trim(),
+            format!(
+                "set -t stringv {} metadata::emblems emblem-ok emblem-new",
+                dir.path().display()
+            )
+        );
+    }
+
+    #[test]
+    fn metadata_key_strips_namespace() {
+        assert_eq!(
+            metadata_key("metadata::custom-icon").unwrap(),
+            "custom-icon"
+        );
+        assert!(matches!(
+            metadata_key("standard::icon"),
+            Err(GvfsError::NotMetadataKey(_))
+        ));
+        assert!(matches!(
+            metadata_key("metadata::"),
+            Err(GvfsError::NotMetadataKey(_))
+        ));
+    }
+
+    #[test]
+    fn bytestring_is_nul_terminated() {
+        assert_eq!(bytestring("/a b"), b"/a b\0");
+    }
+
+    #[test]
+    fn locate_in_home_uses_home_tree() {
+        let data_dir = Path::new("/home/ada/.local/share/gvfs-metadata");
+        assert_eq!(
+            locate_in_home(
+                Path::new("/home/ada/Projects/x"),
+                Path::new("/home/ada"),
+                data_dir
+            ),
+            Some((data_dir.join("home"), PathBuf::from("/Projects/x")))
+        );
+        assert_eq!(
+            locate_in_home(Path::new("/home/ada"), Path::new("/home/ada"), data_dir),
+            Some((data_dir.join("home"), PathBuf::from("/")))
+        );
+        assert!(locate_in_home(Path::new("/srv/x"), Path::new("/home/ada"), data_dir).is_none());
+    }
+
+    #[test]
+    fn dev_numbers_match_glibc_encoding() {
+        // makedev(8, 1) and makedev(259, 65537)
+        assert_eq!((dev_major(0x801), dev_minor(0x801)), (8, 1));
+        let dev = (259u64 & 0xfff) << 8 | (65537u64 & 0xff) | ((65537u64 & !0xff) << 12);
+        assert_eq!((dev_major(dev), dev_minor(dev)), (259, 65537));
+    }
+
+    #[test]
+    fn cli_uses_configured_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_gio(
+            dir.path(),
+            "echo 'attributes:'; echo '  metadata::custom-icon: file:///x.png'",
+        );
+        let gvfs = GvfsMetadata::new(GvfsTransport::Cli { program });
+        assert_eq!(
+            gvfs.get(dir.path(), "metadata::custom-icon")
+                .unwrap()
+                .as_deref(),
+            Some("file:///x.png")
+        );
+    }
+
+    #[test]
+    fn cli_output_larger_than_the_pipe_buffer_is_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_gio(
+            dir.path(),
+            "echo 'attributes:'; \
+             i=0; while [ $i -lt 4000 ]; do echo \"  standard::x$i: padding padding\"; i=$((i+1)); done; \
+             echo '  metadata::custom-icon: file:///x.png'",
+        );
+        let gvfs =
+            GvfsMetadata::new(GvfsTransport::Cli { program }).with_timeout(Duration::from_secs(5));
+        assert_eq!(
+            gvfs.get(dir.path(), "metadata::custom-icon")
+                .unwrap()
+                .as_deref(),
+            Some("file:///x.png")
+        );
+    }
+
+    #[test]
+    fn cli_failure_reports_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_gio(
+            dir.path(),
+            "echo 'Setting attribute not supported' >&2; exit 1",
+        );
+        let err = GvfsMetadata::new(GvfsTransport::Cli { program })
+            .set(dir.path(), "metadata::custom-icon", "file:///x.png")
+            .unwrap_err();
+        assert!(
+            matches!(&err, GvfsError::CommandFailed(msg) if msg == "Setting attribute not supported"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn cli_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_gio(dir.path(), "sleep 5");
+        let timeout = Duration::from_millis(100);
+        let started = Instant::now();
+        let err = GvfsMetadata::new(GvfsTransport::Cli { program })
+            .with_timeout(timeout)
+            .unset(dir.path(), "metadata::custom-icon")
+            .unwrap_err();
+        assert!(
+            matches!(err, GvfsError::Timeout(t) if t == timeout),
+            "{err:?}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cli_missing_program_is_spawn_error() {
+        let gvfs = GvfsMetadata::new(GvfsTransport::Cli {
+            program: PathBuf::from("/nonexistent/gio"),
+        });
+        assert!(matches!(
+            gvfs.unset("/tmp", "metadata::custom-icon"),
+            Err(GvfsError::Spawn(..))
+        ));
+    }
+
+    #[test]
+    fn cli_does_not_support_remove_or_move() {
+        let gvfs = GvfsMetadata::gio();
+        assert!(matches!(
+            gvfs.remove("/tmp/x"),
+            Err(GvfsError::Unsupported(_))
+        ));
+        assert!(matches!(
+            gvfs.move_metadata("/tmp/x", "/tmp/y"),
+            Err(GvfsError::Unsupported(_))
+        ));
+    }
+}
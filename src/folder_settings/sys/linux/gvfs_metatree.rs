@@ -0,0 +1,572 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use super::error::GvfsError;
+
+const TREE_MAGIC: &[u8; 6] = b"\xda\x1ameta";
+const JOURNAL_MAGIC: &[u8; 6] = b"\xda\x1ajour";
+const MAJOR_VERSION: u8 = 1;
+
+/// Size of the tree header: magic, version, `rotated`, `random_tag`, `root`,
+/// `attributes` and `time_t_base`.
+const TREE_HEADER_SIZE: usize = 32;
+/// Size of the journal header: magic, version, `random_tag`, `file_size`
+/// and `num_entries`.
+const JOURNAL_HEADER_SIZE: usize = 20;
+/// Smallest journal entry: size, crc32, mtime, op, an empty path and the
+/// trailing size.
+const MIN_ENTRY_SIZE: usize = 4 + 4 + 8 + 1 + 1 + 4;
+
+/// Set on a data key's attribute index when its value is a string list.
+const KEY_IS_LIST: u32 = 1 << 31;
+
+/// Deeper trees are treated as corrupt.
+const MAX_DEPTH: usize = 128;
+
+/// How often a tree that was rotated while being read is re-opened.
+const ROTATION_RETRIES: usize = 3;
+
+const METADATA_NAMESPACE: &str = "metadata::";
+
+const OP_SET: u8 = 0;
+const OP_SETV: u8 = 1;
+const OP_UNSET: u8 = 2;
+const OP_COPY: u8 = 3;
+const OP_REMOVE: u8 = 4;
+
+/// A GVFS metadata value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    String(String),
+    List(Vec<String>),
+}
+
+/// Every metadata entry of one GVFS metadata tree (e.g. `home`, `root`),
+/// read without `gio` or `gvfsd-metadata`.
+///
+/// gvfsd-metadata keeps each tree as a compact binary file plus a
+/// `<tree>-<tag>.log` journal of changes not yet merged into it; both are
+/// read and the journal replayed, so the result matches what `gio info`
+/// would report. Paths are relative to the tree's mount (the home directory
+/// for `home`) and start with `/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GvfsMetadataTree {
+    entries: BTreeMap<PathBuf, BTreeMap<String, MetadataValue>>,
+}
+
+impl GvfsMetadataTree {
+    /// Read a tree from the current user's `gvfs-metadata` directory by
+    /// name, e.g. `"home"`.
+    pub fn open_user(name: &str) -> Result<Self, GvfsError> {
+        let dir = super::gvfs_metadata::gvfs_data_dir()
+            .ok_or_else(|| GvfsError::NoTree(PathBuf::from(name)))?;
+        Self::open(dir.join(name))
+    }
+
+    /// Read a tree file and its journal, if any.
+    pub fn open<P: AsRef<Path>>(tree_file: P) -> Result<Self, GvfsError> {
+        let tree_file = tree_file.as_ref();
+        for _ in 0..ROTATION_RETRIES {
+            let tree = std::fs::read(tree_file)
+                .map_err(|e| GvfsError::Read(tree_file.to_path_buf(), e))?;
+            let header = TreeHeader::parse(&tree)?;
+            if header.rotated != 0 {
+                // gvfsd-metadata replaced the file after we opened it.
+                continue;
+            }
+            let journal_file = journal_path(tree_file, header.random_tag);
+            let journal = match std::fs::read(&journal_file) {
+                Ok(journal) => Some(journal),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(GvfsError::Read(journal_file, e)),
+            };
+            return Self::parse(&tree, journal.as_deref());
+        }
+        Err(GvfsError::Database(format!(
+            "{} kept being rotated while reading",
+            tree_file.display()
+        )))
+    }
+
+    /// Parse a tree file's contents and replay a journal over it. A journal
+    /// belonging to another tree is ignored, and replay stops at the first
+    /// torn or corrupt entry, as gvfsd-metadata does.
+    pub fn parse(tree: &[u8], journal: Option<&[u8]>) -> Result<Self, GvfsError> {
+        let header = TreeHeader::parse(tree)?;
+        let reader = Reader(tree);
+
+        let attributes = reader
+            .stringv(header.attributes)?
+            .into_iter()
+            .map(|a| String::from_utf8_lossy(a).into_owned())
+            .collect::<Vec<_>>();
+
+        let mut this = Self::default();
+        this.read_dirents(&reader, &attributes, header.root)?;
+        if let Some(journal) = journal {
+            this.replay(journal, header.random_tag);
+        }
+        Ok(this)
+    }
+
+    /// Every path with metadata, ordered component by component (so a
+    /// folder's descendants directly follow it).
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &BTreeMap<String, MetadataValue>)> {
+        self.entries
+            .iter()
+            .map(|(path, data)| (path.as_path(), data))
+    }
+
+    /// The value of `key` (e.g. `metadata::custom-icon`) for `path`.
+    pub fn get<P: AsRef<Path>>(&self, path: P, key: &str) -> Option<&MetadataValue> {
+        let name = key.strip_prefix(METADATA_NAMESPACE)?;
+        self.entries.get(path.as_ref())?.get(name)
+    }
+
+    /// Every `metadata::custom-icon` entry, as (path, icon URI) pairs.
+    pub fn custom_icons(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.entries()
+            .filter_map(|(path, data)| match data.get("custom-icon") {
+                Some(MetadataValue::String(uri)) => Some((path, uri.as_str())),
+                _ => None,
+            })
+    }
+
+    /// Every `metadata::emblems` entry, as (path, emblem names) pairs.
+    pub fn emblems(&self) -> impl Iterator<Item = (&Path, &[String])> {
+        self.entries()
+            .filter_map(|(path, data)| match data.get("emblems") {
+                Some(MetadataValue::List(emblems)) => Some((path, emblems.as_slice())),
+                _ => None,
+            })
+    }
+
+    /// Walk the directory entries from `root`. Each entry is visited once,
+    /// so offsets pointing back up the tree are reported as corrupt.
+    fn read_dirents(
+        &mut self,
+        reader: &Reader,
+        attributes: &[String],
+        root: u32,
+    ) -> Result<(), GvfsError> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![(root, PathBuf::from("/"), 0)];
+        while let Some((offset, path, depth)) = pending.pop() {
+            if depth > MAX_DEPTH {
+                return Err(GvfsError::Database("directory tree too deep".to_string()));
+            }
+            if !visited.insert(offset) {
+                return Err(GvfsError::Database(format!(
+                    "directory entry at {offset} is reached twice"
+                )));
+            }
+            let children = reader.u32(field(offset, 4)?)?;
+            let metadata = reader.u32(field(offset, 8)?)?;
+
+            if metadata != 0 {
+                let mut data = BTreeMap::new();
+                let count = reader.u32(metadata)?;
+                for i in 0..count {
+                    let entry = element(metadata, i, 8)?;
+                    let key = reader.u32(entry)?;
+                    let value = reader.u32(field(entry, 4)?)?;
+                    let name = attributes
+                        .get((key & !KEY_IS_LIST) as usize)
+                        .ok_or_else(|| GvfsError::Database(format!("unknown attribute {key}")))?;
+                    let value = if key & KEY_IS_LIST != 0 {
+                        MetadataValue::List(
+                            reader
+                                .stringv(value)?
+                                .into_iter()
+                                .map(|s| String::from_utf8_lossy(s).into_owned())
+                                .collect(),
+                        )
+                    } else {
+                        MetadataValue::String(
+                            String::from_utf8_lossy(reader.cstr(value)?).into_owned(),
+                        )
+                    };
+                    data.insert(name.clone(), value);
+                }
+                if !data.is_empty() {
+                    self.entries.insert(path.clone(), data);
+                }
+            }
+
+            if children != 0 {
+                let count = reader.u32(children)?;
+                for i in 0..count {
+                    let child = element(children, i, 16)?;
+                    let name = reader.cstr(reader.u32(child)?)?;
+                    pending.push((child, path.join(OsStr::from_bytes(name)), depth + 1));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn replay(&mut self, journal: &[u8], random_tag: u32) {
+        let reader = Reader(journal);
+        if journal.len() < JOURNAL_HEADER_SIZE
+            || &journal[..6] != JOURNAL_MAGIC
+            || journal[6] != MAJOR_VERSION
+            || reader.u32(8).ok() != Some(random_tag)
+        {
+            return;
+        }
+        let end = reader
+            .u32(12)
+            .map_or(journal.len(), |size| (size as usize).min(journal.len()));
+
+        let mut offset = JOURNAL_HEADER_SIZE;
+        while let Some(entry) = journal_entry(journal, offset, end) {
+            offset += entry.len();
+            // Entries are validated before replay, so parsing can't fail.
+            let _ = self.apply(entry);
+        }
+    }
+
+    fn apply(&mut self, entry: &[u8]) -> Option<()> {
+        let op = entry[16];
+        let mut fields = entry[17..entry.len() - 4].split(|&b| b == 0);
+        let path = PathBuf::from(OsStr::from_bytes(fields.next()?));
+
+        match op {
+            OP_SET => {
+                let key = utf8(fields.next()?);
+                let value = utf8(fields.next()?);
+                self.entries
+                    .entry(path)
+                    .or_default()
+                    .insert(key, MetadataValue::String(value));
+            }
+            OP_SETV => {
+                let key = fields.next()?;
+                // The string count is aligned to 4 bytes from the entry start.
+                let mut pos = 17 + path.as_os_str().len() + 1 + key.len() + 1;
+                pos = pos.next_multiple_of(4);
+                let count = u32::from_be_bytes(entry.get(pos..pos + 4)?.try_into().ok()?);
+                let values = entry[pos + 4..entry.len() - 4]
+                    .split(|&b| b == 0)
+                    .take(count as usize)
+                    .map(utf8)
+                    .collect::<Vec<_>>();
+                if values.len() != count as usize {
+                    return None;
+                }
+                self.entries
+                    .entry(path)
+                    .or_default()
+                    .insert(utf8(key), MetadataValue::List(values));
+            }
+            OP_UNSET => {
+                let key = utf8(fields.next()?);
+                if let Some(data) = self.entries.get_mut(&path) {
+                    data.remove(&key);
+                    if data.is_empty() {
+                        self.entries.remove(&path);
+                    }
+                }
+            }
+            OP_COPY => {
+                let source = PathBuf::from(OsStr::from_bytes(fields.next()?));
+                let copied = self
+                    .subtree(&source)
+                    .map(|(p, data)| {
+                        let rest = p.strip_prefix(&source).unwrap_or(Path::new(""));
+                        (path.join(rest), data.clone())
+                    })
+                    .collect::<Vec<_>>();
+                self.remove_subtree(&path);
+                self.entries.extend(copied);
+            }
+            OP_REMOVE => self.remove_subtree(&path),
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// `path` and everything below it.
+    fn subtree<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> impl Iterator<Item = (&'a PathBuf, &'a BTreeMap<String, MetadataValue>)> {
+        self.entries
+            .range(path.to_path_buf()..)
+            .take_while(move |(p, _)| p.starts_with(path))
+    }
+
+    fn remove_subtree(&mut self, path: &Path) {
+        self.entries.retain(|p, _| !p.starts_with(path));
+    }
+}
+
+/// The journal file of a tree: `<tree>-<tag as 8 hex digits>.log` next to it.
+fn journal_path(tree_file: &Path, random_tag: u32) -> PathBuf {
+    let mut name = tree_file.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("-{random_tag:08x}.log"));
+    tree_file.with_file_name(name)
+}
+
+/// The valid journal entry at `offset`, or `None` at the end of the journal
+/// or at a torn/corrupt entry.
+fn journal_entry(journal: &[u8], offset: usize, end: usize) -> Option<&[u8]> {
+    let size = u32::from_be_bytes(journal.get(offset..offset + 4)?.try_into().ok()?) as usize;
+    if size < MIN_ENTRY_SIZE || offset + size > end {
+        return None;
+    }
+    let entry = &journal[offset..offset + size];
+    let trailing = u32::from_be_bytes(entry[size - 4..].try_into().ok()?) as usize;
+    let crc = u32::from_be_bytes(entry[4..8].try_into().ok()?);
+    (trailing == size && crc32fast::hash(&entry[8..]) == crc).then_some(entry)
+}
+
+fn utf8(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+struct TreeHeader {
+    rotated: u32,
+    random_tag: u32,
+    root: u32,
+    attributes: u32,
+}
+
+impl TreeHeader {
+    fn parse(tree: &[u8]) -> Result<Self, GvfsError> {
+        if tree.len() < TREE_HEADER_SIZE || &tree[..6] != TREE_MAGIC {
+            return Err(GvfsError::Database("not a GVFS metadata tree".to_string()));
+        }
+        if tree[6] != MAJOR_VERSION {
+            return Err(GvfsError::Database(format!(
+                "unsupported metadata tree version {}.{}",
+                tree[6], tree[7]
+            )));
+        }
+        let reader = Reader(tree);
+        Ok(Self {
+            rotated: reader.u32(8)?,
+            random_tag: reader.u32(12)?,
+            root: reader.u32(16)?,
+            attributes: reader.u32(20)?,
+        })
+    }
+}
+
+/// Bounds-checked access to big-endian values at file offsets.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn u32(&self, offset: u32) -> Result<u32, GvfsError> {
+        let offset = offset as usize;
+        self.0
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| out_of_bounds(offset))
+    }
+
+    /// The NUL-terminated string at `offset`, without the NUL.
+    fn cstr(&self, offset: u32) -> Result<&'a [u8], GvfsError> {
+        let rest = self
+            .0
+            .get(offset as usize..)
+            .ok_or_else(|| out_of_bounds(offset as usize))?;
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| GvfsError::Database("unterminated string".to_string()))?;
+        Ok(&rest[..len])
+    }
+
+    /// A count followed by that many string offsets.
+    fn stringv(&self, offset: u32) -> Result<Vec<&'a [u8]>, GvfsError> {
+        let count = self.u32(offset)?;
+        (0..count)
+            .map(|i| self.cstr(self.u32(element(offset, i, 4)?)?))
+            .collect()
+    }
+}
+
+/// `base + delta`, failing instead of wrapping on a corrupt offset.
+fn field(base: u32, delta: u32) -> Result<u32, GvfsError> {
+    base.checked_add(delta)
+        .ok_or_else(|| out_of_bounds(base as usize))
+}
+
+/// Entry `index` of `size` bytes in the array at `base`, after its count.
+fn element(base: u32, index: u32, size: u32) -> Result<u32, GvfsError> {
+    index
+        .checked_mul(size)
+        .and_then(|n| n.checked_add(4))
+        .and_then(|n| base.checked_add(n))
+        .ok_or_else(|| out_of_bounds(base as usize))
+}
+
+fn out_of_bounds(offset: usize) -> GvfsError {
+    GvfsError::Database(format!("offset {offset} is out of bounds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gvfs-metadata");
+        std::fs::read(dir.join(name)).unwrap()
+    }
+
+    fn string(s: &str) -> MetadataValue {
+        MetadataValue::String(s.to_string())
+    }
+
+    fn list(items: &[&str]) -> MetadataValue {
+        MetadataValue::List(items.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn parse_tree_without_journal() {
+        let tree = GvfsMetadataTree::parse(&fixture("home"), None).unwrap();
+        let paths: Vec<_> = tree.entries().map(|(p, _)| p.to_path_buf()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/Documents",
+                "/Music",
+                "/Projects",
+                "/Projects/old",
+                "/Removed",
+                "/Removed/child"
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            tree.get("/Projects/old", "metadata::emblems"),
+            Some(&list(&["emblem-important", "emblem-favorite"]))
+        );
+        assert_eq!(
+            tree.get("/Documents", "metadata::nautilus-icon-view-sort-by"),
+            Some(&string("name"))
+        );
+        assert!(tree.get("/Documents", "custom-icon").is_none());
+    }
+
+    #[test]
+    fn journal_is_replayed_over_tree() {
+        let tree =
+            GvfsMetadataTree::parse(&fixture("home"), Some(&fixture("home-1ceb00da.log"))).unwrap();
+        assert_eq!(
+            tree.custom_icons().collect::<Vec<_>>(),
+            [
+                (
+                    Path::new("/Documents"),
+                    "file:///home/ada/Documents/.icon.png"
+                ),
+                (
+                    Path::new("/Projects"),
+                    "file:///home/ada/Projects/icon-sys-1.png"
+                ),
+                (
+                    Path::new("/Projects-copy"),
+                    "file:///home/ada/Projects/icon-sys-1.png"
+                ),
+            ]
+        );
+        assert_eq!(
+            tree.emblems().collect::<Vec<_>>(),
+            [
+                (Path::new("/Music"), &["emblem-shared".to_string()][..]),
+                (
+                    Path::new("/Projects/old"),
+                    &[
+                        "emblem-important".to_string(),
+                        "emblem-favorite".to_string()
+                    ][..]
+                ),
+                (
+                    Path::new("/Projects-copy/old"),
+                    &[
+                        "emblem-important".to_string(),
+                        "emblem-favorite".to_string()
+                    ][..]
+                ),
+            ]
+        );
+        // Replay stops at the corrupt entry.
+        assert!(tree.get("/Bad", "metadata::custom-icon").is_none());
+        assert!(tree.get("/AfterBad", "metadata::custom-icon").is_none());
+        assert!(tree.entries().all(|(p, _)| !p.starts_with("/Removed")));
+    }
+
+    #[test]
+    fn journal_of_another_tree_is_ignored() {
+        let mut journal = fixture("home-1ceb00da.log");
+        journal[8..12].copy_from_slice(&0xdeadbeefu32.to_be_bytes());
+        let with_journal = GvfsMetadataTree::parse(&fixture("home"), Some(&journal)).unwrap();
+        let without = GvfsMetadataTree::parse(&fixture("home"), None).unwrap();
+        assert_eq!(with_journal, without);
+    }
+
+    #[test]
+    fn open_reads_journal_next_to_tree() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gvfs-metadata");
+        let tree = GvfsMetadataTree::open(dir.join("home")).unwrap();
+        assert!(
+            tree.get("/Projects-copy", "metadata::custom-icon")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_truncation() {
+        assert!(matches!(
+            GvfsMetadataTree::parse(b"not a tree at all, just some bytes", None),
+            Err(GvfsError::Database(_))
+        ));
+        let tree = fixture("home");
+        assert!(matches!(
+            GvfsMetadataTree::parse(&tree[..tree.len() / 2], None),
+            Err(GvfsError::Database(_))
+        ));
+    }
+
+    /// A tree whose only child lists itself as its own children.
+    fn cyclic_tree() -> Vec<u8> {
+        let mut tree = TREE_MAGIC.to_vec();
+        tree.extend([MAJOR_VERSION, 0]);
+        // rotated, random_tag, root, attributes and time_t_base.
+        for word in [0, 1, 36, 32, 0, 0] {
+            tree.extend(u32::to_be_bytes(word));
+        }
+        // No attributes; the root entry; the children array with one entry.
+        for word in [0, 72, 52, 0, 0, 1, 72, 52, 0, 0] {
+            tree.extend(u32::to_be_bytes(word));
+        }
+        tree.extend(b"a\0");
+        tree
+    }
+
+    #[test]
+    fn rejects_cycles_and_overflowing_offsets() {
+        let tree = cyclic_tree();
+        assert!(matches!(
+            GvfsMetadataTree::parse(&tree, None),
+            Err(GvfsError::Database(_))
+        ));
+        let mut tree = cyclic_tree();
+        tree[16..20].copy_from_slice(&(u32::MAX - 2).to_be_bytes());
+        assert!(matches!(
+            GvfsMetadataTree::parse(&tree, None),
+            Err(GvfsError::Database(_))
+        ));
+    }
+
+    #[test]
+    fn journal_path_appends_hex_tag() {
+        assert_eq!(
+            journal_path(Path::new("/d/gvfs-metadata/home"), 0xab),
+            PathBuf::from("/d/gvfs-metadata/home-000000ab.log")
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::LinuxFolderSettingsError;
+use super::icon_theme_cache::CACHE_FILE;
+use super::{DesktopEntry, IconThemeCache, icon_theme, xdg};
+use crate::icon::sys::linux::LinuxIconSet;
+
+const THEME_GROUP: &str = "Icon Theme";
+const INDEX_FILE: &str = "index.theme";
+
+/// Written when no system hicolor `index.theme` can be copied.
+const MINIMAL_INDEX: &str =
+    "[Icon Theme]\nName=Hicolor\nComment=Fallback icon theme\nHidden=true\nDirectories=\n";
+
+/// Extensions an installed icon may have.
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// The icon theme directory contexts of the Icon Naming Specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconContext {
+    Actions,
+    Applications,
+    Categories,
+    Devices,
+    Emblems,
+    MimeTypes,
+    Places,
+    Status,
+}
+
+impl IconContext {
+    /// The directory name, e.g. `apps` in `48x48/apps`.
+    pub fn directory(self) -> &'static str {
+        match self {
+            Self::Actions => "actions",
+            Self::Applications => "apps",
+            Self::Categories => "categories",
+            Self::Devices => "devices",
+            Self::Emblems => "emblems",
+            Self::MimeTypes => "mimetypes",
+            Self::Places => "places",
+            Self::Status => "status",
+        }
+    }
+
+    /// The `Context=` value of the directory in `index.theme`.
+    pub fn index_name(self) -> &'static str {
+        match self {
+            Self::Actions => "Actions",
+            Self::Applications => "Applications",
+            Self::Categories => "Categories",
+            Self::Devices => "Devices",
+            Self::Emblems => "Emblems",
+            Self::MimeTypes => "MimeTypes",
+            Self::Places => "Places",
+            Self::Status => "Status",
+        }
+    }
+}
+
+/// A hicolor theme directory, by default the user's
+/// (`~/.local/share/icons/hicolor`), where applications install the icons
+/// that desktop files, `.directory` files and toolkits refer to by name.
+///
+/// Every theme inherits hicolor, so a named icon installed here is found
+/// whatever theme is active, unless that theme has its own icon of the
+/// same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HicolorTheme {
+    theme_dir: PathBuf,
+}
+
+impl HicolorTheme {
+    /// The hicolor theme in `$XDG_DATA_HOME/icons`, if it can be located.
+    pub fn for_current_user() -> Option<Self> {
+        xdg::data_home().map(|data| Self::at(data.join("icons/hicolor")))
+    }
+
+    /// The theme rooted at `theme_dir`.
+    pub fn at(theme_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            theme_dir: theme_dir.into(),
+        }
+    }
+
+    pub fn theme_dir(&self) -> &Path {
+        &self.theme_dir
+    }
+
+    /// Install `icon_set` as the icon `name`: one
+    /// `<N>x<N>/<context>/<name>.png` per raster size and
+    /// `scalable/<context>/<name>.svg` for the SVG. Files of a previous
+    /// install in other sizes are removed, the directories are added to
+    /// `index.theme` (created if missing) and an existing
+    /// `icon-theme.cache` is rebuilt. Returns the written files.
+    pub fn install(
+        &self,
+        context: IconContext,
+        name: &str,
+        icon_set: &LinuxIconSet,
+    ) -> Result<Vec<PathBuf>, LinuxFolderSettingsError> {
+        validate_name(&self.theme_dir, name)?;
+        if icon_set.is_empty() {
+            return Err(LinuxFolderSettingsError::IconOperation(
+                self.theme_dir.clone(),
+                "Icon set contains neither an SVG nor any raster images".to_string(),
+            ));
+        }
+        self.remove_files(context, name)?;
+
+        let mut written = Vec::new();
+        let mut directories = Vec::new();
+        for (size, image) in icon_set {
+            let directory = format!("{size}x{size}/{}", context.directory());
+            let path = self.theme_dir.join(&directory).join(format!("{name}.png"));
+            fs::create_dir_all(self.theme_dir.join(&directory))?;
+            image.image.save(&path).map_err(|e| {
+                LinuxFolderSettingsError::IconOperation(path.clone(), e.to_string())
+            })?;
+            directories.push((directory, Some(*size)));
+            written.push(path);
+        }
+        if let Some(svg) = icon_set.svg() {
+            let directory = format!("scalable/{}", context.directory());
+            let path = self.theme_dir.join(&directory).join(format!("{name}.svg"));
+            fs::create_dir_all(self.theme_dir.join(&directory))?;
+            fs::write(&path, svg).map_err(|e| {
+                LinuxFolderSettingsError::IconOperation(path.clone(), e.to_string())
+            })?;
+            directories.push((directory, None));
+            written.push(path);
+        }
+
+        self.register_directories(context, &directories)?;
+        self.refresh()?;
+        Ok(written)
+    }
+
+    /// Remove the icon `name` of `context` in every size. Returns whether
+    /// any file was removed.
+    pub fn uninstall(
+        &self,
+        context: IconContext,
+        name: &str,
+    ) -> Result<bool, LinuxFolderSettingsError> {
+        validate_name(&self.theme_dir, name)?;
+        let removed = self.remove_files(context, name)?;
+        if removed {
+            self.refresh()?;
+        }
+        Ok(removed)
+    }
+
+    /// Whether `name` is installed in `context` in any size.
+    pub fn contains(&self, context: IconContext, name: &str) -> bool {
+        self.icon_files(context, name).any(|path| path.is_file())
+    }
+
+    /// Every path the icon could have: each size directory of the context,
+    /// with each extension.
+    fn icon_files<'a>(
+        &'a self,
+        context: IconContext,
+        name: &'a str,
+    ) -> impl Iterator<Item = PathBuf> + 'a {
+        fs::read_dir(&self.theme_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(move |size| size.path().join(context.directory()))
+            .flat_map(move |directory| {
+                ICON_EXTENSIONS
+                    .iter()
+                    .map(move |extension| directory.join(format!("{name}.{extension}")))
+            })
+    }
+
+    fn remove_files(
+        &self,
+        context: IconContext,
+        name: &str,
+    ) -> Result<bool, LinuxFolderSettingsError> {
+        let mut removed = false;
+        for path in self.icon_files(context, name) {
+            match fs::remove_file(&path) {
+                Ok(()) => removed = true,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+                    ) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Make sure `index.theme` lists every directory, with its size
+    /// (`None` for scalable) and context. A missing index is seeded from the
+    /// system's hicolor theme: GTK reads only the first `index.theme` it
+    /// finds for a theme, so a bare one here would hide every system hicolor
+    /// directory it doesn't list.
+    fn register_directories(
+        &self,
+        context: IconContext,
+        directories: &[(String, Option<u32>)],
+    ) -> Result<(), LinuxFolderSettingsError> {
+        let path = self.theme_dir.join(INDEX_FILE);
+        let original = match fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut index = DesktopEntry::parse(
+            original
+                .clone()
+                .or_else(|| self.system_index())
+                .as_deref()
+                .unwrap_or(MINIMAL_INDEX),
+        );
+
+        let mut listed: Vec<String> = index
+            .get(THEME_GROUP, "Directories")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|d| !d.is_empty())
+            .map(str::to_string)
+            .collect();
+        for (directory, size) in directories {
+            if listed.contains(directory) {
+                continue;
+            }
+            listed.push(directory.clone());
+            match size {
+                Some(size) => {
+                    index.set(directory, "Size", &size.to_string());
+                    index.set(directory, "Type", "Threshold");
+                }
+                None => {
+                    index.set(directory, "MinSize", "1");
+                    index.set(directory, "Size", "128");
+                    index.set(directory, "MaxSize", "256");
+                    index.set(directory, "Type", "Scalable");
+                }
+            }
+            index.set(directory, "Context", context.index_name());
+        }
+        index.set(THEME_GROUP, "Directories", &listed.join(","));
+
+        let updated = index.to_string();
+        if original.as_deref() != Some(updated.as_str()) {
+            fs::create_dir_all(&self.theme_dir)?;
+            fs::write(&path, updated)?;
+        }
+        Ok(())
+    }
+
+    /// The `index.theme` of hicolor in another base directory.
+    fn system_index(&self) -> Option<String> {
+        icon_theme::theme_base_dirs()
+            .into_iter()
+            .map(|base| base.join("hicolor"))
+            .filter(|dir| *dir != self.theme_dir)
+            .find_map(|dir| fs::read_to_string(dir.join(INDEX_FILE)).ok())
+    }
+
+    /// Bump the theme directory's mtime so running applications rescan it,
+    /// and rebuild the cache if there is one: GTK trusts a cache that is
+    /// not older than the directory, so a stale one would hide the change.
+    fn refresh(&self) -> Result<(), LinuxFolderSettingsError> {
+        filetime::set_file_mtime(&self.theme_dir, filetime::FileTime::now())?;
+        if self.theme_dir.join(CACHE_FILE).exists() {
+            IconThemeCache::update(&self.theme_dir)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_name(theme_dir: &Path, name: &str) -> Result<(), LinuxFolderSettingsError> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(LinuxFolderSettingsError::IconOperation(
+            theme_dir.to_path_buf(),
+            format!("invalid icon name {name:?}"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icon::sys::linux::LinuxIconImage;
+    use image::{DynamicImage, RgbaImage};
+    use std::borrow::Cow;
+
+    fn icon_set(sizes: &[u32]) -> LinuxIconSet<'static> {
+        LinuxIconSet::from_icons(sizes.iter().map(|&size| LinuxIconImage {
+            size,
+            image: Cow::Owned(DynamicImage::ImageRgba8(RgbaImage::new(size, size))),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn install_writes_sizes_and_registers_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = HicolorTheme::at(dir.path().join("hicolor"));
+        fs::create_dir_all(theme.theme_dir()).unwrap();
+        fs::write(
+            theme.theme_dir().join(INDEX_FILE),
+            "[Icon Theme]\nName=Hicolor\n# kept\nDirectories=48x48/apps\n\n\
+             [48x48/apps]\nSize=48\nContext=Applications\nType=Threshold\n",
+        )
+        .unwrap();
+
+        let mut set = icon_set(&[48, 40]);
+        set.set_svg("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\"/>")
+            .unwrap();
+        let written = theme
+            .install(IconContext::Applications, "acme", &set)
+            .unwrap();
+        assert_eq!(written.len(), 3);
+        assert!(theme.contains(IconContext::Applications, "acme"));
+        assert!(!theme.contains(IconContext::MimeTypes, "acme"));
+
+        let index = DesktopEntry::load(theme.theme_dir().join(INDEX_FILE)).unwrap();
+        assert_eq!(
+            index.get(THEME_GROUP, "Directories").as_deref(),
+            Some("48x48/apps,40x40/apps,scalable/apps")
+        );
+        assert_eq!(index.get("40x40/apps", "Size").as_deref(), Some("40"));
+        assert_eq!(
+            index.get("scalable/apps", "Type").as_deref(),
+            Some("Scalable")
+        );
+        assert!(index.to_string().contains("# kept\n"));
+
+        // Reinstalling with fewer sizes drops the stale ones.
+        theme
+            .install(IconContext::Applications, "acme", &icon_set(&[48]))
+            .unwrap();
+        assert!(!theme.theme_dir().join("40x40/apps/acme.png").exists());
+        assert!(!theme.theme_dir().join("scalable/apps/acme.svg").exists());
+
+        assert!(theme.uninstall(IconContext::Applications, "acme").unwrap());
+        assert!(!theme.uninstall(IconContext::Applications, "acme").unwrap());
+        assert!(!theme.contains(IconContext::Applications, "acme"));
+    }
+
+    #[test]
+    fn missing_index_is_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = HicolorTheme::at(dir.path().join("hicolor"));
+        theme
+            .install(
+                IconContext::MimeTypes,
+                "application-x-acme",
+                &icon_set(&[32]),
+            )
+            .unwrap();
+        let index = DesktopEntry::load(theme.theme_dir().join(INDEX_FILE)).unwrap();
+        assert!(
+            index
+                .get(THEME_GROUP, "Directories")
+                .unwrap()
+                .split(',')
+                .any(|d| d == "32x32/mimetypes")
+        );
+        assert!(index.get(THEME_GROUP, "Name").is_some());
+    }
+
+    #[test]
+    fn existing_cache_is_rebuilt() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = HicolorTheme::at(dir.path().join("hicolor"));
+        theme
+            .install(IconContext::Applications, "first", &icon_set(&[16]))
+            .unwrap();
+        IconThemeCache::update(theme.theme_dir()).unwrap();
+
+        theme
+            .install(IconContext::Applications, "second", &icon_set(&[16]))
+            .unwrap();
+        let cache = IconThemeCache::open(theme.theme_dir()).unwrap().unwrap();
+        assert_eq!(cache.lookup("second")[0].directory, "16x16/apps");
+        assert_eq!(cache.lookup("first").len(), 1);
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = HicolorTheme::at(dir.path());
+        for name in ["", "../escape", ".hidden"] {
+            assert!(
+                theme
+                    .install(IconContext::Applications, name, &icon_set(&[16]))
+                    .is_err()
+            );
+        }
+    }
+}
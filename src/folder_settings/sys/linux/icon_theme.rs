@@ -0,0 +1,470 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use super::gvdb::{Gvdb, schema_default_str};
+use super::{DconfProfile, DesktopEntry, IconThemeCache, LinuxFolderSettingsError, xdg};
+use crate::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+
+/// dconf key holding the GNOME icon theme name.
+const ICON_THEME_KEY: &str = "/org/gnome/desktop/interface/icon-theme";
+/// The same key as a GSettings schema and key name.
+const ICON_THEME_SCHEMA: &str = "org.gnome.desktop.interface";
+const ICON_THEME_SCHEMA_KEY: &str = "icon-theme";
+
+/// Raster sizes commonly shipped by freedesktop icon themes.
+const COMMON_SIZES: [u32; 8] = [16, 22, 24, 32, 48, 64, 128, 256];
+
+/// Image suffixes an icon may be stored with.
+const ICON_SUFFIXES: [&str; 3] = ["png", "svg", "xpm"];
+
+/// One freedesktop icon theme, possibly split across several base
+/// directories (e.g. `~/.local/share/icons/Adwaita` and
+/// `/usr/share/icons/Adwaita`).
+///
+/// Lookups use each root's `icon-theme.cache` when it is fresh and fall back
+/// to probing the usual directory layouts otherwise.
+pub(crate) struct IconTheme {
+    roots: Vec<ThemeRoot>,
+}
+
+struct ThemeRoot {
+    path: PathBuf,
+    cache: Option<IconThemeCache>,
+    index: Option<DesktopEntry>,
+}
+
+impl IconTheme {
+    /// Load the theme `name` from every base directory that contains it.
+    pub(crate) fn load(name: &str, bases: &[PathBuf]) -> Self {
+        let roots = bases
+            .iter()
+            .map(|base| base.join(name))
+            .filter(|path| path.is_dir())
+            .map(|path| ThemeRoot {
+                cache: IconThemeCache::open(&path).ok().flatten(),
+                index: DesktopEntry::load(path.join("index.theme")).ok(),
+                path,
+            })
+            .collect();
+        Self { roots }
+    }
+
+    /// Whether the theme ships the icon `name` in any directory or format.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.roots.iter().any(|root| match &root.cache {
+            Some(cache) => !cache.lookup(name).is_empty(),
+            None => root.directories().iter().any(|dir| {
+                ICON_SUFFIXES
+                    .iter()
+                    .any(|ext| root.path.join(dir).join(format!("{name}.{ext}")).exists())
+            }),
+        })
+    }
+
+    /// Candidate files for the PNG icon `name` at `size`, in priority order.
+    pub(crate) fn raster_candidates(&self, name: &str, size: u32) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .flat_map(|root| match &root.cache {
+                Some(cache) => cache
+                    .lookup(name)
+                    .into_iter()
+                    .filter(|hit| hit.has_png() && root.directory_size(hit.directory) == Some(size))
+                    .map(|hit| root.path.join(hit.directory).join(format!("{name}.png")))
+                    .collect(),
+                None => root.uncached_raster_candidates(name, size),
+            })
+            .collect()
+    }
+
+    /// Candidate files for the SVG icon `name`, in priority order.
+    pub(crate) fn svg_candidates(&self, name: &str) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .flat_map(|root| match &root.cache {
+                Some(cache) => cache
+                    .lookup(name)
+                    .into_iter()
+                    .filter(|hit| hit.has_svg())
+                    .map(|hit| root.path.join(hit.directory).join(format!("{name}.svg")))
+                    .collect(),
+                None => root.uncached_svg_candidates(name),
+            })
+            .collect()
+    }
+}
+
+impl ThemeRoot {
+    /// PNG candidates from the directories of `index.theme` with a matching
+    /// size, then the usual "places" layouts for themes without an index.
+    fn uncached_raster_candidates(&self, name: &str, size: u32) -> Vec<PathBuf> {
+        self.directories()
+            .into_iter()
+            .filter(|dir| self.directory_size(dir) == Some(size))
+            .map(|dir| self.path.join(dir).join(format!("{name}.png")))
+            .chain(raster_candidates(&self.path, name, size))
+            .collect()
+    }
+
+    /// SVG candidates from every directory of `index.theme`, scalable ones
+    /// first, then the usual "places" layouts.
+    fn uncached_svg_candidates(&self, name: &str) -> Vec<PathBuf> {
+        let (scalable, fixed): (Vec<_>, Vec<_>) = self
+            .directories()
+            .into_iter()
+            .partition(|dir| self.is_scalable(dir));
+        scalable
+            .into_iter()
+            .chain(fixed)
+            .map(|dir| self.path.join(dir).join(format!("{name}.svg")))
+            .chain(svg_candidates(&self.path, name))
+            .collect()
+    }
+
+    /// Whether `index.theme` declares `directory` as `Type=Scalable`, or its
+    /// name says so.
+    fn is_scalable(&self, directory: &str) -> bool {
+        self.index
+            .as_ref()
+            .and_then(|index| index.get(directory, "Type"))
+            .map_or(directory.contains("scalable"), |t| t.trim() == "Scalable")
+    }
+
+    /// The directories listed in `index.theme`.
+    fn directories(&self) -> Vec<String> {
+        let Some(index) = &self.index else {
+            return Vec::new();
+        };
+        ["Directories", "ScaledDirectories"]
+            .iter()
+            .filter_map(|key| index.get("Icon Theme", key))
+            .flat_map(|dirs| {
+                dirs.split(',')
+                    .map(|dir| dir.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|dir| !dir.is_empty())
+            .collect()
+    }
+
+    /// The nominal size of a theme directory, from its `index.theme` section
+    /// or, failing that, its name (`48x48/places`, `places/48`). HiDPI
+    /// directories (`Scale` > 1, `48x48@2x`) are skipped.
+    fn directory_size(&self, directory: &str) -> Option<u32> {
+        if let Some(index) = &self.index {
+            if index
+                .get(directory, "Scale")
+                .is_some_and(|s| s.trim() != "1")
+            {
+                return None;
+            }
+            if let Some(size) = index
+                .get(directory, "Size")
+                .and_then(|s| s.trim().parse().ok())
+            {
+                return Some(size);
+            }
+        }
+        directory.split('/').find_map(|component| {
+            let (w, h) = component.split_once('x').unwrap_or((component, component));
+            let size: u32 = w.parse().ok()?;
+            (h.parse() == Ok(size)).then_some(size)
+        })
+    }
+}
+
+/// Resolve the active GNOME icon theme from the dconf databases. When no
+/// database sets it, the schema default is read from `gschemas.compiled`,
+/// including distribution overrides such as Ubuntu's Yaru. Falls back to
+/// `hicolor`.
+pub(crate) fn active_theme() -> String {
+    DconfProfile::for_current_user()
+        .read_string(ICON_THEME_KEY)
+        .ok()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .or_else(|| schema_default_theme(schema_dirs()))
+        .unwrap_or_else(|| "hicolor".to_string())
+}
+
+/// Where GLib looks for compiled schemas, highest priority first.
+fn schema_dirs() -> Vec<PathBuf> {
+    let overrides = std::env::var_os("GSETTINGS_SCHEMA_DIR")
+        .map(|dirs| std::env::split_paths(&dirs).collect::<Vec<_>>())
+        .unwrap_or_default();
+    overrides
+        .into_iter()
+        .chain(
+            xdg::data_home()
+                .into_iter()
+                .chain(xdg::data_dirs())
+                .map(|dir| dir.join("glib-2.0/schemas")),
+        )
+        .collect()
+}
+
+/// The default icon theme of the first `gschemas.compiled` in `dirs` that
+/// has the interface schema.
+fn schema_default_theme(dirs: impl IntoIterator<Item = PathBuf>) -> Option<String> {
+    dirs.into_iter()
+        .find_map(|dir| {
+            let data = std::fs::read(dir.join("gschemas.compiled")).ok()?;
+            let schemas = Gvdb::parse(data).ok()?;
+            let schema = schemas.root().ok()?.table(ICON_THEME_SCHEMA)?;
+            Some(
+                schema
+                    .value(ICON_THEME_SCHEMA_KEY)
+                    .and_then(schema_default_str)
+                    .map(str::to_string),
+            )
+        })
+        .flatten()
+}
+
+/// The themes icons are looked up in, in priority order.
+pub(crate) fn active_themes() -> [IconTheme; 3] {
+    let theme = active_theme();
+    let bases = theme_base_dirs();
+    // Search the detected theme first, then Adwaita (the common GNOME default,
+    // present whenever `adwaita-icon-theme` is installed) as a practical
+    // fallback for headless/server environments where theme detection fails,
+    // and finally `hicolor` as the freedesktop-mandated last resort.
+    [theme.as_str(), "Adwaita", "hicolor"].map(|name| IconTheme::load(name, &bases))
+}
+
+/// Load the first of `names` found in the active themes, at every common
+/// raster size plus an optional, validated SVG. Earlier names win even if a
+/// later one has more sizes, so a set never mixes two different icons.
+pub(crate) fn load_icon_set(names: &[&str]) -> Option<LinuxIconSet<'static>> {
+    let themes = active_themes();
+    names
+        .iter()
+        .map(|name| load_named_icon_set(&themes, name))
+        .find(|set| !set.is_empty())
+}
+
+/// Load a single icon file: SVG markup, or a raster image of any size in
+/// any format `image` reads.
+pub(crate) fn load_icon_file(
+    path: &Path,
+) -> Result<LinuxIconSet<'static>, LinuxFolderSettingsError> {
+    let failed = |e: &dyn std::fmt::Display| {
+        LinuxFolderSettingsError::IconOperation(path.to_path_buf(), e.to_string())
+    };
+    let mut set = LinuxIconSet::new();
+    if path.extension().is_some_and(|ext| ext == "svg") {
+        let svg = std::fs::read_to_string(path).map_err(|e| failed(&e))?;
+        set.set_svg(svg).map_err(|e| failed(&e))?;
+    } else {
+        let image = image::open(path).map_err(|e| failed(&e))?;
+        set.add_image(LinuxIconImage {
+            size: image.width(),
+            image: Cow::Owned(image),
+        });
+    }
+    Ok(set)
+}
+
+fn load_named_icon_set(themes: &[IconTheme], name: &str) -> LinuxIconSet<'static> {
+    let mut set = LinuxIconSet::new();
+
+    for size in COMMON_SIZES {
+        'found: for theme in themes {
+            for candidate in theme.raster_candidates(name, size) {
+                if candidate.exists()
+                    && let Ok(img) = image::open(&candidate)
+                {
+                    set.add_image(LinuxIconImage {
+                        size,
+                        image: Cow::Owned(img),
+                    });
+                    break 'found;
+                }
+            }
+        }
+    }
+
+    'svg: for theme in themes {
+        for candidate in theme.svg_candidates(name) {
+            if let Ok(svg) = std::fs::read_to_string(&candidate) {
+                // Parse + validate before keeping it; skip malformed SVGs.
+                if set.set_svg(svg).is_ok() {
+                    break 'svg;
+                }
+            }
+        }
+    }
+
+    set
+}
+
+/// Base directories searched for icon themes, in priority order.
+pub(crate) fn theme_base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(PathBuf::from(&home).join(".local/share/icons"));
+        dirs.push(PathBuf::from(&home).join(".icons"));
+    }
+    dirs.push(PathBuf::from("/usr/share/icons"));
+    dirs
+}
+
+/// Candidate paths for a "places" icon at a given size, across both
+/// `<size>/places` and `places/<size>` theme layouts.
+fn raster_candidates(theme_dir: &Path, name: &str, size: u32) -> Vec<PathBuf> {
+    vec![
+        theme_dir.join(format!("{size}x{size}/places/{name}.png")),
+        theme_dir.join(format!("places/{size}x{size}/{name}.png")),
+        theme_dir.join(format!("{size}/places/{name}.png")),
+    ]
+}
+
+fn svg_candidates(theme_dir: &Path, name: &str) -> Vec<PathBuf> {
+    vec![
+        theme_dir.join(format!("scalable/places/{name}.svg")),
+        theme_dir.join(format!("places/scalable/{name}.svg")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme_with_index(index: &str) -> (tempfile::TempDir, IconTheme) {
+        let base = tempfile::tempdir().unwrap();
+        let root = base.path().join("Test");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("index.theme"), index).unwrap();
+        let theme = IconTheme::load("Test", &[base.path().to_path_buf()]);
+        (base, theme)
+    }
+
+    #[test]
+    fn schema_default_includes_vendor_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("org.gnome.desktop.interface.gschema.xml"),
+            "<schemalist><schema id=\"org.gnome.desktop.interface\" \
+             path=\"/org/gnome/desktop/interface/\"><key name=\"icon-theme\" type=\"s\">\
+             <default>'Adwaita'</default></key></schema></schemalist>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("10_vendor.gschema.override"),
+            "[org.gnome.desktop.interface]\nicon-theme='Yaru'\n",
+        )
+        .unwrap();
+        let compiled = std::process::Command::new("glib-compile-schemas")
+            .arg(dir.path())
+            .status();
+        if !compiled.is_ok_and(|status| status.success()) {
+            eprintln!("glib-compile-schemas unavailable, skipping");
+            return;
+        }
+        let missing = dir.path().join("missing");
+        assert_eq!(
+            schema_default_theme([missing.clone(), dir.path().to_path_buf()]).as_deref(),
+            Some("Yaru")
+        );
+        assert_eq!(schema_default_theme([missing]), None);
+    }
+
+    #[test]
+    fn directory_size_prefers_index_theme() {
+        let (_base, theme) = theme_with_index(
+            "[Icon Theme]\nName=Test\n\n[places/medium]\nSize=48\n\n[hidpi]\nSize=24\nScale=2\n",
+        );
+        let root = &theme.roots[0];
+        assert_eq!(root.directory_size("places/medium"), Some(48));
+        assert_eq!(root.directory_size("hidpi"), None);
+        assert_eq!(root.directory_size("32x32/places"), Some(32));
+        assert_eq!(root.directory_size("places/22"), Some(22));
+        assert_eq!(root.directory_size("48x48@2x/places"), None);
+        assert_eq!(root.directory_size("scalable/places"), None);
+    }
+
+    #[test]
+    fn uncached_theme_probes_known_layouts() {
+        let (base, theme) = theme_with_index("[Icon Theme]\nName=Test\n");
+        assert_eq!(
+            theme.raster_candidates("folder", 48)[0],
+            base.path().join("Test/48x48/places/folder.png")
+        );
+        assert_eq!(
+            theme.svg_candidates("folder")[0],
+            base.path().join("Test/scalable/places/folder.svg")
+        );
+    }
+
+    #[test]
+    fn contains_searches_index_directories() {
+        let (base, theme) = theme_with_index(
+            "[Icon Theme]\nName=Test\nDirectories=16x16/emblems\n\n[16x16/emblems]\nSize=16\n",
+        );
+        let emblems = base.path().join("Test/16x16/emblems");
+        std::fs::create_dir_all(&emblems).unwrap();
+        std::fs::write(emblems.join("emblem-synced.svg"), b"").unwrap();
+        assert!(theme.contains("emblem-synced"));
+        assert!(!theme.contains("emblem-missing"));
+    }
+
+    #[test]
+    fn uncached_theme_uses_index_directories() {
+        let (base, theme) = theme_with_index(
+            "[Icon Theme]\nName=Test\nDirectories=mimes/48,mimes/scalable\n\n\
+             [mimes/48]\nSize=48\n\n[mimes/scalable]\nSize=64\nType=Scalable\n",
+        );
+        let root = base.path().join("Test");
+        assert_eq!(
+            theme.raster_candidates("text-plain", 48)[0],
+            root.join("mimes/48/text-plain.png")
+        );
+        assert_eq!(
+            theme.svg_candidates("text-plain")[..2],
+            [
+                root.join("mimes/scalable/text-plain.svg"),
+                root.join("mimes/48/text-plain.svg")
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_theme_has_no_candidates() {
+        let base = tempfile::tempdir().unwrap();
+        let theme = IconTheme::load("Nope", &[base.path().to_path_buf()]);
+        assert!(theme.raster_candidates("folder", 48).is_empty());
+    }
+
+    #[test]
+    fn cached_theme_resolves_through_cache() {
+        let base = tempfile::tempdir().unwrap();
+        let root = base.path().join("Test");
+        for dir in ["64x64/places", "other/places"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(
+            root.join("index.theme"),
+            "[Icon Theme]\nName=Test\nDirectories=64x64/places,other/places\n\n\
+             [64x64/places]\nSize=64\n\n[other/places]\nSize=64\n",
+        )
+        .unwrap();
+        // Only reachable through the cache: not in a probed layout.
+        std::fs::write(root.join("other/places/folder.png"), b"").unwrap();
+        let indexed = std::process::Command::new("gtk-update-icon-cache")
+            .args(["--force", "--quiet"])
+            .arg(&root)
+            .status()
+            .is_ok_and(|s| s.success());
+        if !indexed {
+            eprintln!("gtk-update-icon-cache unavailable, skipping");
+            return;
+        }
+
+        let theme = IconTheme::load("Test", &[base.path().to_path_buf()]);
+        assert_eq!(
+            theme.raster_candidates("folder", 64),
+            [root.join("other/places/folder.png")]
+        );
+        assert!(theme.raster_candidates("folder", 48).is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::LinuxFolderSettingsError;
+
+pub(crate) const CACHE_FILE: &str = "icon-theme.cache";
+const MAJOR_VERSION: u16 = 1;
+const NO_OFFSET: u32 = u32::MAX;
+
+/// Longer hash chains are treated as corrupt, guarding against cycles.
+const MAX_CHAIN: usize = 1 << 16;
+
+const HAS_SUFFIX_XPM: u16 = 1;
+const HAS_SUFFIX_SVG: u16 = 2;
+const HAS_SUFFIX_PNG: u16 = 4;
+const HAS_ICON_FILE: u16 = 8;
+
+/// A theme directory that contains a looked-up icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedIcon<'a> {
+    /// The directory relative to the theme root, e.g. `48x48/places`.
+    pub directory: &'a str,
+    flags: u16,
+}
+
+impl CachedIcon<'_> {
+    pub fn has_png(&self) -> bool {
+        self.flags & HAS_SUFFIX_PNG != 0
+    }
+
+    pub fn has_svg(&self) -> bool {
+        self.flags & HAS_SUFFIX_SVG != 0
+    }
+
+    pub fn has_xpm(&self) -> bool {
+        self.flags & HAS_SUFFIX_XPM != 0
+    }
+}
+
+/// A GTK `icon-theme.cache`, the index `gtk-update-icon-cache` writes into
+/// a theme's root listing, for every icon name, the directories holding it.
+///
+/// One hash lookup replaces probing candidate paths in every directory. GTK
+/// ignores a cache older than its theme directory, and so does
+/// [`IconThemeCache::open`].
+#[derive(Debug, Clone)]
+pub struct IconThemeCache {
+    data: Vec<u8>,
+}
+
+impl IconThemeCache {
+    /// Open the cache of a theme directory (e.g. `/usr/share/icons/Adwaita`).
+    /// Returns `None` if there is no cache or it is stale, i.e. the theme
+    /// directory was modified after the cache was written.
+    pub fn open<P: AsRef<Path>>(theme_dir: P) -> Result<Option<Self>, LinuxFolderSettingsError> {
+        let theme_dir = theme_dir.as_ref();
+        let path = cache_path(theme_dir);
+        let cache_mtime = match path.metadata().and_then(|m| m.modified()) {
+            Ok(mtime) => mtime,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let dir_mtime = theme_dir
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        // GTK compares whole seconds.
+        if seconds(cache_mtime) < seconds(dir_mtime) {
+            return Ok(None);
+        }
+        Self::parse(std::fs::read(&path)?)
+            .map(Some)
+            .map_err(|e| LinuxFolderSettingsError::IconThemeCache(path, e))
+    }
+
+    /// Index every icon under `theme_dir` and write its cache, as
+    /// `gtk-update-icon-cache --ignore-theme-index` does (without embedded
+    /// image data). The cache is stamped after the directory so GTK trusts it.
+    pub fn update<P: AsRef<Path>>(theme_dir: P) -> Result<(), LinuxFolderSettingsError> {
+        let theme_dir = theme_dir.as_ref();
+        let mut directories = Vec::new();
+        collect_directories(theme_dir, "", &mut directories)?;
+
+        // Icon name to `(directory index, suffix flags)`.
+        let mut icons: BTreeMap<String, Vec<(u16, u16)>> = BTreeMap::new();
+        for (index, directory) in directories.iter().enumerate() {
+            for entry in fs::read_dir(theme_dir.join(directory))?.filter_map(Result::ok) {
+                let file_name = entry.file_name();
+                let Some((name, flag)) = file_name.to_str().and_then(icon_file) else {
+                    continue;
+                };
+                let images = icons.entry(name.to_string()).or_default();
+                match images.iter_mut().find(|(dir, _)| *dir as usize == index) {
+                    Some((_, flags)) => *flags |= flag,
+                    None => images.push((index as u16, flag)),
+                }
+            }
+        }
+
+        let data = build(&directories, &icons);
+        let path = cache_path(theme_dir);
+        let temporary = theme_dir.join(format!(".{CACHE_FILE}.new"));
+        fs::write(&temporary, data)?;
+        fs::rename(&temporary, &path)?;
+        // The rename touched the directory; keep the cache at least as new.
+        filetime::set_file_mtime(&path, filetime::FileTime::now())?;
+        Ok(())
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let this = Self { data };
+        match this.u16(0) {
+            Some(MAJOR_VERSION) => {}
+            Some(major) => return Err(format!("unsupported cache version {major}")),
+            None => return Err("truncated header".to_string()),
+        }
+        if this.u32(4).is_none() || this.u32(8).is_none() {
+            return Err("truncated header".to_string());
+        }
+        Ok(this)
+    }
+
+    /// The theme directories indexed by the cache.
+    pub fn directories(&self) -> Vec<&str> {
+        let Some(list) = self.u32(8) else {
+            return Vec::new();
+        };
+        let count = self.u32(list).unwrap_or(0);
+        (0..count)
+            .map_while(|i| self.u32(element(list, i, 4)?).and_then(|o| self.str(o)))
+            .collect()
+    }
+
+    /// Every directory containing the icon `name` (without extension).
+    pub fn lookup(&self, name: &str) -> Vec<CachedIcon<'_>> {
+        self.find(name).unwrap_or_default()
+    }
+
+    fn find(&self, name: &str) -> Option<Vec<CachedIcon<'_>>> {
+        let hash = self.u32(4)?;
+        let n_buckets = self.u32(hash)?;
+        if n_buckets == 0 {
+            return None;
+        }
+        let bucket = icon_name_hash(name) % n_buckets;
+        let mut icon = self.u32(element(hash, bucket, 4)?)?;
+
+        for _ in 0..MAX_CHAIN {
+            if icon == NO_OFFSET {
+                return None;
+            }
+            if self.str(self.u32(icon.checked_add(4)?)?)? == name {
+                return self.images(self.u32(icon.checked_add(8)?)?);
+            }
+            icon = self.u32(icon)?;
+        }
+        None
+    }
+
+    fn images(&self, list: u32) -> Option<Vec<CachedIcon<'_>>> {
+        let directories = self.directories();
+        let count = self.u32(list)?;
+        (0..count)
+            .map(|i| {
+                let image = element(list, i, 8)?;
+                Some(CachedIcon {
+                    directory: directories.get(self.u16(image)? as usize)?,
+                    flags: self.u16(image.checked_add(2)?)?,
+                })
+            })
+            .collect()
+    }
+
+    fn u16(&self, offset: u32) -> Option<u16> {
+        let offset = offset as usize;
+        Some(u16::from_be_bytes(
+            self.data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32(&self, offset: u32) -> Option<u32> {
+        let offset = offset as usize;
+        Some(u32::from_be_bytes(
+            self.data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn str(&self, offset: u32) -> Option<&str> {
+        let rest = self.data.get(offset as usize..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..len]).ok()
+    }
+}
+
+/// Entry `index` of `size` bytes in the list at `list`, after its count;
+/// `None` when a corrupt offset would wrap.
+fn element(list: u32, index: u32, size: u32) -> Option<u32> {
+    index.checked_mul(size)?.checked_add(4)?.checked_add(list)
+}
+
+/// Subdirectories of `dir` (relative to the theme root, `/`-separated) that
+/// contain at least one icon, depth first.
+fn collect_directories(
+    root: &Path,
+    relative: &str,
+    directories: &mut Vec<String>,
+) -> std::io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(relative))?
+        .filter_map(Result::ok)
+        .collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut has_icons = false;
+    for entry in entries {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if entry.path().is_dir() {
+            let child = if relative.is_empty() {
+                name
+            } else {
+                format!("{relative}/{name}")
+            };
+            collect_directories(root, &child, directories)?;
+        } else if !relative.is_empty() && icon_file(&name).is_some() {
+            has_icons = true;
+        }
+    }
+    if has_icons {
+        directories.push(relative.to_string());
+    }
+    Ok(())
+}
+
+/// The icon name and suffix flag of an icon file name.
+fn icon_file(file_name: &str) -> Option<(&str, u16)> {
+    let (name, extension) = file_name.rsplit_once('.')?;
+    let flag = match extension {
+        "png" => HAS_SUFFIX_PNG,
+        "svg" => HAS_SUFFIX_SVG,
+        "xpm" => HAS_SUFFIX_XPM,
+        "icon" => HAS_ICON_FILE,
+        _ => return None,
+    };
+    (!name.is_empty()).then_some((name, flag))
+}
+
+/// Serialize a cache: header, hash buckets, then per icon its chain
+/// record, image list and name, then the directory list.
+fn build(directories: &[String], icons: &BTreeMap<String, Vec<(u16, u16)>>) -> Vec<u8> {
+    fn put_u32(data: &mut Vec<u8>, value: usize) {
+        data.extend_from_slice(&(value as u32).to_be_bytes());
+    }
+    fn patch(data: &mut [u8], at: usize, value: usize) {
+        data[at..at + 4].copy_from_slice(&(value as u32).to_be_bytes());
+    }
+    fn put_str(data: &mut Vec<u8>, string: &str) -> usize {
+        let offset = data.len();
+        data.extend_from_slice(string.as_bytes());
+        data.push(0);
+        data.resize(data.len().next_multiple_of(4), 0);
+        offset
+    }
+
+    let n_buckets = icons.len().max(1);
+    let mut data = Vec::new();
+    data.extend_from_slice(&MAJOR_VERSION.to_be_bytes());
+    data.extend_from_slice(&0u16.to_be_bytes());
+    put_u32(&mut data, 12);
+    put_u32(&mut data, 0);
+    put_u32(&mut data, n_buckets);
+    let buckets = data.len();
+    data.resize(buckets + 4 * n_buckets, 0xff);
+
+    // The last icon written to each bucket, whose chain link is pending.
+    let mut tails: Vec<Option<usize>> = vec![None; n_buckets];
+    for (name, images) in icons {
+        let bucket = icon_name_hash(name) as usize % n_buckets;
+        let icon = data.len();
+        match tails[bucket] {
+            Some(previous) => patch(&mut data, previous, icon),
+            None => patch(&mut data, buckets + 4 * bucket, icon),
+        }
+        tails[bucket] = Some(icon);
+        put_u32(&mut data, NO_OFFSET as usize);
+        put_u32(&mut data, 0);
+        put_u32(&mut data, icon + 12);
+        put_u32(&mut data, images.len());
+        for (directory, flags) in images {
+            data.extend_from_slice(&directory.to_be_bytes());
+            data.extend_from_slice(&flags.to_be_bytes());
+            put_u32(&mut data, 0);
+        }
+        let name = put_str(&mut data, name);
+        patch(&mut data, icon + 4, name);
+    }
+
+    let list = data.len();
+    patch(&mut data, 8, list);
+    put_u32(&mut data, directories.len());
+    data.resize(list + 4 + 4 * directories.len(), 0);
+    for (i, directory) in directories.iter().enumerate() {
+        let name = put_str(&mut data, directory);
+        patch(&mut data, list + 4 + 4 * i, name);
+    }
+    data
+}
+
+/// GTK's icon name hash: `h = h * 31 + c` over signed chars, seeded with
+/// the first character.
+fn icon_name_hash(name: &str) -> u32 {
+    let mut bytes = name.bytes().map(|b| b as i8 as u32);
+    let first = bytes.next().unwrap_or(0);
+    bytes.fold(first, |h, c| (h << 5).wrapping_sub(h).wrapping_add(c))
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Path of the cache file inside a theme directory.
+fn cache_path(theme_dir: &Path) -> PathBuf {
+    theme_dir.join(CACHE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// Build a small theme and index it with `gtk-update-icon-cache`.
+    /// Returns `None` if the tool is unavailable.
+    fn indexed_theme() -> Option<tempfile::TempDir> {
+        let theme = tempfile::tempdir().unwrap();
+        let root = theme.path();
+        std::fs::write(
+            root.join("index.theme"),
+            "[Icon Theme]\nName=Test\nDirectories=48x48/places,scalable/places\n\n\
+             [48x48/places]\nSize=48\nType=Fixed\n\n\
+             [scalable/places]\nSize=64\nType=Scalable\n",
+        )
+        .unwrap();
+        for (dir, file) in [
+            ("48x48/places", "folder.png"),
+            ("48x48/places", "user-home.png"),
+            ("scalable/places", "folder.svg"),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join(file), b"").unwrap();
+        }
+        let status = Command::new("gtk-update-icon-cache")
+            .args(["--force", "--quiet", "--ignore-theme-index"])
+            .arg(root)
+            .status()
+            .ok()?;
+        status.success().then_some(theme)
+    }
+
+    #[test]
+    fn icon_name_hash_matches_gtk() {
+        assert_eq!(icon_name_hash(""), 0);
+        assert_eq!(icon_name_hash("a"), 97);
+        assert_eq!(icon_name_hash("ab"), 97 * 31 + 98);
+    }
+
+    #[test]
+    fn lookup_lists_directories_and_suffixes() {
+        let Some(theme) = indexed_theme() else {
+            eprintln!("gtk-update-icon-cache unavailable, skipping");
+            return;
+        };
+        let cache = IconThemeCache::open(theme.path()).unwrap().unwrap();
+
+        let mut dirs = cache.directories();
+        dirs.sort();
+        assert_eq!(dirs, ["48x48/places", "scalable/places"]);
+
+        let mut hits = cache.lookup("folder");
+        hits.sort_by_key(|hit| hit.directory);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].directory, "48x48/places");
+        assert!(hits[0].has_png() && !hits[0].has_svg());
+        assert_eq!(hits[1].directory, "scalable/places");
+        assert!(hits[1].has_svg() && !hits[1].has_png());
+
+        assert_eq!(cache.lookup("user-home").len(), 1);
+        assert!(cache.lookup("missing").is_empty());
+    }
+
+    #[test]
+    fn stale_cache_is_ignored() {
+        let Some(theme) = indexed_theme() else {
+            eprintln!("gtk-update-icon-cache unavailable, skipping");
+            return;
+        };
+        let cache_mtime = cache_path(theme.path())
+            .metadata()
+            .unwrap()
+            .modified()
+            .unwrap();
+        let later = cache_mtime + std::time::Duration::from_secs(10);
+        std::fs::File::open(theme.path())
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(IconThemeCache::open(theme.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn update_writes_a_cache_gtk_accepts() {
+        let theme = tempfile::tempdir().unwrap();
+        let root = theme.path();
+        for (dir, file) in [
+            ("48x48/places", "folder.png"),
+            ("48x48/places", "folder.svg"),
+            ("48x48/places", "user-home.png"),
+            ("scalable/apps", "acme.svg"),
+            ("scalable/apps", "README"),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join(file), b"").unwrap();
+        }
+        IconThemeCache::update(root).unwrap();
+
+        let cache = IconThemeCache::open(root).unwrap().unwrap();
+        let mut dirs = cache.directories();
+        dirs.sort();
+        assert_eq!(dirs, ["48x48/places", "scalable/apps"]);
+        let folder = cache.lookup("folder");
+        assert_eq!(folder.len(), 1);
+        assert!(folder[0].has_png() && folder[0].has_svg());
+        assert_eq!(cache.lookup("acme")[0].directory, "scalable/apps");
+        assert!(cache.lookup("README").is_empty());
+
+        let validated = Command::new("gtk-update-icon-cache")
+            .args(["--validate", "--quiet"])
+            .arg(root)
+            .status();
+        match validated {
+            Ok(status) => assert!(status.success(), "gtk-update-icon-cache rejected the cache"),
+            Err(_) => eprintln!("gtk-update-icon-cache unavailable, skipping validation"),
+        }
+    }
+
+    #[test]
+    fn missing_cache_is_none() {
+        let theme = tempfile::tempdir().unwrap();
+        assert!(IconThemeCache::open(theme.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupt_offsets_find_nothing() {
+        // One bucket whose chain starts near the end of the address space.
+        let mut data = vec![0, 1, 0, 0, 0, 0, 0, 12, 0, 0, 0, 20, 0, 0, 0, 1];
+        data.extend((u32::MAX - 2).to_be_bytes());
+        data.extend([0; 4]);
+        let cache = IconThemeCache::parse(data).unwrap();
+        assert!(cache.lookup("folder").is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        assert!(IconThemeCache::parse(vec![0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(IconThemeCache::parse(vec![0, 1]).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{
+    DesktopEntry, HicolorTheme, IconContext, IconThemeCache, IconThemeSetting,
+    LinuxFolderSettingsError, xdg,
+};
+use crate::icon::sys::linux::LinuxIconSet;
+
+const DEFAULT_THEME_NAME: &str = "icon-sys-override";
+const THEME_GROUP: &str = "Icon Theme";
+const INDEX_FILE: &str = "index.theme";
+/// Records the theme that was active before the override, so it can be
+/// switched back. Absent when the setting was unset.
+const PREVIOUS_THEME_KEY: &str = "X-IconSys-PreviousTheme";
+/// Marks a theme as generated, so that only those are ever replaced or
+/// removed.
+const GENERATED_KEY: &str = "X-IconSys-Generated";
+
+/// Replaces named icons for the whole session with a generated user theme.
+///
+/// The theme lives in `$XDG_DATA_HOME/icons/icon-sys-override`, `Inherits=`
+/// the theme that was active and holds only the replaced icons, so every
+/// other icon still comes from the original theme. Applying it switches the
+/// desktop's icon theme setting; restoring switches back and removes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconThemeOverride {
+    icons_dir: PathBuf,
+    theme_name: String,
+    setting: IconThemeSetting,
+}
+
+impl IconThemeOverride {
+    /// The override in `$XDG_DATA_HOME/icons`, switched through the setting
+    /// of the running desktop.
+    pub fn for_current_user() -> Result<Self, LinuxFolderSettingsError> {
+        let icons_dir = xdg::data_home()
+            .map(|data| data.join("icons"))
+            .ok_or_else(|| {
+                LinuxFolderSettingsError::Error("could not locate the data directory".to_string())
+            })?;
+        Ok(Self::new(
+            icons_dir,
+            IconThemeSetting::for_current_session()?,
+        ))
+    }
+
+    /// The override in the icon theme base directory `icons_dir`, switched
+    /// through `setting`.
+    pub fn new(icons_dir: impl Into<PathBuf>, setting: IconThemeSetting) -> Self {
+        Self {
+            icons_dir: icons_dir.into(),
+            theme_name: DEFAULT_THEME_NAME.to_string(),
+            setting,
+        }
+    }
+
+    /// Use a different name for the generated theme.
+    pub fn with_theme_name(mut self, name: &str) -> Self {
+        self.theme_name = name.to_string();
+        self
+    }
+
+    pub fn theme_name(&self) -> &str {
+        &self.theme_name
+    }
+
+    pub fn theme_dir(&self) -> PathBuf {
+        self.icons_dir.join(&self.theme_name)
+    }
+
+    pub fn setting(&self) -> &IconThemeSetting {
+        &self.setting
+    }
+
+    /// Whether the generated theme is the configured one.
+    pub fn is_active(&self) -> Result<bool, LinuxFolderSettingsError> {
+        Ok(self.setting.read()?.as_deref() == Some(self.theme_name.as_str()))
+    }
+
+    /// Regenerate the theme with exactly `icons`, e.g. `("folder", ..)` and
+    /// `("folder-open", ..)`, each at every size of its set, and make it the
+    /// active theme. Icons of an earlier apply that are not listed are
+    /// dropped. When the override is already active, the theme it inherits
+    /// from is kept.
+    pub fn apply(&self, icons: &[(&str, &LinuxIconSet)]) -> Result<(), LinuxFolderSettingsError> {
+        let theme_dir = self.theme_dir();
+        if self.theme_name.is_empty() || self.theme_name.contains('/') {
+            return Err(LinuxFolderSettingsError::IconOperation(
+                theme_dir,
+                format!("invalid theme name {:?}", self.theme_name),
+            ));
+        }
+        if icons.is_empty() {
+            return Err(LinuxFolderSettingsError::IconOperation(
+                theme_dir,
+                "no icons to override".to_string(),
+            ));
+        }
+
+        let existing = load_generated_index(&theme_dir)?;
+        let active = self.is_active()?;
+        let previous = if active {
+            existing.and_then(|index| index.get(THEME_GROUP, PREVIOUS_THEME_KEY))
+        } else {
+            self.setting.read()?
+        };
+        let base = previous
+            .as_deref()
+            .unwrap_or_else(|| self.setting.default_theme());
+
+        match fs::remove_dir_all(&theme_dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        fs::create_dir_all(&theme_dir)?;
+        let mut index = DesktopEntry::parse("");
+        index.set(THEME_GROUP, "Name", &format!("{base} (customized)"));
+        index.set(
+            THEME_GROUP,
+            "Comment",
+            &format!("{base} with replaced icons"),
+        );
+        let inherits = match base {
+            "hicolor" => base.to_string(),
+            _ => format!("{base},hicolor"),
+        };
+        index.set(THEME_GROUP, "Inherits", &inherits);
+        index.set(THEME_GROUP, "Directories", "");
+        index.set(THEME_GROUP, GENERATED_KEY, "true");
+        if let Some(previous) = &previous {
+            index.set(THEME_GROUP, PREVIOUS_THEME_KEY, previous);
+        }
+        index.save(theme_dir.join(INDEX_FILE))?;
+
+        // Sizes, directories and index entries work as in hicolor; the index
+        // already exists, so nothing is seeded from the system hicolor.
+        let theme = HicolorTheme::at(&theme_dir);
+        for (name, icon_set) in icons {
+            theme.install(IconContext::Places, name, icon_set)?;
+        }
+        IconThemeCache::update(&theme_dir)?;
+
+        if !active {
+            self.setting.write(&self.theme_name)?;
+        }
+        Ok(())
+    }
+
+    /// Switch back to the theme that was active before `apply` (or unset the
+    /// setting if it was unset) and remove the generated theme. Returns
+    /// whether there was anything to undo.
+    pub fn restore(&self) -> Result<bool, LinuxFolderSettingsError> {
+        let theme_dir = self.theme_dir();
+        let index = load_generated_index(&theme_dir)?;
+        let active = self.is_active()?;
+        if active {
+            match index.and_then(|index| index.get(THEME_GROUP, PREVIOUS_THEME_KEY)) {
+                Some(previous) => self.setting.write(&previous)?,
+                None => self.setting.reset()?,
+            }
+        }
+        let removed = match fs::remove_dir_all(&theme_dir) {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(active || removed)
+    }
+}
+
+/// The index of a theme generated by [`IconThemeOverride::apply`], or `None`
+/// when `theme_dir` does not exist. Any other directory is refused, so that
+/// an installed theme is never replaced or removed.
+fn load_generated_index(
+    theme_dir: &Path,
+) -> Result<Option<DesktopEntry>, LinuxFolderSettingsError> {
+    if !theme_dir.exists() {
+        return Ok(None);
+    }
+    let index = match DesktopEntry::load(theme_dir.join(INDEX_FILE)) {
+        Ok(index) => Some(index),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    match index {
+        Some(index) if index.get(THEME_GROUP, GENERATED_KEY).is_some() => Ok(Some(index)),
+        _ => Err(LinuxFolderSettingsError::IconOperation(
+            theme_dir.to_path_buf(),
+            "not a theme generated by icon-sys".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icon::sys::linux::LinuxIconImage;
+    use image::{DynamicImage, RgbaImage};
+    use std::borrow::Cow;
+
+    fn icon_set(sizes: &[u32]) -> LinuxIconSet<'static> {
+        LinuxIconSet::from_icons(sizes.iter().map(|&size| LinuxIconImage {
+            size,
+            image: Cow::Owned(DynamicImage::ImageRgba8(RgbaImage::new(size, size))),
+        }))
+        .unwrap()
+    }
+
+    fn kde_override(dir: &Path) -> (IconThemeOverride, IconThemeSetting) {
+        let setting = IconThemeSetting::KdeGlobals(dir.join("kdeglobals"));
+        (
+            IconThemeOverride::new(dir.join("icons"), setting.clone()),
+            setting,
+        )
+    }
+
+    #[test]
+    fn apply_inherits_the_active_theme_and_restore_switches_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (theme, setting) = kde_override(dir.path());
+        setting.write("breeze").unwrap();
+
+        theme
+            .apply(&[
+                ("folder", &icon_set(&[16, 48])),
+                ("folder-open", &icon_set(&[48])),
+            ])
+            .unwrap();
+        assert_eq!(setting.read().unwrap().as_deref(), Some(DEFAULT_THEME_NAME));
+        assert!(theme.theme_dir().join("48x48/places/folder.png").is_file());
+        assert!(theme.theme_dir().join("16x16/places/folder.png").is_file());
+        let index = DesktopEntry::load(theme.theme_dir().join(INDEX_FILE)).unwrap();
+        assert_eq!(
+            index.get(THEME_GROUP, "Inherits").as_deref(),
+            Some("breeze,hicolor")
+        );
+        let cache = IconThemeCache::open(theme.theme_dir()).unwrap().unwrap();
+        assert_eq!(cache.lookup("folder").len(), 2);
+
+        // Applying again keeps the original base and drops unlisted icons.
+        theme.apply(&[("folder", &icon_set(&[32]))]).unwrap();
+        let index = DesktopEntry::load(theme.theme_dir().join(INDEX_FILE)).unwrap();
+        assert_eq!(
+            index.get(THEME_GROUP, PREVIOUS_THEME_KEY).as_deref(),
+            Some("breeze")
+        );
+        assert!(
+            !theme
+                .theme_dir()
+                .join("48x48/places/folder-open.png")
+                .exists()
+        );
+
+        assert!(theme.restore().unwrap());
+        assert_eq!(setting.read().unwrap().as_deref(), Some("breeze"));
+        assert!(!theme.theme_dir().exists());
+        assert!(!theme.restore().unwrap());
+    }
+
+    #[test]
+    fn restore_unsets_a_previously_unset_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        let (theme, setting) = kde_override(dir.path());
+
+        theme.apply(&[("folder", &icon_set(&[48]))]).unwrap();
+        let index = DesktopEntry::load(theme.theme_dir().join(INDEX_FILE)).unwrap();
+        assert_eq!(
+            index.get(THEME_GROUP, "Inherits").as_deref(),
+            Some("breeze,hicolor")
+        );
+
+        theme.restore().unwrap();
+        assert_eq!(setting.read().unwrap(), None);
+    }
+
+    #[test]
+    fn empty_overrides_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (theme, setting) = kde_override(dir.path());
+        assert!(theme.apply(&[]).is_err());
+        assert!(
+            theme
+                .with_theme_name("../escape")
+                .apply(&[("folder", &icon_set(&[48]))])
+                .is_err()
+        );
+        assert_eq!(setting.read().unwrap(), None);
+    }
+
+    #[test]
+    fn installed_themes_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let (theme, setting) = kde_override(dir.path());
+        let theme = theme.with_theme_name("Papirus");
+        let index = theme.theme_dir().join(INDEX_FILE);
+        fs::create_dir_all(theme.theme_dir()).unwrap();
+        fs::write(&index, "[Icon Theme]\nName=Papirus\n").unwrap();
+
+        assert!(theme.apply(&[("folder", &icon_set(&[48]))]).is_err());
+        assert!(theme.restore().is_err());
+        assert!(index.is_file());
+        assert_eq!(setting.read().unwrap(), None);
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use zbus::blocking::Connection;
+
+use super::{DesktopDetector, DesktopEntry, LinuxFolderSettingsError, xdg};
+
+const KDE_ICONS_GROUP: &str = "Icons";
+const KDE_THEME_KEY: &str = "Theme";
+/// `KGlobalSettings::IconChanged`.
+const KDE_ICON_CHANGED: i32 = 4;
+const XFCONF_CHANNEL: &str = "xsettings";
+const XFCONF_PROPERTY: &str = "/Net/IconThemeName";
+
+/// Where a desktop environment stores the name of the active icon theme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IconThemeSetting {
+    /// A GSettings key, read and written with `gsettings` (GNOME, Budgie,
+    /// Cinnamon, MATE).
+    GSettings { schema: String, key: String },
+    /// `Theme=` in the `[Icons]` group of a `kdeglobals` file (KDE Plasma).
+    /// Running applications are told with KDE's
+    /// `org.kde.KGlobalSettings.notifyChange` signal.
+    KdeGlobals(PathBuf),
+    /// The `/Net/IconThemeName` property of the `xsettings` xfconf channel,
+    /// read and written with `xfconf-query` (Xfce).
+    Xfconf,
+}
+
+impl IconThemeSetting {
+    /// GNOME's `org.gnome.desktop.interface icon-theme`.
+    pub fn gnome() -> Self {
+        Self::gsettings("org.gnome.desktop.interface", "icon-theme")
+    }
+
+    pub fn gsettings(schema: &str, key: &str) -> Self {
+        Self::GSettings {
+            schema: schema.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    /// The setting of a desktop name as reported by `DesktopDetection`
+    /// (`"gnome"`, `"kde"`, `"xfce"`, ...), if it is known.
+    pub fn for_desktop(desktop: &str) -> Option<Self> {
+        match desktop.to_ascii_lowercase().as_str() {
+            "gnome" | "budgie" | "unity" | "ubuntu" | "pop" => Some(Self::gnome()),
+            "cinnamon" => Some(Self::gsettings(
+                "org.cinnamon.desktop.interface",
+                "icon-theme",
+            )),
+            "mate" => Some(Self::gsettings("org.mate.interface", "icon-theme")),
+            "kde" | "plasma" => xdg::config_home().map(|c| Self::KdeGlobals(c.join("kdeglobals"))),
+            "xfce" => Some(Self::Xfconf),
+            _ => None,
+        }
+    }
+
+    /// The setting of the running desktop, detected with the default
+    /// `DesktopDetector`.
+    pub fn for_current_session() -> Result<Self, LinuxFolderSettingsError> {
+        let detection = DesktopDetector::new().detect()?;
+        Self::for_desktop(&detection.desktop).ok_or(LinuxFolderSettingsError::UndetectedDesktop)
+    }
+
+    /// The configured theme name, or `None` when the setting is unset.
+    pub fn read(&self) -> Result<Option<String>, LinuxFolderSettingsError> {
+        let value = match self {
+            Self::GSettings { schema, key } => {
+                let output = run("gsettings", &["get", schema, key])?;
+                unquote_gvariant(output.trim())
+            }
+            Self::KdeGlobals(path) => load_entry(path)?.get(KDE_ICONS_GROUP, KDE_THEME_KEY),
+            Self::Xfconf => {
+                match run(
+                    "xfconf-query",
+                    &["-c", XFCONF_CHANNEL, "-p", XFCONF_PROPERTY],
+                ) {
+                    Ok(output) => Some(output.trim().to_string()),
+                    Err(LinuxFolderSettingsError::SettingsTool(_, message))
+                        if message.contains("does not exist") =>
+                    {
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        Ok(value.filter(|v| !v.is_empty()))
+    }
+
+    /// The theme the desktop shows while the setting is unset.
+    pub fn default_theme(&self) -> &'static str {
+        match self {
+            Self::GSettings { schema, .. } if schema.starts_with("org.cinnamon.") => "Mint-Y",
+            Self::GSettings { schema, .. } if schema.starts_with("org.mate.") => "menta",
+            Self::GSettings { .. } | Self::Xfconf => "Adwaita",
+            Self::KdeGlobals(_) => "breeze",
+        }
+    }
+
+    /// Switch the desktop to the theme `name`.
+    pub fn write(&self, name: &str) -> Result<(), LinuxFolderSettingsError> {
+        match self {
+            Self::GSettings { schema, key } => {
+                run("gsettings", &["set", schema, key, name])?;
+            }
+            Self::KdeGlobals(path) => {
+                let mut entry = load_entry(path)?;
+                entry.set(KDE_ICONS_GROUP, KDE_THEME_KEY, name);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                entry.save(path)?;
+                notify_kde_icon_change();
+            }
+            Self::Xfconf => {
+                run(
+                    "xfconf-query",
+                    &[
+                        "-c",
+                        XFCONF_CHANNEL,
+                        "-p",
+                        XFCONF_PROPERTY,
+                        "--create",
+                        "-t",
+                        "string",
+                        "-s",
+                        name,
+                    ],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unset the setting, so the desktop falls back to its default theme.
+    pub fn reset(&self) -> Result<(), LinuxFolderSettingsError> {
+        match self {
+            Self::GSettings { schema, key } => {
+                run("gsettings", &["reset", schema, key])?;
+            }
+            Self::KdeGlobals(path) => {
+                let mut entry = load_entry(path)?;
+                if entry.remove(KDE_ICONS_GROUP, KDE_THEME_KEY) {
+                    if entry.is_group_empty(KDE_ICONS_GROUP) {
+                        entry.remove_group(KDE_ICONS_GROUP);
+                    }
+                    entry.save(path)?;
+                    notify_kde_icon_change();
+                }
+            }
+            Self::Xfconf => {
+                run(
+                    "xfconf-query",
+                    &["-c", XFCONF_CHANNEL, "-p", XFCONF_PROPERTY, "-r"],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run a settings tool and return its stdout.
+fn run(program: &str, args: &[&str]) -> Result<String, LinuxFolderSettingsError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| LinuxFolderSettingsError::SettingsTool(program.to_string(), e.to_string()))?;
+    if !output.status.success() {
+        return Err(LinuxFolderSettingsError::SettingsTool(
+            program.to_string(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The string of a GVariant text such as `'Adwaita'`, as printed by
+/// `gsettings get`.
+fn unquote_gvariant(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+fn load_entry(path: &std::path::Path) -> Result<DesktopEntry, LinuxFolderSettingsError> {
+    match DesktopEntry::load(path) {
+        Ok(entry) => Ok(entry),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DesktopEntry::parse("")),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Ask running KDE applications to reload their icons. Best effort: without
+/// a session bus there is nothing running to notify, and Plasma reads
+/// `kdeglobals` on its next start.
+fn notify_kde_icon_change() {
+    if let Ok(connection) = Connection::session() {
+        let _ = connection.emit_signal(
+            None::<&str>,
+            "/KGlobalSettings",
+            "org.kde.KGlobalSettings",
+            "notifyChange",
+            &(KDE_ICON_CHANGED, 0i32),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktops_map_to_settings() {
+        assert_eq!(
+            IconThemeSetting::for_desktop("GNOME"),
+            Some(IconThemeSetting::gnome())
+        );
+        assert_eq!(
+            IconThemeSetting::for_desktop("mate"),
+            Some(IconThemeSetting::gsettings(
+                "org.mate.interface",
+                "icon-theme"
+            ))
+        );
+        assert_eq!(
+            IconThemeSetting::for_desktop("xfce"),
+            Some(IconThemeSetting::Xfconf)
+        );
+        assert_eq!(IconThemeSetting::for_desktop("lxqt"), None);
+    }
+
+    #[test]
+    fn kdeglobals_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config/kdeglobals");
+        let setting = IconThemeSetting::KdeGlobals(path.clone());
+        assert_eq!(setting.read().unwrap(), None);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "[General]\nColorScheme=BreezeDark\n").unwrap();
+        setting.write("Papirus").unwrap();
+        assert_eq!(setting.read().unwrap().as_deref(), Some("Papirus"));
+
+        setting.reset().unwrap();
+        assert_eq!(setting.read().unwrap(), None);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[General]\nColorScheme=BreezeDark\n"
+        );
+    }
+
+    #[test]
+    fn gvariant_strings_are_unquoted() {
+        assert_eq!(unquote_gvariant("'Adwaita'").as_deref(), Some("Adwaita"));
+        assert_eq!(unquote_gvariant(r"'It\'s'").as_deref(), Some("It's"));
+        assert_eq!(unquote_gvariant("@ms nothing"), None);
+    }
+}
@@ -0,0 +1,455 @@
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::uri::file_path;
+use super::{HicolorTheme, IconContext, LinuxFolderSettingsError, xdg};
+use crate::folder_settings::xml::{self, Element};
+use crate::icon::sys::linux::LinuxIconSet;
+
+const PLACES_FILE: &str = "user-places.xbel";
+const BOOKMARK_NAMESPACE: &str = "http://www.freedesktop.org/standards/desktop-bookmarks";
+const DEFAULT_BOOKMARK_PREFIX: &str = "bookmark";
+/// Owner of the `<metadata>` block holding the bookmark's icon.
+const FREEDESKTOP_OWNER: &str = "http://freedesktop.org";
+
+/// The entries of KDE's Places panel (Dolphin's sidebar and the file
+/// dialogs), stored as XBEL bookmarks in `$XDG_DATA_HOME/user-places.xbel`.
+///
+/// Each entry names its own icon in
+/// `<info><metadata owner="http://freedesktop.org"><bookmark:icon name="..."/>`,
+/// independent of the folder's `.directory` file. Edits replace only the
+/// affected elements, so the rest of the document is kept byte for byte.
+/// Running KDE applications watch the file and reload it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdePlaces {
+    path: PathBuf,
+    icon_theme: Option<HicolorTheme>,
+}
+
+impl KdePlaces {
+    /// The user's Places file, with icons installed into the user's hicolor
+    /// theme, if the data directory can be located.
+    pub fn for_current_user() -> Option<Self> {
+        let data = xdg::data_home()?;
+        Some(Self::at(data.join(PLACES_FILE)).with_icon_theme(HicolorTheme::for_current_user()?))
+    }
+
+    /// The Places file at `path`.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            icon_theme: None,
+        }
+    }
+
+    /// Where `install_icon` puts icons.
+    pub fn with_icon_theme(mut self, theme: HicolorTheme) -> Self {
+        self.icon_theme = Some(theme);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether `folder` has an entry in Places.
+    pub fn contains<P: AsRef<Path>>(&self, folder: P) -> Result<bool, LinuxFolderSettingsError> {
+        let target = absolute(folder.as_ref())?;
+        let Some(content) = self.read()? else {
+            return Ok(false);
+        };
+        let root = self.parse(&content)?;
+        Ok(!bookmarks_for(&root, &target).is_empty())
+    }
+
+    /// The icon name of `folder`'s entry, or `None` if it has no entry or
+    /// the entry has no icon.
+    pub fn icon<P: AsRef<Path>>(
+        &self,
+        folder: P,
+    ) -> Result<Option<String>, LinuxFolderSettingsError> {
+        let target = absolute(folder.as_ref())?;
+        let Some(content) = self.read()? else {
+            return Ok(None);
+        };
+        let root = self.parse(&content)?;
+        let icon_tag = format!("{}:icon", bookmark_prefix(&root));
+        Ok(bookmarks_for(&root, &target)
+            .into_iter()
+            .find_map(|bookmark| {
+                icon_element(bookmark, &icon_tag)
+                    .and_then(|icon| icon.attr("name"))
+                    .map(str::to_string)
+            }))
+    }
+
+    /// Set the icon of `folder`'s entries to a theme icon name (or an
+    /// absolute path to an image), or remove it with `None` so the generic
+    /// icon is shown. Folders without an entry are not added; returns
+    /// whether there was one.
+    pub fn set_icon<P: AsRef<Path>>(
+        &self,
+        folder: P,
+        icon: Option<&str>,
+    ) -> Result<bool, LinuxFolderSettingsError> {
+        let target = absolute(folder.as_ref())?;
+        let Some(content) = self.read()? else {
+            return Ok(false);
+        };
+        let root = self.parse(&content)?;
+        let bookmarks = bookmarks_for(&root, &target);
+        if bookmarks.is_empty() {
+            return Ok(false);
+        }
+
+        let declared = declared_prefix(&root);
+        let prefix = declared.unwrap_or(DEFAULT_BOOKMARK_PREFIX);
+        let icon_tag = format!("{prefix}:icon");
+        let icon_markup = icon.map(|name| format!("<{icon_tag} name=\"{}\"/>", xml::escape(name)));
+
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        for bookmark in bookmarks {
+            match (icon_element(bookmark, &icon_tag), &icon_markup) {
+                (Some(existing), Some(markup)) => {
+                    edits.push((existing.span.clone(), markup.clone()))
+                }
+                (Some(existing), None) => {
+                    edits.push((line_span(&content, &existing.span), String::new()))
+                }
+                (None, Some(markup)) => edits.push(add_icon(&content, bookmark, markup)),
+                (None, None) => {}
+            }
+        }
+        if icon.is_some() && declared.is_none() {
+            let at = root.span.start + 1 + root.name.len();
+            edits.push((
+                at..at,
+                format!(" xmlns:{DEFAULT_BOOKMARK_PREFIX}=\"{BOOKMARK_NAMESPACE}\""),
+            ));
+        }
+
+        let mut updated = content.clone();
+        edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, replacement) in edits {
+            updated.replace_range(range, &replacement);
+        }
+        if updated != content {
+            self.write(&updated)?;
+        }
+        Ok(true)
+    }
+
+    /// Install `icon_set` as the icon `name` (see `HicolorTheme::install`)
+    /// and use it for `folder`'s entries. Returns whether there was one; the
+    /// icon is installed either way.
+    pub fn install_icon<P: AsRef<Path>>(
+        &self,
+        folder: P,
+        name: &str,
+        icon_set: &LinuxIconSet,
+    ) -> Result<bool, LinuxFolderSettingsError> {
+        let theme = self.icon_theme.as_ref().ok_or_else(|| {
+            LinuxFolderSettingsError::Error("no icon theme to install into".to_string())
+        })?;
+        theme.install(IconContext::Places, name, icon_set)?;
+        self.set_icon(folder, Some(name))
+    }
+
+    fn read(&self) -> Result<Option<String>, LinuxFolderSettingsError> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn parse(&self, content: &str) -> Result<Element, LinuxFolderSettingsError> {
+        let root = xml::parse(content)
+            .map_err(|e| LinuxFolderSettingsError::Xbel(self.path.clone(), e))?;
+        if root.name != "xbel" {
+            return Err(LinuxFolderSettingsError::Xbel(
+                self.path.clone(),
+                format!("root element is <{}>, not <xbel>", root.name),
+            ));
+        }
+        Ok(root)
+    }
+
+    /// Replace the file in one step, so a KDE application reloading it
+    /// never sees a partial document.
+    fn write(&self, content: &str) -> Result<(), LinuxFolderSettingsError> {
+        let mut temporary = self.path.as_os_str().to_os_string();
+        temporary.push(".new");
+        fs::write(&temporary, content)?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, LinuxFolderSettingsError> {
+    std::path::absolute(path)
+        .map_err(|e| LinuxFolderSettingsError::IconOperation(path.to_path_buf(), e.to_string()))
+}
+
+/// The prefix the document binds to the desktop-bookmarks namespace.
+fn declared_prefix(root: &Element) -> Option<&str> {
+    root.attributes
+        .iter()
+        .find(|(name, value)| name.starts_with("xmlns:") && value == BOOKMARK_NAMESPACE)
+        .map(|(name, _)| &name["xmlns:".len()..])
+}
+
+fn bookmark_prefix(root: &Element) -> &str {
+    declared_prefix(root).unwrap_or(DEFAULT_BOOKMARK_PREFIX)
+}
+
+/// Every `<bookmark>` pointing at `target`, including those nested in
+/// `<folder>`s.
+fn bookmarks_for<'a>(parent: &'a Element, target: &Path) -> Vec<&'a Element> {
+    let mut found = Vec::new();
+    for element in parent.elements() {
+        match element.name.as_str() {
+            "bookmark" if element.attr("href").and_then(file_path).as_deref() == Some(target) => {
+                found.push(element)
+            }
+            "folder" => found.extend(bookmarks_for(element, target)),
+            _ => {}
+        }
+    }
+    found
+}
+
+fn freedesktop_metadata(bookmark: &Element) -> Option<&Element> {
+    bookmark
+        .child("info")?
+        .elements_named("metadata")
+        .find(|m| m.attr("owner") == Some(FREEDESKTOP_OWNER))
+}
+
+fn icon_element<'a>(bookmark: &'a Element, icon_tag: &str) -> Option<&'a Element> {
+    freedesktop_metadata(bookmark)?.child(icon_tag)
+}
+
+/// The edit adding an icon to a bookmark that has none, creating the
+/// `<info>` and `<metadata>` elements when they are missing.
+fn add_icon(content: &str, bookmark: &Element, markup: &str) -> (Range<usize>, String) {
+    let metadata_start = format!("<metadata owner=\"{FREEDESKTOP_OWNER}\">");
+    if let Some(metadata) = freedesktop_metadata(bookmark) {
+        insert_child(content, metadata, markup)
+    } else if let Some(info) = bookmark.child("info") {
+        insert_child(
+            content,
+            info,
+            &format!("{metadata_start}{markup}</metadata>"),
+        )
+    } else {
+        let info = format!("<info>{metadata_start}{markup}</metadata></info>");
+        insert_child(content, bookmark, &info)
+    }
+}
+
+/// The edit appending `markup` as the last child of `parent`, indented one
+/// step deeper than `parent` (or like its existing children).
+fn insert_child(content: &str, parent: &Element, markup: &str) -> (Range<usize>, String) {
+    let indent = indentation(content, parent.span.start).unwrap_or("");
+    let child_indent = parent
+        .elements()
+        .next()
+        .and_then(|child| indentation(content, child.span.start))
+        .map_or_else(|| format!("{indent} "), str::to_string);
+
+    if parent.content.is_empty() && parent.span.end == parent.content.end {
+        // `<parent .../>`: reopen it with the child inside.
+        let start_tag = content[parent.span.start..parent.span.end - 2].trim_end();
+        let replacement = format!(
+            "{start_tag}>\n{child_indent}{markup}\n{indent}</{}>",
+            parent.name
+        );
+        return (parent.span.clone(), replacement);
+    }
+    let inner = &content[parent.content.clone()];
+    let kept = inner.trim_end().len();
+    if kept == 0 {
+        return (
+            parent.content.clone(),
+            format!("\n{child_indent}{markup}\n{indent}"),
+        );
+    }
+    let at = parent.content.start + kept;
+    (at..at, format!("\n{child_indent}{markup}"))
+}
+
+/// The whitespace before `pos` on its line, if only whitespace precedes it.
+fn indentation(content: &str, pos: usize) -> Option<&str> {
+    let line_start = content[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let before = &content[line_start..pos];
+    before.trim().is_empty().then_some(before)
+}
+
+/// `span` widened to its whole line when nothing else is on it, so removing
+/// an element leaves no blank line behind.
+fn line_span(content: &str, span: &Range<usize>) -> Range<usize> {
+    let alone_after = content[span.end..]
+        .split('\n')
+        .next()
+        .is_some_and(|rest| rest.trim().is_empty());
+    match indentation(content, span.start) {
+        Some(indent) if alone_after => {
+            let start = span.start - indent.len();
+            let end = content[span.end..]
+                .find('\n')
+                .map_or(content.len(), |i| span.end + i + 1);
+            start..end
+        }
+        _ => span.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icon::sys::linux::LinuxIconImage;
+    use image::{DynamicImage, RgbaImage};
+    use std::borrow::Cow;
+
+    const PLACES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE xbel>
+<xbel xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks" xmlns:kdepriv="http://www.kde.org/kdepriv">
+ <info>
+  <metadata owner="http://www.kde.org">
+   <kde_places_version>4</kde_places_version>
+  </metadata>
+ </info>
+ <bookmark href="file:///home/ada">
+  <title>Home</title>
+  <info>
+   <metadata owner="http://freedesktop.org">
+    <bookmark:icon name="user-home"/>
+   </metadata>
+   <metadata owner="http://www.kde.org">
+    <ID>1700000000/0</ID>
+    <isSystemItem>true</isSystemItem>
+   </metadata>
+  </info>
+ </bookmark>
+ <bookmark href="file:///home/ada/My%20Projects">
+  <title>My Projects</title>
+  <info>
+   <metadata owner="http://www.kde.org">
+    <ID>1700000000/1</ID>
+   </metadata>
+  </info>
+ </bookmark>
+</xbel>
+"#;
+
+    fn places(content: &str) -> (tempfile::TempDir, KdePlaces) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PLACES_FILE);
+        fs::write(&path, content).unwrap();
+        (dir, KdePlaces::at(path))
+    }
+
+    #[test]
+    fn reads_icons_by_folder() {
+        let (_dir, places) = places(PLACES);
+        assert_eq!(
+            places.icon("/home/ada/").unwrap().as_deref(),
+            Some("user-home")
+        );
+        assert_eq!(places.icon("/home/ada/My Projects").unwrap(), None);
+        assert!(places.contains("/home/ada/My Projects").unwrap());
+        assert!(!places.contains("/srv").unwrap());
+    }
+
+    #[test]
+    fn replacing_an_icon_changes_only_that_element() {
+        let (_dir, places) = places(PLACES);
+        assert!(places.set_icon("/home/ada", Some("folder-red")).unwrap());
+        let updated = fs::read_to_string(places.path()).unwrap();
+        assert_eq!(
+            updated,
+            PLACES.replace("name=\"user-home\"", "name=\"folder-red\"")
+        );
+    }
+
+    #[test]
+    fn missing_metadata_is_created_and_removed_cleanly() {
+        let (_dir, places) = places(PLACES);
+        assert!(
+            places
+                .set_icon("/home/ada/My Projects", Some("folder-blue"))
+                .unwrap()
+        );
+        assert_eq!(
+            places.icon("/home/ada/My Projects").unwrap().as_deref(),
+            Some("folder-blue")
+        );
+        let updated = fs::read_to_string(places.path()).unwrap();
+        assert!(updated.contains(
+            "   </metadata>\n   <metadata owner=\"http://freedesktop.org\">\
+             <bookmark:icon name=\"folder-blue\"/></metadata>\n  </info>"
+        ));
+
+        assert!(places.set_icon("/home/ada", None).unwrap());
+        let updated = fs::read_to_string(places.path()).unwrap();
+        assert!(!updated.contains("user-home"));
+        assert!(updated.contains("<metadata owner=\"http://freedesktop.org\">\n   </metadata>"));
+        assert_eq!(places.icon("/home/ada").unwrap(), None);
+    }
+
+    #[test]
+    fn undeclared_namespace_and_empty_elements() {
+        let (_dir, places) =
+            places("<xbel>\n <folder>\n  <bookmark href=\"file:///data\"/>\n </folder>\n</xbel>\n");
+        assert!(places.set_icon("/data", Some("drive-harddisk")).unwrap());
+        assert_eq!(
+            fs::read_to_string(places.path()).unwrap(),
+            "<xbel xmlns:bookmark=\"http://www.freedesktop.org/standards/desktop-bookmarks\">\n \
+             <folder>\n  <bookmark href=\"file:///data\">\n   <info><metadata owner=\"http://freedesktop.org\">\
+             <bookmark:icon name=\"drive-harddisk\"/></metadata></info>\n  </bookmark>\n </folder>\n</xbel>\n"
+        );
+        assert_eq!(
+            places.icon("/data").unwrap().as_deref(),
+            Some("drive-harddisk")
+        );
+    }
+
+    #[test]
+    fn folders_without_an_entry_are_left_alone() {
+        let (_dir, places) = places(PLACES);
+        assert!(!places.set_icon("/srv", Some("folder")).unwrap());
+        assert_eq!(fs::read_to_string(places.path()).unwrap(), PLACES);
+
+        let missing = KdePlaces::at(places.path().with_file_name("missing.xbel"));
+        assert!(!missing.set_icon("/home/ada", Some("folder")).unwrap());
+        fs::write(places.path(), "<xbel><bookmark></xbel>").unwrap();
+        assert!(matches!(
+            places.icon("/home/ada"),
+            Err(LinuxFolderSettingsError::Xbel(..))
+        ));
+    }
+
+    #[test]
+    fn install_icon_uses_the_installed_name() {
+        let (dir, places) = places(PLACES);
+        let theme = HicolorTheme::at(dir.path().join("hicolor"));
+        let places = places.with_icon_theme(theme.clone());
+        let set = LinuxIconSet::from_icons([LinuxIconImage {
+            size: 32,
+            image: Cow::Owned(DynamicImage::ImageRgba8(RgbaImage::new(32, 32))),
+        }])
+        .unwrap();
+        assert!(
+            places
+                .install_icon("/home/ada/My Projects", "acme-projects", &set)
+                .unwrap()
+        );
+        assert!(theme.contains(IconContext::Places, "acme-projects"));
+        assert_eq!(
+            places.icon("/home/ada/My Projects").unwrap().as_deref(),
+            Some("acme-projects")
+        );
+    }
+}
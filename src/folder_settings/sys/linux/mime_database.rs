@@ -0,0 +1,1745 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::LinuxFolderSettingsError;
+use super::{HicolorTheme, IconContext, xdg};
+use crate::folder_settings::xml;
+use crate::icon::sys::linux::LinuxIconSet;
+
+/// Namespace of shared-mime-info package files.
+const NAMESPACE: &str = "http://www.freedesktop.org/standards/shared-mime-info";
+
+/// Header `update-mime-database` puts on its text files.
+const GENERATED_HEADER: &str = "# This file was automatically generated by the\n# update-mime-database command. DO NOT EDIT!\n";
+
+/// Comment marking the per-type XML files as generated.
+const GENERATED_COMMENT: &str =
+    "<!--Created automatically by update-mime-database. DO NOT EDIT!-->";
+
+/// Package applied after all others, for local overrides.
+const OVERRIDE_PACKAGE: &str = "Override.xml";
+
+const DEFAULT_GLOB_WEIGHT: u32 = 50;
+const DEFAULT_MAGIC_PRIORITY: u32 = 50;
+
+/// Flag or'd into a `mime.cache` glob weight for case-sensitive globs.
+const CASE_SENSITIVE_FLAG: u32 = 0x100;
+
+/// Package elements that are compiled rather than copied into the
+/// per-type XML files.
+const COMPILED_ELEMENTS: [&str; 5] = [
+    "magic",
+    "magic-deleteall",
+    "glob-deleteall",
+    "root-XML",
+    "treemagic",
+];
+
+/// A MIME type to register, written as a `<mime-type>` element of a
+/// shared-mime-info package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeTypeDefinition {
+    mime_type: String,
+    /// `(language, text)`; the unlocalized comment has no language.
+    comments: Vec<(Option<String>, String)>,
+    globs: Vec<GlobRule>,
+    parents: Vec<String>,
+    aliases: Vec<String>,
+    icon: Option<String>,
+    generic_icon: Option<String>,
+    magic: Vec<(u32, Vec<MimeMagicMatch>)>,
+    root_xml: Vec<(String, String)>,
+}
+
+impl MimeTypeDefinition {
+    pub fn new(mime_type: impl Into<String>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            comments: Vec::new(),
+            globs: Vec::new(),
+            parents: Vec::new(),
+            aliases: Vec::new(),
+            icon: None,
+            generic_icon: None,
+            magic: Vec::new(),
+            root_xml: Vec::new(),
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// The icon name file managers look up for this type: the explicit
+    /// icon, or the type with `/` replaced by `-`.
+    pub fn icon_name(&self) -> String {
+        self.icon
+            .clone()
+            .unwrap_or_else(|| self.mime_type.replace('/', "-"))
+    }
+
+    /// The human-readable description, e.g. "Acme project".
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comments.push((None, comment.into()));
+        self
+    }
+
+    /// A translated description for `lang`, e.g. `de`.
+    pub fn with_localized_comment(
+        mut self,
+        lang: impl Into<String>,
+        comment: impl Into<String>,
+    ) -> Self {
+        self.comments.push((Some(lang.into()), comment.into()));
+        self
+    }
+
+    /// A file name pattern such as `*.acme`, with the default weight,
+    /// matched case-insensitively.
+    pub fn with_glob(self, pattern: impl Into<String>) -> Self {
+        self.with_glob_options(pattern, DEFAULT_GLOB_WEIGHT, false)
+    }
+
+    /// A file name pattern with an explicit weight (0-100, default 50).
+    pub fn with_glob_options(
+        mut self,
+        pattern: impl Into<String>,
+        weight: u32,
+        case_sensitive: bool,
+    ) -> Self {
+        self.globs.push(GlobRule {
+            pattern: pattern.into(),
+            weight,
+            case_sensitive,
+        });
+        self
+    }
+
+    /// A type this one is a subclass of, e.g. `application/zip`.
+    pub fn with_parent(mut self, mime_type: impl Into<String>) -> Self {
+        self.parents.push(mime_type.into());
+        self
+    }
+
+    /// Another name for this type.
+    pub fn with_alias(mut self, mime_type: impl Into<String>) -> Self {
+        self.aliases.push(mime_type.into());
+        self
+    }
+
+    /// The icon name to use instead of the one derived from the type.
+    pub fn with_icon(mut self, name: impl Into<String>) -> Self {
+        self.icon = Some(name.into());
+        self
+    }
+
+    /// The fallback icon name, e.g. `x-office-document`.
+    pub fn with_generic_icon(mut self, name: impl Into<String>) -> Self {
+        self.generic_icon = Some(name.into());
+        self
+    }
+
+    /// Content rules at `priority` (0-100, default 50); the type matches
+    /// when any of `matches` does.
+    pub fn with_magic(
+        mut self,
+        priority: u32,
+        matches: impl IntoIterator<Item = MimeMagicMatch>,
+    ) -> Self {
+        self.magic.push((priority, matches.into_iter().collect()));
+        self
+    }
+
+    /// XML documents whose root element is `local_name` in `namespace_uri`.
+    pub fn with_root_xml(
+        mut self,
+        namespace_uri: impl Into<String>,
+        local_name: impl Into<String>,
+    ) -> Self {
+        self.root_xml
+            .push((namespace_uri.into(), local_name.into()));
+        self
+    }
+
+    fn write_xml(&self, out: &mut String) {
+        let _ = writeln!(
+            out,
+            "  <mime-type type=\"{}\">",
+            xml::escape(&self.mime_type)
+        );
+        for (lang, comment) in &self.comments {
+            let lang = lang
+                .as_ref()
+                .map(|lang| format!(" xml:lang=\"{}\"", xml::escape(lang)))
+                .unwrap_or_default();
+            let _ = writeln!(out, "    <comment{lang}>{}</comment>", xml::escape(comment));
+        }
+        for (element, name) in [("icon", &self.icon), ("generic-icon", &self.generic_icon)] {
+            if let Some(name) = name {
+                let _ = writeln!(out, "    <{element} name=\"{}\"/>", xml::escape(name));
+            }
+        }
+        for (element, types) in [("sub-class-of", &self.parents), ("alias", &self.aliases)] {
+            for mime_type in types {
+                let _ = writeln!(out, "    <{element} type=\"{}\"/>", xml::escape(mime_type));
+            }
+        }
+        for glob in &self.globs {
+            let _ = write!(out, "    <glob pattern=\"{}\"", xml::escape(&glob.pattern));
+            if glob.weight != DEFAULT_GLOB_WEIGHT {
+                let _ = write!(out, " weight=\"{}\"", glob.weight);
+            }
+            if glob.case_sensitive {
+                out.push_str(" case-sensitive=\"true\"");
+            }
+            out.push_str("/>\n");
+        }
+        for (priority, matches) in &self.magic {
+            let _ = writeln!(out, "    <magic priority=\"{priority}\">");
+            for magic_match in matches {
+                magic_match.write_xml(out, 3);
+            }
+            out.push_str("    </magic>\n");
+        }
+        for (namespace_uri, local_name) in &self.root_xml {
+            let _ = writeln!(
+                out,
+                "    <root-XML namespaceURI=\"{}\" localName=\"{}\"/>",
+                xml::escape(namespace_uri),
+                xml::escape(local_name)
+            );
+        }
+        out.push_str("  </mime-type>\n");
+    }
+}
+
+/// How a magic value is compared; see the shared-mime-info specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MagicValueType {
+    /// Bytes, with `\xHH`, octal and C escapes.
+    String,
+    Byte,
+    Big16,
+    Big32,
+    Little16,
+    Little32,
+    Host16,
+    Host32,
+}
+
+impl MagicValueType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Byte => "byte",
+            Self::Big16 => "big16",
+            Self::Big32 => "big32",
+            Self::Little16 => "little16",
+            Self::Little32 => "little32",
+            Self::Host16 => "host16",
+            Self::Host32 => "host32",
+        }
+    }
+}
+
+/// A `<match>` content rule. Values and masks use the package syntax:
+/// escaped strings for `string`, C integer literals (`0x..`, `0..` octal
+/// or decimal) for the numeric types, and `0x..` hex for string masks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeMagicMatch {
+    value_type: MagicValueType,
+    offset: u32,
+    last_offset: Option<u32>,
+    value: String,
+    mask: Option<String>,
+    children: Vec<MimeMagicMatch>,
+}
+
+impl MimeMagicMatch {
+    /// Match `value` at byte `offset`.
+    pub fn new(value_type: MagicValueType, offset: u32, value: impl Into<String>) -> Self {
+        Self {
+            value_type,
+            offset,
+            last_offset: None,
+            value: value.into(),
+            mask: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Match at any offset from the start offset through `last_offset`.
+    pub fn with_last_offset(mut self, last_offset: u32) -> Self {
+        self.last_offset = Some(last_offset);
+        self
+    }
+
+    /// Compare only the bits set in `mask`.
+    pub fn with_mask(mut self, mask: impl Into<String>) -> Self {
+        self.mask = Some(mask.into());
+        self
+    }
+
+    /// A rule that must also match for this one to count.
+    pub fn with_child(mut self, child: MimeMagicMatch) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn write_xml(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let _ = write!(
+            out,
+            "{indent}<match type=\"{}\" value=\"{}\" offset=\"{}",
+            self.value_type.as_str(),
+            xml::escape(&self.value),
+            self.offset
+        );
+        if let Some(last) = self.last_offset {
+            let _ = write!(out, ":{last}");
+        }
+        out.push('"');
+        if let Some(mask) = &self.mask {
+            let _ = write!(out, " mask=\"{}\"", xml::escape(mask));
+        }
+        if self.children.is_empty() {
+            out.push_str("/>\n");
+            return;
+        }
+        out.push_str(">\n");
+        for child in &self.children {
+            child.write_xml(out, depth + 1);
+        }
+        let _ = writeln!(out, "{indent}</match>");
+    }
+}
+
+/// The per-user (or any) shared-mime-info database under a data
+/// directory: packages in `mime/packages`, the generated files beside them,
+/// and type icons in the `icons/hicolor` theme.
+///
+/// Rebuilding is done natively and writes what `update-mime-database`
+/// writes, `mime.cache` and `treemagic` included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeDatabase {
+    data_dir: PathBuf,
+}
+
+impl MimeDatabase {
+    /// The database in `$XDG_DATA_HOME`, if it can be located.
+    pub fn for_current_user() -> Option<Self> {
+        xdg::data_home().map(Self::at)
+    }
+
+    /// The database in `data_dir`, e.g. `~/.local/share`.
+    pub fn at(data_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            data_dir: data_dir.into(),
+        }
+    }
+
+    pub fn mime_dir(&self) -> PathBuf {
+        self.data_dir.join("mime")
+    }
+
+    /// The package file for `name`, e.g. `mime/packages/acme.xml`.
+    pub fn package_path(&self, name: &str) -> PathBuf {
+        self.mime_dir().join("packages").join(format!("{name}.xml"))
+    }
+
+    /// Write `types` as the package `name`, replacing any previous version,
+    /// and rebuild the database.
+    pub fn install_package(
+        &self,
+        name: &str,
+        types: &[MimeTypeDefinition],
+    ) -> Result<PathBuf, LinuxFolderSettingsError> {
+        let path = self.package_path(name);
+        if name.is_empty() || name.contains('/') {
+            return Err(LinuxFolderSettingsError::MimePackage(
+                path,
+                format!("invalid package name {name:?}"),
+            ));
+        }
+
+        let mut package = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(package, "<mime-info xmlns=\"{NAMESPACE}\">");
+        for definition in types {
+            definition.write_xml(&mut package);
+        }
+        package.push_str("</mime-info>\n");
+        // Refuse to install what the rebuild would skip.
+        parse_package(&package)
+            .map_err(|e| LinuxFolderSettingsError::MimePackage(path.clone(), e))?;
+
+        write_file(&path, package.as_bytes())?;
+        self.rebuild()?;
+        Ok(path)
+    }
+
+    /// Remove the package `name` and rebuild the database. Returns whether
+    /// the package existed.
+    pub fn uninstall_package(&self, name: &str) -> Result<bool, LinuxFolderSettingsError> {
+        match fs::remove_file(self.package_path(name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        self.rebuild()?;
+        Ok(true)
+    }
+
+    /// Install `icon_set` as the `mimetypes` icon `icon_name` in the hicolor
+    /// theme of the data directory (see [`HicolorTheme::install`]).
+    pub fn install_icon(
+        &self,
+        icon_name: &str,
+        icon_set: &LinuxIconSet,
+    ) -> Result<Vec<PathBuf>, LinuxFolderSettingsError> {
+        self.hicolor()
+            .install(IconContext::MimeTypes, icon_name, icon_set)
+    }
+
+    /// Remove the `mimetypes` icon `icon_name` from the hicolor theme.
+    /// Returns whether any file was removed.
+    pub fn uninstall_icon(&self, icon_name: &str) -> Result<bool, LinuxFolderSettingsError> {
+        self.hicolor().uninstall(IconContext::MimeTypes, icon_name)
+    }
+
+    /// Regenerate the database files from `mime/packages/*.xml`, applied in
+    /// name order with `Override.xml` last. Packages that don't parse are
+    /// skipped, as `update-mime-database` skips them.
+    pub fn rebuild(&self) -> Result<(), LinuxFolderSettingsError> {
+        let mime_dir = self.mime_dir();
+        let mut packages: Vec<PathBuf> = match fs::read_dir(mime_dir.join("packages")) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        packages.sort_by_key(|path| {
+            let name = path.file_name().unwrap_or_default().to_os_string();
+            (name == OVERRIDE_PACKAGE, name)
+        });
+
+        let mut types: BTreeMap<String, MimeTypeRules> = BTreeMap::new();
+        for path in packages {
+            let Ok(source) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let Ok(package) = parse_package(&source) else {
+                continue;
+            };
+            for (mime_type, rules) in package {
+                types.entry(mime_type).or_default().merge(rules);
+            }
+        }
+
+        fs::create_dir_all(&mime_dir)?;
+        self.remove_type_files()?;
+        for (mime_type, rules) in &types {
+            write_file(
+                &mime_dir.join(format!("{mime_type}.xml")),
+                type_xml(mime_type, rules).as_bytes(),
+            )?;
+        }
+        let database = Database::new(&types);
+        for (name, content) in [
+            ("globs", database.globs()),
+            ("globs2", database.globs2()),
+            ("magic", database.magic()),
+            ("aliases", database.aliases()),
+            ("subclasses", database.subclasses()),
+            ("icons", database.icons(false)),
+            ("generic-icons", database.icons(true)),
+            ("XMLnamespaces", database.namespaces()),
+            ("types", database.types()),
+            ("treemagic", database.tree_magic()),
+            ("mime.cache", database.cache()),
+        ] {
+            write_file(&mime_dir.join(name), &content)?;
+        }
+        Ok(())
+    }
+
+    fn hicolor(&self) -> HicolorTheme {
+        HicolorTheme::at(self.data_dir.join("icons/hicolor"))
+    }
+
+    /// Remove the per-type XML files of a previous rebuild so types whose
+    /// package was removed disappear.
+    fn remove_type_files(&self) -> Result<(), LinuxFolderSettingsError> {
+        let mime_dir = self.mime_dir();
+        for media in fs::read_dir(&mime_dir)?.filter_map(Result::ok) {
+            let media = media.path();
+            if !media.is_dir() || media.ends_with("packages") {
+                continue;
+            }
+            for file in fs::read_dir(&media)?.filter_map(Result::ok) {
+                let file = file.path();
+                let generated = file.extension().is_some_and(|ext| ext == "xml")
+                    && fs::read_to_string(&file)
+                        .is_ok_and(|content| content.contains(GENERATED_COMMENT));
+                if generated {
+                    fs::remove_file(&file)?;
+                }
+            }
+            // Only succeeds once nothing else is left in it.
+            let _ = fs::remove_dir(&media);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GlobRule {
+    pattern: String,
+    weight: u32,
+    case_sensitive: bool,
+}
+
+/// A compiled `<match>`: the value and mask as bytes, multi-byte host
+/// words stored big-endian with their word size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Matchlet {
+    offset: u32,
+    range: u32,
+    word_size: u32,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    children: Vec<Matchlet>,
+}
+
+impl Matchlet {
+    /// The furthest byte this rule or its children can look at.
+    /// `parse_match` rejects rules whose extent does not fit.
+    fn extent(&self) -> u32 {
+        self.children.iter().map(Matchlet::extent).fold(
+            self.offset
+                .saturating_add(self.range)
+                .saturating_add(self.value.len() as u32),
+            u32::max,
+        )
+    }
+}
+
+/// A compiled `<treematch>`: a path below a mounted volume's root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TreeMatchlet {
+    path: String,
+    /// `file`, `directory`, `link` or `any`.
+    file_type: &'static str,
+    match_case: bool,
+    executable: bool,
+    non_empty: bool,
+    /// The type the file itself must have.
+    mime_type: Option<String>,
+    children: Vec<TreeMatchlet>,
+}
+
+/// Everything the packages say about one type.
+#[derive(Debug, Clone, Default)]
+struct MimeTypeRules {
+    globs: Vec<GlobRule>,
+    magic: Vec<(u32, Vec<Matchlet>)>,
+    parents: Vec<String>,
+    aliases: Vec<String>,
+    icon: Option<String>,
+    generic_icon: Option<String>,
+    namespaces: Vec<(String, String)>,
+    /// Source of the elements copied into the per-type XML file.
+    elements: Vec<String>,
+    delete_globs: bool,
+    delete_magic: bool,
+    tree_magic: Vec<(u32, Vec<TreeMatchlet>)>,
+}
+
+impl MimeTypeRules {
+    /// Apply a later package's rules for the same type.
+    fn merge(&mut self, later: MimeTypeRules) {
+        if later.delete_globs {
+            self.globs.clear();
+        }
+        if later.delete_magic {
+            self.magic.clear();
+        }
+        self.globs.extend(later.globs);
+        self.magic.extend(later.magic);
+        for parent in later.parents {
+            if !self.parents.contains(&parent) {
+                self.parents.push(parent);
+            }
+        }
+        for alias in later.aliases {
+            if !self.aliases.contains(&alias) {
+                self.aliases.push(alias);
+            }
+        }
+        self.icon = later.icon.or(self.icon.take());
+        self.generic_icon = later.generic_icon.or(self.generic_icon.take());
+        self.namespaces.extend(later.namespaces);
+        self.elements.extend(later.elements);
+        self.tree_magic.extend(later.tree_magic);
+    }
+}
+
+/// Parse a package into the rules for each type it mentions.
+fn parse_package(source: &str) -> Result<Vec<(String, MimeTypeRules)>, String> {
+    let root = xml::parse(source)?;
+    if root.name != "mime-info" {
+        return Err(format!("expected <mime-info>, found <{}>", root.name));
+    }
+    let mut types = Vec::new();
+    for element in root.elements_named("mime-type") {
+        let mime_type = element
+            .attr("type")
+            .filter(|t| is_mime_type(t))
+            .ok_or("<mime-type> without a valid type")?;
+        types.push((
+            mime_type.to_ascii_lowercase(),
+            parse_mime_type(element, source)?,
+        ));
+    }
+    Ok(types)
+}
+
+fn parse_mime_type(element: &xml::Element, source: &str) -> Result<MimeTypeRules, String> {
+    let mut rules = MimeTypeRules::default();
+    for child in element.elements() {
+        let required = |name: &str| {
+            child
+                .attr(name)
+                .map(str::to_string)
+                .ok_or_else(|| format!("<{}> without {name}", child.name))
+        };
+        match child.name.as_str() {
+            "glob" => {
+                let case_sensitive = child.attr("case-sensitive") == Some("true");
+                let pattern = required("pattern")?;
+                rules.globs.push(GlobRule {
+                    pattern: if case_sensitive {
+                        pattern
+                    } else {
+                        pattern.to_lowercase()
+                    },
+                    weight: parse_attr_number(child, "weight", DEFAULT_GLOB_WEIGHT)?,
+                    case_sensitive,
+                });
+            }
+            "glob-deleteall" => {
+                rules.globs.clear();
+                rules.delete_globs = true;
+            }
+            "magic" => {
+                let priority = parse_attr_number(child, "priority", DEFAULT_MAGIC_PRIORITY)?;
+                let matches = child
+                    .elements_named("match")
+                    .map(parse_match)
+                    .collect::<Result<_, _>>()?;
+                rules.magic.push((priority, matches));
+            }
+            "magic-deleteall" => {
+                rules.magic.clear();
+                rules.delete_magic = true;
+            }
+            "sub-class-of" => rules.parents.push(required("type")?),
+            "alias" => rules.aliases.push(required("type")?),
+            "icon" => rules.icon = Some(required("name")?),
+            "generic-icon" => rules.generic_icon = Some(required("name")?),
+            "root-XML" => rules
+                .namespaces
+                .push((required("namespaceURI")?, required("localName")?)),
+            "treemagic" => {
+                let priority = parse_attr_number(child, "priority", DEFAULT_MAGIC_PRIORITY)?;
+                let matches = child
+                    .elements_named("treematch")
+                    .map(parse_tree_match)
+                    .collect::<Result<_, _>>()?;
+                rules.tree_magic.push((priority, matches));
+            }
+            _ => {}
+        }
+        if !COMPILED_ELEMENTS.contains(&child.name.as_str()) {
+            rules.elements.push(source[child.span.clone()].to_string());
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_attr_number(element: &xml::Element, name: &str, default: u32) -> Result<u32, String> {
+    element.attr(name).map_or(Ok(default), |value| {
+        value
+            .parse()
+            .map_err(|_| format!("invalid {name} {value:?} on <{}>", element.name))
+    })
+}
+
+fn parse_match(element: &xml::Element) -> Result<Matchlet, String> {
+    let attr = |name: &str| {
+        element
+            .attr(name)
+            .ok_or_else(|| format!("<match> without {name}"))
+    };
+    let value_type = attr("type")?;
+    let offset = attr("offset")?;
+    let (start, end) = match offset.split_once(':') {
+        Some((start, end)) => (start, end),
+        None => (offset, offset),
+    };
+    let (start, end): (u32, u32) = start
+        .parse()
+        .ok()
+        .zip(end.parse().ok())
+        .filter(|(start, end)| start <= end)
+        .ok_or_else(|| format!("invalid offset {offset:?}"))?;
+    let range = (end - start)
+        .checked_add(1)
+        .ok_or_else(|| format!("invalid offset {offset:?}"))?;
+
+    let (value, word_size) = encode_value(value_type, attr("value")?)?;
+    let mask = element
+        .attr("mask")
+        .map(|mask| match value_type {
+            "string" => parse_hex(mask),
+            _ => encode_value(value_type, mask).map(|(mask, _)| mask),
+        })
+        .transpose()?;
+    if mask.as_ref().is_some_and(|mask| mask.len() != value.len()) {
+        return Err(format!(
+            "mask {:?} does not fit the value",
+            element.attr("mask")
+        ));
+    }
+    u32::try_from(value.len())
+        .ok()
+        .and_then(|len| start.checked_add(range)?.checked_add(len))
+        .ok_or_else(|| format!("invalid offset {offset:?}"))?;
+    Ok(Matchlet {
+        offset: start,
+        range,
+        word_size,
+        value,
+        mask,
+        children: element
+            .elements_named("match")
+            .map(parse_match)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_tree_match(element: &xml::Element) -> Result<TreeMatchlet, String> {
+    let path = element
+        .attr("path")
+        .ok_or("<treematch> without path")?
+        .to_string();
+    let file_type = match element.attr("type") {
+        None => "any",
+        Some("file") => "file",
+        Some("directory") => "directory",
+        Some("link") => "link",
+        Some(other) => return Err(format!("invalid treematch type {other:?}")),
+    };
+    let flag = |name: &str| element.attr(name) == Some("true");
+    Ok(TreeMatchlet {
+        path,
+        file_type,
+        match_case: flag("match-case"),
+        executable: flag("executable"),
+        non_empty: flag("non-empty"),
+        mime_type: element.attr("mimetype").map(str::to_string),
+        children: element
+            .elements_named("treematch")
+            .map(parse_tree_match)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// The bytes to compare and the word size for a `<match>` value.
+fn encode_value(value_type: &str, value: &str) -> Result<(Vec<u8>, u32), String> {
+    let number = || parse_c_number(value).ok_or_else(|| format!("invalid number {value:?}"));
+    let encoded = match value_type {
+        "string" => (unescape_string(value)?, 1),
+        "byte" => (vec![number()? as u8], 1),
+        "big16" => ((number()? as u16).to_be_bytes().to_vec(), 1),
+        "big32" => ((number()? as u32).to_be_bytes().to_vec(), 1),
+        "little16" => ((number()? as u16).to_le_bytes().to_vec(), 1),
+        "little32" => ((number()? as u32).to_le_bytes().to_vec(), 1),
+        // Host words are stored big-endian; readers swap them as needed.
+        "host16" => ((number()? as u16).to_be_bytes().to_vec(), 2),
+        "host32" => ((number()? as u32).to_be_bytes().to_vec(), 4),
+        _ => return Err(format!("unknown match type {value_type:?}")),
+    };
+    if encoded.0.is_empty() {
+        return Err("empty match value".to_string());
+    }
+    Ok(encoded)
+}
+
+/// `0x1f` (hex), `017` (octal) or `15` (decimal), as `strtol` reads them.
+fn parse_c_number(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// `0xff00...` to bytes.
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid hex mask {text:?}");
+    let digits = text.strip_prefix("0x").ok_or_else(invalid)?;
+    if digits.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// Resolve `\xHH`, octal `\NNN` and the C escapes of a string value.
+fn unescape_string(text: &str) -> Result<Vec<u8>, String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let escape = *bytes
+            .get(i + 1)
+            .ok_or_else(|| format!("trailing backslash in {text:?}"))?;
+        i += 2;
+        let digits = |i: usize, max: usize, radix: u32| {
+            bytes[i..]
+                .iter()
+                .take(max)
+                .take_while(|b| (**b as char).is_digit(radix))
+                .count()
+        };
+        match escape {
+            b'x' => {
+                let len = digits(i, 2, 16);
+                if len == 0 {
+                    return Err(format!("invalid \\x escape in {text:?}"));
+                }
+                out.push(u8::from_str_radix(&text[i..i + len], 16).unwrap_or_default());
+                i += len;
+            }
+            b'0'..=b'7' => {
+                let len = 1 + digits(i, 2, 8);
+                let octal = &text[i - 1..i - 1 + len];
+                out.push(u16::from_str_radix(octal, 8).unwrap_or_default() as u8);
+                i += len - 1;
+            }
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(0x0b),
+            other => out.push(other),
+        }
+    }
+    Ok(out)
+}
+
+fn is_mime_type(text: &str) -> bool {
+    text.split_once('/').is_some_and(|(media, subtype)| {
+        !media.is_empty() && !subtype.is_empty() && !subtype.contains('/')
+    })
+}
+
+/// The `mime/<media>/<subtype>.xml` file for a type.
+fn type_xml(mime_type: &str, rules: &MimeTypeRules) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<mime-type xmlns=\"{NAMESPACE}\" type=\"{}\">",
+        xml::escape(mime_type)
+    );
+    let _ = writeln!(out, "  {GENERATED_COMMENT}");
+    for element in &rules.elements {
+        let _ = writeln!(out, "  {element}");
+    }
+    out.push_str("</mime-type>\n");
+    out
+}
+
+/// Write through a temporary file so readers never see a partial file.
+fn write_file(path: &Path, content: &[u8]) -> Result<(), LinuxFolderSettingsError> {
+    create_parent(path)?;
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".new");
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> Result<(), LinuxFolderSettingsError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+/// The merged packages, flattened into the lists the database files hold.
+struct Database<'a> {
+    types: &'a BTreeMap<String, MimeTypeRules>,
+    /// `(weight, type, glob)` by descending weight, then type.
+    globs: Vec<(u32, &'a str, &'a GlobRule)>,
+    /// `(priority, type, matchlets)` by descending priority, then type.
+    magic: Vec<(u32, &'a str, &'a [Matchlet])>,
+    /// The same for `<treemagic>`.
+    tree_magic: Vec<(u32, &'a str, &'a [TreeMatchlet])>,
+}
+
+impl<'a> Database<'a> {
+    fn new(types: &'a BTreeMap<String, MimeTypeRules>) -> Self {
+        let mut globs: Vec<_> = types
+            .iter()
+            .flat_map(|(mime_type, rules)| {
+                rules
+                    .globs
+                    .iter()
+                    .map(move |glob| (glob.weight, mime_type.as_str(), glob))
+            })
+            .collect();
+        globs.sort_by_key(|(weight, mime_type, _)| (std::cmp::Reverse(*weight), *mime_type));
+        let mut magic: Vec<_> = types
+            .iter()
+            .flat_map(|(mime_type, rules)| {
+                rules.magic.iter().map(move |(priority, matchlets)| {
+                    (*priority, mime_type.as_str(), matchlets.as_slice())
+                })
+            })
+            .collect();
+        magic.sort_by_key(|(priority, mime_type, _)| (std::cmp::Reverse(*priority), *mime_type));
+        let mut tree_magic: Vec<_> = types
+            .iter()
+            .flat_map(|(mime_type, rules)| {
+                rules.tree_magic.iter().map(move |(priority, matchlets)| {
+                    (*priority, mime_type.as_str(), matchlets.as_slice())
+                })
+            })
+            .collect();
+        tree_magic
+            .sort_by_key(|(priority, mime_type, _)| (std::cmp::Reverse(*priority), *mime_type));
+        Self {
+            types,
+            globs,
+            magic,
+            tree_magic,
+        }
+    }
+
+    fn globs(&self) -> Vec<u8> {
+        let mut out = String::from(GENERATED_HEADER);
+        for (_, mime_type, glob) in &self.globs {
+            let _ = writeln!(out, "{mime_type}:{}", glob.pattern);
+        }
+        out.into_bytes()
+    }
+
+    /// Case-sensitive globs are listed twice, the second time without the
+    /// flag, for readers that predate it.
+    fn globs2(&self) -> Vec<u8> {
+        let mut out = String::from(GENERATED_HEADER);
+        for (weight, mime_type, glob) in &self.globs {
+            if glob.case_sensitive {
+                let _ = writeln!(out, "{weight}:{mime_type}:{}:cs", glob.pattern);
+            }
+            let _ = writeln!(out, "{weight}:{mime_type}:{}", glob.pattern);
+        }
+        out.into_bytes()
+    }
+
+    fn magic(&self) -> Vec<u8> {
+        fn write_rule(out: &mut Vec<u8>, rule: &Matchlet, indent: usize) {
+            if indent > 0 {
+                out.extend_from_slice(indent.to_string().as_bytes());
+            }
+            out.extend_from_slice(format!(">{}=", rule.offset).as_bytes());
+            out.extend_from_slice(&(rule.value.len() as u16).to_be_bytes());
+            out.extend_from_slice(&rule.value);
+            if let Some(mask) = &rule.mask {
+                out.push(b'&');
+                out.extend_from_slice(mask);
+            }
+            if rule.word_size != 1 {
+                out.extend_from_slice(format!("~{}", rule.word_size).as_bytes());
+            }
+            if rule.range != 1 {
+                out.extend_from_slice(format!("+{}", rule.range).as_bytes());
+            }
+            out.push(b'\n');
+            for child in &rule.children {
+                write_rule(out, child, indent + 1);
+            }
+        }
+
+        let mut out = b"MIME-Magic\0\n".to_vec();
+        for (priority, mime_type, matchlets) in &self.magic {
+            out.extend_from_slice(format!("[{priority}:{mime_type}]\n").as_bytes());
+            for rule in *matchlets {
+                write_rule(&mut out, rule, 0);
+            }
+        }
+        out
+    }
+
+    /// The `treemagic` file GLib reads to guess the content type of a
+    /// volume; unlike magic, `mime.cache` has no copy of it.
+    fn tree_magic(&self) -> Vec<u8> {
+        fn write_rule(out: &mut String, rule: &TreeMatchlet, indent: usize) {
+            if indent > 0 {
+                let _ = write!(out, "{indent}");
+            }
+            let _ = write!(out, ">\"{}\"={}", rule.path, rule.file_type);
+            for (set, flag) in [
+                (rule.match_case, "match-case"),
+                (rule.executable, "executable"),
+                (rule.non_empty, "non-empty"),
+            ] {
+                if set {
+                    let _ = write!(out, ",{flag}");
+                }
+            }
+            if let Some(mime_type) = &rule.mime_type {
+                let _ = write!(out, ",{mime_type}");
+            }
+            out.push('\n');
+            for child in &rule.children {
+                write_rule(out, child, indent + 1);
+            }
+        }
+
+        let mut out = String::from("MIME-TreeMagic\0\n");
+        for (priority, mime_type, matchlets) in &self.tree_magic {
+            let _ = writeln!(out, "[{priority}:{mime_type}]");
+            for rule in *matchlets {
+                write_rule(&mut out, rule, 0);
+            }
+        }
+        out.into_bytes()
+    }
+
+    /// `(alias, type)` sorted by alias.
+    fn alias_pairs(&self) -> Vec<(&'a str, &'a str)> {
+        let mut pairs: Vec<_> = self
+            .types
+            .iter()
+            .flat_map(|(mime_type, rules)| {
+                rules
+                    .aliases
+                    .iter()
+                    .map(move |alias| (alias.as_str(), mime_type.as_str()))
+            })
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    fn aliases(&self) -> Vec<u8> {
+        self.alias_pairs()
+            .iter()
+            .map(|(alias, mime_type)| format!("{alias} {mime_type}\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn subclasses(&self) -> Vec<u8> {
+        let mut out = String::new();
+        for (mime_type, rules) in self.types {
+            for parent in &rules.parents {
+                let _ = writeln!(out, "{mime_type} {parent}");
+            }
+        }
+        out.into_bytes()
+    }
+
+    /// `(type, icon)` sorted by type, for `<icon>` or `<generic-icon>`.
+    fn icon_pairs(&self, generic: bool) -> Vec<(&'a str, &'a str)> {
+        self.types
+            .iter()
+            .filter_map(|(mime_type, rules)| {
+                let icon = if generic {
+                    &rules.generic_icon
+                } else {
+                    &rules.icon
+                };
+                icon.as_deref().map(|icon| (mime_type.as_str(), icon))
+            })
+            .collect()
+    }
+
+    fn icons(&self, generic: bool) -> Vec<u8> {
+        self.icon_pairs(generic)
+            .iter()
+            .map(|(mime_type, icon)| format!("{mime_type}:{icon}\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// `(namespace URI, local name, type)` sorted by namespace and name.
+    fn namespace_triples(&self) -> Vec<(&'a str, &'a str, &'a str)> {
+        let mut triples: Vec<_> = self
+            .types
+            .iter()
+            .flat_map(|(mime_type, rules)| {
+                rules
+                    .namespaces
+                    .iter()
+                    .map(move |(uri, local)| (uri.as_str(), local.as_str(), mime_type.as_str()))
+            })
+            .collect();
+        triples.sort();
+        triples
+    }
+
+    fn namespaces(&self) -> Vec<u8> {
+        self.namespace_triples()
+            .iter()
+            .map(|(uri, local, mime_type)| format!("{uri} {local} {mime_type}\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn types(&self) -> Vec<u8> {
+        self.types
+            .keys()
+            .map(|mime_type| format!("{mime_type}\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// The binary `mime.cache` (format 1.2), which GLib reads instead of the
+    /// text files.
+    fn cache(&self) -> Vec<u8> {
+        let mut strings = BTreeSet::new();
+        for (mime_type, rules) in self.types {
+            strings.insert(mime_type.as_str());
+            strings.extend(rules.parents.iter().map(String::as_str));
+            strings.extend(rules.aliases.iter().map(String::as_str));
+            strings.extend(rules.icon.as_deref());
+            strings.extend(rules.generic_icon.as_deref());
+            for (uri, local) in &rules.namespaces {
+                strings.extend([uri.as_str(), local.as_str()]);
+            }
+        }
+        let globs: Vec<_> = self
+            .globs
+            .iter()
+            .map(|(weight, mime_type, glob)| {
+                let flags = if glob.case_sensitive {
+                    CASE_SENSITIVE_FLAG
+                } else {
+                    0
+                };
+                (glob.pattern.as_str(), *mime_type, weight | flags)
+            })
+            .collect();
+        let mut literals: Vec<_> = globs
+            .iter()
+            .filter(|(pattern, ..)| !has_wildcards(pattern))
+            .copied()
+            .collect();
+        literals.sort();
+        let suffixes: Vec<_> = globs
+            .iter()
+            .filter_map(|&(pattern, mime_type, weight)| {
+                let suffix = pattern.strip_prefix('*')?;
+                (!suffix.is_empty() && !has_wildcards(suffix))
+                    .then_some((suffix, mime_type, weight))
+            })
+            .collect();
+        let other_globs: Vec<_> = globs
+            .iter()
+            .filter(|(pattern, ..)| {
+                has_wildcards(pattern)
+                    && !pattern
+                        .strip_prefix('*')
+                        .is_some_and(|suffix| !suffix.is_empty() && !has_wildcards(suffix))
+            })
+            .copied()
+            .collect();
+        strings.extend(literals.iter().map(|(pattern, ..)| *pattern));
+        strings.extend(other_globs.iter().map(|(pattern, ..)| *pattern));
+
+        let mut cache = CacheWriter::default();
+        cache.put_u16(1);
+        cache.put_u16(2);
+        let header = cache.reserve(10);
+        for string in strings {
+            cache.put_string(string);
+        }
+
+        // Aliases.
+        cache.patch(header, cache.len());
+        let aliases = self.alias_pairs();
+        cache.put_u32(aliases.len() as u32);
+        for (alias, mime_type) in aliases {
+            cache.put_str_ref(alias);
+            cache.put_str_ref(mime_type);
+        }
+
+        // Parents: a sorted list of types pointing at their parent lists.
+        cache.patch(header + 4, cache.len());
+        let children: Vec<_> = self
+            .types
+            .iter()
+            .filter(|(_, rules)| !rules.parents.is_empty())
+            .collect();
+        cache.put_u32(children.len() as u32);
+        let entries = cache.reserve(children.len() * 2);
+        for (i, (mime_type, rules)) in children.into_iter().enumerate() {
+            cache.patch(entries + 8 * i, cache.string(mime_type));
+            cache.patch(entries + 8 * i + 4, cache.len());
+            cache.put_u32(rules.parents.len() as u32);
+            for parent in &rules.parents {
+                cache.put_str_ref(parent);
+            }
+        }
+
+        cache.patch(header + 8, cache.len());
+        cache.put_glob_list(&literals);
+
+        cache.patch(header + 12, cache.len());
+        let tree = SuffixNode::build(&suffixes);
+        cache.put_u32(tree.len() as u32);
+        let first = cache.reserve(1);
+        let nodes = cache.put_suffix_nodes(&tree);
+        cache.patch(first, nodes);
+
+        cache.patch(header + 16, cache.len());
+        cache.put_glob_list(&other_globs);
+
+        cache.patch(header + 20, cache.len());
+        cache.put_magic(&self.magic);
+
+        cache.patch(header + 24, cache.len());
+        let namespaces = self.namespace_triples();
+        cache.put_u32(namespaces.len() as u32);
+        for (uri, local, mime_type) in namespaces {
+            cache.put_str_ref(uri);
+            cache.put_str_ref(local);
+            cache.put_str_ref(mime_type);
+        }
+
+        for (slot, generic) in [(28, false), (32, true)] {
+            cache.patch(header + slot, cache.len());
+            let icons = self.icon_pairs(generic);
+            cache.put_u32(icons.len() as u32);
+            for (mime_type, icon) in icons {
+                cache.put_str_ref(mime_type);
+                cache.put_str_ref(icon);
+            }
+        }
+
+        // update-mime-database ends with one zero word per type. No reader
+        // uses it; it is kept so the layout matches.
+        cache.patch(header + 36, cache.len());
+        cache.put_u32(self.types.len() as u32);
+        cache.reserve(self.types.len());
+        cache.bytes
+    }
+}
+
+fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// A node of the reverse suffix tree: one character of a suffix, read from
+/// its end, with the types whose suffix ends here as leaves.
+#[derive(Debug, Default)]
+struct SuffixNode {
+    character: char,
+    /// `(type, weight and flags)`.
+    leaves: Vec<(String, u32)>,
+    children: Vec<SuffixNode>,
+}
+
+impl SuffixNode {
+    fn build(suffixes: &[(&str, &str, u32)]) -> Vec<SuffixNode> {
+        let mut root = SuffixNode::default();
+        for (suffix, mime_type, weight) in suffixes {
+            let mut node = &mut root;
+            for character in suffix.chars().rev() {
+                let index = match node
+                    .children
+                    .binary_search_by_key(&character, |n| n.character)
+                {
+                    Ok(index) => index,
+                    Err(index) => {
+                        node.children.insert(
+                            index,
+                            SuffixNode {
+                                character,
+                                ..SuffixNode::default()
+                            },
+                        );
+                        index
+                    }
+                };
+                node = &mut node.children[index];
+            }
+            node.leaves.push((mime_type.to_string(), *weight));
+        }
+        root.children
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheWriter {
+    bytes: Vec<u8>,
+    strings: BTreeMap<String, u32>,
+}
+
+impl CacheWriter {
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Append `words` zero words to fill in later; returns their offset.
+    fn reserve(&mut self, words: usize) -> usize {
+        let offset = self.len();
+        self.bytes.resize(offset + 4 * words, 0);
+        offset
+    }
+
+    fn patch(&mut self, at: usize, value: usize) {
+        self.bytes[at..at + 4].copy_from_slice(&(value as u32).to_be_bytes());
+    }
+
+    /// Append bytes, padded to a word boundary; returns their offset.
+    fn put_data(&mut self, data: &[u8]) -> usize {
+        let offset = self.len();
+        self.bytes.extend_from_slice(data);
+        self.bytes.resize(self.len().next_multiple_of(4), 0);
+        offset
+    }
+
+    fn put_string(&mut self, string: &str) {
+        let mut data = string.as_bytes().to_vec();
+        data.push(0);
+        let offset = self.put_data(&data);
+        self.strings.insert(string.to_string(), offset as u32);
+    }
+
+    fn string(&self, string: &str) -> usize {
+        self.strings[string] as usize
+    }
+
+    fn put_str_ref(&mut self, string: &str) {
+        self.put_u32(self.strings[string]);
+    }
+
+    fn put_glob_list(&mut self, globs: &[(&str, &str, u32)]) {
+        self.put_u32(globs.len() as u32);
+        for (pattern, mime_type, weight) in globs {
+            self.put_str_ref(pattern);
+            self.put_str_ref(mime_type);
+            self.put_u32(*weight);
+        }
+    }
+
+    /// Write sibling nodes, leaves first, then their subtrees; returns the
+    /// offset of the first sibling.
+    fn put_suffix_nodes(&mut self, nodes: &[SuffixNode]) -> usize {
+        let start = self.reserve(3 * nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            let at = start + 12 * i;
+            self.patch(at, node.character as usize);
+            self.patch(at + 4, node.leaves.len() + node.children.len());
+            let first = self.reserve(3 * node.leaves.len());
+            for (j, (mime_type, weight)) in node.leaves.iter().enumerate() {
+                self.patch(first + 12 * j + 4, self.string(mime_type));
+                self.patch(first + 12 * j + 8, *weight as usize);
+            }
+            if !node.children.is_empty() {
+                // Leaves and children must be contiguous.
+                debug_assert_eq!(self.len(), first + 12 * node.leaves.len());
+                self.put_suffix_nodes(&node.children);
+            }
+            self.patch(at + 8, first);
+        }
+        start
+    }
+
+    fn put_magic(&mut self, magic: &[(u32, &str, &[Matchlet])]) {
+        self.put_u32(magic.len() as u32);
+        let extent = magic
+            .iter()
+            .flat_map(|(_, _, matchlets)| matchlets.iter().map(Matchlet::extent))
+            .max()
+            .unwrap_or(0);
+        self.put_u32(extent);
+        let first = self.reserve(1);
+        let matches = self.reserve(4 * magic.len());
+        self.patch(first, matches);
+        for (i, (priority, mime_type, matchlets)) in magic.iter().enumerate() {
+            let at = matches + 16 * i;
+            self.patch(at, *priority as usize);
+            self.patch(at + 4, self.string(mime_type));
+            self.patch(at + 8, matchlets.len());
+            let offset = self.put_matchlets(matchlets);
+            self.patch(at + 12, offset);
+        }
+    }
+
+    /// Write sibling matchlets, then each one's children and data; returns
+    /// the offset of the first.
+    fn put_matchlets(&mut self, matchlets: &[Matchlet]) -> usize {
+        let start = self.reserve(8 * matchlets.len());
+        for (i, matchlet) in matchlets.iter().enumerate() {
+            let at = start + 32 * i;
+            self.patch(at, matchlet.offset as usize);
+            self.patch(at + 4, matchlet.range as usize);
+            self.patch(at + 8, matchlet.word_size as usize);
+            self.patch(at + 12, matchlet.value.len());
+            let value = self.put_data(&matchlet.value);
+            self.patch(at + 16, value);
+            if let Some(mask) = &matchlet.mask {
+                let mask = self.put_data(mask);
+                self.patch(at + 20, mask);
+            }
+            self.patch(at + 24, matchlet.children.len());
+            if !matchlet.children.is_empty() {
+                let children = self.put_matchlets(&matchlet.children);
+                self.patch(at + 28, children);
+            }
+        }
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folder_settings::sys::linux::SharedMimeInfo;
+    use std::process::Command;
+
+    fn acme() -> MimeTypeDefinition {
+        MimeTypeDefinition::new("application/x-acme-project")
+            .with_comment("Acme project")
+            .with_localized_comment("de", "Acme-Projekt")
+            .with_icon("application-x-acme-project")
+            .with_generic_icon("x-office-document")
+            .with_parent("application/zip")
+            .with_alias("application/x-acme")
+            .with_glob_options("*.acme", 60, false)
+            .with_glob_options("Acmefile", 50, true)
+            .with_glob("acme-*.cfg")
+            .with_magic(
+                60,
+                [
+                    MimeMagicMatch::new(MagicValueType::String, 0, "ACME").with_child(
+                        MimeMagicMatch::new(MagicValueType::Big16, 4, "0x0102")
+                            .with_last_offset(8)
+                            .with_mask("0xff0f"),
+                    ),
+                    MimeMagicMatch::new(MagicValueType::String, 16, "\\x01ACME"),
+                ],
+            )
+            .with_root_xml("http://acme.example/ns", "project")
+    }
+
+    #[test]
+    fn install_writes_the_package_and_database_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MimeDatabase::at(dir.path());
+        let package = db.install_package("acme", &[acme()]).unwrap();
+        assert_eq!(package, dir.path().join("mime/packages/acme.xml"));
+
+        let mime = dir.path().join("mime");
+        let read = |name: &str| fs::read_to_string(mime.join(name)).unwrap();
+        assert_eq!(
+            read("globs2"),
+            format!(
+                "{GENERATED_HEADER}60:application/x-acme-project:*.acme\n\
+                 50:application/x-acme-project:Acmefile:cs\n\
+                 50:application/x-acme-project:Acmefile\n\
+                 50:application/x-acme-project:acme-*.cfg\n"
+            )
+        );
+        assert_eq!(
+            read("aliases"),
+            "application/x-acme application/x-acme-project\n"
+        );
+        assert_eq!(
+            read("subclasses"),
+            "application/x-acme-project application/zip\n"
+        );
+        assert_eq!(
+            read("XMLnamespaces"),
+            "http://acme.example/ns project application/x-acme-project\n"
+        );
+        assert_eq!(
+            fs::read(mime.join("magic")).unwrap(),
+            b"MIME-Magic\0\n[60:application/x-acme-project]\n\
+              >0=\0\x04ACME\n1>4=\0\x02\x01\x02&\xff\x0f+5\n>16=\0\x05\x01ACME\n"
+        );
+        let type_file = read("application/x-acme-project.xml");
+        assert!(type_file.contains("<comment xml:lang=\"de\">Acme-Projekt</comment>"));
+        assert!(!type_file.contains("<magic"));
+
+        let info = SharedMimeInfo::from_dirs(&[&mime]);
+        assert_eq!(
+            info.mime_type_for_name("plan.ACME").as_deref(),
+            Some("application/x-acme-project")
+        );
+        assert_eq!(
+            info.unalias("application/x-acme"),
+            "application/x-acme-project"
+        );
+        assert_eq!(
+            info.mime_type_for_data(b"ACME\x01\x02"),
+            "application/x-acme-project"
+        );
+        assert_eq!(
+            info.icon_names("application/x-acme-project")[0],
+            "application-x-acme-project"
+        );
+
+        assert!(db.uninstall_package("acme").unwrap());
+        assert!(!db.uninstall_package("acme").unwrap());
+        assert_eq!(read("types"), "");
+        assert!(!mime.join("application").exists());
+    }
+
+    #[test]
+    fn invalid_definitions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MimeDatabase::at(dir.path());
+        let bad_magic = MimeTypeDefinition::new("application/x-bad").with_magic(
+            50,
+            [MimeMagicMatch::new(MagicValueType::Big16, 0, "twelve")],
+        );
+        assert!(matches!(
+            db.install_package("bad", &[bad_magic]),
+            Err(LinuxFolderSettingsError::MimePackage(..))
+        ));
+        let whole_range = MimeTypeDefinition::new("application/x-bad").with_magic(
+            50,
+            [MimeMagicMatch::new(MagicValueType::Byte, 0, "1").with_last_offset(u32::MAX)],
+        );
+        assert!(matches!(
+            db.install_package("bad", &[whole_range]),
+            Err(LinuxFolderSettingsError::MimePackage(..))
+        ));
+        assert!(db.install_package("a/b", &[acme()]).is_err());
+        assert!(!db.package_path("bad").exists());
+    }
+
+    #[test]
+    fn tree_magic_is_compiled() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = dir.path().join("mime/packages");
+        fs::create_dir_all(&packages).unwrap();
+        fs::write(
+            packages.join("camera.xml"),
+            format!(
+                "<mime-info xmlns=\"{NAMESPACE}\"><mime-type type=\"x-content/image-dcf\">\
+                 <treemagic><treematch path=\"dcim\" type=\"directory\" non-empty=\"true\">\
+                 <treematch path=\"dcim/autorun\" type=\"file\" executable=\"true\" \
+                 mimetype=\"application/x-shellscript\"/></treematch></treemagic>\
+                 <treemagic priority=\"60\"><treematch path=\"PICTURES\" match-case=\"true\"/>\
+                 </treemagic></mime-type></mime-info>"
+            ),
+        )
+        .unwrap();
+        let db = MimeDatabase::at(dir.path());
+        db.install_package("acme", &[acme()]).unwrap();
+
+        let tree_magic = fs::read(dir.path().join("mime/treemagic")).unwrap();
+        assert_eq!(
+            String::from_utf8(tree_magic).unwrap(),
+            "MIME-TreeMagic\0\n\
+             [60:x-content/image-dcf]\n\
+             >\"PICTURES\"=any,match-case\n\
+             [50:x-content/image-dcf]\n\
+             >\"dcim\"=directory,non-empty\n\
+             1>\"dcim/autorun\"=file,executable,application/x-shellscript\n"
+        );
+        let globs = fs::read_to_string(dir.path().join("mime/globs2")).unwrap();
+        assert!(
+            globs.contains("application/x-acme-project:*.acme"),
+            "{globs}"
+        );
+    }
+
+    #[test]
+    fn later_packages_and_overrides_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = dir.path().join("mime/packages");
+        fs::create_dir_all(&packages).unwrap();
+        let package = |globs: &str| {
+            format!(
+                "<mime-info xmlns=\"{NAMESPACE}\"><mime-type type=\"text/x-zeta\">\
+                 {globs}</mime-type></mime-info>"
+            )
+        };
+        fs::write(
+            packages.join("Override.xml"),
+            package("<glob-deleteall/><glob pattern=\"*.z3\"/>"),
+        )
+        .unwrap();
+        fs::write(packages.join("a.xml"), package("<glob pattern=\"*.Z1\"/>")).unwrap();
+        fs::write(packages.join("b.xml"), package("<glob pattern=\"*.z2\"/>")).unwrap();
+        fs::write(packages.join("broken.xml"), "<mime-info>").unwrap();
+        MimeDatabase::at(dir.path()).rebuild().unwrap();
+
+        let globs = fs::read_to_string(dir.path().join("mime/globs")).unwrap();
+        assert_eq!(globs, format!("{GENERATED_HEADER}text/x-zeta:*.z3\n"));
+    }
+
+    #[test]
+    fn magic_values_decode_like_update_mime_database() {
+        assert_eq!(encode_value("host16", "0x0102").unwrap(), (vec![1, 2], 2));
+        assert_eq!(
+            encode_value("little32", "258").unwrap(),
+            (vec![2, 1, 0, 0], 1)
+        );
+        assert_eq!(encode_value("byte", "010").unwrap(), (vec![8], 1));
+        assert_eq!(
+            encode_value("string", "AB\\n\\101\\x7").unwrap(),
+            (b"AB\nA\x07".to_vec(), 1)
+        );
+        assert!(encode_value("big64", "1").is_err());
+        assert_eq!(parse_hex("0xff00").unwrap(), [0xff, 0]);
+    }
+
+    #[test]
+    fn cache_matches_update_mime_database_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        MimeDatabase::at(dir.path())
+            .install_package("acme", &[acme()])
+            .unwrap();
+        let cache = fs::read(dir.path().join("mime/mime.cache")).unwrap();
+        let word = |at: usize| u32::from_be_bytes(cache[at..at + 4].try_into().unwrap()) as usize;
+        let string = |at: usize| {
+            let end = cache[at..].iter().position(|&b| b == 0).unwrap();
+            std::str::from_utf8(&cache[at..at + end]).unwrap()
+        };
+        assert_eq!(&cache[..4], [0, 1, 0, 2]);
+
+        let literals = word(12);
+        assert_eq!(word(literals), 1);
+        assert_eq!(string(word(literals + 4)), "Acmefile");
+        assert_eq!(word(literals + 12), 50 | CASE_SENSITIVE_FLAG as usize);
+
+        // ".acme" reversed: e, m, c, a, '.', then a leaf.
+        let mut node = word(word(16) + 4);
+        for character in "emca.".chars() {
+            assert_eq!(word(node), character as usize);
+            node = word(node + 8);
+        }
+        assert_eq!(word(node), 0);
+        assert_eq!(string(word(node + 4)), "application/x-acme-project");
+        assert_eq!(word(node + 8), 60);
+
+        let magic = word(24);
+        assert_eq!((word(magic), word(magic + 4)), (1, 22));
+        let first = word(word(magic + 8) + 12);
+        let child = word(first + 28);
+        assert_eq!((word(child), word(child + 4), word(child + 12)), (4, 5, 2));
+        assert_eq!(&cache[word(child + 20)..word(child + 20) + 2], [0xff, 0x0f]);
+
+        let generic = word(36);
+        assert_eq!(string(word(generic + 8)), "x-office-document");
+    }
+
+    #[test]
+    fn text_files_agree_with_update_mime_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let ours = MimeDatabase::at(dir.path().join("ours"));
+        ours.install_package("acme", &[acme()]).unwrap();
+        let theirs = dir.path().join("theirs/mime");
+        fs::create_dir_all(theirs.join("packages")).unwrap();
+        fs::copy(ours.package_path("acme"), theirs.join("packages/acme.xml")).unwrap();
+        let status = Command::new("update-mime-database").arg(&theirs).output();
+        if !status.is_ok_and(|output| output.status.success()) {
+            eprintln!("update-mime-database unavailable, skipping");
+            return;
+        }
+
+        for name in [
+            "globs",
+            "globs2",
+            "magic",
+            "aliases",
+            "subclasses",
+            "icons",
+            "generic-icons",
+            "XMLnamespaces",
+            "types",
+            "treemagic",
+            "application/x-acme-project.xml",
+        ] {
+            assert_eq!(
+                fs::read(ours.mime_dir().join(name)).unwrap(),
+                fs::read(theirs.join(name)).unwrap(),
+                "{name} differs"
+            );
+        }
+        assert_eq!(
+            fs::metadata(ours.mime_dir().join("mime.cache"))
+                .unwrap()
+                .len(),
+            fs::metadata(theirs.join("mime.cache")).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn icons_install_into_hicolor_mimetypes() {
+        use crate::icon::sys::linux::LinuxIconImage;
+        use image::{DynamicImage, RgbaImage};
+        use std::borrow::Cow;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = MimeDatabase::at(dir.path());
+        let hicolor = dir.path().join("icons/hicolor");
+        fs::create_dir_all(&hicolor).unwrap();
+        let image = |size| LinuxIconImage {
+            size,
+            image: Cow::Owned(DynamicImage::ImageRgba8(RgbaImage::new(size, size))),
+        };
+
+        let set = LinuxIconSet::from_icons([image(16), image(48)]).unwrap();
+        let written = db.install_icon("application-x-acme-project", &set).unwrap();
+        assert_eq!(written.len(), 2);
+        assert!(
+            hicolor
+                .join("48x48/mimetypes/application-x-acme-project.png")
+                .is_file()
+        );
+
+        let set = LinuxIconSet::from_icons([image(32)]).unwrap();
+        db.install_icon("application-x-acme-project", &set).unwrap();
+        assert!(
+            !hicolor
+                .join("48x48/mimetypes/application-x-acme-project.png")
+                .exists()
+        );
+
+        assert!(db.uninstall_icon("application-x-acme-project").unwrap());
+        assert!(!db.uninstall_icon("application-x-acme-project").unwrap());
+        assert!(db.install_icon("empty", &LinuxIconSet::new()).is_err());
+    }
+}
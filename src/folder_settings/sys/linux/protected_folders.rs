@@ -0,0 +1,216 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::xdg;
+
+/// System directories whose icons are never modified.
+const SYSTEM_ROOTS: [&str; 21] = [
+    "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib32", "/lib64", "/media", "/mnt",
+    "/opt", "/proc", "/root", "/run", "/sbin", "/srv", "/sys", "/tmp", "/usr", "/var",
+];
+
+/// Why a folder is protected from icon changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtectedFolderKind {
+    /// The user's home directory.
+    Home,
+    /// An XDG user directory, named by its `user-dirs.dirs` key
+    /// (e.g. `XDG_DESKTOP_DIR`).
+    UserDir(String),
+    /// A filesystem root or top-level system directory.
+    SystemRoot,
+}
+
+impl fmt::Display for ProtectedFolderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtectedFolderKind::Home => f.write_str("home directory"),
+            ProtectedFolderKind::UserDir(key) => write!(f, "XDG user directory {key}"),
+            ProtectedFolderKind::SystemRoot => f.write_str("system directory"),
+        }
+    }
+}
+
+/// The Linux counterpart to the Windows known-folder check: the set of
+/// folders whose icons must not be modified.
+///
+/// Covers the home directory, the XDG user directories from
+/// `user-dirs.dirs` (Desktop, Downloads, ...) and top-level system
+/// directories. Matching is exact, so subfolders remain writable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectedFolders {
+    folders: Vec<(PathBuf, ProtectedFolderKind)>,
+}
+
+impl ProtectedFolders {
+    /// Protected folders for the current user, read from `$HOME` and
+    /// `$XDG_CONFIG_HOME/user-dirs.dirs` (default `~/.config`).
+    pub fn for_current_user() -> Self {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let user_dirs =
+            xdg::config_home().and_then(|c| std::fs::read_to_string(c.join("user-dirs.dirs")).ok());
+        Self::new(home.as_deref(), user_dirs.as_deref())
+    }
+
+    /// Build the protected set from a home directory and the contents of a
+    /// `user-dirs.dirs` file, if any.
+    ///
+    /// Per the xdg-user-dirs spec, a missing file still implies
+    /// `XDG_DESKTOP_DIR="$HOME/Desktop"`.
+    pub fn new(home: Option<&Path>, user_dirs: Option<&str>) -> Self {
+        let mut folders: Vec<_> = SYSTEM_ROOTS
+            .iter()
+            .map(|root| (PathBuf::from(root), ProtectedFolderKind::SystemRoot))
+            .collect();
+
+        if let Some(home) = home {
+            folders.push((home.to_path_buf(), ProtectedFolderKind::Home));
+            let user_dirs = match user_dirs {
+                Some(content) => parse_user_dirs(content, home),
+                None => vec![("XDG_DESKTOP_DIR".to_string(), home.join("Desktop"))],
+            };
+            folders.extend(
+                user_dirs
+                    .into_iter()
+                    .map(|(key, path)| (path, ProtectedFolderKind::UserDir(key))),
+            );
+        }
+
+        Self { folders }
+    }
+
+    /// Returns why `path` is protected, or `None` if its icon may be changed.
+    ///
+    /// Both sides are canonicalized where possible so symlinked homes
+    /// (e.g. `/home` -> `/var/home`) are still recognized.
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Option<&ProtectedFolderKind> {
+        let path = normalize(path.as_ref());
+        self.folders
+            .iter()
+            .find(|(folder, _)| normalize(folder) == path)
+            .map(|(_, kind)| kind)
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Parse `user-dirs.dirs` lines of the form `XDG_DESKTOP_DIR="$HOME/Desktop"`.
+///
+/// Values must be quoted and either absolute or relative to `$HOME`; other
+/// lines (comments, malformed entries) are skipped, as xdg-user-dirs does.
+/// Entries pointing at the home directory itself disable that directory and
+/// are left out, since the home directory is protected on its own.
+fn parse_user_dirs(content: &str, home: &Path) -> Vec<(String, PathBuf)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            if !(key.starts_with("XDG_") && key.ends_with("_DIR")) {
+                return None;
+            }
+            let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let path = if let Some(rest) = value.strip_prefix("$HOME") {
+                let rest = rest.trim_start_matches('/');
+                if rest.is_empty() {
+                    return None;
+                }
+                home.join(rest)
+            } else if value.starts_with('/') {
+                PathBuf::from(value)
+            } else {
+                return None;
+            };
+            Some((key.to_string(), path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_DIRS: &str = r#"# This file is written by xdg-user-dirs-update
+# If you want to change or add directories, just edit the line you're
+# interested in.
+XDG_DESKTOP_DIR="$HOME/Desktop"
+XDG_DOWNLOAD_DIR="$HOME/Downloads"
+XDG_TEMPLATES_DIR="$HOME/"
+XDG_MUSIC_DIR="/srv/music"
+XDG_VIDEOS_DIR=relative/ignored
+"#;
+
+    #[test]
+    fn parse_user_dirs_resolves_home_and_absolute_paths() {
+        let dirs = parse_user_dirs(USER_DIRS, Path::new("/home/ada"));
+        assert_eq!(
+            dirs,
+            [
+                (
+                    "XDG_DESKTOP_DIR".to_string(),
+                    PathBuf::from("/home/ada/Desktop")
+                ),
+                (
+                    "XDG_DOWNLOAD_DIR".to_string(),
+                    PathBuf::from("/home/ada/Downloads")
+                ),
+                ("XDG_MUSIC_DIR".to_string(), PathBuf::from("/srv/music")),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_reports_home_user_dirs_and_system_roots() {
+        let protected = ProtectedFolders::new(Some(Path::new("/home/ada")), Some(USER_DIRS));
+        assert_eq!(
+            protected.check("/home/ada"),
+            Some(&ProtectedFolderKind::Home)
+        );
+        assert_eq!(
+            protected.check("/home/ada/Downloads"),
+            Some(&ProtectedFolderKind::UserDir(
+                "XDG_DOWNLOAD_DIR".to_string()
+            ))
+        );
+        assert_eq!(protected.check("/"), Some(&ProtectedFolderKind::SystemRoot));
+        assert_eq!(
+            protected.check("/usr"),
+            Some(&ProtectedFolderKind::SystemRoot)
+        );
+    }
+
+    #[test]
+    fn check_allows_subfolders() {
+        let protected = ProtectedFolders::new(Some(Path::new("/home/ada")), Some(USER_DIRS));
+        assert!(protected.check("/home/ada/Downloads/project").is_none());
+        assert!(protected.check("/usr/share/project").is_none());
+    }
+
+    #[test]
+    fn missing_user_dirs_file_still_protects_desktop() {
+        let protected = ProtectedFolders::new(Some(Path::new("/home/ada")), None);
+        assert_eq!(
+            protected.check("/home/ada/Desktop"),
+            Some(&ProtectedFolderKind::UserDir("XDG_DESKTOP_DIR".to_string()))
+        );
+        assert!(protected.check("/home/ada/Downloads").is_none());
+    }
+
+    #[test]
+    fn check_matches_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("real-home");
+        std::fs::create_dir(&home).unwrap();
+        let link = dir.path().join("home-link");
+        std::os::unix::fs::symlink(&home, &link).unwrap();
+
+        let protected = ProtectedFolders::new(Some(&home), None);
+        assert_eq!(protected.check(&link), Some(&ProtectedFolderKind::Home));
+    }
+}
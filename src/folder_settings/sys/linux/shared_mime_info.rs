@@ -0,0 +1,682 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::xdg;
+
+/// Type reported for data nothing else matches.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Type reported for empty files.
+const ZERO_SIZE: &str = "application/x-zerosize";
+
+/// Type reported for directories.
+const DIRECTORY: &str = "inode/directory";
+
+/// How much of a file is sniffed when guessing whether it is text.
+const TEXT_SNIFF_LEN: usize = 512;
+
+/// The shared-mime-info database (`globs2`, `magic`, `aliases`,
+/// `subclasses`, `icons`, `generic-icons`) used to map file names and
+/// contents to MIME types and MIME types to icon names.
+///
+/// The files are merged from every `mime` directory under `XDG_DATA_HOME`
+/// and `XDG_DATA_DIRS`, as written by `update-mime-database`.
+#[derive(Debug, Clone, Default)]
+pub struct SharedMimeInfo {
+    globs: Vec<Glob>,
+    magic: Vec<MagicMatch>,
+    aliases: HashMap<String, String>,
+    parents: HashMap<String, Vec<String>>,
+    icons: HashMap<String, String>,
+    generic_icons: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+struct Glob {
+    weight: u32,
+    mime_type: String,
+    pattern: String,
+    case_sensitive: bool,
+}
+
+/// One `[priority:type]` section of the magic file.
+#[derive(Debug, Clone)]
+struct MagicMatch {
+    priority: u32,
+    mime_type: String,
+    rules: Vec<MagicRule>,
+}
+
+#[derive(Debug, Clone)]
+struct MagicRule {
+    indent: usize,
+    offset: usize,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    range: usize,
+}
+
+impl SharedMimeInfo {
+    /// Load the database from the XDG data directories, most important
+    /// first: `$XDG_DATA_HOME/mime`, then `$XDG_DATA_DIRS/*/mime`.
+    pub fn for_current_user() -> Self {
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+        let dirs: Vec<PathBuf> = xdg::data_home()
+            .into_iter()
+            .chain(std::env::split_paths(&data_dirs))
+            .map(|dir| dir.join("mime"))
+            .collect();
+        Self::from_dirs(&dirs)
+    }
+
+    /// Load the database from `mime` directories, most important first.
+    /// Missing files are skipped.
+    pub fn from_dirs<P: AsRef<Path>>(dirs: &[P]) -> Self {
+        let mut db = Self::default();
+        // Types whose globs a more important directory replaced.
+        let mut no_globs = HashSet::new();
+        for dir in dirs {
+            let dir = dir.as_ref();
+            if let Ok(content) = fs::read_to_string(dir.join("globs2")) {
+                db.add_globs(&content, &mut no_globs);
+            }
+            if let Ok(bytes) = fs::read(dir.join("magic")) {
+                db.magic.extend(parse_magic(&bytes));
+            }
+            for (name, map) in [
+                ("aliases", &mut db.aliases),
+                ("icons", &mut db.icons),
+                ("generic-icons", &mut db.generic_icons),
+            ] {
+                let separator = if name == "aliases" { ' ' } else { ':' };
+                for (key, value) in read_pairs(&dir.join(name), separator) {
+                    map.entry(key).or_insert(value);
+                }
+            }
+            for (child, parent) in read_pairs(&dir.join("subclasses"), ' ') {
+                let parents = db.parents.entry(child).or_default();
+                if !parents.contains(&parent) {
+                    parents.push(parent);
+                }
+            }
+        }
+        db.magic.sort_by_key(|m| std::cmp::Reverse(m.priority));
+        db
+    }
+
+    /// The canonical name of `mime_type`, resolving aliases such as
+    /// `application/x-pdf`.
+    pub fn unalias(&self, mime_type: &str) -> String {
+        let mime_type = mime_type.to_ascii_lowercase();
+        self.aliases.get(&mime_type).cloned().unwrap_or(mime_type)
+    }
+
+    /// The MIME type of the file at `path`, from its name and, when the name
+    /// is unknown or ambiguous, its contents. Directories are
+    /// `inode/directory`.
+    pub fn mime_type_for_path<P: AsRef<Path>>(&self, path: P) -> std::io::Result<String> {
+        let path = path.as_ref();
+        if fs::metadata(path)?.is_dir() {
+            return Ok(DIRECTORY.to_string());
+        }
+        let candidates = path
+            .file_name()
+            .map(|name| self.glob_candidates(&name.to_string_lossy()))
+            .unwrap_or_default();
+        if let [only] = candidates.as_slice() {
+            return Ok(only.clone());
+        }
+
+        let mut data = Vec::new();
+        fs::File::open(path)?
+            .take(self.sniff_len() as u64)
+            .read_to_end(&mut data)?;
+        let sniffed = self.mime_type_for_data(&data);
+        // Contents only decide between the name's candidates.
+        Ok(match candidates.first() {
+            Some(first) if !candidates.contains(&sniffed) => first.clone(),
+            _ => sniffed,
+        })
+    }
+
+    /// The MIME type for a file name alone, e.g. `report.pdf`.
+    pub fn mime_type_for_name(&self, name: &str) -> Option<String> {
+        self.glob_candidates(name).into_iter().next()
+    }
+
+    /// The MIME type of file contents from the magic rules, falling back to
+    /// `text/plain` for text and `application/octet-stream` otherwise.
+    pub fn mime_type_for_data(&self, data: &[u8]) -> String {
+        if data.is_empty() {
+            return ZERO_SIZE.to_string();
+        }
+        if let Some(found) = self.magic.iter().find(|m| m.matches(data)) {
+            return found.mime_type.clone();
+        }
+        let head = &data[..data.len().min(TEXT_SNIFF_LEN)];
+        let text = !head.contains(&0)
+            && match std::str::from_utf8(head) {
+                Ok(_) => true,
+                // A multi-byte character cut off at the end is still text.
+                Err(e) => e.error_len().is_none(),
+            };
+        if text { "text/plain" } else { OCTET_STREAM }.to_string()
+    }
+
+    /// Every parent of `mime_type`, nearest first, including the implicit
+    /// `text/plain` parent of text types and `application/octet-stream`.
+    pub fn ancestors(&self, mime_type: &str) -> Vec<String> {
+        let mime_type = self.unalias(mime_type);
+        let mut seen = HashSet::from([mime_type.clone()]);
+        let mut ancestors = Vec::new();
+        let mut queue = VecDeque::from([mime_type]);
+        while let Some(current) = queue.pop_front() {
+            let mut parents = self.parents.get(&current).cloned().unwrap_or_default();
+            if current.starts_with("text/") && current != "text/plain" {
+                parents.push("text/plain".to_string());
+            }
+            if !current.starts_with("inode/") && current != OCTET_STREAM {
+                parents.push(OCTET_STREAM.to_string());
+            }
+            for parent in parents {
+                let parent = self.unalias(&parent);
+                if seen.insert(parent.clone()) {
+                    ancestors.push(parent.clone());
+                    queue.push_back(parent);
+                }
+            }
+        }
+        // The catch-all goes last, after every more specific parent.
+        if let Some(i) = ancestors.iter().position(|a| a == OCTET_STREAM) {
+            let catch_all = ancestors.remove(i);
+            ancestors.push(catch_all);
+        }
+        ancestors
+    }
+
+    /// Icon names for `mime_type`, best first: its own icon
+    /// (`application-pdf`), those of its parents, then generic icons
+    /// (`x-office-document`, `text-x-generic`). The catch-all
+    /// `application-octet-stream` comes last.
+    pub fn icon_names(&self, mime_type: &str) -> Vec<String> {
+        let mime_type = self.unalias(mime_type);
+        let lineage: Vec<String> = std::iter::once(mime_type.clone())
+            .chain(self.ancestors(&mime_type))
+            .collect();
+
+        let specific = lineage.iter().filter(|t| *t != OCTET_STREAM).map(|t| {
+            self.icons
+                .get(t)
+                .cloned()
+                .unwrap_or_else(|| t.replace('/', "-"))
+        });
+        let generic = lineage
+            .iter()
+            .filter_map(|t| self.generic_icons.get(t).cloned());
+        let media = mime_type
+            .split_once('/')
+            .map(|(media, _)| format!("{media}-x-generic"));
+
+        let mut seen = HashSet::new();
+        specific
+            .chain(generic)
+            .chain(media)
+            .chain((!mime_type.starts_with("inode/")).then(|| OCTET_STREAM.replace('/', "-")))
+            .filter(|name| seen.insert(name.clone()))
+            .collect()
+    }
+
+    /// Types whose globs match `name` with the highest weight, longest
+    /// pattern first.
+    fn glob_candidates(&self, name: &str) -> Vec<String> {
+        let lower = name.to_lowercase();
+        let mut matches: Vec<&Glob> = self
+            .globs
+            .iter()
+            .filter(|glob| {
+                if glob.case_sensitive {
+                    glob_match(&glob.pattern, name)
+                } else {
+                    glob_match(&glob.pattern.to_lowercase(), &lower)
+                }
+            })
+            .collect();
+        let Some(best) = matches.iter().map(|glob| glob.weight).max() else {
+            return Vec::new();
+        };
+        matches.retain(|glob| glob.weight == best);
+        matches.sort_by_key(|glob| std::cmp::Reverse(glob.pattern.len()));
+
+        let mut candidates: Vec<String> = Vec::new();
+        for glob in matches {
+            if !candidates.contains(&glob.mime_type) {
+                candidates.push(glob.mime_type.clone());
+            }
+        }
+        candidates
+    }
+
+    /// Bytes needed to evaluate every magic rule.
+    fn sniff_len(&self) -> usize {
+        self.magic
+            .iter()
+            .flat_map(|m| &m.rules)
+            .map(|rule| rule.offset + rule.range + rule.value.len())
+            .max()
+            .unwrap_or(0)
+            .max(TEXT_SNIFF_LEN)
+    }
+
+    fn add_globs(&mut self, content: &str, no_globs: &mut HashSet<String>) {
+        let mut replaced = HashSet::new();
+        for line in content.lines().filter(|l| !l.starts_with('#')) {
+            let mut fields = line.splitn(4, ':');
+            let (Some(weight), Some(mime_type), Some(pattern)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if no_globs.contains(mime_type) {
+                continue;
+            }
+            if pattern == "__NOGLOBS__" {
+                replaced.insert(mime_type.to_string());
+                continue;
+            }
+            let Ok(weight) = weight.parse() else {
+                continue;
+            };
+            let case_sensitive = fields
+                .next()
+                .is_some_and(|flags| flags.split(',').any(|flag| flag == "cs"));
+            self.globs.push(Glob {
+                weight,
+                mime_type: mime_type.to_string(),
+                pattern: pattern.to_string(),
+                case_sensitive,
+            });
+        }
+        no_globs.extend(replaced);
+    }
+}
+
+impl MagicMatch {
+    fn matches(&self, data: &[u8]) -> bool {
+        (0..self.rules.len())
+            .filter(|&i| self.rules[i].indent == 0)
+            .any(|i| self.rule_tree_matches(i, data))
+    }
+
+    /// A rule matches if it matches itself and, when it has nested rules,
+    /// at least one of them does.
+    fn rule_tree_matches(&self, index: usize, data: &[u8]) -> bool {
+        let rule = &self.rules[index];
+        if !rule.matches(data) {
+            return false;
+        }
+        let mut children = self.rules[index + 1..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, child)| child.indent > rule.indent)
+            .filter(|(_, child)| child.indent == rule.indent + 1)
+            .map(|(offset, _)| index + 1 + offset)
+            .peekable();
+        children.peek().is_none() || children.any(|child| self.rule_tree_matches(child, data))
+    }
+}
+
+impl MagicRule {
+    fn matches(&self, data: &[u8]) -> bool {
+        (self.offset..self.offset + self.range).any(|start| {
+            let Some(window) = data.get(start..start + self.value.len()) else {
+                return false;
+            };
+            match &self.mask {
+                None => window == self.value,
+                Some(mask) => window
+                    .iter()
+                    .zip(&self.value)
+                    .zip(mask)
+                    .all(|((byte, value), mask)| byte & mask == value & mask),
+            }
+        })
+    }
+}
+
+/// Parse a `magic` file. Sections or rules that don't parse are skipped.
+fn parse_magic(bytes: &[u8]) -> Vec<MagicMatch> {
+    let Some(mut rest) = bytes.strip_prefix(b"MIME-Magic\0\n") else {
+        return Vec::new();
+    };
+    let mut sections: Vec<MagicMatch> = Vec::new();
+    while !rest.is_empty() {
+        if rest[0] == b'[' {
+            let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            let header = String::from_utf8_lossy(&rest[1..end]);
+            rest = &rest[end + 1..];
+            if let Some((priority, mime_type)) =
+                header.strip_suffix(']').and_then(|h| h.split_once(':'))
+                && let Ok(priority) = priority.parse()
+            {
+                sections.push(MagicMatch {
+                    priority,
+                    mime_type: mime_type.to_string(),
+                    rules: Vec::new(),
+                });
+            }
+            continue;
+        }
+        match parse_magic_rule(rest) {
+            Some((rule, remaining)) => {
+                if let Some(section) = sections.last_mut() {
+                    section.rules.push(rule);
+                }
+                rest = remaining;
+            }
+            // Skip an unparseable line; values may contain newlines, so this
+            // can only be a best effort.
+            None => match rest.iter().position(|&b| b == b'\n') {
+                Some(end) => rest = &rest[end + 1..],
+                None => break,
+            },
+        }
+    }
+    sections
+}
+
+/// Parse `[indent]>offset=<len:u16be><value>[&mask][~word][+range]\n`.
+fn parse_magic_rule(line: &[u8]) -> Option<(MagicRule, &[u8])> {
+    let (indent, rest) = parse_number(line).unwrap_or((0, line));
+    let rest = rest.strip_prefix(b">")?;
+    let (offset, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix(b"=")?;
+    let len = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+    let mut value = rest.get(2..2 + len)?.to_vec();
+    let mut rest = &rest[2 + len..];
+
+    let mut mask = None;
+    if let Some(after) = rest.strip_prefix(b"&") {
+        mask = Some(after.get(..len)?.to_vec());
+        rest = &after[len..];
+    }
+    let mut word_size = 1;
+    if let Some(after) = rest.strip_prefix(b"~") {
+        (word_size, rest) = parse_number(after)?;
+    }
+    let mut range = 1;
+    if let Some(after) = rest.strip_prefix(b"+") {
+        (range, rest) = parse_number(after)?;
+    }
+    let rest = rest.strip_prefix(b"\n")?;
+
+    // Multi-byte words are stored big-endian and compared in host order.
+    if cfg!(target_endian = "little") && matches!(word_size, 2 | 4) {
+        for bytes in std::iter::once(&mut value).chain(mask.as_mut()) {
+            bytes
+                .chunks_exact_mut(word_size)
+                .for_each(|word| word.reverse());
+        }
+    }
+
+    Some((
+        MagicRule {
+            indent,
+            offset,
+            value,
+            mask,
+            range,
+        },
+        rest,
+    ))
+}
+
+fn parse_number(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&bytes[..digits]).ok()?.parse().ok()?;
+    Some((number, &bytes[digits..]))
+}
+
+/// `key<separator>value` lines of a database file, skipping comments.
+fn read_pairs(path: &Path, separator: char) -> Vec<(String, String)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(separator))
+        .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Shell-style glob match supporting `*`, `?` and `[...]` classes.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match_chars(&pattern, &name)
+}
+
+fn glob_match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| glob_match_chars(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && glob_match_chars(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(close) = pattern.iter().skip(2).position(|&c| c == ']') else {
+                return name.first() == Some(&'[') && glob_match_chars(&pattern[1..], &name[1..]);
+            };
+            let class = &pattern[1..close + 2];
+            let Some(&c) = name.first() else {
+                return false;
+            };
+            let (negated, class) = match class.first() {
+                Some('!') | Some('^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    found |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= class[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && glob_match_chars(&pattern[close + 3..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && glob_match_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A magic rule line as `update-mime-database` writes it.
+    fn rule(indent: &str, offset: usize, value: &[u8], extra: &[u8]) -> Vec<u8> {
+        let mut line = format!("{indent}>{offset}=").into_bytes();
+        line.extend((value.len() as u16).to_be_bytes());
+        line.extend(value);
+        line.extend(extra);
+        line.push(b'\n');
+        line
+    }
+
+    fn database() -> (tempfile::TempDir, SharedMimeInfo) {
+        let dir = tempfile::tempdir().unwrap();
+        let (user, system) = (dir.path().join("user"), dir.path().join("system"));
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&system).unwrap();
+
+        fs::write(
+            system.join("globs2"),
+            "# comment\n\
+             50:application/pdf:*.pdf\n\
+             50:application/x-compressed-tar:*.tar.gz\n\
+             50:application/gzip:*.gz\n\
+             50:text/x-c++src:*.C:cs\n\
+             50:text/x-csrc:*.c\n\
+             50:text/x-makefile:[Mm]akefile\n\
+             50:application/x-old:*.old\n\
+             50:text/x-ambiguous:*.amb\n\
+             50:application/x-ambiguous:*.amb\n",
+        )
+        .unwrap();
+        fs::write(
+            user.join("globs2"),
+            "50:application/x-old:__NOGLOBS__\n60:application/x-new:*.old\n",
+        )
+        .unwrap();
+
+        let mut magic = b"MIME-Magic\0\n".to_vec();
+        magic.extend(b"[80:application/pdf]\n");
+        magic.extend(rule("", 0, b"%PDF-", b""));
+        magic.extend(b"[60:application/x-ambiguous]\n");
+        magic.extend(rule("", 0, b"AMB", b""));
+        magic.extend(rule("1", 8, b"\x01\x02", b"&\xff\x0f"));
+        magic.extend(b"[50:application/x-searched]\n");
+        magic.extend(rule("", 4, b"NEEDLE", b"+8"));
+        fs::write(system.join("magic"), magic).unwrap();
+
+        fs::write(
+            system.join("aliases"),
+            "application/x-pdf application/pdf\n",
+        )
+        .unwrap();
+        fs::write(
+            system.join("subclasses"),
+            "application/x-compressed-tar application/gzip\ntext/x-csrc text/plain\n",
+        )
+        .unwrap();
+        fs::write(system.join("icons"), "application/pdf:pdf-icon\n").unwrap();
+        fs::write(
+            system.join("generic-icons"),
+            "application/pdf:x-office-document\napplication/gzip:package-x-generic\n",
+        )
+        .unwrap();
+
+        let db = SharedMimeInfo::from_dirs(&[user, system]);
+        (dir, db)
+    }
+
+    #[test]
+    fn globs_prefer_weight_then_length_and_honor_case() {
+        let (_dir, db) = database();
+        let name = |n| db.mime_type_for_name(n);
+        assert_eq!(name("REPORT.PDF").as_deref(), Some("application/pdf"));
+        assert_eq!(
+            name("src.tar.gz").as_deref(),
+            Some("application/x-compressed-tar")
+        );
+        assert_eq!(name("main.C").as_deref(), Some("text/x-c++src"));
+        assert_eq!(name("main.c").as_deref(), Some("text/x-csrc"));
+        assert_eq!(name("makefile").as_deref(), Some("text/x-makefile"));
+        assert_eq!(name("notes"), None);
+        // The user database replaced the system globs for x-old.
+        assert_eq!(name("a.old").as_deref(), Some("application/x-new"));
+    }
+
+    #[test]
+    fn magic_rules_match_nested_masked_and_ranged() {
+        let (_dir, db) = database();
+        assert_eq!(db.mime_type_for_data(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(
+            db.mime_type_for_data(b"AMB.....\x01\xf2"),
+            "application/x-ambiguous"
+        );
+        // The nested rule fails, so the section does not match.
+        assert_eq!(db.mime_type_for_data(b"AMB.....\x02\x02"), "text/plain");
+        assert_eq!(
+            db.mime_type_for_data(b"\0\0\0\0....NEEDLE"),
+            "application/x-searched"
+        );
+        assert_eq!(db.mime_type_for_data(b"\0\x01\x02"), OCTET_STREAM);
+        assert_eq!(db.mime_type_for_data(b""), ZERO_SIZE);
+    }
+
+    #[test]
+    fn contents_decide_between_ambiguous_globs() {
+        let (dir, db) = database();
+        let file = dir.path().join("data.amb");
+        fs::write(&file, b"AMB.....\x01\x02").unwrap();
+        assert_eq!(
+            db.mime_type_for_path(&file).unwrap(),
+            "application/x-ambiguous"
+        );
+        fs::write(&file, b"plain words").unwrap();
+        assert_eq!(db.mime_type_for_path(&file).unwrap(), "text/x-ambiguous");
+
+        let unnamed = dir.path().join("unnamed");
+        fs::write(&unnamed, b"%PDF-1.4").unwrap();
+        assert_eq!(db.mime_type_for_path(&unnamed).unwrap(), "application/pdf");
+        assert_eq!(db.mime_type_for_path(dir.path()).unwrap(), DIRECTORY);
+    }
+
+    #[test]
+    fn icon_names_walk_parents_then_generic_icons() {
+        let (_dir, db) = database();
+        assert_eq!(
+            db.icon_names("application/x-pdf"),
+            [
+                "pdf-icon",
+                "x-office-document",
+                "application-x-generic",
+                "application-octet-stream"
+            ]
+        );
+        assert_eq!(
+            db.icon_names("application/x-compressed-tar"),
+            [
+                "application-x-compressed-tar",
+                "application-gzip",
+                "package-x-generic",
+                "application-x-generic",
+                "application-octet-stream"
+            ]
+        );
+        assert_eq!(
+            db.icon_names("text/x-csrc"),
+            [
+                "text-x-csrc",
+                "text-plain",
+                "text-x-generic",
+                "application-octet-stream"
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_classes_and_wildcards() {
+        assert!(glob_match("*.[ch]", "main.h"));
+        assert!(!glob_match("*.[!ch]", "main.h"));
+        assert!(glob_match("[a-c]?x*", "box.tar"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn system_database_resolves_common_types() {
+        if !Path::new("/usr/share/mime/globs2").exists() {
+            eprintln!("shared-mime-info unavailable, skipping");
+            return;
+        }
+        let db = SharedMimeInfo::from_dirs(&["/usr/share/mime"]);
+        assert_eq!(
+            db.mime_type_for_name("report.pdf").as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(db.mime_type_for_data(b"%PDF-1.7\n"), "application/pdf");
+        assert!(
+            db.icon_names("text/x-python")
+                .contains(&"text-x-generic".to_string())
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use md5::{Digest, Md5};
+
+use super::uri::file_uri;
+
+/// Size flavors defined by the Thumbnail Managing Standard.
+const SIZE_DIRS: [&str; 4] = ["normal", "large", "x-large", "xx-large"];
+
+/// Directory holding per-application failure markers (`fail/<app>/`).
+const FAIL_DIR: &str = "fail";
+
+/// The freedesktop thumbnail cache, as described by the Thumbnail Managing
+/// Standard.
+///
+/// Thumbnails are stored as `<root>/<flavor>/<md5(uri)>.png`, where the URI
+/// is the file's canonical `file://` URI. File managers key folder previews
+/// the same way, so removing the entries forces them to be regenerated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailCache {
+    root: PathBuf,
+}
+
+impl ThumbnailCache {
+    /// The current user's cache: `$XDG_CACHE_HOME/thumbnails`, defaulting to
+    /// `~/.cache/thumbnails`. Returns `None` if neither can be determined.
+    pub fn user() -> Option<Self> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+        Some(Self::at(cache_home.join("thumbnails")))
+    }
+
+    /// A cache rooted at an explicit directory.
+    pub fn at<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The cache file name for a URI: the lowercase hex MD5 of the URI with
+    /// a `.png` extension.
+    pub fn thumbnail_name(uri: &str) -> String {
+        let digest = Md5::digest(uri.as_bytes());
+        let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        format!("{hex}.png")
+    }
+
+    /// Remove every cached thumbnail and failure marker for `uri`, returning
+    /// how many files were removed.
+    pub fn invalidate_uri(&self, uri: &str) -> io::Result<usize> {
+        let name = Self::thumbnail_name(uri);
+
+        let mut dirs: Vec<PathBuf> = SIZE_DIRS.iter().map(|d| self.root.join(d)).collect();
+        match fs::read_dir(self.root.join(FAIL_DIR)) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    if entry.file_type()?.is_dir() {
+                        dirs.push(entry.path());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut removed = 0;
+        for dir in dirs {
+            match fs::remove_file(dir.join(&name)) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Remove every cached thumbnail for a local path. Relative paths are
+    /// made absolute first, since the cache is keyed by absolute URIs.
+    pub fn invalidate_path<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let path = std::path::absolute(path.as_ref())?;
+        self.invalidate_uri(&file_uri(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnail_name_matches_spec_example() {
+        // Example from the Thumbnail Managing Standard.
+        assert_eq!(
+            ThumbnailCache::thumbnail_name("file:///home/jens/photos/me.png"),
+            "c6ee772d9e49320e97ec29a7eb5b1697.png"
+        );
+    }
+
+    #[test]
+    fn invalidate_uri_removes_every_flavor_and_failure_marker() {
+        let root = tempfile::tempdir().unwrap();
+        let uri = "file:///home/ada/Projects";
+        let name = ThumbnailCache::thumbnail_name(uri);
+        let targets = [
+            root.path().join("normal"),
+            root.path().join("x-large"),
+            root.path().join("fail/gnome-thumbnail-factory"),
+        ];
+        for dir in &targets {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join(&name), b"png").unwrap();
+        }
+        // An unrelated thumbnail must survive.
+        let other = root.path().join("normal").join("0123.png");
+        fs::write(&other, b"png").unwrap();
+
+        let removed = ThumbnailCache::at(root.path()).invalidate_uri(uri).unwrap();
+        assert_eq!(removed, targets.len());
+        assert!(targets.iter().all(|dir| !dir.join(&name).exists()));
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn invalidate_uri_on_missing_cache_is_noop() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::at(root.path().join("does-not-exist"));
+        assert_eq!(cache.invalidate_uri("file:///x").unwrap(), 0);
+    }
+}
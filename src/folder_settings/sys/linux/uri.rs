@@ -0,0 +1,74 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// Build a `file://` URI for an absolute path, escaped the way GLib's
+/// `g_filename_to_uri` does. The exact form matters wherever a URI is used
+/// as a key, e.g. thumbnail cache names are the MD5 of this string.
+pub(crate) fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"!$&'()*+,-./:=@_~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// The local path a `file://` URI points to, undoing percent-escapes.
+/// Returns `None` for other schemes and URIs with a remote host.
+pub(crate) fn file_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut input = rest.bytes();
+    while let Some(byte) = input.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [input.next()?, input.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_uri_keeps_path_safe_characters() {
+        assert_eq!(
+            file_uri(Path::new("/home/ada/My_Files-1.0/(a)+b~")),
+            "file:///home/ada/My_Files-1.0/(a)+b~"
+        );
+    }
+
+    #[test]
+    fn file_uri_escapes_spaces_reserved_and_non_ascii() {
+        assert_eq!(
+            file_uri(Path::new("/home/ada/100% ok#?/caf\u{e9}")),
+            "file:///home/ada/100%25%20ok%23%3F/caf%C3%A9"
+        );
+    }
+
+    #[test]
+    fn file_path_reverses_file_uri() {
+        let path = Path::new("/home/ada/100% ok#?/caf\u{e9}");
+        assert_eq!(file_path(&file_uri(path)).as_deref(), Some(path));
+        assert_eq!(
+            file_path("file://localhost/tmp/a%20b").as_deref(),
+            Some(Path::new("/tmp/a b"))
+        );
+        assert_eq!(file_path("file://server/share"), None);
+        assert_eq!(file_path("trash:///x"), None);
+        assert_eq!(file_path("file:///bad%2"), None);
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt};
+use crate::api::{IconImage, IconSet};
+use crate::folder_settings::error::{FolderSettingsError, Result};
+use crate::folder_settings::{
+    DesktopIniFolderSettingsProvider, FolderSettingsProvider, IconLibrary, IconLocation,
+};
+use crate::icon::sys::linux::LinuxIconSet;
+
+/// Environment variables found in icon paths written by Windows, and what
+/// they stand for on a standard installation.
+const WINDOWS_VARIABLES: [(&str, &str); 8] = [
+    ("SystemDrive", "C:"),
+    ("SystemRoot", r"C:\Windows"),
+    ("windir", r"C:\Windows"),
+    ("ProgramFiles", r"C:\Program Files"),
+    ("ProgramFiles(x86)", r"C:\Program Files (x86)"),
+    ("ProgramW6432", r"C:\Program Files"),
+    ("CommonProgramFiles", r"C:\Program Files\Common Files"),
+    ("ProgramData", r"C:\ProgramData"),
+];
+
+/// Gives folders copied from Windows, e.g. off an NTFS partition, the icons
+/// Explorer showed for them: the icon named by each folder's `desktop.ini`
+/// (see [`DesktopIniFolderSettingsProvider::icon_location`]) is loaded from
+/// the `.ico` file, or the icon group of the `.dll`, `.exe` or `.icl` file,
+/// and applied with a [`LinuxFolderSettingsProvider`].
+///
+/// Relative icon paths are resolved against the folder. Paths on a drive,
+/// such as `%SystemRoot%\system32\shell32.dll`, need the drive's location,
+/// e.g. the mounted Windows partition or a Wine prefix's `drive_c` (see
+/// [`with_drive`](Self::with_drive)). Path components are matched
+/// case-insensitively, as on Windows.
+#[derive(Debug)]
+pub struct WindowsFolderIconImport {
+    provider: LinuxFolderSettingsProvider,
+    recursive: bool,
+    drives: BTreeMap<char, PathBuf>,
+}
+
+/// What [`WindowsFolderIconImport::import`] did. Folders without an icon in
+/// their `desktop.ini` are left out.
+#[derive(Debug, Default)]
+pub struct WindowsIconImportReport {
+    /// Folders that were given their Windows icon.
+    pub imported: Vec<PathBuf>,
+    /// Folders whose icon could not be found or read.
+    pub unresolved: Vec<SkippedFolder>,
+    /// Folders whose icon was read but could not be applied.
+    pub failed: Vec<SkippedFolder>,
+}
+
+/// A folder whose Windows icon was not imported.
+#[derive(Debug)]
+pub struct SkippedFolder {
+    pub folder: PathBuf,
+    /// The icon named by the folder's `desktop.ini`, if it could be read.
+    pub location: Option<IconLocation>,
+    pub error: FolderSettingsError,
+}
+
+impl WindowsFolderIconImport {
+    pub fn new(provider: LinuxFolderSettingsProvider) -> Self {
+        Self {
+            provider,
+            recursive: false,
+            drives: BTreeMap::new(),
+        }
+    }
+
+    /// Also import the icons of every folder below the given one. Symbolic
+    /// links are not followed.
+    pub fn with_recursion(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Where the root of drive `letter` (e.g. `C`) is.
+    pub fn with_drive<P: Into<PathBuf>>(mut self, letter: char, root: P) -> Self {
+        self.drives.insert(letter.to_ascii_uppercase(), root.into());
+        self
+    }
+
+    /// Import the icon of `path`, and with recursion those of its
+    /// subfolders. Only errors walking the folders end the import; icons
+    /// that can't be imported are listed in the report.
+    pub fn import<P: AsRef<Path>>(&self, path: P) -> Result<WindowsIconImportReport> {
+        let root = path.as_ref();
+        if !root.is_dir() {
+            return Err(FolderSettingsError::NotAFolder(root.to_path_buf()));
+        }
+        let reader = DesktopIniFolderSettingsProvider::new();
+        let mut report = WindowsIconImportReport::default();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(folder) = pending.pop() {
+            if self.recursive {
+                let io_error = |e| FolderSettingsError::Io(folder.clone(), e);
+                let mut subfolders = Vec::new();
+                for entry in fs::read_dir(&folder).map_err(io_error)? {
+                    let entry = entry.map_err(io_error)?;
+                    if entry.file_type().map_err(io_error)?.is_dir() {
+                        subfolders.push(entry.path());
+                    }
+                }
+                // Visit folders in name order.
+                subfolders.sort_unstable_by(|a, b| b.cmp(a));
+                pending.extend(subfolders);
+            }
+            self.import_folder(&reader, folder, &mut report);
+        }
+        Ok(report)
+    }
+
+    fn import_folder(
+        &self,
+        reader: &DesktopIniFolderSettingsProvider,
+        folder: PathBuf,
+        report: &mut WindowsIconImportReport,
+    ) {
+        let location = match reader.icon_location(&folder) {
+            Ok(Some(location)) => location,
+            Ok(None) => return,
+            Err(error) => {
+                report.unresolved.push(SkippedFolder {
+                    folder,
+                    location: None,
+                    error,
+                });
+                return;
+            }
+        };
+        let icon_set = match self.load_icon(&folder, &location) {
+            Ok(icon_set) => icon_set,
+            Err(error) => {
+                report.unresolved.push(SkippedFolder {
+                    folder,
+                    location: Some(location),
+                    error,
+                });
+                return;
+            }
+        };
+        match self
+            .provider
+            .set_icon_for_folder_linux(&folder, &LinuxIconSet::from(&icon_set))
+        {
+            Ok(_) => report.imported.push(folder),
+            Err(error) => report.failed.push(SkippedFolder {
+                folder,
+                location: Some(location),
+                error,
+            }),
+        }
+    }
+
+    fn load_icon(&self, folder: &Path, location: &IconLocation) -> Result<IconSet> {
+        let path = self.resolve(folder, &location.path)?;
+        let library = IconLibrary::load(&path)?;
+        let group = library.group_at_index(location.index).ok_or_else(|| {
+            FolderSettingsError::IconLibrary(format!(
+                "{} has no icon {}",
+                path.display(),
+                location.index
+            ))
+        })?;
+        let images = library.images(group)?;
+        Ok(IconSet {
+            images: images.into_iter().map(|data| IconImage { data }).collect(),
+            svg: None,
+        })
+    }
+
+    /// The file a Windows icon path names.
+    fn resolve(&self, folder: &Path, windows_path: &str) -> Result<PathBuf> {
+        let unresolved = |reason: String| {
+            FolderSettingsError::Io(
+                PathBuf::from(windows_path),
+                std::io::Error::new(std::io::ErrorKind::NotFound, reason),
+            )
+        };
+        let expanded = expand_variables(windows_path).map_err(unresolved)?;
+        if expanded.starts_with(r"\\") {
+            return Err(unresolved("network paths are not supported".to_string()));
+        }
+
+        // `..` stops at the root of a drive, as on Windows, so paths can't
+        // leave the mapped directory.
+        let (base, rest, at_drive) = match drive_letter(&expanded) {
+            Some(letter) => {
+                let root = self
+                    .drives
+                    .get(&letter)
+                    .ok_or_else(|| unresolved(format!("drive {letter}: is not mapped")))?;
+                (root.clone(), &expanded[2..], true)
+            }
+            None if expanded.starts_with(['\\', '/']) => {
+                return Err(unresolved("the path has no drive".to_string()));
+            }
+            None => (folder.to_path_buf(), expanded.as_str(), false),
+        };
+
+        let mut path = base;
+        let mut depth = 0usize;
+        for component in rest
+            .split(['\\', '/'])
+            .filter(|c| !c.is_empty() && *c != ".")
+        {
+            if component == ".." {
+                if !at_drive || depth > 0 {
+                    path.pop();
+                    depth = depth.saturating_sub(1);
+                }
+                continue;
+            }
+            depth += 1;
+            path = find_case_insensitive(&path, component).ok_or_else(|| {
+                unresolved(format!("{} does not exist", path.join(component).display()))
+            })?;
+        }
+        Ok(path)
+    }
+}
+
+/// Replace `%Name%` variables, case-insensitively.
+fn expand_variables(path: &str) -> std::result::Result<String, String> {
+    let mut out = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find('%') {
+        let end = rest[start + 1..]
+            .find('%')
+            .ok_or_else(|| format!("unterminated variable in {path}"))?
+            + start
+            + 1;
+        let name = &rest[start + 1..end];
+        let value = WINDOWS_VARIABLES
+            .iter()
+            .find(|(variable, _)| variable.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+            .ok_or_else(|| format!("unknown variable %{name}%"))?;
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn drive_letter(path: &str) -> Option<char> {
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+            Some(letter.to_ascii_uppercase())
+        }
+        _ => None,
+    }
+}
+
+/// `dir/name`, or the entry of `dir` whose name differs only in case.
+fn find_case_insensitive(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|entry_name| entry_name.eq_ignore_ascii_case(name))
+        })
+        .map(|entry| entry.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folder_settings::icon_library::builder::{fixture_resources, pe};
+    use crate::folder_settings::sys::linux::LinuxBackend;
+    use crate::icon::sys::windows::WindowsIconSize;
+    use image::DynamicImage;
+
+    fn importer() -> WindowsFolderIconImport {
+        WindowsFolderIconImport::new(LinuxFolderSettingsProvider::new_linux(
+            LinuxBackend::DirectoryFile,
+            Some("test"),
+            false,
+        ))
+    }
+
+    fn desktop_ini(folder: &Path, shell_class_info: &str) {
+        fs::create_dir_all(folder).unwrap();
+        fs::write(
+            folder.join("desktop.ini"),
+            format!("[.ShellClassInfo]\r\n{shell_class_info}\r\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn expands_variables_and_drive_letters() {
+        assert_eq!(
+            expand_variables(r"%SYSTEMROOT%\System32\shell32.dll").unwrap(),
+            r"C:\Windows\System32\shell32.dll"
+        );
+        assert!(expand_variables(r"%USERPROFILE%\icon.ico").is_err());
+        assert!(expand_variables(r"%SystemRoot\icon.ico").is_err());
+        assert_eq!(drive_letter(r"d:\Icons"), Some('D'));
+        assert_eq!(drive_letter(r"Icons\d.ico"), None);
+    }
+
+    #[test]
+    fn parent_components_stop_at_the_drive_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let drive = dir.path().join("drive");
+        fs::create_dir_all(drive.join("Icons")).unwrap();
+        fs::write(drive.join("Icons/a.ico"), b"").unwrap();
+        fs::write(dir.path().join("outside.ico"), b"").unwrap();
+        let folder = drive.join("Icons");
+
+        let importer = importer().with_drive('C', &drive);
+        assert_eq!(
+            importer.resolve(&folder, r"C:\..\..\icons\A.ICO").unwrap(),
+            drive.join("Icons/a.ico")
+        );
+        assert!(importer.resolve(&folder, r"C:\..\outside.ico").is_err());
+        // Relative paths may leave the folder.
+        assert_eq!(
+            importer.resolve(&folder, r"..\..\outside.ico").unwrap(),
+            dir.path().join("outside.ico")
+        );
+    }
+
+    #[test]
+    fn imports_icons_written_by_the_desktop_ini_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("Projects");
+        fs::create_dir(&folder).unwrap();
+        let icon_set = IconSet {
+            images: WindowsIconSize::all()
+                .map(|size| IconImage {
+                    data: DynamicImage::new_rgba8(size.dimension(), size.dimension()),
+                })
+                .collect(),
+            svg: None,
+        };
+        DesktopIniFolderSettingsProvider::new()
+            .with_dos_attribute_store(crate::folder_settings::DosAttributeStore::None)
+            .set_icon_for_folder(&folder, &icon_set)
+            .unwrap();
+
+        let importer = importer();
+        let report = importer.import(&folder).unwrap();
+        assert_eq!(report.imported, [folder.as_path()]);
+        let imported = importer.provider.icon_for_path_linux(&folder).unwrap();
+        assert!(imported.is_some());
+    }
+
+    #[test]
+    fn resolves_icon_groups_on_mapped_drives_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        let drive = dir.path().join("windows");
+        fs::create_dir_all(drive.join("Windows/System32")).unwrap();
+        fs::write(
+            drive.join("Windows/System32/SHELL32.dll"),
+            pe(&fixture_resources(), false),
+        )
+        .unwrap();
+
+        let root = dir.path().join("copied");
+        desktop_ini(&root, r"IconResource=%SystemRoot%\system32\shell32.dll,-1");
+        desktop_ini(
+            &root.join("b"),
+            "IconFile=C:\\Windows\\System32\\shell32.dll\r\nIconIndex=7",
+        );
+        desktop_ini(&root.join("a/nested"), r"IconResource=D:\Icons\a.ico,0");
+        fs::create_dir(root.join("plain")).unwrap();
+
+        let report = importer()
+            .with_drive('c', &drive)
+            .with_recursion(true)
+            .import(&root)
+            .unwrap();
+        assert_eq!(report.imported, [root.as_path()]);
+        assert!(report.failed.is_empty());
+        let unresolved: Vec<_> = report
+            .unresolved
+            .iter()
+            .map(|skipped| {
+                (
+                    skipped.folder.clone(),
+                    skipped.location.as_ref().unwrap().index,
+                )
+            })
+            .collect();
+        assert_eq!(
+            unresolved,
+            [(root.join("a/nested"), 0), (root.join("b"), 7)]
+        );
+        let error = report.unresolved[0].error.to_string();
+        assert!(error.contains("drive D: is not mapped"), "{error}");
+
+        let report = importer().import(root.join("b")).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.unresolved.len(), 1);
+    }
+}
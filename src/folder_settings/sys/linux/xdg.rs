@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+/// `$XDG_DATA_HOME`, defaulting to `~/.local/share` when it is unset or not
+/// absolute, as the XDG Base Directory Specification requires.
+pub(crate) fn data_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+}
+
+/// `$XDG_DATA_DIRS`, defaulting to `/usr/local/share:/usr/share` when it is
+/// unset or empty. Relative entries are ignored.
+pub(crate) fn data_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    std::env::split_paths(&dirs)
+        .filter(|p| p.is_absolute())
+        .collect()
+}
+
+/// `$XDG_CONFIG_HOME`, defaulting to `~/.config` under the same rules.
+pub(crate) fn config_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+}
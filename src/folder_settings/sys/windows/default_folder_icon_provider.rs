@@ -20,6 +20,7 @@ use windows::core::{HSTRING, PCWSTR};
 
 use super::WindowsFolderSettingsError;
 use crate::folder_settings::DefaultFolderIconProvider;
+use crate::folder_settings::icon_library::{IconGroupEntry, parse_group_directory};
 use crate::icon::sys::windows::{WindowsIconImage, WindowsIconSet, WindowsIconSize};
 
 /// Rust implementation of the Win32 `MAKEINTRESOURCEW` macro: packs a numeric
@@ -35,29 +36,6 @@ const SHELL_32_DLL: &str = "shell32.dll";
 // The default folder icon resource id in shell32.dll.
 const FOLDER_ICON_RESOURCE_ID: i32 = 4;
 
-// Struct definitions for parsing group icon directory resources in the
-// Win32 API.
-// See https://devblogs.microsoft.com/oldnewthing/20120720-00/?p=7083
-#[repr(C, packed(2))]
-struct GrpIconDirEntry {
-    b_width: u8,          // Width, in pixels, of the image
-    b_height: u8,         // Height, in pixels, of the image
-    b_color_count: u8,    // Number of colors in image (0 if >=8bpp)
-    b_reserved: u8,       // Reserved
-    w_planes: u16,        // Color Planes
-    w_bit_count: u16,     // Bits per pixel
-    dw_bytes_in_res: u32, // How many bytes in this resource?
-    n_id: u16,            // The ID
-}
-
-#[repr(C, packed(2))]
-struct GrpIconDir {
-    id_reserved: u16,                 // Reserved (must be 0)
-    id_type: u16,                     // Resource type (1 for icons)
-    id_count: u16,                    // How many images?
-    id_entries: [GrpIconDirEntry; 1], // Array of entries for each image
-}
-
 pub trait WindowsDefaultFolderIconProviderExt {
     /// Dump the default folder icon
     fn dump_default_folder_icon_windows(
@@ -165,7 +143,7 @@ fn load_icon_group<'a>(
     let mut icons: Vec<WindowsIconImage> = Vec::with_capacity(icon_directory.len());
 
     for entry in icon_directory {
-        let icon = load_specific_icon(module, entry.n_id)?;
+        let icon = load_specific_icon(module, entry.icon_id)?;
         let (dimension, image) = icon_to_rgba_image(dc.handle(), &icon)?;
 
         let size = WindowsIconSize::from_dimension(dimension).ok_or_else(|| {
@@ -274,10 +252,10 @@ fn swap_bgra_to_rgba(pixels: &mut [u8]) {
 }
 
 /// Returns metadata for each size contained in an icon group resource.
-fn get_icon_directory<'a>(
+fn get_icon_directory(
     module: HMODULE,
     group_resource: PCWSTR,
-) -> Result<Vec<&'a GrpIconDirEntry>, WindowsFolderSettingsError> {
+) -> Result<Vec<IconGroupEntry>, WindowsFolderSettingsError> {
     // Find and load the icon group resource data.
     let h_rsrc = {
         let mut h_rsrc = unsafe { FindResourceW(Some(module), group_resource, RT_GROUP_ICON) };
@@ -296,22 +274,12 @@ fn get_icon_directory<'a>(
             .ok_or_else(windows::core::Error::from_thread)?;
         raw_res_ptr
     };
+    let size = unsafe { SizeofResource(Some(module), h_rsrc) } as usize;
 
-    // Reinterpret the raw resource bytes as a parseable group icon directory.
-    let grp_icon_dir = unsafe { &*(raw_res_ptr as *const GrpIconDir) };
-
-    // The entries are a flexible array member, so walk them with pointer
-    // arithmetic starting from the first entry.
-    let first_entry_ptr = grp_icon_dir.id_entries.as_ptr();
-    let count = grp_icon_dir.id_count as usize;
-
-    let mut icon_dir = Vec::with_capacity(count);
-    for i in 0..count {
-        let entry_ptr = unsafe { first_entry_ptr.add(i) };
-        icon_dir.push(unsafe { &*entry_ptr });
-    }
-
-    Ok(icon_dir)
+    // The directory is parsed with the same bounds-checked code as icon
+    // libraries read from disk.
+    let data = unsafe { slice::from_raw_parts(raw_res_ptr as *const u8, size) };
+    parse_group_directory(data).map_err(WindowsFolderSettingsError::ProviderError)
 }
 
 /// Loads a single icon image resource by ID and wraps it in an owning handle.
@@ -5,10 +5,14 @@ use std::{
 };
 
 use super::WindowsFolderSettingsError;
+use crate::folder_settings::desktop_ini::{
+    DISPLAY_NAME_KEY, TOOLTIP_KEY, read_shell_class_info, write_shell_class_info,
+};
 use crate::folder_settings::error::Result;
+use crate::folder_settings::ico::{is_generated_icon, to_ico_frames};
 use crate::{
-    folder_settings::FolderSettingsProvider,
-    icon::sys::windows::{WindowsIconSet, WindowsIconSize},
+    folder_settings::{FolderMetadata, FolderSettingsProvider},
+    icon::sys::windows::WindowsIconSet,
 };
 
 use image::codecs::ico::{IcoEncoder, IcoFrame};
@@ -18,7 +22,8 @@ use windows::Win32::{
     System::Com::{CLSCTX_ALL, COINIT_APARTMENTTHREADED, CoInitializeEx},
     UI::Shell::{
         FCS_FORCEWRITE, FCSM_ICONFILE, FFFP_EXACTMATCH, IKnownFolderManager, KnownFolderManager,
-        SHFOLDERCUSTOMSETTINGS, SHGetSetFolderCustomSettings,
+        SHCNE_UPDATEDIR, SHCNF_PATHW, SHChangeNotify, SHFOLDERCUSTOMSETTINGS,
+        SHGetSetFolderCustomSettings,
     },
 };
 use windows::core::{HSTRING, PWSTR};
@@ -26,7 +31,8 @@ use windows::core::{HSTRING, PWSTR};
 use windows::Win32::System::Com::CoCreateInstance;
 
 use windows::Win32::Storage::FileSystem::{
-    FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM, GetFileAttributesW, SetFileAttributesW,
+    FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM,
+    GetFileAttributesW, SetFileAttributesW,
 };
 
 const DEFAULT_GENERATED_ICON_PREFIX: &str = env!("CARGO_PKG_NAME");
@@ -81,6 +87,27 @@ impl FolderSettingsProvider for WindowsFolderSettingsProvider {
     }
 }
 
+/// Display names and tooltips are written to the folder's `desktop.ini`,
+/// which is then given the Hidden and System attributes and the folder the
+/// ReadOnly attribute so Explorer reads it.
+impl FolderMetadata for WindowsFolderSettingsProvider {
+    fn display_name<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), DISPLAY_NAME_KEY)
+    }
+
+    fn set_display_name<P: AsRef<Path>>(&self, path: P, name: Option<&str>) -> Result<()> {
+        self.write_shell_class_info_windows(path, DISPLAY_NAME_KEY, name)
+    }
+
+    fn tooltip<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        read_shell_class_info(path.as_ref(), TOOLTIP_KEY)
+    }
+
+    fn set_tooltip<P: AsRef<Path>>(&self, path: P, tooltip: Option<&str>) -> Result<()> {
+        self.write_shell_class_info_windows(path, TOOLTIP_KEY, tooltip)
+    }
+}
+
 impl WindowsFolderSettingsProviderExt for WindowsFolderSettingsProvider {
     fn new_windows(block_known_folders: bool, generated_icon_prefix: Option<&str>) -> Self {
         let com_known_folder_manager = block_known_folders.then(|| {
@@ -178,6 +205,60 @@ impl WindowsFolderSettingsProvider {
         Ok(())
     }
 
+    /// Set or remove a `[.ShellClassInfo]` key of the folder's `desktop.ini`
+    /// and apply the attributes Explorer requires.
+    fn write_shell_class_info_windows<P: AsRef<Path>>(
+        &self,
+        directory: P,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<()> {
+        self.validate_folder(&directory)?;
+        let ini_path = directory.as_ref().join("desktop.ini");
+
+        // Windows refuses to truncate a hidden or system file unless the
+        // attributes are passed back in, so clear them before rewriting.
+        let previous_attribs = ini_path
+            .exists()
+            .then(|| get_file_attributes(&ini_path))
+            .transpose()?;
+        if previous_attribs.is_some() {
+            set_file_attributes(&ini_path, FILE_ATTRIBUTE_NORMAL)?;
+        }
+
+        if let Err(e) = write_shell_class_info(directory.as_ref(), key, value) {
+            if let Some(attribs) = previous_attribs {
+                let _ = set_file_attributes(&ini_path, attribs);
+            }
+            return Err(e);
+        }
+
+        if ini_path.exists() {
+            set_file_attributes(
+                &ini_path,
+                previous_attribs
+                    .filter(|&attribs| attribs != FILE_ATTRIBUTE_NORMAL)
+                    .unwrap_or_default()
+                    | FILE_ATTRIBUTE_HIDDEN
+                    | FILE_ATTRIBUTE_SYSTEM,
+            )?;
+            let folder_attribs = get_file_attributes(&directory)?;
+            set_file_attributes(&directory, folder_attribs | FILE_ATTRIBUTE_READONLY)?;
+        }
+
+        let directory_hstr = HSTRING::from(directory.as_ref());
+        unsafe {
+            SHChangeNotify(
+                SHCNE_UPDATEDIR,
+                SHCNF_PATHW,
+                Some(directory_hstr.as_ptr() as *const _),
+                None,
+            )
+        };
+
+        Ok(())
+    }
+
     /// Find and remove any existing generated .ico files in the provided directory.
     fn remove_existing_generated_ico<P: AsRef<Path>>(
         &self,
@@ -220,19 +301,6 @@ impl WindowsFolderSettingsProvider {
     }
 }
 
-/// Returns whether `file_name` names one of this crate's generated icon files:
-/// a `.ico` whose name begins with the configured prefix.
-///
-/// Kept free of filesystem access so the match logic can be unit-tested in
-/// isolation, mirroring the pure helpers used by the other platform backends.
-fn is_generated_icon(file_name: &str, prefix: &str) -> bool {
-    Path::new(file_name)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        == Some("ico")
-        && file_name.starts_with(prefix)
-}
-
 /// 1. Encode and write the provided icon set to a .ico file at the provided path.
 /// 2. Write shell attributes hiding the .ico in Explorer
 fn encode_to_system<P: AsRef<Path>>(icon_set: &WindowsIconSet, ico_path: P) -> Result<()> {
@@ -280,25 +348,27 @@ fn encode_to_system<P: AsRef<Path>>(icon_set: &WindowsIconSet, ico_path: P) -> R
     Ok(())
 }
 
-/// Convert RGBA bitmaps to individual .ico sizes
-fn to_ico_frames<'a>(
-    windows_icon_set: &'a WindowsIconSet<'a>,
-) -> core::result::Result<Vec<IcoFrame<'a>>, image::error::ImageError> {
-    windows_icon_set.iter().try_fold(
-        Vec::with_capacity(WindowsIconSize::NUM_SIZES),
-        |mut ico_frames, (res, img)| {
-            let dim = res.dimension();
-            let ico_frame = IcoFrame::as_png(
-                img.image.as_bytes(),
-                dim,
-                dim,
-                image::ExtendedColorType::Rgba8,
-            )?;
+fn get_file_attributes<P: AsRef<Path>>(path: P) -> Result<FILE_FLAGS_AND_ATTRIBUTES> {
+    let attribs = unsafe { GetFileAttributesW(&HSTRING::from(path.as_ref())) };
+    if attribs == INVALID_FILE_ATTRIBUTES {
+        let e = windows::core::Error::from_thread();
+        return Err(WindowsFolderSettingsError::IconOperation(
+            path.as_ref().to_path_buf(),
+            format!("Failed to get file attributes: {}", e.message()),
+        )
+        .into());
+    }
+    Ok(FILE_FLAGS_AND_ATTRIBUTES(attribs))
+}
 
-            ico_frames.push(ico_frame);
-            Ok(ico_frames)
-        },
-    )
+fn set_file_attributes<P: AsRef<Path>>(path: P, attribs: FILE_FLAGS_AND_ATTRIBUTES) -> Result<()> {
+    unsafe { SetFileAttributesW(&HSTRING::from(path.as_ref()), attribs) }.map_err(|e| {
+        WindowsFolderSettingsError::IconOperation(
+            path.as_ref().to_path_buf(),
+            format!("Failed to set file attributes: {}", e.message()),
+        )
+    })?;
+    Ok(())
 }
 
 // Write IcoFrames to an .ico file
@@ -375,8 +445,6 @@ fn clear_folder_icon_settings<P: AsRef<Path>>(directory: P) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::icon::sys::windows::{WindowsIconImage, WindowsIconSize};
-    use std::borrow::Cow;
 
     /// Builds a provider that does not initialize COM, keeping unit tests free
     /// of side effects. With `block_known_folders` disabled no COM apartment is
@@ -385,17 +453,6 @@ mod tests {
         WindowsFolderSettingsProvider::new_windows(false, Some(prefix))
     }
 
-    fn sample_icon_set() -> WindowsIconSet<'static> {
-        let icons = WindowsIconSize::all().map(|size| {
-            let dim = size.dimension();
-            WindowsIconImage {
-                size,
-                image: Cow::Owned(image::DynamicImage::new_rgba8(dim, dim)),
-            }
-        });
-        WindowsIconSet::from_icons(icons).unwrap()
-    }
-
     #[test]
     fn default_prefix_is_crate_name() {
         let p = WindowsFolderSettingsProvider::new_windows(false, None);
@@ -426,31 +483,4 @@ mod tests {
         let name = p.generate_unique_ico_file_name();
         assert!(is_generated_icon(&name, "gen"));
     }
-
-    #[test]
-    fn is_generated_icon_matches_prefixed_ico() {
-        assert!(is_generated_icon("icon-sys-1234.ico", "icon-sys"));
-    }
-
-    #[test]
-    fn is_generated_icon_rejects_wrong_extension() {
-        assert!(!is_generated_icon("icon-sys-1234.png", "icon-sys"));
-    }
-
-    #[test]
-    fn is_generated_icon_rejects_missing_extension() {
-        assert!(!is_generated_icon("icon-sys-1234", "icon-sys"));
-    }
-
-    #[test]
-    fn is_generated_icon_rejects_wrong_prefix() {
-        assert!(!is_generated_icon("other-1234.ico", "icon-sys"));
-    }
-
-    #[test]
-    fn to_ico_frames_produces_one_frame_per_size() {
-        let set = sample_icon_set();
-        let frames = to_ico_frames(&set).unwrap();
-        assert_eq!(frames.len(), WindowsIconSize::NUM_SIZES);
-    }
 }
@@ -0,0 +1,315 @@
+use std::ops::Range;
+
+/// An element of a parsed XML document.
+///
+/// Byte spans into the source are kept so callers can edit a document in
+/// place, replacing only the elements they change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Element {
+    /// The qualified name as written, e.g. `bookmark:icon`.
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Node>,
+    /// The whole element, from `<` of the start tag to `>` of the end tag.
+    pub(crate) span: Range<usize>,
+    /// Everything between the start and end tags. Empty, at the end of the
+    /// start tag, for `<empty/>` elements.
+    pub(crate) content: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    Element(Element),
+    /// Character data with entities resolved, including CDATA sections.
+    Text(String),
+}
+
+impl Element {
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Child elements in document order.
+    pub(crate) fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// The first child element called `name`.
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// Child elements called `name`.
+    pub(crate) fn elements_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.elements().filter(move |e| e.name == name)
+    }
+}
+
+/// Parse a document and return its root element. Declarations, processing
+/// instructions, comments and the DOCTYPE are skipped; namespaces are not
+/// resolved.
+pub(crate) fn parse(input: &str) -> Result<Element, String> {
+    let bytes = input.as_bytes();
+    let mut pos = 0;
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    while pos < bytes.len() {
+        if bytes[pos] != b'<' {
+            let end = input[pos..].find('<').map_or(bytes.len(), |i| pos + i);
+            let text = &input[pos..end];
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(Node::Text(unescape(text)?)),
+                None if !text.trim().is_empty() => {
+                    return Err(format!("text outside the root element at byte {pos}"));
+                }
+                None => {}
+            }
+            pos = end;
+            continue;
+        }
+
+        let rest = &input[pos..];
+        if let Some(body) = rest.strip_prefix("<!--") {
+            pos += 4 + body.find("-->").ok_or("unterminated comment")? + 3;
+        } else if let Some(body) = rest.strip_prefix("<![CDATA[") {
+            let len = body.find("]]>").ok_or("unterminated CDATA section")?;
+            let parent = stack.last_mut().ok_or("CDATA outside the root element")?;
+            parent.children.push(Node::Text(body[..len].to_string()));
+            pos += 9 + len + 3;
+        } else if rest.starts_with("<?") {
+            pos += rest
+                .find("?>")
+                .ok_or("unterminated processing instruction")?
+                + 2;
+        } else if rest.starts_with("<!") {
+            pos += doctype_len(rest)?;
+        } else if let Some(body) = rest.strip_prefix("</") {
+            let len = body.find('>').ok_or("unterminated end tag")?;
+            let name = body[..len].trim();
+            let mut element = stack.pop().ok_or("end tag without start tag")?;
+            if element.name != name {
+                return Err(format!(
+                    "</{name}> does not close <{}> at byte {pos}",
+                    element.name
+                ));
+            }
+            element.content.end = pos;
+            pos += 2 + len + 1;
+            element.span.end = pos;
+            close(element, &mut stack, &mut root)?;
+        } else {
+            let (mut element, len, empty) = parse_start_tag(rest)?;
+            element.span = pos..pos;
+            pos += len;
+            element.content = pos..pos;
+            if empty {
+                element.span.end = pos;
+                close(element, &mut stack, &mut root)?;
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+
+    if let Some(open) = stack.last() {
+        return Err(format!("<{}> is never closed", open.name));
+    }
+    root.ok_or_else(|| "document has no root element".to_string())
+}
+
+/// Attach a finished element to its parent, or make it the root.
+fn close(
+    element: Element,
+    stack: &mut [Element],
+    root: &mut Option<Element>,
+) -> Result<(), String> {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(Node::Element(element)),
+        None if root.is_none() => *root = Some(element),
+        None => return Err("more than one root element".to_string()),
+    }
+    Ok(())
+}
+
+/// Length of a `<!DOCTYPE ...>` declaration, including an internal subset.
+fn doctype_len(rest: &str) -> Result<usize, String> {
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            '>' if depth == 0 => return Ok(i + 1),
+            _ => {}
+        }
+    }
+    Err("unterminated declaration".to_string())
+}
+
+/// Parse `<name attr="value" ...>` or `<name .../>`, returning the element,
+/// the tag's length and whether it is empty.
+fn parse_start_tag(tag: &str) -> Result<(Element, usize, bool), String> {
+    let body = &tag[1..];
+    let name_len = body
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .ok_or("unterminated start tag")?;
+    let name = body[..name_len].to_string();
+    if name.is_empty() {
+        return Err("start tag without a name".to_string());
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = &body[name_len..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            let len = tag.len() - after.len();
+            return Ok((element(name, attributes), len, true));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            let len = tag.len() - after.len();
+            return Ok((element(name, attributes), len, false));
+        }
+        let eq = rest.find('=').ok_or("attribute without a value")?;
+        let attr_name = rest[..eq].trim().to_string();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or("unquoted attribute value")?;
+        let close = value[1..]
+            .find(quote)
+            .ok_or("unterminated attribute value")?;
+        attributes.push((attr_name, unescape(&value[1..1 + close])?));
+        rest = &value[1 + close + 1..];
+    }
+}
+
+fn element(name: String, attributes: Vec<(String, String)>) -> Element {
+    Element {
+        name,
+        attributes,
+        children: Vec::new(),
+        span: 0..0,
+        content: 0..0,
+    }
+}
+
+/// Resolve the predefined entities and character references.
+fn unescape(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let semi = rest[amp..]
+            .find(';')
+            .ok_or("unterminated entity reference")?;
+        let entity = &rest[amp + 1..amp + semi];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("unknown entity &{entity};"))?,
+        };
+        out.push(c);
+        rest = &rest[amp + semi + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Escape text for use in character data or a double-quoted attribute.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "<?xml version=\"1.0\"?>\n\
+        <!DOCTYPE xbel [<!ENTITY x \"y\">]>\n\
+        <!-- comment -->\n\
+        <root a='1' b=\"x &amp; y\">\n  \
+          <item name=\"one\"/>\n  \
+          <item name=\"two\">Text &lt;here&gt; &#65;&#x42;<![CDATA[<raw>]]></item>\n\
+        </root>\n";
+
+    #[test]
+    fn parses_elements_attributes_and_text() {
+        let root = parse(DOC).unwrap();
+        assert_eq!(root.name, "root");
+        assert_eq!(root.attr("a"), Some("1"));
+        assert_eq!(root.attr("b"), Some("x & y"));
+        let items: Vec<_> = root.elements_named("item").collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].attr("name"), Some("two"));
+        assert_eq!(
+            items[1].children,
+            [
+                Node::Text("Text <here> AB".to_string()),
+                Node::Text("<raw>".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_cover_the_source() {
+        let root = parse(DOC).unwrap();
+        assert!(DOC[root.span.clone()].starts_with("<root a="));
+        assert!(DOC[root.span.clone()].ends_with("</root>"));
+        let items: Vec<_> = root.elements_named("item").collect();
+        assert_eq!(&DOC[items[0].span.clone()], "<item name=\"one\"/>");
+        assert!(items[0].content.is_empty());
+        assert_eq!(
+            &DOC[items[1].content.clone()],
+            "Text &lt;here&gt; &#65;&#x42;<![CDATA[<raw>]]>"
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse("<a x=1/>").is_err());
+        assert!(parse("<a>&bogus;</a>").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        let text = "a < b & \"c\" > d";
+        let root = parse(&format!("<r v=\"{0}\">{0}</r>", escape(text))).unwrap();
+        assert_eq!(root.attr("v"), Some(text));
+        assert_eq!(root.children, [Node::Text(text.to_string())]);
+    }
+}
@@ -1,5 +1,5 @@
 pub mod sys {
-    #[cfg(target_os = "windows")]
+    // Plain image data, also filled from icon libraries on other platforms.
     pub mod windows;
 
     #[cfg(target_os = "macos")]
@@ -0,0 +1,132 @@
+#!/usr/bin/env python3
+"""Generate the dconf (GVDB) fixtures used by the reader tests.
+
+Writes little-endian GVDB files laid out like glib's gvdb-builder.c output
+for dconf: every key has a chain of parent directory items ("/", "/org/",
+...) and system databases keep locked keys in a nested ".locks" table.
+Run from this directory.
+"""
+import struct
+
+INTERFACE = "/org/gnome/desktop/interface/"
+
+DATABASES = {
+    # ~/.config/dconf/user
+    "user": ({INTERFACE + "icon-theme": "Papirus", INTERFACE + "gtk-theme": "Adwaita-dark"}, []),
+    # /etc/dconf/db/defaults: site defaults, overridable by the user.
+    "defaults": ({INTERFACE + "icon-theme": "Yaru", INTERFACE + "cursor-theme": "DMZ"}, []),
+    # /etc/dconf/db/locked: a mandatory value.
+    "locked": ({INTERFACE + "icon-theme": "Corporate"}, [INTERFACE + "icon-theme"]),
+}
+
+
+def djb_hash(key):
+    h = 5381
+    for c in key.encode():
+        if c > 127:
+            c -= 256
+        h = (h * 33 + c) & 0xFFFFFFFF
+    return h
+
+
+def string_variant(s):
+    # A serialized "v": the child's data, a NUL, then its type string.
+    return s.encode() + b"\0" + b"\0" + b"s"
+
+
+class Builder:
+    def __init__(self):
+        self.buf = bytearray(24)
+
+    def alloc(self, size, align):
+        while len(self.buf) % align:
+            self.buf.append(0)
+        start = len(self.buf)
+        self.buf += bytes(size)
+        return start, start + size
+
+    def put(self, data, align):
+        start, end = self.alloc(len(data), align)
+        self.buf[start:end] = data
+        return start, end
+
+    def table(self, items):
+        """items: list of (full_key, relative_key, parent_full_key, type, value)."""
+        n_buckets = max(len(items), 1)
+        ordered = sorted(items, key=lambda item: djb_hash(item[0]) % n_buckets)
+        index = {item[0]: i for i, item in enumerate(ordered)}
+
+        size = 8 + 4 * n_buckets + 24 * len(ordered)
+        start, end = self.alloc(size, 4)
+        struct.pack_into("<II", self.buf, start, 0, n_buckets)
+        buckets = [len(ordered)] * n_buckets
+        for i, item in reversed(list(enumerate(ordered))):
+            buckets[djb_hash(item[0]) % n_buckets] = i
+        # Empty buckets point at the next bucket's first item.
+        for b in range(n_buckets - 2, -1, -1):
+            if buckets[b] == len(ordered):
+                buckets[b] = buckets[b + 1]
+        struct.pack_into(f"<{n_buckets}I", self.buf, start + 8, *buckets)
+
+        items_start = start + 8 + 4 * n_buckets
+        for i, (full, rel, parent, kind, value) in enumerate(ordered):
+            key_start, _ = self.put(rel.encode(), 1)
+            if kind == "v":
+                value_ptr = self.put(string_variant(value), 8)
+            elif kind == "L":
+                value_ptr = self.put(struct.pack(f"<{len(value)}I", *(index[c] for c in value)), 4)
+            else:  # "H"
+                value_ptr = self.table(value)
+            struct.pack_into(
+                "<IIIHccII", self.buf, items_start + 24 * i,
+                djb_hash(full),
+                index[parent] if parent is not None else 0xFFFFFFFF,
+                key_start, len(rel.encode()), kind.encode(), b"\0", *value_ptr,
+            )
+        return start, end
+
+    def finish(self, root):
+        self.buf[0:8] = b"GVariant"
+        struct.pack_into("<IIII", self.buf, 8, 0, 0, *root)
+        return bytes(self.buf)
+
+
+def dconf_items(values):
+    items = {}
+    children = {}
+
+    def add_dir(path):
+        if path in items:
+            return
+        if path == "/":
+            items[path] = [path, path, None, "L", None]
+        else:
+            parent = path[: path.rstrip("/").rindex("/") + 1]
+            add_dir(parent)
+            items[path] = [path, path[len(parent):], parent, "L", None]
+            children.setdefault(parent, []).append(path)
+
+    for key, value in values.items():
+        parent = key[: key.rindex("/") + 1]
+        add_dir(parent)
+        items[key] = [key, key[len(parent):], parent, "v", value]
+        children.setdefault(parent, []).append(key)
+    for path, item in items.items():
+        if item[3] == "L":
+            item[4] = children.get(path, [])
+    return [tuple(item) for item in items.values()]
+
+
+def build(values, locks):
+    b = Builder()
+    items = dconf_items(values)
+    if locks:
+        lock_items = [(key, key, None, "v", "") for key in locks]
+        items.append((".locks", ".locks", None, "H", lock_items))
+    return b.finish(b.table(items))
+
+
+if __name__ == "__main__":
+    for name, (values, locks) in DATABASES.items():
+        with open(name, "wb") as f:
+            f.write(build(values, locks))
@@ -0,0 +1,166 @@
+#!/usr/bin/env python3
+"""Generate the GVFS metadata fixtures used by the reader tests.
+
+Writes a `home` tree file and its `home-<tag>.log` journal in the layout of
+gvfs' metadata/metatree.c (all integers big-endian). Run from this directory.
+"""
+import struct
+import zlib
+
+RANDOM_TAG = 0x1CEB00DA
+TIME_T_BASE = 1_700_000_000
+
+ATTRIBUTES = ["custom-icon", "emblems", "nautilus-icon-view-sort-by"]
+KEY_IS_LIST = 0x80000000
+
+# path -> {key: str | list[str]}
+TREE = {
+    "/Documents": {"nautilus-icon-view-sort-by": "name"},
+    "/Music": {"emblems": ["emblem-favorite"]},
+    "/Projects": {"custom-icon": "file:///home/ada/Projects/icon-sys-1.png"},
+    "/Projects/old": {
+        "custom-icon": "file:///home/ada/Projects/old/icon.png",
+        "emblems": ["emblem-important", "emblem-favorite"],
+    },
+    "/Removed": {"custom-icon": "file:///home/ada/Removed/icon.png"},
+    "/Removed/child": {"custom-icon": "file:///home/ada/Removed/child/icon.png"},
+}
+
+
+class Writer:
+    def __init__(self):
+        self.buf = bytearray()
+
+    def u32(self, v):
+        self.buf += struct.pack(">I", v)
+
+    def patch(self, offset, v):
+        self.buf[offset:offset + 4] = struct.pack(">I", v)
+
+    def string(self, s):
+        offset = len(self.buf)
+        self.buf += s.encode() + b"\0"
+        self.align()
+        return offset
+
+    def align(self):
+        while len(self.buf) % 4:
+            self.buf.append(0)
+
+
+def build_tree():
+    root = {"children": {}, "data": {}}
+    for path, data in TREE.items():
+        node = root
+        for part in path.strip("/").split("/"):
+            node = node["children"].setdefault(part, {"children": {}, "data": {}})
+        node["data"] = data
+
+    w = Writer()
+    w.buf += b"\xda\x1ameta" + bytes([1, 0])
+    w.u32(0)  # rotated
+    w.u32(RANDOM_TAG)
+    root_slot = len(w.buf)
+    w.u32(0)  # root
+    attributes_slot = len(w.buf)
+    w.u32(0)  # attributes
+    w.buf += struct.pack(">Q", TIME_T_BASE)
+
+    attr_offsets = [w.string(a) for a in ATTRIBUTES]
+    w.patch(attributes_slot, len(w.buf))
+    w.u32(len(ATTRIBUTES))
+    for offset in attr_offsets:
+        w.u32(offset)
+
+    def write_data(data):
+        if not data:
+            return 0
+        values = []
+        for key in sorted(data, key=ATTRIBUTES.index):
+            value = data[key]
+            index = ATTRIBUTES.index(key)
+            if isinstance(value, list):
+                strings = [w.string(s) for s in value]
+                offset = len(w.buf)
+                w.u32(len(strings))
+                for s in strings:
+                    w.u32(s)
+                values.append((index | KEY_IS_LIST, offset))
+            else:
+                values.append((index, w.string(value)))
+        offset = len(w.buf)
+        w.u32(len(values))
+        for key, value in values:
+            w.u32(key)
+            w.u32(value)
+        return offset
+
+    def write_dirent_fields(slot, name, node):
+        w.patch(slot, w.string(name))
+        w.patch(slot + 8, write_data(node["data"]))
+        w.patch(slot + 12, 42)  # last_changed, relative to time_t_base
+        if node["children"]:
+            names = sorted(node["children"])
+            dir_offset = len(w.buf)
+            w.u32(len(names))
+            slots = []
+            for _ in names:
+                slots.append(len(w.buf))
+                w.buf += bytes(16)
+            w.patch(slot + 4, dir_offset)
+            for child_slot, child in zip(slots, names):
+                write_dirent_fields(child_slot, child, node["children"][child])
+
+    root_offset = len(w.buf)
+    w.buf += bytes(16)
+    w.patch(root_slot, root_offset)
+    write_dirent_fields(root_offset, "/", root)
+    return bytes(w.buf)
+
+
+SET, SETV, UNSET, COPY, REMOVE = range(5)
+
+
+def entry(op, path, *fields, strings=None, corrupt=False):
+    out = bytearray(8)
+    out += struct.pack(">Q", TIME_T_BASE + 100)
+    out.append(op)
+    for s in (path, *fields):
+        out += s.encode() + b"\0"
+    if strings is not None:
+        while len(out) % 4:
+            out.append(0)
+        out += struct.pack(">I", len(strings))
+        for s in strings:
+            out += s.encode() + b"\0"
+    size = len(out) + 4
+    out += struct.pack(">I", size)
+    out[0:4] = struct.pack(">I", size)
+    crc = zlib.crc32(bytes(out[8:]))
+    out[4:8] = struct.pack(">I", crc ^ (1 if corrupt else 0))
+    return bytes(out)
+
+
+def build_journal():
+    entries = [
+        entry(SET, "/Documents", "custom-icon", "file:///home/ada/Documents/.icon.png"),
+        entry(SETV, "/Music", "emblems", strings=["emblem-shared"]),
+        entry(UNSET, "/Projects/old", "custom-icon"),
+        entry(REMOVE, "/Removed"),
+        entry(COPY, "/Projects-copy", "/Projects"),
+        # A torn write: replay must stop here.
+        entry(SET, "/Bad", "custom-icon", "file:///bad.png", corrupt=True),
+        entry(SET, "/AfterBad", "custom-icon", "file:///after-bad.png"),
+    ]
+    body = b"".join(entries)
+    header = b"\xda\x1ajour" + bytes([1, 0])
+    header += struct.pack(">III", RANDOM_TAG, 20 + len(body) + 64, len(entries))
+    # Journals are preallocated; unused space is zeroed.
+    return header + body + bytes(64)
+
+
+if __name__ == "__main__":
+    with open("home", "wb") as f:
+        f.write(build_tree())
+    with open(f"home-{RANDOM_TAG:08x}.log", "wb") as f:
+        f.write(build_journal())
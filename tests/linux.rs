@@ -22,6 +22,33 @@ fn test_default_folder_icon_provider() {
     assert!(!icon_set.is_empty(), "Icon set should not be empty");
 }
 
+#[test]
+fn test_default_file_icon_provider() {
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxDefaultFileIconProvider, LinuxDefaultFileIconProviderExt,
+    };
+    use tempfile::tempdir;
+
+    if !std::path::Path::new("/usr/share/mime/globs2").exists() {
+        eprintln!("shared-mime-info unavailable, skipping");
+        return;
+    }
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let report = temp_dir.path().join("report.pdf");
+    std::fs::write(&report, b"%PDF-1.7\n").unwrap();
+
+    let provider = LinuxDefaultFileIconProvider::default();
+    assert_eq!(
+        provider.mime_info().mime_type_for_path(&report).unwrap(),
+        "application/pdf"
+    );
+    // Every theme falls back to at least a generic document icon.
+    let icon_set = provider
+        .dump_default_file_icon_linux(&report)
+        .expect("Failed to get default file icon");
+    assert!(!icon_set.is_empty(), "Icon set should not be empty");
+}
+
 #[test]
 fn test_set_folder_icon() {
     use icon_sys::folder_settings::sys::linux::{
@@ -152,3 +179,882 @@ fn test_reset_folder_icon() {
         .any(|e| e.path().extension().and_then(|x| x.to_str()) == Some("png"));
     assert!(!leftover_png, "Generated PNG should be cleaned up");
 }
+
+#[test]
+fn test_set_same_folder_icon_is_noop() {
+    use icon_sys::folder_settings::sys::linux::{
+        IconUpdate, LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let icon_set_of = |pixel: [u8; 4]| {
+        let img = image::RgbaImage::from_pixel(64, 64, image::Rgba(pixel));
+        LinuxIconSet::from_icons([LinuxIconImage {
+            size: 64,
+            image: Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+        }])
+        .expect("Failed to create LinuxIconSet")
+    };
+    let generated_files = |folder: &std::path::Path| {
+        let mut files: Vec<_> = std::fs::read_dir(folder)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().and_then(|x| x.to_str()) == Some("png"))
+            .collect();
+        files.sort();
+        files
+    };
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false);
+
+    let first = provider
+        .set_icon_for_folder_linux(folder_path, &icon_set_of([255, 0, 0, 255]))
+        .expect("Failed to set folder icon");
+    assert_eq!(first.update, IconUpdate::Applied);
+    let files_before = generated_files(folder_path);
+    let directory_before = std::fs::read_to_string(folder_path.join(".directory")).unwrap();
+
+    // Same pixels: nothing on disk should change.
+    let second = provider
+        .set_icon_for_folder_linux(folder_path, &icon_set_of([255, 0, 0, 255]))
+        .expect("Failed to set folder icon");
+    assert_eq!(second.update, IconUpdate::Unchanged);
+    assert_eq!(generated_files(folder_path), files_before);
+    assert_eq!(
+        std::fs::read_to_string(folder_path.join(".directory")).unwrap(),
+        directory_before
+    );
+
+    // Different pixels: the icon is rewritten under a new name.
+    let third = provider
+        .set_icon_for_folder_linux(folder_path, &icon_set_of([0, 0, 255, 255]))
+        .expect("Failed to set folder icon");
+    assert_eq!(third.update, IconUpdate::Applied);
+    assert_ne!(generated_files(folder_path), files_before);
+}
+
+#[test]
+fn test_reset_all_backends_clears_directory_file() {
+    use icon_sys::folder_settings::FolderSettingsError;
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxBackend, LinuxFolderSettingsError, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::new_rgba8(64, 64)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+
+    // Icon applied from a KDE session...
+    LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false)
+        .set_icon_for_folder_linux(folder_path, &icon_set)
+        .expect("Failed to set folder icon");
+    assert!(folder_path.join(".directory").exists());
+
+    // ...is cleared by a reset covering every mechanism. `gio` may be
+    // unavailable (or lack GVFS metadata support) in the test environment, but
+    // the `.directory` entry must be removed regardless.
+    let result = LinuxFolderSettingsProvider::new_linux(LinuxBackend::All, None, false)
+        .reset_icon_for_folder_linux(folder_path);
+    assert!(
+        matches!(
+            result,
+            Ok(_)
+                | Err(FolderSettingsError::Linux(
+                    LinuxFolderSettingsError::Gio(_) | LinuxFolderSettingsError::Gvfs(_)
+                ))
+        ),
+        "Unexpected reset result: {result:?}"
+    );
+    assert!(
+        !folder_path.join(".directory").exists(),
+        ".directory file should be removed on reset"
+    );
+}
+
+#[test]
+fn test_set_folder_icon_falls_back_to_directory_file() {
+    use icon_sys::folder_settings::sys::linux::{
+        IconUpdate, LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::new_rgba8(64, 64)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false)
+        .with_fallbacks([LinuxBackend::DirectoryFile]);
+    let outcome = provider
+        .set_icon_for_folder_linux(folder_path, &icon_set)
+        .expect("Fallback chain should succeed");
+    assert_eq!(outcome.update, IconUpdate::Applied);
+
+    // Whether GVFS metadata works depends on the environment; if it did not,
+    // the failure must be recorded and the `.directory` fallback used.
+    match outcome.backend {
+        LinuxBackend::GioMetadata => assert!(outcome.failed_attempts.is_empty()),
+        LinuxBackend::DirectoryFile => {
+            assert_eq!(outcome.failed_attempts.len(), 1);
+            assert_eq!(outcome.failed_attempts[0].0, LinuxBackend::GioMetadata);
+            assert!(folder_path.join(".directory").exists());
+        }
+        other => panic!("unexpected backend: {other:?}"),
+    }
+}
+
+#[test]
+fn test_protected_folders_are_refused() {
+    use icon_sys::folder_settings::FolderSettingsError;
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxBackend, LinuxFolderSettingsError, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt, ProtectedFolderKind,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::new_rgba8(64, 64)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false);
+
+    let result = provider.set_icon_for_folder_linux("/", &icon_set);
+    assert!(
+        matches!(
+            result,
+            Err(FolderSettingsError::Linux(
+                LinuxFolderSettingsError::ProtectedFolder(_, ProtectedFolderKind::SystemRoot)
+            ))
+        ),
+        "Unexpected result for /: {result:?}"
+    );
+
+    if let Some(home) = std::env::var_os("HOME").filter(|h| std::path::Path::new(h).is_dir()) {
+        let result = provider.set_icon_for_folder_linux(&home, &icon_set);
+        assert!(
+            matches!(
+                result,
+                Err(FolderSettingsError::Linux(
+                    LinuxFolderSettingsError::ProtectedFolder(..)
+                ))
+            ),
+            "Unexpected result for $HOME: {result:?}"
+        );
+    }
+}
+
+/// A private `dbus-daemon` session bus for the duration of a test.
+struct PrivateBus {
+    child: std::process::Child,
+    address: String,
+}
+
+impl PrivateBus {
+    /// Start a private bus, or `None` if `dbus-daemon` is unavailable.
+    fn start() -> Option<Self> {
+        use std::io::BufRead;
+
+        let mut child = std::process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        std::io::BufReader::new(child.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        let address = address.trim().to_string();
+        if address.is_empty() {
+            let _ = child.kill();
+            return None;
+        }
+        Some(Self { child, address })
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn test_set_folder_icon_notifies_file_managers_over_dbus() {
+    use icon_sys::folder_settings::sys::linux::{
+        FileManagerNotifier, LinuxBackend, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    let Some(bus) = PrivateBus::start() else {
+        eprintln!("dbus-daemon not available; skipping");
+        return;
+    };
+
+    // Listen for KDirNotify signals before triggering the change.
+    let listener = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .expect("Failed to connect to private bus");
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .interface("org.kde.KDirNotify")
+        .unwrap()
+        .member("FilesChanged")
+        .unwrap()
+        .build();
+    let mut messages =
+        zbus::blocking::MessageIterator::for_match_rule(rule, &listener, Some(8)).unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if let Some(Ok(msg)) = messages.next() {
+            let (uris,): (Vec<String>,) = msg.body().deserialize().unwrap();
+            let _ = tx.send(uris);
+        }
+    });
+
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::new_rgba8(64, 64)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+
+    // D-Bus notification only, without bumping the folder's mtime.
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false)
+        .with_file_manager_notifier(Some(FileManagerNotifier::with_address(&bus.address)));
+    provider
+        .set_icon_for_folder_linux(folder_path, &icon_set)
+        .expect("Failed to set folder icon");
+
+    let uris = rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("No FilesChanged signal received");
+    assert_eq!(uris, [format!("file://{}", folder_path.display())]);
+}
+
+#[test]
+fn test_set_folder_icon_invalidates_thumbnails() {
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt, ThumbnailCache,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let icon_set_of = |pixel: [u8; 4]| {
+        let img = image::RgbaImage::from_pixel(64, 64, image::Rgba(pixel));
+        LinuxIconSet::from_icons([LinuxIconImage {
+            size: 64,
+            image: Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+        }])
+        .expect("Failed to create LinuxIconSet")
+    };
+    // Plant a thumbnail for `path` as a file manager would.
+    let plant = |cache_root: &std::path::Path, path: &std::path::Path| {
+        let name = ThumbnailCache::thumbnail_name(&format!("file://{}", path.display()));
+        let thumb = cache_root.join("large").join(name);
+        std::fs::create_dir_all(thumb.parent().unwrap()).unwrap();
+        std::fs::write(&thumb, b"png").unwrap();
+        thumb
+    };
+
+    let cache_dir = tempdir().expect("Failed to create temp dir");
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false)
+        .with_thumbnail_cache(Some(ThumbnailCache::at(cache_dir.path())));
+
+    let folder_thumb = plant(cache_dir.path(), folder_path);
+    provider
+        .set_icon_for_folder_linux(folder_path, &icon_set_of([255, 0, 0, 255]))
+        .expect("Failed to set folder icon");
+    assert!(!folder_thumb.exists(), "Folder thumbnail should be removed");
+
+    // Replacing the icon also drops the thumbnail of the old generated file.
+    let old_icon = std::fs::read_dir(folder_path)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.extension().and_then(|x| x.to_str()) == Some("png"))
+        .expect("a generated .png should exist");
+    let icon_thumb = plant(cache_dir.path(), &old_icon);
+    provider
+        .set_icon_for_folder_linux(folder_path, &icon_set_of([0, 255, 0, 255]))
+        .expect("Failed to set folder icon");
+    assert!(!icon_thumb.exists(), "Old icon thumbnail should be removed");
+
+    let folder_thumb = plant(cache_dir.path(), folder_path);
+    provider
+        .reset_icon_for_folder_linux(folder_path)
+        .expect("Failed to reset folder icon");
+    assert!(
+        !folder_thumb.exists(),
+        "Folder thumbnail should be removed on reset"
+    );
+}
+
+/// Calls recorded by [`FakeGvfsMetadata`]: method, tree file, in-tree path
+/// and (for `Set`) the key/value pairs.
+type RecordedCalls = std::sync::Arc<std::sync::Mutex<Vec<(String, String, String, Vec<String>)>>>;
+
+/// Stand-in for `gvfsd-metadata`'s `org.gtk.vfs.Metadata` interface.
+struct FakeGvfsMetadata {
+    calls: RecordedCalls,
+}
+
+/// Decode a NUL-terminated D-Bus bytestring.
+fn from_bytestring(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(
+        bytes
+            .strip_suffix(b"\0")
+            .expect("bytestring is NUL-terminated"),
+    )
+    .into_owned()
+}
+
+#[zbus::interface(name = "org.gtk.vfs.Metadata")]
+impl FakeGvfsMetadata {
+    fn set(
+        &self,
+        treefile: Vec<u8>,
+        path: Vec<u8>,
+        data: std::collections::HashMap<String, zbus::zvariant::OwnedValue>,
+    ) {
+        let mut data: Vec<String> = data
+            .into_iter()
+            .map(|(key, value)| {
+                if let Ok(s) = value.downcast_ref::<&str>() {
+                    format!("{key}={s}")
+                } else if let Ok(list) = Vec::<String>::try_from(value) {
+                    format!("{key}=[{}]", list.join(","))
+                } else {
+                    format!("{key} unset")
+                }
+            })
+            .collect();
+        data.sort();
+        self.calls.lock().unwrap().push((
+            "Set".to_string(),
+            from_bytestring(&treefile),
+            from_bytestring(&path),
+            data,
+        ));
+    }
+
+    fn remove(&self, treefile: Vec<u8>, path: Vec<u8>) {
+        self.calls.lock().unwrap().push((
+            "Remove".to_string(),
+            from_bytestring(&treefile),
+            from_bytestring(&path),
+            Vec::new(),
+        ));
+    }
+
+    /// Never answers in time, to exercise client timeouts.
+    fn r#move(&self, _treefile: Vec<u8>, _path: Vec<u8>, _dest_path: Vec<u8>) {
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+
+    fn get_tree_from_device(&self, _major: u32, _minor: u32) -> String {
+        "root".to_string()
+    }
+}
+
+#[test]
+fn test_gvfs_metadata_over_dbus() {
+    use icon_sys::folder_settings::sys::linux::{
+        GvfsError, GvfsMetadata, GvfsTransport, LinuxBackend, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    let Some(bus) = PrivateBus::start() else {
+        eprintln!("dbus-daemon unavailable, skipping");
+        return;
+    };
+    let gvfs = GvfsMetadata::new(GvfsTransport::DBus {
+        address: Some(bus.address.clone()),
+    })
+    .with_timeout(Duration::from_millis(500));
+
+    // Nobody owns the name yet.
+    assert!(matches!(
+        gvfs.unset("/tmp", "metadata::custom-icon"),
+        Err(GvfsError::ServiceUnavailable(_))
+    ));
+
+    let calls = RecordedCalls::default();
+    let _service = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.gtk.vfs.Metadata")
+        .unwrap()
+        .serve_at(
+            "/org/gtk/vfs/metadata",
+            FakeGvfsMetadata {
+                calls: calls.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .expect("Failed to start stand-in gvfsd-metadata");
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path().canonicalize().unwrap();
+    let folder_name = folder_path
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let img = image::RgbaImage::from_pixel(64, 64, image::Rgba([255, 0, 0, 255]));
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false)
+        .with_gvfs_metadata(gvfs.clone());
+    provider
+        .set_icon_for_folder_linux(&folder_path, &icon_set)
+        .expect("Failed to set folder icon over D-Bus");
+    provider
+        .reset_icon_for_folder_linux(&folder_path)
+        .expect("Failed to reset folder icon over D-Bus");
+    gvfs.remove(&folder_path)
+        .expect("Failed to remove metadata");
+
+    let calls = calls.lock().unwrap().clone();
+    assert_eq!(calls.len(), 3, "{calls:?}");
+    for (_, treefile, path, _) in &calls {
+        // A temp dir outside $HOME lives in a device tree.
+        assert!(treefile.ends_with("gvfs-metadata/root"), "{treefile}");
+        assert!(
+            path.starts_with('/') && path.ends_with(&folder_name),
+            "{path}"
+        );
+    }
+    let (method, _, _, data) = &calls[0];
+    assert_eq!(method, "Set");
+    assert_eq!(data.len(), 1);
+    assert!(data[0].starts_with("custom-icon=file://"), "{data:?}");
+    assert_eq!(calls[1].0, "Set");
+    assert_eq!(calls[1].3, ["custom-icon unset"]);
+    assert_eq!(calls[2].0, "Remove");
+
+    assert!(matches!(
+        gvfs.move_metadata(&folder_path, folder_path.with_extension("moved")),
+        Err(GvfsError::Timeout(_))
+    ));
+}
+
+#[test]
+fn test_emblems_are_validated_and_written_over_dbus() {
+    use icon_sys::folder_settings::FolderSettingsError;
+    use icon_sys::folder_settings::sys::linux::{
+        GvfsMetadata, GvfsTransport, LinuxBackend, LinuxFolderSettingsError,
+        LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    };
+    use tempfile::tempdir;
+
+    if !std::path::Path::new("/usr/share/icons/Adwaita").is_dir() {
+        eprintln!("Adwaita icon theme unavailable, skipping");
+        return;
+    }
+    let Some(bus) = PrivateBus::start() else {
+        eprintln!("dbus-daemon unavailable, skipping");
+        return;
+    };
+    let calls = RecordedCalls::default();
+    let _service = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.gtk.vfs.Metadata")
+        .unwrap()
+        .serve_at(
+            "/org/gtk/vfs/metadata",
+            FakeGvfsMetadata {
+                calls: calls.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .expect("Failed to start stand-in gvfsd-metadata");
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false)
+        .with_gvfs_metadata(GvfsMetadata::new(GvfsTransport::DBus {
+            address: Some(bus.address.clone()),
+        }));
+
+    for bogus in ["folder", "emblem-does-not-exist"] {
+        assert!(matches!(
+            provider.add_emblem_linux(folder_path, bogus),
+            Err(FolderSettingsError::Linux(
+                LinuxFolderSettingsError::UnknownEmblem(_)
+            ))
+        ));
+    }
+    assert!(calls.lock().unwrap().is_empty());
+
+    provider
+        .add_emblem_linux(folder_path, "emblem-favorite")
+        .expect("Failed to add emblem");
+    // Nothing is shown yet, so removing is a no-op.
+    provider
+        .remove_emblem_linux(folder_path, "emblem-important")
+        .expect("Failed to remove emblem");
+    provider
+        .clear_emblems_linux(folder_path)
+        .expect("Failed to clear emblems");
+
+    let data: Vec<_> = calls.lock().unwrap().iter().map(|c| c.3.clone()).collect();
+    assert_eq!(
+        data,
+        [
+            vec!["emblems=[emblem-favorite]".to_string()],
+            vec!["emblems unset".to_string()],
+        ]
+    );
+}
+
+#[test]
+fn test_directory_file_round_trip_preserves_dolphin_settings() {
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    const ORIGINAL: &str = "# Dolphin view properties\n\
+        [Dolphin]\n\
+        Timestamp=2024,1,2,3,4,5.678\n\
+        ViewMode=1\n\
+        \n\
+        [Desktop Entry]\n\
+        Name[de]=Projekte\n\
+        \n\
+        [Settings]\n\
+        HiddenFilesShown=true\n";
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+    let directory_file = folder_path.join(".directory");
+    std::fs::write(&directory_file, ORIGINAL).unwrap();
+
+    let img = image::RgbaImage::from_pixel(64, 64, image::Rgba([0, 0, 255, 255]));
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 64,
+        image: Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::DirectoryFile, None, false);
+
+    provider
+        .set_icon_for_folder_linux(folder_path, &icon_set)
+        .expect("Failed to set folder icon");
+    let with_icon = std::fs::read_to_string(&directory_file).unwrap();
+    let icon_line = with_icon
+        .lines()
+        .find(|l| l.starts_with("Icon="))
+        .expect("Icon entry should be written");
+    assert_eq!(
+        with_icon,
+        ORIGINAL.replace(
+            "Name[de]=Projekte\n",
+            &format!("Name[de]=Projekte\n{icon_line}\n")
+        )
+    );
+
+    provider
+        .reset_icon_for_folder_linux(folder_path)
+        .expect("Failed to reset folder icon");
+    assert_eq!(std::fs::read_to_string(&directory_file).unwrap(), ORIGINAL);
+}
+
+#[test]
+fn test_folder_metadata_round_trips_through_directory_file() {
+    use icon_sys::folder_settings::FolderMetadata;
+    use icon_sys::folder_settings::sys::linux::{
+        LinuxBackend, LinuxFolderSettingsProvider, LinuxFolderSettingsProviderExt,
+    };
+    use tempfile::tempdir;
+
+    const ORIGINAL: &str = "[Dolphin]\nViewMode=1\n";
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let folder_path = temp_dir.path();
+    let directory_file = folder_path.join(".directory");
+    std::fs::write(&directory_file, ORIGINAL).unwrap();
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false);
+
+    provider
+        .set_display_name(folder_path, Some("Projects"))
+        .expect("Failed to set display name");
+    provider
+        .set_display_name_linux(folder_path, Some("Projekte"), Some("de"))
+        .unwrap();
+    provider
+        .set_tooltip(folder_path, Some("Work in progress"))
+        .expect("Failed to set tooltip");
+    assert_eq!(
+        std::fs::read_to_string(&directory_file).unwrap(),
+        "[Dolphin]\nViewMode=1\n\n[Desktop Entry]\nName=Projects\nName[de]=Projekte\n\
+         Comment=Work in progress\n"
+    );
+    assert_eq!(
+        provider
+            .display_name_linux(folder_path, Some("de_AT.UTF-8"))
+            .unwrap()
+            .as_deref(),
+        Some("Projekte")
+    );
+    assert_eq!(
+        provider
+            .display_name_linux(folder_path, Some("fr_FR"))
+            .unwrap()
+            .as_deref(),
+        Some("Projects")
+    );
+    assert_eq!(
+        provider
+            .tooltip_linux(folder_path, None)
+            .unwrap()
+            .as_deref(),
+        Some("Work in progress")
+    );
+
+    provider.set_display_name(folder_path, None).unwrap();
+    provider
+        .set_display_name_linux(folder_path, None, Some("de"))
+        .unwrap();
+    provider.set_tooltip(folder_path, None).unwrap();
+    assert_eq!(std::fs::read_to_string(&directory_file).unwrap(), ORIGINAL);
+    assert_eq!(
+        provider.display_name_linux(folder_path, None).unwrap(),
+        None
+    );
+}
+
+/// A `gio` stand-in that keeps `metadata::custom-icon` values in `store`,
+/// one file per path, so reads see earlier writes.
+fn fake_metadata_gio(store: &std::path::Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let program = store.join("gio");
+    let script = format!(
+        r#"#!/bin/sh
+slot() {{ echo "{store}/$(printf '%s' "$1" | md5sum | cut -c1-32)"; }}
+case "$1" in
+set)
+    if [ "$2" = "-t" ]; then rm -f "$(slot "$4")"; else printf '%s' "$4" > "$(slot "$2")"; fi ;;
+info)
+    echo "uri: file://$4"
+    echo "attributes:"
+    if [ -f "$(slot "$4")" ]; then echo "  metadata::custom-icon: $(cat "$(slot "$4")")"; fi ;;
+esac
+"#,
+        store = store.display()
+    );
+    std::fs::write(&program, script).unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    program
+}
+
+#[test]
+fn test_icon_provider_sets_and_reads_back_file_icons() {
+    use icon_sys::folder_settings::sys::linux::{
+        GvfsMetadata, GvfsTransport, IconUpdate, LinuxBackend, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use icon_sys::{IconProvider, IconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let store = tempdir().expect("Failed to create temp dir");
+    let icon_dir = tempdir().expect("Failed to create temp dir");
+    let work = tempdir().expect("Failed to create temp dir");
+    let file_path = work.path().join("report.pdf");
+    std::fs::write(&file_path, b"%PDF-1.7").unwrap();
+
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false)
+        .with_gvfs_metadata(GvfsMetadata::new(GvfsTransport::Cli {
+            program: fake_metadata_gio(store.path()),
+        }))
+        .with_file_icon_dir(icon_dir.path());
+
+    assert!(provider.icon_for_path(&file_path).unwrap().is_none());
+
+    let img = image::RgbaImage::from_pixel(48, 48, image::Rgba([0, 128, 0, 255]));
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 48,
+        image: Cow::Owned(image::DynamicImage::ImageRgba8(img)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+    provider
+        .set_icon_for_path(&file_path, &IconSet::from(icon_set.clone()))
+        .expect("Failed to set file icon");
+
+    let generated: Vec<_> = std::fs::read_dir(icon_dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(generated.len(), 1, "{generated:?}");
+    assert!(
+        std::fs::read_dir(work.path()).unwrap().count() == 1,
+        "nothing is written next to the file"
+    );
+
+    let read_back = provider
+        .icon_for_path(&file_path)
+        .unwrap()
+        .expect("icon should be read back");
+    assert_eq!(read_back.images.len(), 1);
+    assert_eq!(read_back.images[0].data.width(), 48);
+    assert_eq!(
+        provider
+            .set_icon_for_file_linux(&file_path, &icon_set)
+            .unwrap(),
+        IconUpdate::Unchanged
+    );
+
+    provider
+        .reset_icon_for_path(&file_path)
+        .expect("Failed to reset file icon");
+    assert!(provider.icon_for_path(&file_path).unwrap().is_none());
+    assert_eq!(std::fs::read_dir(icon_dir.path()).unwrap().count(), 0);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn test_registered_mime_type_is_seen_by_gio() {
+    use icon_sys::folder_settings::sys::linux::{
+        MagicValueType, MimeDatabase, MimeMagicMatch, MimeTypeDefinition,
+    };
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let data_dir = temp_dir.path().join("share");
+    let definition = MimeTypeDefinition::new("application/x-acme-project")
+        .with_comment("Acme project")
+        .with_glob("*.acme")
+        .with_magic(60, [MimeMagicMatch::new(MagicValueType::String, 0, "ACME")]);
+    let db = MimeDatabase::at(&data_dir);
+    db.install_package("acme", &[definition])
+        .expect("Failed to install package");
+
+    let by_name = temp_dir.path().join("plan.acme");
+    let by_content = temp_dir.path().join("plan");
+    std::fs::write(&by_name, b"name only").unwrap();
+    std::fs::write(&by_content, b"ACME project file").unwrap();
+
+    // Only our database is visible, so GLib must read the generated
+    // mime.cache to know the type at all.
+    let content_type = |path: &std::path::Path| {
+        Command::new("gio")
+            .args(["info", "-a", "standard::content-type"])
+            .arg(path)
+            .env("XDG_DATA_HOME", &data_dir)
+            .env("XDG_DATA_DIRS", &data_dir)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let Some(info) = content_type(&by_name) else {
+        eprintln!("gio unavailable, skipping");
+        return;
+    };
+    assert!(info.contains("standard::content-type: application/x-acme-project"));
+    let info = content_type(&by_content).unwrap();
+    assert!(info.contains("standard::content-type: application/x-acme-project"));
+
+    assert!(db.uninstall_package("acme").unwrap());
+    let info = content_type(&by_name).unwrap();
+    assert!(!info.contains("application/x-acme-project"));
+}
+
+#[test]
+fn test_gio_folder_icons_escape_the_icon_uri() {
+    use icon_sys::folder_settings::sys::linux::{
+        GvfsMetadata, GvfsTransport, IconUpdate, LinuxBackend, LinuxFolderSettingsProvider,
+        LinuxFolderSettingsProviderExt,
+    };
+    use icon_sys::icon::sys::linux::{LinuxIconImage, LinuxIconSet};
+    use std::borrow::Cow;
+    use tempfile::tempdir;
+
+    let store = tempdir().expect("Failed to create temp dir");
+    let work = tempdir().expect("Failed to create temp dir");
+    let folder = work.path().join("100% done");
+    std::fs::create_dir(&folder).unwrap();
+
+    let provider = LinuxFolderSettingsProvider::new_linux(LinuxBackend::GioMetadata, None, false)
+        .with_gvfs_metadata(GvfsMetadata::new(GvfsTransport::Cli {
+            program: fake_metadata_gio(store.path()),
+        }));
+    let icon_set = LinuxIconSet::from_icons([LinuxIconImage {
+        size: 48,
+        image: Cow::Owned(image::DynamicImage::new_rgba8(48, 48)),
+    }])
+    .expect("Failed to create LinuxIconSet");
+    provider
+        .set_icon_for_folder_linux(&folder, &icon_set)
+        .expect("Failed to set folder icon");
+
+    let stored: Vec<String> = std::fs::read_dir(store.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name().unwrap() != "gio")
+        .map(|p| std::fs::read_to_string(p).unwrap())
+        .collect();
+    assert_eq!(stored.len(), 1, "{stored:?}");
+    assert!(stored[0].contains("/100%25%20done/"), "{stored:?}");
+
+    assert!(provider.icon_for_path_linux(&folder).unwrap().is_some());
+    assert_eq!(
+        provider
+            .set_icon_for_folder_linux(&folder, &icon_set)
+            .unwrap()
+            .update,
+        IconUpdate::Unchanged
+    );
+}